@@ -22,13 +22,20 @@
 //! - `validate_evidence_quality()` with Admiralty Code algorithms
 //! - `apply_prisma_methodology()` with automated compliance checking
 //! - `cross_validate_findings()` with consistency scoring algorithms
+//!
+//! # Storage maintenance
+//!
+//! `systematic-researcher verify` reports drift between REDB and the vector
+//! index instead of running the walkthrough; `systematic-researcher repair`
+//! fixes it (`--delete-unindexed` drops knowledge rows without a vector,
+//! `--delete-unreadable` deletes undecodable rows instead of quarantining them).
 
 use acs_example::{
     ACSFramework, ACSFrameworkOperations, ACSTask, ACSKnowledge,
-    TaskType, TaskPriority, create_research_framework,
+    TaskType, TaskPriority, RepairPolicy, create_research_framework,
+    storage::{MissingVectorRepair, UnreadableRowRepair},
 };
 use std::collections::HashMap;
-use tokio;
 use tracing::{info, error};
 
 #[tokio::main]
@@ -42,6 +49,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let framework = create_research_framework().await?;
     info!("ACS Framework initialized successfully");
 
+    // Storage maintenance commands replace the walkthrough
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        return run_storage_command(&framework, command, &args[1..]).await;
+    }
+
     // Demonstrate storing knowledge for semantic understanding
     info!("Storing sample knowledge for semantic routing...");
 
@@ -58,6 +71,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 metadata
             },
             tags: vec!["rust".to_string(), "performance".to_string(), "systems".to_string()],
+            score: None,
         },
         ACSKnowledge {
             title: "Vector Database Integration Patterns".to_string(),
@@ -71,6 +85,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 metadata
             },
             tags: vec!["qdrant".to_string(), "vector".to_string(), "semantic".to_string()],
+            score: None,
         },
         ACSKnowledge {
             title: "Hybrid AI Architecture Benefits".to_string(),
@@ -84,6 +99,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 metadata
             },
             tags: vec!["ai".to_string(), "hybrid".to_string(), "architecture".to_string()],
+            score: None,
         },
     ];
 
@@ -144,8 +160,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Found {} semantically similar knowledge items:", search_results.len());
     for (i, knowledge) in search_results.iter().enumerate() {
-        info!("  {}. {} (Source: {}, Credibility: {})",
-            i + 1, knowledge.title, knowledge.source, knowledge.credibility_rating);
+        info!("  {}. {} (Source: {}, Credibility: {}, Score: {:.3})",
+            i + 1, knowledge.title, knowledge.source, knowledge.credibility_rating,
+            knowledge.score.unwrap_or_default());
         info!("     Content: {}",
             if knowledge.content.len() > 100 {
                 format!("{}...", &knowledge.content[..100])
@@ -253,11 +270,63 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Run `verify` or `repair` against the framework's storage
+async fn run_storage_command(
+    framework: &ACSFramework,
+    command: &str,
+    options: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        "verify" => {
+            let report = framework.verify_storage().await?;
+            info!("Checked {} rows and {} vector points in {}",
+                report.rows_checked, report.points_checked, report.collection);
+            if report.pending_writes > 0 {
+                info!("{} vector writes are still pending; run repair to apply them first", report.pending_writes);
+            }
+            for issue in &report.issues {
+                info!("  {}", issue);
+            }
+
+            framework.shutdown().await?;
+            if !report.is_consistent() {
+                return Err(format!("{} storage issues found", report.issues.len()).into());
+            }
+            info!("Storage is consistent");
+        }
+        "repair" => {
+            let mut policy = RepairPolicy::default();
+            for option in options {
+                match option.as_str() {
+                    "--delete-unindexed" => policy.missing_vector = MissingVectorRepair::DeleteRow,
+                    "--delete-unreadable" => policy.unreadable_row = UnreadableRowRepair::Delete,
+                    other => return Err(format!("Unknown repair option: {}", other).into()),
+                }
+            }
+
+            let report = framework.repair_storage(policy).await?;
+            info!("Repaired {} of {} storage issues ({} ignored)",
+                report.repaired, report.found.issues.len(), report.ignored);
+            for failure in &report.failures {
+                error!("  {}: {}", failure.issue, failure.error);
+            }
+
+            framework.shutdown().await?;
+            if !report.failures.is_empty() {
+                return Err(format!("{} storage issues could not be repaired", report.failures.len()).into());
+            }
+        }
+        other => return Err(format!("Unknown command: {} (expected verify or repair)", other).into()),
+    }
+
+    Ok(())
+}
+
 /// Additional helper function to demonstrate behavioral component composition
 #[allow(dead_code)]
 async fn demonstrate_behavioral_composition() -> Result<(), Box<dyn std::error::Error>> {
     use acs_example::{
-        SystematicResearchAgent, ValidationConfig, CredibilityRating,
+        SystematicResearchAgent, CredibilityRating,
         behavioral::systematic_research::*,
     };
 
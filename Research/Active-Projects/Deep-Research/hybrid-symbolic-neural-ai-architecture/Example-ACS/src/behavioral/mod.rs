@@ -9,8 +9,6 @@ use std::collections::HashMap;
 use uuid::Uuid;
 
 pub mod systematic_research;
-pub mod evidence_validation;
-pub mod decision_making;
 
 /// Core behavioral trait that all agents must implement
 #[async_trait]
@@ -187,7 +185,7 @@ pub enum AgentError {
     ValidationError(String),
 
     #[error("Storage error: {0}")]
-    StorageError(#[from] redb::Error),
+    StorageError(#[from] Box<redb::Error>),
 
     #[error("Network error: {0}")]
     NetworkError(String),
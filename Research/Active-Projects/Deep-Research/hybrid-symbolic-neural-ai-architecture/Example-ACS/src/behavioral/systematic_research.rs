@@ -3,11 +3,20 @@
 //! This module demonstrates how agent.md behavioral descriptions like:
 //! "Be systematic and evidence-based in research methodology"
 //! translate to concrete algorithmic implementations in Rust.
+//!
+//! With storage attached through `with_storage`, the search also draws findings
+//! from stored knowledge, restricted to the evidence threshold by the storage
+//! query itself. Cross-validation keeps the conflicts it finds between stored
+//! findings as `contradicts` relations, so a later run reports them even when
+//! it does not detect them again.
 
 use super::*;
+use crate::storage::{Direction, EntityRef, HybridStorage, KnowledgeEntity, KnowledgeQuery, Relation, RelationKind};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 
 /// Systematic Research Behavioral Trait
 ///
@@ -32,12 +41,26 @@ pub trait SystematicResearcher: AgentBehavior {
 }
 
 /// Concrete implementation of SystematicResearcher behavior
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct SystematicResearchAgent {
     pub agent_id: Uuid,
     pub search_strategies: Vec<SearchStrategy>,
     pub validation_config: ValidationConfig,
     pub evidence_threshold: CredibilityRating,
+    /// Stored knowledge searched for findings, also holding conflicts between findings across runs
+    storage: Option<Arc<dyn HybridStorage>>,
+}
+
+impl fmt::Debug for SystematicResearchAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SystematicResearchAgent")
+            .field("agent_id", &self.agent_id)
+            .field("search_strategies", &self.search_strategies)
+            .field("validation_config", &self.validation_config)
+            .field("evidence_threshold", &self.evidence_threshold)
+            .field("storage", &self.storage.is_some())
+            .finish()
+    }
 }
 
 #[async_trait]
@@ -112,15 +135,24 @@ impl SystematicResearcher for SystematicResearchAgent {
             all_evidence.extend(strategy_results.evidence);
         }
 
+        // Stored knowledge is already restricted to the evidence threshold by the query
+        if let Some(storage) = &self.storage {
+            for finding in self.search_stored_knowledge(storage.as_ref(), query).await? {
+                all_evidence.extend(finding.evidence.iter().cloned());
+                all_findings.push(finding);
+            }
+        }
+
         // Apply deduplication algorithm
         let deduplicated_findings = self.deduplicate_findings(all_findings);
+        let quality_metrics = self.calculate_quality_metrics(&all_evidence);
 
         Ok(SearchResults {
             query: query.clone(),
             findings: deduplicated_findings,
             evidence: all_evidence,
             methodology: ResearchMethodology::Systematic,
-            quality_metrics: self.calculate_quality_metrics(&all_evidence),
+            quality_metrics,
         })
     }
 
@@ -164,8 +196,8 @@ impl SystematicResearcher for SystematicResearchAgent {
             } else {
                 ValidationStatus::Passed
             },
-            checks,
             confidence_score: self.calculate_confidence_score(&checks),
+            checks,
         }
     }
 
@@ -184,7 +216,7 @@ impl SystematicResearcher for SystematicResearchAgent {
         // 2. Screening phase
         let high_quality_sources = findings.iter()
             .filter(|f| f.evidence.iter().any(|e|
-                matches!(e.credibility_rating, CredibilityRating::A1..=CredibilityRating::B3)
+                is_high_quality(&e.credibility_rating)
             ))
             .count();
 
@@ -220,8 +252,8 @@ impl SystematicResearcher for SystematicResearchAgent {
         });
 
         PrismaValidation {
-            checks: prisma_checks,
             overall_compliance: prisma_checks.values().all(|check| check.criteria_met),
+            checks: prisma_checks,
             methodology_version: "Enhanced PRISMA 2020".to_string(),
         }
     }
@@ -234,12 +266,13 @@ impl SystematicResearcher for SystematicResearchAgent {
         for (i, finding_a) in findings.iter().enumerate() {
             for finding_b in findings.iter().skip(i + 1) {
                 let consistency_score = self.calculate_consistency_score(finding_a, finding_b);
+                let detected = self.identify_conflicts(finding_a, finding_b);
 
                 validation_pairs.push(ValidationPair {
                     finding_a_id: finding_a.id.clone(),
                     finding_b_id: finding_b.id.clone(),
                     consistency_score,
-                    conflicts: self.identify_conflicts(finding_a, finding_b),
+                    conflicts: self.carry_conflicts(finding_a, finding_b, detected).await,
                 });
             }
         }
@@ -247,13 +280,14 @@ impl SystematicResearcher for SystematicResearchAgent {
         let average_consistency = validation_pairs.iter()
             .map(|pair| pair.consistency_score)
             .sum::<f64>() / validation_pairs.len() as f64;
+        let conflicts_detected = validation_pairs.iter()
+            .map(|pair| pair.conflicts.len())
+            .sum();
 
         CrossValidationResult {
             validation_pairs,
             average_consistency,
-            conflicts_detected: validation_pairs.iter()
-                .map(|pair| pair.conflicts.len())
-                .sum(),
+            conflicts_detected,
             confidence_level: self.calculate_cross_validation_confidence(average_consistency),
         }
     }
@@ -287,6 +321,78 @@ impl SystematicResearcher for SystematicResearchAgent {
     }
 }
 
+/// A or B1-B3 ratings, the sources PRISMA screening and quality metrics count as high quality
+fn is_high_quality(rating: &CredibilityRating) -> bool {
+    use CredibilityRating::*;
+    matches!(rating, A1 | A2 | A3 | A4 | A5 | A6 | B1 | B2 | B3)
+}
+
+/// Lowercased words of a conclusion without its negations, and whether it is negated
+///
+/// "cannot" and "-n't" contractions count as a negation of their stem, so
+/// "Ice doesn't melt" reads as the words of "Ice does melt", negated.
+fn polarity(conclusion: &str) -> (Vec<String>, bool) {
+    let mut words = Vec::new();
+    let mut negated = false;
+    for word in conclusion.split(|c: char| !c.is_alphanumeric() && c != '\'').filter(|word| !word.is_empty()) {
+        let word = word.to_lowercase();
+        match word.as_str() {
+            "not" | "no" | "never" => negated = !negated,
+            "cannot" => {
+                negated = !negated;
+                words.push("can".to_string());
+            }
+            _ => match word.strip_suffix("n't") {
+                Some(stem) => {
+                    negated = !negated;
+                    words.push(stem.to_string());
+                }
+                None => words.push(word),
+            },
+        }
+    }
+    (words, negated)
+}
+
+/// Whether one conclusion is the negation of the other
+fn are_opposite(a: &str, b: &str) -> bool {
+    let (words_a, negated_a) = polarity(a);
+    let (words_b, negated_b) = polarity(b);
+    !words_a.is_empty() && words_a == words_b && negated_a != negated_b
+}
+
+/// A stored knowledge entry as a finding; `conclusions` and `topic` are read from its metadata
+///
+/// Untitled entries are titled by their content, so deduplication does not merge
+/// them. Entries whose rating is not an Admiralty rating are skipped.
+fn stored_finding(knowledge: KnowledgeEntity, domain: &str) -> Option<Finding> {
+    let rating = serde_json::Value::String(knowledge.credibility_rating.trim().to_ascii_uppercase());
+    let credibility_rating: CredibilityRating = serde_json::from_value(rating).ok()?;
+    let conclusions = knowledge.metadata.get("conclusions")
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+        .unwrap_or_default();
+    let topic = knowledge.metadata.get("topic")
+        .and_then(|value| value.as_str())
+        .unwrap_or(domain)
+        .to_string();
+
+    Some(Finding {
+        id: knowledge.id.to_string(),
+        title: knowledge.title.clone().unwrap_or_else(|| knowledge.content.clone()),
+        conclusions,
+        topic: Some(topic),
+        evidence: vec![Evidence {
+            source_id: knowledge.source.clone(),
+            source_type: SourceType::Unknown,
+            credibility_rating,
+            content: knowledge.content.clone(),
+            validation_checks: vec![],
+            timestamp: knowledge.created_at,
+        }],
+        content: knowledge.content,
+    })
+}
+
 // Helper implementations for the SystematicResearchAgent
 impl SystematicResearchAgent {
     pub fn new(validation_config: ValidationConfig) -> Self {
@@ -300,9 +406,16 @@ impl SystematicResearchAgent {
             ],
             validation_config,
             evidence_threshold: CredibilityRating::B3, // Minimum B3 as per research
+            storage: None,
         }
     }
 
+    /// Search knowledge in `storage` and keep conflicts between findings stored there, across runs
+    pub fn with_storage(mut self, storage: Arc<dyn HybridStorage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
     fn parse_search_intent(&self, intent: &Intent) -> Result<SearchQuery, AgentError> {
         let query_text = intent.parameters
             .get("query")
@@ -351,6 +464,31 @@ impl SystematicResearchAgent {
         }
     }
 
+    /// Stored knowledge relevant to `query` rated at least `evidence_threshold`, as findings
+    async fn search_stored_knowledge(&self, storage: &dyn HybridStorage, query: &SearchQuery) -> Result<Vec<Finding>, AgentError> {
+        let stored = storage
+            .query_knowledge(&KnowledgeQuery {
+                text: query.text.clone(),
+                limit: query.max_sources,
+                min_credibility: Some(format!("{:?}", self.evidence_threshold)),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| AgentError::Internal(format!("Failed to query stored knowledge: {}", e)))?;
+
+        Ok(stored.results.into_iter().filter_map(|knowledge| stored_finding(knowledge, &query.domain)).collect())
+    }
+
+    /// Keep findings backed by at least one piece of evidence that passes validation
+    fn validate_search_results(&self, results: &SearchResults) -> Result<Vec<Finding>, AgentError> {
+        Ok(results.findings.iter()
+            .filter(|finding| finding.evidence.iter().any(|evidence| {
+                !matches!(self.validate_evidence_quality(evidence).overall_status, ValidationStatus::Failed)
+            }))
+            .cloned()
+            .collect())
+    }
+
     fn deduplicate_findings(&self, findings: Vec<Finding>) -> Vec<Finding> {
         // Simple deduplication based on content similarity
         // Production implementation would use more sophisticated algorithms
@@ -407,7 +545,7 @@ impl SystematicResearchAgent {
 
     fn calculate_quality_metrics(&self, evidence: &[Evidence]) -> QualityMetrics {
         let high_quality_count = evidence.iter()
-            .filter(|e| matches!(e.credibility_rating, CredibilityRating::A1..=CredibilityRating::B3))
+            .filter(|e| is_high_quality(&e.credibility_rating))
             .count();
 
         QualityMetrics {
@@ -426,7 +564,7 @@ impl SystematicResearchAgent {
             .sum::<f64>() / evidence.len().max(1) as f64
     }
 
-    fn assess_relevance(&self, finding: &Finding) -> f64 {
+    fn assess_relevance(&self, _finding: &Finding) -> f64 {
         // Simplified relevance assessment
         // Production would use semantic similarity via vector embeddings
         0.8 // Mock high relevance
@@ -446,9 +584,75 @@ impl SystematicResearchAgent {
         }
     }
 
+    /// Conclusions of two findings that contradict each other, when the findings share a source or topic
+    ///
+    /// Conflicts are described the same way whichever finding comes first, so
+    /// the ones recorded by an earlier run are recognised again.
     fn identify_conflicts(&self, finding_a: &Finding, finding_b: &Finding) -> Vec<String> {
-        // Simplified conflict detection
-        vec![] // Mock no conflicts
+        // Simplified conflict detection: a conclusion against its negation
+        // Production would use natural language inference
+        let shares_source = finding_a.evidence.iter()
+            .any(|a| finding_b.evidence.iter().any(|b| a.source_id == b.source_id));
+        let shares_topic = finding_a.topic.is_some() && finding_a.topic == finding_b.topic;
+        if !shares_source && !shares_topic {
+            return vec![];
+        }
+
+        let mut conflicts = Vec::new();
+        for a in &finding_a.conclusions {
+            for b in finding_b.conclusions.iter().filter(|b| are_opposite(a, b)) {
+                let (first, second) = if a <= b { (a, b) } else { (b, a) };
+                let conflict = format!("Opposite conclusions: \"{}\" vs \"{}\"", first, second);
+                if !conflicts.contains(&conflict) {
+                    conflicts.push(conflict);
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Merge `detected` with the conflicts recorded between two stored findings, recording any new ones
+    ///
+    /// Findings are matched to knowledge by ID; pairs that are not both stored,
+    /// and storage failures, leave `detected` as it is.
+    async fn carry_conflicts(&self, finding_a: &Finding, finding_b: &Finding, detected: Vec<String>) -> Vec<String> {
+        let Some(storage) = &self.storage else {
+            return detected;
+        };
+        let (Ok(a), Ok(b)) = (Uuid::parse_str(&finding_a.id), Uuid::parse_str(&finding_b.id)) else {
+            return detected;
+        };
+        let (a, b) = (EntityRef::knowledge(a), EntityRef::knowledge(b));
+
+        let recorded = match storage.neighbors(&a, Direction::Both, &[RelationKind::Contradicts]).await {
+            Ok(relations) => relations.into_iter().find(|relation| relation.from == b || relation.to == b),
+            Err(e) => {
+                tracing::warn!("Could not load conflicts between findings {} and {}: {}", a.id, b.id, e);
+                return detected;
+            }
+        };
+
+        let mut conflicts: Vec<String> = recorded.as_ref()
+            .and_then(|relation| relation.metadata.get("conflicts"))
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .unwrap_or_default();
+        let known = conflicts.len();
+        for conflict in detected {
+            if !conflicts.contains(&conflict) {
+                conflicts.push(conflict);
+            }
+        }
+        if conflicts.len() == known {
+            return conflicts;
+        }
+
+        let mut relation = recorded.unwrap_or_else(|| Relation::new(a, RelationKind::Contradicts, b));
+        relation.metadata.insert("conflicts".to_string(), serde_json::json!(conflicts));
+        relation.metadata.insert("recorded_by".to_string(), serde_json::json!(self.agent_id));
+        if let Err(e) = storage.add_relation(&relation).await {
+            tracing::warn!("Could not record conflicts between findings {} and {}: {}", a.id, b.id, e);
+        }
+        conflicts
     }
 
     fn calculate_cross_validation_confidence(&self, average_consistency: f64) -> ConfidenceLevel {
@@ -504,7 +708,7 @@ impl SystematicResearchAgent {
 
     fn calculate_evidence_strength(&self, findings: &[Finding]) -> EvidenceStrength {
         let strong_evidence = findings.iter()
-            .filter(|f| f.evidence.iter().any(|e| matches!(e.credibility_rating, CredibilityRating::A1..=CredibilityRating::A6)))
+            .filter(|f| f.evidence.iter().any(|e| e.credibility_rating.meets_threshold(&CredibilityRating::A1)))
             .count();
 
         match strong_evidence {
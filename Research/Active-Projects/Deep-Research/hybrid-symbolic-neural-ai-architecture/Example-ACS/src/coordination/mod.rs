@@ -4,8 +4,8 @@
 //! showing how agents with algorithmic behaviors coordinate through REDB state management
 //! and semantic understanding via Qdrant integration.
 
-use crate::behavioral::{AgentBehavior, AgentContext, AgentAction, AgentError, Intent};
-use crate::storage::{HybridStorage, HybridStorageCoordinator, AgentEntity, KnowledgeEntity, CoordinationEntity};
+use crate::behavioral::{AgentBehavior, AgentContext, AgentAction, Intent};
+use crate::storage::{HybridStorage, AgentEntity, CoordinationEntity, TaskHistoryEntity};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -13,14 +13,11 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
-pub mod task_delegation;
-pub mod consensus;
-pub mod event_sourcing;
 
 /// Multi-agent coordination hub implementing research patterns
 pub struct AgentCoordinationHub {
     // Hybrid storage for state and semantic operations
-    storage: Arc<HybridStorageCoordinator>,
+    storage: Arc<dyn HybridStorage>,
 
     // Registered agents with their behavioral implementations
     agents: Arc<RwLock<HashMap<Uuid, Arc<dyn AgentBehavior>>>>,
@@ -78,7 +75,7 @@ enum SessionStatus {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-enum TaskStatus {
+pub enum TaskStatus {
     Pending,
     InProgress,
     Completed,
@@ -94,13 +91,13 @@ enum AgentStatus {
     Error,
 }
 
-#[derive(Debug, Default, Clone)]
-struct CoordinationMetrics {
-    total_sessions: u64,
-    successful_completions: u64,
-    failed_operations: u64,
-    average_completion_time_ms: f64,
-    agent_utilization: HashMap<Uuid, f64>,
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CoordinationMetrics {
+    pub total_sessions: u64,
+    pub successful_completions: u64,
+    pub failed_operations: u64,
+    pub average_completion_time_ms: f64,
+    pub agent_utilization: HashMap<Uuid, f64>,
 }
 
 /// Coordination traits for multi-agent operations
@@ -168,9 +165,9 @@ pub struct CoordinationStatus {
 }
 
 impl AgentCoordinationHub {
-    /// Create new coordination hub with hybrid storage
+    /// Create new coordination hub over any storage backend
     pub async fn new(
-        storage: Arc<HybridStorageCoordinator>,
+        storage: Arc<dyn HybridStorage>,
         config: CoordinationConfig,
     ) -> Result<Self, CoordinationError> {
         Ok(Self {
@@ -192,20 +189,16 @@ impl AgentCoordinationHub {
                 .join(" ")
         );
 
-        // Search for similar successful tasks
+        // Search for similar past tasks
         let similar_tasks = self.storage
-            .search_knowledge(&search_query, 5)
+            .search_task_history(&search_query, 5)
             .await
             .map_err(|e| CoordinationError::SemanticRoutingError(format!("Failed to search for similar tasks: {}", e)))?;
 
+        // Route to the agent that handled the closest task, if it is still registered
         if let Some(best_match) = similar_tasks.first() {
-            // Extract agent ID from metadata
-            if let Some(agent_id_value) = best_match.metadata.get("agent_id") {
-                if let Some(agent_id_str) = agent_id_value.as_str() {
-                    if let Ok(agent_id) = Uuid::parse_str(agent_id_str) {
-                        return Ok(agent_id);
-                    }
-                }
+            if self.agents.read().await.contains_key(&best_match.entity.agent_id) {
+                return Ok(best_match.entity.agent_id);
             }
         }
 
@@ -239,7 +232,7 @@ impl AgentCoordinationHub {
         let agents = self.agents.read().await;
         let agent = agents
             .get(&agent_id)
-            .ok_or_else(|| CoordinationError::AgentNotFound(agent_id))?
+            .ok_or(CoordinationError::AgentNotFound(agent_id))?
             .clone();
         drop(agents);
 
@@ -278,42 +271,37 @@ impl AgentCoordinationHub {
         result.map_err(|e| CoordinationError::AgentExecutionError(format!("Agent execution failed: {}", e)))
     }
 
-    /// Store task execution knowledge for future semantic routing
-    async fn store_task_knowledge(
+    /// Record task execution history for future semantic routing
+    async fn record_task_history(
         &self,
         task: &CoordinationTask,
-        agent_id: Uuid,
+        session_id: Uuid,
         result: &CoordinationResult,
     ) -> Result<(), CoordinationError> {
-        let knowledge = KnowledgeEntity {
+        let entry = TaskHistoryEntity {
             id: Uuid::new_v4(),
-            content: format!(
+            session_id,
+            task_id: task.task_id,
+            agent_id: result.agent_id,
+            action_type: task.intent.action_type.clone(),
+            status: format!("{:?}", result.status),
+            summary: format!(
                 "Task: {} executed by agent {} with status {:?}. Intent: {} {}",
                 task.task_id,
-                agent_id,
+                result.agent_id,
                 result.status,
                 task.intent.action_type,
                 task.intent.context
             ),
-            metadata: {
-                let mut metadata = HashMap::new();
-                metadata.insert("task_id".to_string(), task.task_id.to_string().into());
-                metadata.insert("agent_id".to_string(), agent_id.to_string().into());
-                metadata.insert("action_type".to_string(), task.intent.action_type.clone().into());
-                metadata.insert("status".to_string(), serde_json::to_value(&result.status).unwrap());
-                metadata.insert("execution_time_ms".to_string(), result.execution_time_ms.into());
-                metadata
-            },
+            execution_time_ms: result.execution_time_ms,
             embeddings: None, // Will be generated by storage layer
-            source: "coordination_hub".to_string(),
-            credibility_rating: "A1".to_string(), // Internal system data
             created_at: chrono::Utc::now(),
         };
 
         self.storage
-            .store_knowledge(&knowledge)
+            .record_task_history(&entry)
             .await
-            .map_err(|e| CoordinationError::StorageError(format!("Failed to store task knowledge: {}", e)))?;
+            .map_err(|e| CoordinationError::StorageError(format!("Failed to record task history: {}", e)))?;
 
         Ok(())
     }
@@ -398,8 +386,8 @@ impl AgentCoordination for AgentCoordinationHub {
             evidence: agent_action.evidence.iter().map(|e| e.source_id.clone()).collect(),
         };
 
-        // Store task knowledge for future semantic routing
-        self.store_task_knowledge(task, agent_id, &result).await?;
+        // Record task history for future semantic routing
+        self.record_task_history(task, context.session_id, &result).await?;
 
         // Store coordination record
         let coordination_entity = CoordinationEntity {
@@ -410,6 +398,7 @@ impl AgentCoordination for AgentCoordinationHub {
             data: serde_json::to_value(&result)
                 .map_err(|e| CoordinationError::SerializationError(format!("Failed to serialize result: {}", e)))?,
             timestamp: chrono::Utc::now(),
+            agent_id: Some(result.agent_id),
         };
 
         self.storage
@@ -447,7 +436,7 @@ impl AgentCoordination for AgentCoordinationHub {
             .filter(|status| matches!(status, AgentStatus::Available))
             .count();
 
-        let average_load = if agents.len() > 0 {
+        let average_load = if !agents.is_empty() {
             (agents.len() - available_agents) as f64 / agents.len() as f64
         } else {
             0.0
@@ -46,7 +46,15 @@ pub use behavioral::{
 
 pub use storage::{
     HybridStorage, HybridStorageCoordinator, StorageConfig, StorageError,
-    AgentEntity, KnowledgeEntity, CoordinationEntity,
+    AgentEntity, KnowledgeEntity, CoordinationEntity, SyncStatus, VectorBackend, VectorIndex,
+    EmbeddingBackend, EmbeddingProvider, BatchReport, BatchItemResult, FusionStrategy,
+    KnowledgeQuery, KnowledgeQueryResult, KnowledgeFacets, KnowledgeSearch, ScoredKnowledge, SearchPage,
+    ConsistencyWarning, MigrationReport, SchemaMigration, ExportOptions, ImportOptions, ImportMode,
+    ReindexOptions, ReindexProgress, ReindexReport, ConsistencyReport, RepairPolicy, RepairReport,
+    StorageMetrics, EncryptionConfig, KeySource, TaskHistoryEntity, ScoredTaskHistory, RetentionConfig,
+    RetentionPolicy, RetentionReport, KnowledgeRevision, DeduplicationConfig, DuplicateAction, IngestOutcome,
+    IngestReport, ChunkStrategy, ChunkingConfig, DocumentMatch, DocumentPassage, DocumentReport, DocumentSearch,
+    TimeRange, TagMatch, Direction, EntityKind, EntityRef, PathQuery, Relation, RelationKind,
 };
 
 pub use coordination::{
@@ -56,13 +64,14 @@ pub use coordination::{
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use uuid::Uuid;
 
 /// Main ACS Framework facade providing high-level operations
 pub struct ACSFramework {
     coordination_hub: Arc<AgentCoordinationHub>,
-    storage: Arc<HybridStorageCoordinator>,
+    storage: Arc<dyn HybridStorage>,
     config: ACSConfig,
 }
 
@@ -103,12 +112,60 @@ pub trait ACSFrameworkOperations {
     /// Execute a task using the best available agent
     async fn execute_task(&self, task: ACSTask) -> Result<ACSResult, ACSError>;
 
-    /// Store knowledge for semantic understanding
-    async fn store_knowledge(&self, knowledge: ACSKnowledge) -> Result<(), ACSError>;
+    /// Store knowledge for semantic understanding, reporting whether it duplicated an existing entry
+    async fn store_knowledge(&self, knowledge: ACSKnowledge) -> Result<IngestReport, ACSError>;
+
+    /// Store many knowledge entries at once, reporting which ones were stored
+    async fn store_knowledge_batch(&self, knowledge: Vec<ACSKnowledge>) -> Result<BatchReport, ACSError>;
+
+    /// Store a long document (e.g. a research report) as overlapping chunks linked to it
+    async fn store_document(&self, document: ACSKnowledge, chunking: ChunkingConfig) -> Result<DocumentReport, ACSError>;
+
+    /// Search document chunks, grouped by document with surrounding context
+    async fn search_documents(&self, search: DocumentSearch) -> Result<Vec<DocumentMatch>, ACSError>;
 
     /// Search knowledge using semantic similarity
     async fn search_knowledge(&self, query: &str, limit: usize) -> Result<Vec<ACSKnowledge>, ACSError>;
 
+    /// Search knowledge with explicit options (e.g. hybrid lexical + semantic ranking)
+    async fn search_knowledge_with_options(
+        &self,
+        query: &str,
+        options: ACSSearchOptions,
+    ) -> Result<Vec<ACSKnowledge>, ACSError>;
+
+    /// Search knowledge with source, credibility, date, metadata and tag filters, plus facet counts
+    async fn query_knowledge(&self, query: KnowledgeQuery) -> Result<KnowledgeQueryResult, ACSError>;
+
+    /// Every tag in use with the number of knowledge entries carrying it
+    async fn list_tags(&self) -> Result<BTreeMap<String, usize>, ACSError>;
+
+    /// Knowledge carrying all or any of `tags`, oldest first
+    async fn find_by_tags(&self, tags: &[String], matching: TagMatch) -> Result<Vec<ACSKnowledge>, ACSError>;
+
+    /// Relate two stored entities, e.g. a finding that contradicts another
+    async fn add_relation(&self, relation: Relation) -> Result<(), ACSError>;
+
+    /// Remove a relation, returning whether it existed
+    async fn remove_relation(&self, from: EntityRef, kind: RelationKind, to: EntityRef) -> Result<bool, ACSError>;
+
+    /// Relations of an entity in a direction, limited to `kinds` unless empty
+    async fn neighbors(
+        &self,
+        entity: EntityRef,
+        direction: Direction,
+        kinds: &[RelationKind],
+    ) -> Result<Vec<Relation>, ACSError>;
+
+    /// Shortest chain of relations from one entity to another
+    async fn find_path(&self, from: EntityRef, to: EntityRef, query: PathQuery) -> Result<Option<Vec<Relation>>, ACSError>;
+
+    /// Check that knowledge rows and vector points agree and every stored entity is readable
+    async fn verify_storage(&self) -> Result<ConsistencyReport, ACSError>;
+
+    /// Fix storage inconsistencies found by `verify_storage`
+    async fn repair_storage(&self, policy: RepairPolicy) -> Result<RepairReport, ACSError>;
+
     /// Get framework status and metrics
     async fn get_status(&self) -> ACSStatus;
 
@@ -199,6 +256,36 @@ pub struct ACSKnowledge {
     pub credibility_rating: String,
     pub metadata: std::collections::HashMap<String, serde_json::Value>,
     pub tags: Vec<String>,
+    /// Relevance score when returned by a search; ignored when storing
+    #[serde(default)]
+    pub score: Option<f32>,
+}
+
+/// Knowledge search options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ACSSearchOptions {
+    pub limit: usize,
+    pub offset: usize,
+    /// Drop results scoring below this value
+    pub min_score: Option<f32>,
+    pub mode: SearchMode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SearchMode {
+    Semantic,                // Vector similarity only
+    Hybrid(FusionStrategy),  // BM25 exact-term ranking fused with vector similarity
+}
+
+impl Default for ACSSearchOptions {
+    fn default() -> Self {
+        Self {
+            limit: 10,
+            offset: 0,
+            min_score: None,
+            mode: SearchMode::Semantic,
+        }
+    }
 }
 
 /// Framework status information
@@ -223,11 +310,49 @@ pub struct FrameworkMetrics {
 }
 
 impl ACSFramework {
+    /// Build the framework over an already opened storage backend, e.g. `HybridStorageCoordinator::in_memory`
+    ///
+    /// `config.storage` is not used to open anything.
+    pub async fn with_storage(config: ACSConfig, storage: Arc<dyn HybridStorage>) -> Result<Self, ACSError> {
+        // Initialize coordination hub
+        let coordination_hub = Arc::new(
+            AgentCoordinationHub::new(storage.clone(), config.coordination.clone())
+                .await
+                .map_err(|e| ACSError::InitializationError(format!("Coordination hub initialization failed: {}", e)))?
+        );
+
+        // Create framework instance
+        let framework = Self {
+            coordination_hub,
+            storage,
+            config,
+        };
+
+        // Register default agents if enabled
+        if framework.config.behavioral.enable_systematic_research {
+            // Searches stored knowledge and keeps the conflicts it finds between stored findings as relations
+            let research_agent = behavioral::systematic_research::SystematicResearchAgent::new(
+                Self::research_validation_config(&framework.config.behavioral),
+            )
+            .with_storage(framework.storage.clone());
+            framework.register_agent(Arc::new(research_agent)).await?;
+        }
+
+        Ok(framework)
+    }
+
     /// Create a new systematic research agent (demonstration of behavioral translation)
     pub fn create_systematic_research_agent(
         config: &BehavioralConfig,
     ) -> Arc<dyn AgentBehavior> {
-        let validation_config = behavioral::systematic_research::ValidationConfig {
+        Arc::new(behavioral::systematic_research::SystematicResearchAgent::new(
+            Self::research_validation_config(config),
+        ))
+    }
+
+    /// Validation settings of the systematic research agent
+    fn research_validation_config(config: &BehavioralConfig) -> behavioral::systematic_research::ValidationConfig {
+        behavioral::systematic_research::ValidationConfig {
             minimum_sources: 3,
             required_credibility: match config.evidence_threshold.as_str() {
                 "A1" => CredibilityRating::A1,
@@ -240,9 +365,7 @@ impl ACSFramework {
             },
             enable_bias_detection: true,
             cross_validation_threshold: 0.75,
-        };
-
-        Arc::new(behavioral::systematic_research::SystematicResearchAgent::new(validation_config))
+        }
     }
 
     /// Convert high-level task to coordination task
@@ -307,6 +430,36 @@ impl ACSFramework {
             agent_info,
         }
     }
+
+    /// Convert high-level knowledge to a storage entity with a fresh ID
+    fn knowledge_entity(knowledge: ACSKnowledge) -> KnowledgeEntity {
+        KnowledgeEntity {
+            id: Uuid::new_v4(),
+            title: Some(knowledge.title),
+            content: knowledge.content,
+            metadata: knowledge.metadata,
+            embeddings: None, // Will be generated by storage layer
+            source: knowledge.source,
+            credibility_rating: knowledge.credibility_rating,
+            created_at: chrono::Utc::now(),
+            tags: knowledge.tags,
+            parent_id: None,
+            chunk_index: None,
+        }
+    }
+
+    /// Convert a storage entity back to high-level knowledge; entries stored without a title are "Untitled"
+    fn acs_knowledge(entity: KnowledgeEntity, score: Option<f32>) -> ACSKnowledge {
+        ACSKnowledge {
+            title: entity.title.unwrap_or_else(|| "Untitled".to_string()),
+            content: entity.content,
+            source: entity.source,
+            credibility_rating: entity.credibility_rating,
+            metadata: entity.metadata,
+            tags: entity.tags,
+            score,
+        }
+    }
 }
 
 #[async_trait]
@@ -319,27 +472,7 @@ impl ACSFrameworkOperations for ACSFramework {
                 .map_err(|e| ACSError::InitializationError(format!("Storage initialization failed: {}", e)))?
         );
 
-        // Initialize coordination hub
-        let coordination_hub = Arc::new(
-            AgentCoordinationHub::new(storage.clone(), config.coordination.clone())
-                .await
-                .map_err(|e| ACSError::InitializationError(format!("Coordination hub initialization failed: {}", e)))?
-        );
-
-        // Create framework instance
-        let framework = Self {
-            coordination_hub,
-            storage,
-            config,
-        };
-
-        // Register default agents if enabled
-        if framework.config.behavioral.enable_systematic_research {
-            let research_agent = Self::create_systematic_research_agent(&framework.config.behavioral);
-            framework.register_agent(research_agent).await?;
-        }
-
-        Ok(framework)
+        Self::with_storage(config, storage).await
     }
 
     async fn register_agent(&self, agent: Arc<dyn AgentBehavior>) -> Result<Uuid, ACSError> {
@@ -370,16 +503,8 @@ impl ACSFrameworkOperations for ACSFramework {
         Ok(self.convert_result(result, agent_info))
     }
 
-    async fn store_knowledge(&self, knowledge: ACSKnowledge) -> Result<(), ACSError> {
-        let knowledge_entity = KnowledgeEntity {
-            id: Uuid::new_v4(),
-            content: knowledge.content,
-            metadata: knowledge.metadata,
-            embeddings: None, // Will be generated by storage layer
-            source: knowledge.source,
-            credibility_rating: knowledge.credibility_rating,
-            created_at: chrono::Utc::now(),
-        };
+    async fn store_knowledge(&self, knowledge: ACSKnowledge) -> Result<IngestReport, ACSError> {
+        let knowledge_entity = Self::knowledge_entity(knowledge);
 
         self.storage
             .store_knowledge(&knowledge_entity)
@@ -387,30 +512,138 @@ impl ACSFrameworkOperations for ACSFramework {
             .map_err(|e| ACSError::StorageError(format!("Failed to store knowledge: {}", e)))
     }
 
+    async fn store_knowledge_batch(&self, knowledge: Vec<ACSKnowledge>) -> Result<BatchReport, ACSError> {
+        let knowledge_entities: Vec<KnowledgeEntity> = knowledge
+            .into_iter()
+            .map(Self::knowledge_entity)
+            .collect();
+
+        self.storage
+            .store_knowledge_batch(&knowledge_entities)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to store knowledge batch: {}", e)))
+    }
+
+    async fn store_document(&self, document: ACSKnowledge, chunking: ChunkingConfig) -> Result<DocumentReport, ACSError> {
+        let document_entity = Self::knowledge_entity(document);
+
+        self.storage
+            .store_document(&document_entity, &chunking)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to store document: {}", e)))
+    }
+
+    async fn search_documents(&self, search: DocumentSearch) -> Result<Vec<DocumentMatch>, ACSError> {
+        self.storage
+            .search_documents(&search)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to search documents: {}", e)))
+    }
+
     async fn search_knowledge(&self, query: &str, limit: usize) -> Result<Vec<ACSKnowledge>, ACSError> {
-        let knowledge_entities = self.storage
-            .search_knowledge(query, limit)
+        self.search_knowledge_with_options(query, ACSSearchOptions { limit, ..Default::default() }).await
+    }
+
+    async fn search_knowledge_with_options(
+        &self,
+        query: &str,
+        options: ACSSearchOptions,
+    ) -> Result<Vec<ACSKnowledge>, ACSError> {
+        let search = KnowledgeSearch {
+            query: query.to_string(),
+            limit: options.limit,
+            offset: options.offset,
+            cursor: None,
+            min_score: options.min_score,
+            fusion: match options.mode {
+                SearchMode::Semantic => None,
+                SearchMode::Hybrid(fusion) => Some(fusion),
+            },
+        };
+
+        let page = self.storage
+            .search_knowledge_page(&search)
             .await
             .map_err(|e| ACSError::StorageError(format!("Failed to search knowledge: {}", e)))?;
 
-        let acs_knowledge: Vec<ACSKnowledge> = knowledge_entities
+        let acs_knowledge: Vec<ACSKnowledge> = page.results
             .into_iter()
-            .map(|entity| ACSKnowledge {
-                title: entity.metadata.get("title")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Untitled")
-                    .to_string(),
-                content: entity.content,
-                source: entity.source,
-                credibility_rating: entity.credibility_rating,
-                metadata: entity.metadata,
-                tags: vec![], // Would be extracted from metadata
-            })
+            .map(|ScoredKnowledge { entity, score, .. }| Self::acs_knowledge(entity, Some(score)))
             .collect();
 
         Ok(acs_knowledge)
     }
 
+    async fn query_knowledge(&self, query: KnowledgeQuery) -> Result<KnowledgeQueryResult, ACSError> {
+        self.storage
+            .query_knowledge(&query)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to query knowledge: {}", e)))
+    }
+
+    async fn list_tags(&self) -> Result<BTreeMap<String, usize>, ACSError> {
+        self.storage
+            .list_tags()
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to list tags: {}", e)))
+    }
+
+    async fn find_by_tags(&self, tags: &[String], matching: TagMatch) -> Result<Vec<ACSKnowledge>, ACSError> {
+        let entities = self.storage
+            .find_by_tags(tags, matching)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to find knowledge by tags: {}", e)))?;
+
+        Ok(entities.into_iter().map(|entity| Self::acs_knowledge(entity, None)).collect())
+    }
+
+    async fn add_relation(&self, relation: Relation) -> Result<(), ACSError> {
+        self.storage
+            .add_relation(&relation)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to add relation: {}", e)))
+    }
+
+    async fn remove_relation(&self, from: EntityRef, kind: RelationKind, to: EntityRef) -> Result<bool, ACSError> {
+        self.storage
+            .remove_relation(&from, kind, &to)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to remove relation: {}", e)))
+    }
+
+    async fn neighbors(
+        &self,
+        entity: EntityRef,
+        direction: Direction,
+        kinds: &[RelationKind],
+    ) -> Result<Vec<Relation>, ACSError> {
+        self.storage
+            .neighbors(&entity, direction, kinds)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to load relations: {}", e)))
+    }
+
+    async fn find_path(&self, from: EntityRef, to: EntityRef, query: PathQuery) -> Result<Option<Vec<Relation>>, ACSError> {
+        self.storage
+            .find_path(&from, &to, &query)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to find relation path: {}", e)))
+    }
+
+    async fn verify_storage(&self) -> Result<ConsistencyReport, ACSError> {
+        self.storage
+            .verify()
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to verify storage: {}", e)))
+    }
+
+    async fn repair_storage(&self, policy: RepairPolicy) -> Result<RepairReport, ACSError> {
+        self.storage
+            .repair(&policy)
+            .await
+            .map_err(|e| ACSError::StorageError(format!("Failed to repair storage: {}", e)))
+    }
+
     async fn get_status(&self) -> ACSStatus {
         let coordination_status = self.coordination_hub.get_coordination_status().await;
         let storage_metrics = self.storage.get_metrics().await;
@@ -419,7 +652,8 @@ impl ACSFrameworkOperations for ACSFramework {
             framework_version: "0.1.0".to_string(),
             registered_agents: coordination_status.registered_agents,
             active_tasks: coordination_status.active_sessions,
-            knowledge_base_size: 0, // Would be retrieved from storage
+            // Top-level entries only; document chunks are not counted
+            knowledge_base_size: storage_metrics.usage.knowledge_entries as usize,
             storage_status: "Online".to_string(),
             coordination_status: "Active".to_string(),
             performance_metrics: FrameworkMetrics {
@@ -431,7 +665,7 @@ impl ACSFrameworkOperations for ACSFramework {
                 } else {
                     0.0
                 },
-                storage_utilization: 0.0, // Would be calculated from storage metrics
+                storage_utilization: storage_metrics.usage.utilization(),
                 agent_utilization: coordination_status.average_load,
             },
         }
@@ -450,6 +684,8 @@ impl ACSFrameworkOperations for ACSFramework {
             .await
             .map_err(|e| ACSError::ShutdownError(format!("Failed to synchronize storage: {}", e)))?;
 
+        self.storage.shutdown().await;
+
         Ok(())
     }
 }
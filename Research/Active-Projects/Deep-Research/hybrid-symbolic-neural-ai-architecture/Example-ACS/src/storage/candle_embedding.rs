@@ -0,0 +1,210 @@
+//! Local Sentence Embeddings (candle)
+//!
+//! `EmbeddingProvider` running a BERT-family sentence-transformer (for example
+//! all-MiniLM-L6-v2) on the CPU with candle. The model directory must contain
+//! `config.json`, `tokenizer.json` and `model.safetensors`. Inputs are embedded
+//! in batches, mean-pooled over the attention mask and L2-normalized; results
+//! are cached by the SHA-256 of their content so re-embedding unchanged text is
+//! free.
+
+use super::embedding::{content_hash, EmbeddingProvider};
+use super::StorageError;
+use async_trait::async_trait;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+
+/// Number of embeddings kept in the content-hash cache
+const EMBEDDING_CACHE_CAPACITY: usize = 10_000;
+
+/// SHA-256 of the embedded text
+type CacheKey = [u8; 32];
+
+/// Sentence-transformer embedder running on the local CPU
+pub struct CandleEmbedder {
+    model: Arc<LoadedModel>,
+    batch_size: usize,
+    cache: Mutex<EmbeddingCache>,
+}
+
+struct LoadedModel {
+    bert: BertModel,
+    tokenizer: Tokenizer,
+    device: Device,
+    dimension: usize,
+    /// SHA-256 over the config, tokenizer and weights files
+    fingerprint: String,
+}
+
+/// Bounded cache evicting the oldest entry first
+#[derive(Default)]
+struct EmbeddingCache {
+    entries: HashMap<CacheKey, Vec<f32>>,
+    order: VecDeque<CacheKey>,
+}
+
+impl CandleEmbedder {
+    /// Load the model and tokenizer from `model_dir`
+    pub fn load(model_dir: impl AsRef<Path>, batch_size: usize) -> Result<Self, StorageError> {
+        let model_dir = model_dir.as_ref();
+        let device = Device::Cpu;
+
+        let config_data = std::fs::read_to_string(model_dir.join("config.json"))
+            .map_err(|e| StorageError::InitializationError(format!("Failed to read model config: {}", e)))?;
+        let config: Config = serde_json::from_str(&config_data)
+            .map_err(|e| StorageError::InitializationError(format!("Invalid model config: {}", e)))?;
+
+        let mut tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json"))
+            .map_err(|e| StorageError::InitializationError(format!("Failed to load tokenizer: {}", e)))?;
+        tokenizer.with_padding(Some(PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            ..Default::default()
+        }));
+        tokenizer.with_truncation(Some(TruncationParams {
+            max_length: config.max_position_embeddings,
+            ..Default::default()
+        }))
+        .map_err(|e| StorageError::InitializationError(format!("Failed to configure tokenizer: {}", e)))?;
+
+        // Safety: the weights file is memory-mapped read-only and must not change while loaded
+        let vb = unsafe {
+            VarBuilder::from_mmaped_safetensors(&[model_dir.join("model.safetensors")], DTYPE, &device)
+        }
+        .map_err(|e| StorageError::InitializationError(format!("Failed to load model weights: {}", e)))?;
+        let bert = BertModel::load(vb, &config)
+            .map_err(|e| StorageError::InitializationError(format!("Failed to build model: {}", e)))?;
+        let fingerprint = model_fingerprint(model_dir)?;
+
+        Ok(Self {
+            model: Arc::new(LoadedModel {
+                bert,
+                tokenizer,
+                device,
+                dimension: config.hidden_size,
+                fingerprint,
+            }),
+            batch_size: batch_size.max(1),
+            cache: Mutex::new(EmbeddingCache::default()),
+        })
+    }
+
+    /// Number of embeddings currently cached
+    pub fn cached_embeddings(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+}
+
+impl LoadedModel {
+    fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, StorageError> {
+        let encodings = self.tokenizer.encode_batch(texts, true)
+            .map_err(|e| StorageError::VectorError(format!("Tokenization failed: {}", e)))?;
+
+        let to_tensor = |rows: Vec<Vec<u32>>| -> candle_core::Result<Tensor> {
+            let rows = rows.into_iter()
+                .map(|row| Tensor::new(row.as_slice(), &self.device))
+                .collect::<candle_core::Result<Vec<_>>>()?;
+            Tensor::stack(&rows, 0)
+        };
+
+        let run = || -> candle_core::Result<Vec<Vec<f32>>> {
+            let input_ids = to_tensor(encodings.iter().map(|e| e.get_ids().to_vec()).collect())?;
+            let type_ids = to_tensor(encodings.iter().map(|e| e.get_type_ids().to_vec()).collect())?;
+            let mask = to_tensor(encodings.iter().map(|e| e.get_attention_mask().to_vec()).collect())?;
+
+            let hidden = self.bert.forward(&input_ids, &type_ids, Some(&mask))?;
+
+            // Mean-pool token embeddings, ignoring padding
+            let mask = mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+            let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+            let counts = mask.sum(1)?.clamp(1e-9, f32::MAX)?;
+            let pooled = summed.broadcast_div(&counts)?;
+
+            let norms = pooled.sqr()?.sum_keepdim(1)?.sqrt()?.clamp(1e-12, f32::MAX)?;
+            pooled.broadcast_div(&norms)?.to_vec2::<f32>()
+        };
+
+        run().map_err(|e| StorageError::VectorError(format!("Embedding inference failed: {}", e)))
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for CandleEmbedder {
+    fn dimension(&self) -> usize {
+        self.model.dimension
+    }
+
+    fn identity(&self) -> String {
+        format!("candle:{}", self.model.fingerprint)
+    }
+
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, StorageError> {
+        let keys: Vec<CacheKey> = texts.iter().map(|text| content_hash(text)).collect();
+        let mut results: Vec<Option<Vec<f32>>> = {
+            let cache = self.cache.lock().unwrap();
+            keys.iter().map(|key| cache.entries.get(key).cloned()).collect()
+        };
+
+        // Embed each distinct cache miss once, batch by batch off the async runtime
+        let mut waiting: HashMap<CacheKey, Vec<usize>> = HashMap::new();
+        let mut missing: Vec<(CacheKey, String)> = Vec::new();
+        for (index, result) in results.iter().enumerate() {
+            if result.is_none() {
+                waiting.entry(keys[index])
+                    .or_insert_with(|| {
+                        missing.push((keys[index], texts[index].to_string()));
+                        Vec::new()
+                    })
+                    .push(index);
+            }
+        }
+
+        for batch in missing.chunks(self.batch_size) {
+            let model = self.model.clone();
+            let batch_texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+            let embeddings = tokio::task::spawn_blocking(move || model.embed_batch(batch_texts))
+                .await
+                .map_err(|e| StorageError::VectorError(format!("Embedding task failed: {}", e)))??;
+
+            let mut cache = self.cache.lock().unwrap();
+            for ((key, _), embedding) in batch.iter().zip(embeddings) {
+                for index in &waiting[key] {
+                    results[*index] = Some(embedding.clone());
+                }
+                cache.insert(*key, embedding);
+            }
+        }
+
+        Ok(results.into_iter().map(Option::unwrap_or_default).collect())
+    }
+}
+
+/// Hex SHA-256 over the files that determine the model's output
+fn model_fingerprint(model_dir: &Path) -> Result<String, StorageError> {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for name in ["config.json", "tokenizer.json", "model.safetensors"] {
+        let mut file = std::fs::File::open(model_dir.join(name))
+            .map_err(|e| StorageError::InitializationError(format!("Failed to read {}: {}", name, e)))?;
+        std::io::copy(&mut file, &mut hasher)
+            .map_err(|e| StorageError::InitializationError(format!("Failed to read {}: {}", name, e)))?;
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+impl EmbeddingCache {
+    fn insert(&mut self, key: CacheKey, embedding: Vec<f32>) {
+        if self.entries.insert(key, embedding).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > EMBEDDING_CACHE_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
@@ -0,0 +1,606 @@
+//! Document Chunking
+//!
+//! A long document embedded as one entry gets a single vector diluted across
+//! all of its topics. `store_document` keeps the document as a parent entry
+//! and splits its content into overlapping passages, stored as child entries
+//! with `parent_id` and `chunk_index` set. Passages are cut at markdown
+//! headings (fenced code is never split on) or as plain token windows; a
+//! section longer than `max_tokens` is windowed with `overlap_tokens` shared
+//! between neighbouring chunks. Chunks inherit the document's title, source,
+//! credibility, creation time, tags and metadata, and record their heading
+//! under `section`.
+//!
+//! Chunks are ordinary knowledge rows, so vector, lexical and as-of search
+//! find them like any other entry. `search_documents` groups the best chunks
+//! by parent document and returns each with its neighbouring chunks as
+//! context. The parent-to-chunk index is derived from the rows and maintained
+//! through the storage module's `index_derived` and `unindex_derived`.
+//!
+//! Storing a document again replaces all of its chunks, and deleting it
+//! deletes them. `update_knowledge` on a document does not re-chunk it.
+
+use super::coordination::JournaledWrite;
+use super::encryption::RowCipher;
+use super::lexical::FusionStrategy;
+use super::relations::{self, EntityRef};
+use super::schema::EntityTable;
+use super::{
+    knowledge_point, replaced_knowledge_point, revisions, HybridStorageCoordinator, KnowledgeEntity,
+    OperationType, StorageError, VectorIntent, VectorPoint, KNOWLEDGE_TABLE,
+};
+use redb::{ReadableTable, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Chunk IDs keyed by `parent_id \0 chunk_index \0 chunk_id`; values are empty
+pub(crate) const CHUNK_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("knowledge_chunks");
+
+/// `CHUNK_TABLE` key of each chunk, needed to remove it
+pub(crate) const CHUNK_KEYS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("knowledge_chunk_keys");
+
+/// Metadata key holding the heading a chunk was cut from
+pub const SECTION_METADATA_KEY: &str = "section";
+
+/// Smallest candidate pool ranked for a document search
+const MIN_DOCUMENT_POOL: usize = 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChunkStrategy {
+    #[default]
+    MarkdownSections, // Split at headings, windowing sections longer than `max_tokens`
+    TokenWindows,     // Fixed windows of `max_tokens`, ignoring structure
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkingConfig {
+    pub strategy: ChunkStrategy,
+    /// Whitespace-separated tokens per chunk
+    pub max_tokens: usize,
+    /// Tokens repeated at the start of the next window; must be below `max_tokens`
+    pub overlap_tokens: usize,
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        Self {
+            strategy: ChunkStrategy::MarkdownSections,
+            max_tokens: 256,
+            overlap_tokens: 32,
+        }
+    }
+}
+
+impl ChunkingConfig {
+    fn validate(&self) -> Result<(), StorageError> {
+        if self.max_tokens == 0 || self.overlap_tokens >= self.max_tokens {
+            return Err(StorageError::ConfigurationError(format!(
+                "Chunk overlap of {} tokens must be smaller than the chunk size of {} tokens",
+                self.overlap_tokens, self.max_tokens
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Result of `store_document`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentReport {
+    pub id: Uuid,
+    /// New chunk IDs in document order
+    pub chunks: Vec<Uuid>,
+    /// Chunks of a previous version of the document that were removed
+    pub replaced_chunks: usize,
+}
+
+/// Search returning the best chunks grouped by parent document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSearch {
+    pub query: String,
+    /// Documents to return
+    pub limit: usize,
+    /// Best-scoring chunks kept per document
+    pub passages_per_document: usize,
+    /// Neighbouring chunks returned on each side of a passage
+    pub context_chunks: usize,
+    /// `None` ranks by vector similarity only
+    pub fusion: Option<FusionStrategy>,
+}
+
+impl Default for DocumentSearch {
+    fn default() -> Self {
+        Self {
+            query: String::new(),
+            limit: 10,
+            passages_per_document: 3,
+            context_chunks: 1,
+            fusion: None,
+        }
+    }
+}
+
+/// Document with the chunks that matched a search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentMatch {
+    pub document_id: Uuid,
+    /// `None` if the chunks outlived their parent row
+    pub document: Option<KnowledgeEntity>,
+    /// Score of the best passage
+    pub score: f32,
+    /// 1-based position in the result list
+    pub rank: usize,
+    /// Best passages first
+    pub passages: Vec<DocumentPassage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentPassage {
+    pub chunk: KnowledgeEntity,
+    pub score: f32,
+    /// Preceding chunks in document order
+    pub before: Vec<KnowledgeEntity>,
+    /// Following chunks in document order
+    pub after: Vec<KnowledgeEntity>,
+}
+
+/// Passage cut from a document; `text` is a slice of the document content
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Passage<'a> {
+    pub text: &'a str,
+    pub section: Option<&'a str>,
+}
+
+/// Split a document into passages in document order
+pub(crate) fn split_document<'a>(text: &'a str, config: &ChunkingConfig) -> Vec<Passage<'a>> {
+    match config.strategy {
+        ChunkStrategy::TokenWindows => token_windows(text, config)
+            .into_iter()
+            .map(|text| Passage { text, section: None })
+            .collect(),
+        ChunkStrategy::MarkdownSections => markdown_sections(text)
+            .into_iter()
+            .flat_map(|section| {
+                token_windows(&text[section.start..section.end], config)
+                    .into_iter()
+                    .map(move |text| Passage { text, section: section.heading })
+            })
+            .collect(),
+    }
+}
+
+/// Byte ranges of the whitespace-separated tokens of `text`
+fn token_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(token_start)) => {
+                spans.push((token_start, index));
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    if let Some(token_start) = start {
+        spans.push((token_start, text.len()));
+    }
+    spans
+}
+
+/// Overlapping windows of at most `max_tokens` tokens, trimmed to their first and last token
+fn token_windows<'a>(text: &'a str, config: &ChunkingConfig) -> Vec<&'a str> {
+    let spans = token_spans(text);
+    let step = config.max_tokens - config.overlap_tokens;
+
+    let mut windows = Vec::new();
+    let mut first = 0;
+    while first < spans.len() {
+        let end = (first + config.max_tokens).min(spans.len());
+        windows.push(&text[spans[first].0..spans[end - 1].1]);
+        if end == spans.len() {
+            break;
+        }
+        first += step;
+    }
+    windows
+}
+
+struct Section<'a> {
+    start: usize,
+    end: usize,
+    heading: Option<&'a str>,
+}
+
+/// Sections starting at each ATX heading outside fenced code; a heading with
+/// no text under it is kept with the section that follows
+fn markdown_sections(text: &str) -> Vec<Section<'_>> {
+    let mut sections = vec![Section { start: 0, end: text.len(), heading: None }];
+    let mut in_fence = false;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if !in_fence {
+            if let Some(heading) = heading_text(line) {
+                if let Some(last) = sections.last_mut() {
+                    last.end = offset;
+                }
+                sections.push(Section { start: offset, end: text.len(), heading: Some(heading) });
+            }
+        }
+        offset += line.len();
+    }
+
+    let mut merged = Vec::new();
+    let mut carried_start = None;
+    let mut sections = sections.into_iter().peekable();
+    while let Some(section) = sections.next() {
+        let content = &text[section.start..section.end];
+        let body = match section.heading {
+            Some(_) => content.split_once('\n').map_or("", |(_, body)| body),
+            None => content,
+        };
+
+        if content.trim().is_empty() {
+            continue;
+        }
+        if body.trim().is_empty() && sections.peek().is_some() {
+            carried_start = carried_start.or(Some(section.start));
+            continue;
+        }
+        merged.push(Section { start: carried_start.take().unwrap_or(section.start), ..section });
+    }
+    merged
+}
+
+/// Text of an ATX heading line (`#` to `######`), without its markers
+fn heading_text(line: &str) -> Option<&str> {
+    let unindented = line.trim_start_matches(' ');
+    if line.len() - unindented.len() > 3 {
+        return None;
+    }
+
+    let level = unindented.bytes().take_while(|b| *b == b'#').count();
+    let rest = &unindented[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+        return None;
+    }
+    Some(rest.trim().trim_end_matches('#').trim_end())
+}
+
+fn chunk_key(parent: Uuid, chunk_index: u32, id: &str) -> String {
+    format!("{}\0{:010}\0{}", parent, chunk_index, id)
+}
+
+/// Index a knowledge entry under its parent document, replacing whatever was indexed under its ID
+pub(crate) fn index_chunk(write_txn: &WriteTransaction, knowledge: &KnowledgeEntity) -> Result<(), StorageError> {
+    let id = knowledge.id.to_string();
+    unindex_chunk(write_txn, &id)?;
+
+    let Some(parent) = knowledge.parent_id else {
+        return Ok(());
+    };
+    let key = chunk_key(parent, knowledge.chunk_index.unwrap_or_default(), &id);
+    {
+        let mut chunks = write_txn.open_table(CHUNK_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge chunks: {}", e)))?;
+        chunks.insert(key.as_str(), [].as_slice())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to write knowledge chunk: {}", e)))?;
+    }
+
+    let mut keys = write_txn.open_table(CHUNK_KEYS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge chunk keys: {}", e)))?;
+    keys.insert(id.as_str(), key.as_bytes())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write knowledge chunk key: {}", e)))?;
+    Ok(())
+}
+
+/// Remove a knowledge entry from its parent's chunks; entries that are not chunks are ignored
+pub(crate) fn unindex_chunk(write_txn: &WriteTransaction, id: &str) -> Result<(), StorageError> {
+    let key = {
+        let mut keys = write_txn.open_table(CHUNK_KEYS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge chunk keys: {}", e)))?;
+        let removed = keys.remove(id)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove knowledge chunk key: {}", e)))?;
+        removed.map(|data| String::from_utf8_lossy(data.value()).into_owned())
+    };
+
+    if let Some(key) = key {
+        let mut chunks = write_txn.open_table(CHUNK_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge chunks: {}", e)))?;
+        chunks.remove(key.as_str())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove knowledge chunk: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Drop the chunk index ahead of a rebuild
+pub(crate) fn clear_index(write_txn: &WriteTransaction) -> Result<(), StorageError> {
+    write_txn.delete_table(CHUNK_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to clear knowledge chunks: {}", e)))?;
+    write_txn.delete_table(CHUNK_KEYS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to clear knowledge chunk keys: {}", e)))?;
+    Ok(())
+}
+
+/// IDs of a document's chunks in document order
+fn chunk_ids(
+    chunks: &impl ReadableTable<&'static str, &'static [u8]>,
+    parent: Uuid,
+) -> Result<Vec<Uuid>, StorageError> {
+    // All chunks of a parent sort between "parent\0" and "parent\x01"
+    let start = format!("{}\0", parent);
+    let end = format!("{}\u{1}", parent);
+
+    let mut ids = Vec::new();
+    for row in chunks.range(start.as_str()..end.as_str())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge chunks: {}", e)))?
+    {
+        let (key, _) = row
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge chunks: {}", e)))?;
+        let id = key.value().rsplit('\0').next().and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| StorageError::SerializationError(format!("Invalid knowledge chunk entry: {:?}", key.value())))?;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+/// Remove a document's chunks, which must be exactly `expected`, returning the points to restore on rollback
+fn remove_chunks(
+    txn: &mut JournaledWrite,
+    cipher: Option<&RowCipher>,
+    parent: Uuid,
+    expected: &[Uuid],
+) -> Result<Vec<VectorPoint>, StorageError> {
+    let current = {
+        let chunks = txn.transaction().open_table(CHUNK_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge chunks: {}", e)))?;
+        chunk_ids(&chunks, parent)?
+    };
+    // Their vector deletes were planned from `expected`; anything else would be left behind
+    if current != expected {
+        return Err(StorageError::TransactionError(format!(
+            "Chunks of document {} changed while it was being written", parent
+        )));
+    }
+
+    let mut restored = Vec::new();
+    for id in expected {
+        let key = id.to_string();
+        let previous = txn.remove(KNOWLEDGE_TABLE, &key)?;
+        revisions::archive_replaced(txn, cipher, previous.as_deref(), None)?;
+        super::unindex_derived(txn.transaction(), &key)?;
+        relations::remove_entity_relations(txn, &EntityRef::knowledge(*id))?;
+        restored.extend(replaced_knowledge_point(cipher, previous));
+    }
+    Ok(restored)
+}
+
+impl HybridStorageCoordinator {
+    /// Store a document and its chunks as one coordinated operation, replacing any previous chunks
+    pub(crate) async fn write_document(
+        &self,
+        document: &KnowledgeEntity,
+        chunking: &ChunkingConfig,
+    ) -> Result<DocumentReport, StorageError> {
+        chunking.validate()?;
+        if document.parent_id.is_some() {
+            return Err(StorageError::ConfigurationError(format!(
+                "Knowledge {} is a chunk and cannot be stored as a document", document.id
+            )));
+        }
+        let document = KnowledgeEntity { chunk_index: None, ..document.clone() };
+
+        let chunks: Vec<KnowledgeEntity> = split_document(&document.content, chunking)
+            .into_iter()
+            .enumerate()
+            .map(|(index, passage)| {
+                let mut metadata = document.metadata.clone();
+                if let Some(section) = passage.section {
+                    metadata.insert(SECTION_METADATA_KEY.to_string(), section.into());
+                }
+                KnowledgeEntity {
+                    id: Uuid::new_v4(),
+                    title: document.title.clone(),
+                    content: passage.text.to_string(),
+                    metadata,
+                    embeddings: None,
+                    source: document.source.clone(),
+                    credibility_rating: document.credibility_rating.clone(),
+                    created_at: document.created_at,
+                    tags: document.tags.clone(),
+                    parent_id: Some(document.id),
+                    chunk_index: Some(index as u32),
+                }
+            })
+            .collect();
+
+        // Embed the document (unless the caller supplied a vector) and its chunks in one call
+        let mut texts: Vec<&str> = Vec::with_capacity(chunks.len() + 1);
+        if document.embeddings.is_none() {
+            texts.push(&document.content);
+        }
+        texts.extend(chunks.iter().map(|chunk| chunk.content.as_str()));
+        let mut generated = self.generate_embeddings(&texts).await?.into_iter();
+
+        let mut entries = Vec::with_capacity(chunks.len() + 1);
+        let mut points = Vec::with_capacity(chunks.len() + 1);
+        for entry in std::iter::once(document.clone()).chain(chunks) {
+            let embedding = match &entry.embeddings {
+                Some(embedding) => embedding.clone(),
+                None => generated.next().unwrap_or_default(),
+            };
+            let stored = KnowledgeEntity { embeddings: Some(embedding.clone()), ..entry.clone() };
+            let data = self.encode_row(EntityTable::Knowledge, &stored)?;
+            points.push(knowledge_point(&entry, embedding));
+            entries.push((entry, data));
+        }
+
+        let stale = self.time_redb(|| self.load_chunk_ids(&document.id))?;
+        let active = self.knowledge_collection().await;
+        let collection = active.clone();
+
+        let mut vector_intents = Vec::new();
+        if !stale.is_empty() {
+            vector_intents.push(VectorIntent::Delete { collection: collection.clone(), ids: stale.clone() });
+        }
+        vector_intents.push(VectorIntent::Upsert { collection: collection.clone(), points });
+
+        self.execute_coordinated_transaction(OperationType::Batch, vector_intents, |txn| {
+            let mut restored = remove_chunks(txn, self.cipher(), document.id, &stale)?;
+            let mut removed = Vec::new();
+
+            for (entry, data) in &entries {
+                let previous = txn.insert(KNOWLEDGE_TABLE, &entry.id.to_string(), data)?;
+                revisions::archive_replaced(txn, self.cipher(), previous.as_deref(), Some(entry))?;
+                super::index_derived(txn.transaction(), entry, self.cipher())?;
+                match replaced_knowledge_point(self.cipher(), previous) {
+                    Some(old_point) => restored.push(old_point),
+                    None => removed.push(entry.id),
+                }
+            }
+
+            let mut compensation = Vec::new();
+            if !removed.is_empty() {
+                compensation.push(VectorIntent::Delete { collection: collection.clone(), ids: removed });
+            }
+            if !restored.is_empty() {
+                compensation.push(VectorIntent::Upsert { collection: collection.clone(), points: restored });
+            }
+            Ok(compensation)
+        }).await?;
+
+        Ok(DocumentReport {
+            id: document.id,
+            chunks: entries.iter().skip(1).map(|(entry, _)| entry.id).collect(),
+            replaced_chunks: stale.len(),
+        })
+    }
+
+    /// Remove a document's chunks inside a delete of the document itself
+    pub(crate) fn remove_document_chunks(
+        &self,
+        txn: &mut JournaledWrite,
+        parent: Uuid,
+        expected: &[Uuid],
+    ) -> Result<Vec<VectorPoint>, StorageError> {
+        remove_chunks(txn, self.cipher(), parent, expected)
+    }
+
+    /// IDs of a document's chunks in document order; empty for entries that were not chunked
+    pub(crate) fn load_chunk_ids(&self, parent: &Uuid) -> Result<Vec<Uuid>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        match read_txn.open_table(CHUNK_TABLE) {
+            Ok(chunks) => chunk_ids(&chunks, *parent),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(Vec::new()),
+            Err(e) => Err(StorageError::TransactionError(format!("Failed to open knowledge chunks: {}", e))),
+        }
+    }
+
+    /// A document's chunks in document order, leaving out rows that cannot be read
+    pub(crate) fn load_document_chunks(&self, parent: &Uuid) -> Result<Vec<KnowledgeEntity>, StorageError> {
+        let ids = self.load_chunk_ids(parent)?;
+        Ok(self.load_knowledge_entities(&ids)?
+            .into_iter()
+            .filter_map(|row| row.inspect_err(|warning| warn!("Document chunk left out: {}", warning)).ok())
+            .collect())
+    }
+
+    pub(crate) async fn run_document_search(&self, search: &DocumentSearch) -> Result<Vec<DocumentMatch>, StorageError> {
+        if search.limit == 0 || search.passages_per_document == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Deepen the pool until enough distinct documents have matching chunks
+        let mut pool = search.limit
+            .saturating_mul(search.passages_per_document)
+            .saturating_mul(2)
+            .max(MIN_DOCUMENT_POOL);
+        let groups = loop {
+            let ranked = self.rank_knowledge(&search.query, pool, search.fusion).await?;
+            let exhausted = ranked.len() < pool;
+
+            let ids: Vec<Uuid> = ranked.iter().map(|(id, _)| *id).collect();
+            let rows = self.time_redb(|| self.load_knowledge_entities(&ids))?;
+
+            let mut order: Vec<Uuid> = Vec::new();
+            let mut groups: HashMap<Uuid, Vec<(KnowledgeEntity, f32)>> = HashMap::new();
+            for ((_, score), row) in ranked.into_iter().zip(rows) {
+                let chunk = match row {
+                    Ok(chunk) => chunk,
+                    Err(warning) => {
+                        warn!("Search consistency warning: {}", warning);
+                        continue;
+                    }
+                };
+                // Documents and unchunked entries are ranked through their chunks only
+                let Some(parent) = chunk.parent_id else {
+                    continue;
+                };
+                let passages = groups.entry(parent).or_insert_with(|| {
+                    order.push(parent);
+                    Vec::new()
+                });
+                if passages.len() < search.passages_per_document {
+                    passages.push((chunk, score));
+                }
+            }
+
+            if order.len() >= search.limit || exhausted {
+                order.truncate(search.limit);
+                break order.into_iter()
+                    .map(|parent| (parent, groups.remove(&parent).unwrap_or_default()))
+                    .collect::<Vec<_>>();
+            }
+            pool = pool.saturating_mul(4);
+        };
+
+        let mut matches = Vec::with_capacity(groups.len());
+        for (document_id, passages) in groups {
+            let document = self.get_knowledge_row(&document_id)?;
+            let chunks = self.time_redb(|| self.load_document_chunks(&document_id))?;
+
+            let passages = passages.into_iter()
+                .map(|(chunk, score)| {
+                    let (before, after) = match chunks.iter().position(|sibling| sibling.id == chunk.id) {
+                        Some(position) => (
+                            chunks[position.saturating_sub(search.context_chunks)..position].to_vec(),
+                            chunks[position + 1..(position + 1 + search.context_chunks).min(chunks.len())].to_vec(),
+                        ),
+                        None => (Vec::new(), Vec::new()),
+                    };
+                    DocumentPassage { chunk, score, before, after }
+                })
+                .collect::<Vec<_>>();
+
+            matches.push(DocumentMatch {
+                document_id,
+                document,
+                score: passages.first().map_or(0.0, |passage| passage.score),
+                rank: matches.len() + 1,
+                passages,
+            });
+        }
+
+        Ok(matches)
+    }
+
+    /// Knowledge row by ID, treating an unreadable row as missing
+    fn get_knowledge_row(&self, id: &Uuid) -> Result<Option<KnowledgeEntity>, StorageError> {
+        let mut rows = self.time_redb(|| self.load_knowledge_entities(std::slice::from_ref(id)))?;
+        Ok(match rows.pop() {
+            Some(Ok(entity)) => Some(entity),
+            Some(Err(warning)) => {
+                warn!("Search consistency warning: {}", warning);
+                None
+            }
+            None => None,
+        })
+    }
+}
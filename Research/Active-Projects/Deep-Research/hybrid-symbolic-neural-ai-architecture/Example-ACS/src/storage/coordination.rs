@@ -0,0 +1,775 @@
+//! Coordinated Transactions - Durable Intent Journal
+//!
+//! Two-phase commit between REDB and the vector index:
+//! 1. **Prepare**: entity rows and a journal entry describing the pending vector
+//!    operations are written in a single REDB transaction.
+//! 2. **Apply**: the vector operations are executed; on success the journal entry
+//!    is cleared.
+//!
+//! If the vector index cannot be updated, the entity rows are restored from the
+//! journaled before-images and compensating vector operations are applied;
+//! rows another write has replaced in the meantime are left to that write. A
+//! reindex storing a new embedding with a row does not count as such a write.
+//! Any entry left behind by a crash is rolled forward or rolled back on startup.
+//!
+//! Under `ConsistencyMode::EventDriven` the vector operations go to the outbox
+//! instead of the journal (see `outbox`).
+
+use super::coordination_index::reindex_coordination_row;
+use super::encryption::{self, RowCipher};
+use super::outbox::append_outbox_event;
+use super::reindex::track_knowledge_change;
+use super::schema::EntityTable;
+use super::{
+    HybridStorageCoordinator, KnowledgeEntity, OperationType, PendingOperation, StorageError, VectorPoint,
+    ConsistencyMode, COORDINATION_TABLE, KNOWLEDGE_TABLE,
+};
+use redb::{ReadableTable, ReadableTableMetadata, TableDefinition, TableHandle, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Journal of in-flight coordinated operations, keyed by operation ID
+pub(crate) const JOURNAL_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("intent_journal");
+
+/// Roll-forward entries the vector index keeps rejecting, moved out of the journal by the sync worker
+pub(crate) const DEAD_LETTER_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("intent_dead_letter");
+
+/// Vector index operation recorded in the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum VectorIntent {
+    Upsert { collection: String, points: Vec<VectorPoint> },
+    Delete { collection: String, ids: Vec<Uuid> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum JournalPhase {
+    Prepared,      // Entity rows committed, vector intents not yet applied
+    RollingBack,   // Entity rows restored, compensation not yet applied
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum RecoveryPolicy {
+    RollBack,      // Undo the REDB write when the vector index fails (Immediate)
+    RollForward,   // Keep the REDB write and retry the vector index later
+}
+
+/// Where a coordinated write records its vector operations
+#[derive(Debug, Clone, Copy)]
+enum WritePath {
+    Journal(RecoveryPolicy),
+    Outbox,
+}
+
+/// Result of phase 1
+enum Prepared {
+    RowsOnly,
+    Journaled(JournalEntry),
+    Outboxed,
+}
+
+/// Durable record of a coordinated operation (stored as JSON: points carry `serde_json` payloads)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JournalEntry {
+    pub(crate) operation_id: Uuid,
+    pub(crate) operation_type: OperationType,
+    pub(crate) phase: JournalPhase,
+    pub(crate) policy: RecoveryPolicy,
+    pub(crate) undo: Vec<UndoRecord>,
+    pub(crate) vector_intents: Vec<VectorIntent>,
+    pub(crate) compensation: Vec<VectorIntent>,
+    pub(crate) created_at: chrono::DateTime<chrono::Utc>,
+    /// Why the entry was moved to the dead-letter table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) failure: Option<String>,
+}
+
+/// Before-image of a row touched by a coordinated operation
+///
+/// Row bytes are stored as base64 strings; entries written as JSON number
+/// arrays by older versions still decode.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct UndoRecord {
+    pub(crate) table: String,
+    pub(crate) key: String,
+    #[serde(with = "base64_bytes")]
+    pub(crate) previous: Option<Vec<u8>>,
+    /// Value the operation left in the row; a rollback only restores rows still holding it
+    #[serde(with = "base64_bytes")]
+    pub(crate) written: Option<Vec<u8>>,
+}
+
+/// Serde adapter writing optional row bytes as base64
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Encoded {
+        Base64(String),
+        Numbers(Vec<u8>),
+    }
+
+    pub(super) fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_some(&STANDARD.encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        match Option::<Encoded>::deserialize(deserializer)? {
+            Some(Encoded::Base64(text)) => STANDARD.decode(text).map(Some).map_err(serde::de::Error::custom),
+            Some(Encoded::Numbers(bytes)) => Ok(Some(bytes)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Outcome of a journal recovery pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecoveryReport {
+    pub rolled_forward: usize,
+    pub rolled_back: usize,
+    pub pending: usize,
+}
+
+/// REDB write transaction wrapper that records before-images for rollback
+pub(crate) struct JournaledWrite<'txn> {
+    txn: &'txn WriteTransaction,
+    undo: Vec<UndoRecord>,
+}
+
+impl<'txn> JournaledWrite<'txn> {
+    /// Underlying transaction, for derived tables that are re-derived on rollback rather than undone
+    pub(crate) fn transaction(&self) -> &'txn WriteTransaction {
+        self.txn
+    }
+
+    /// Insert a row, returning the value it replaced
+    pub(crate) fn insert(
+        &mut self,
+        table: TableDefinition<&str, &[u8]>,
+        key: &str,
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let mut handle = self.txn.open_table(table)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open {} table: {}", table.name(), e)))?;
+
+        let previous = handle.insert(key, value)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to insert into {}: {}", table.name(), e)))?
+            .map(|old| old.value().to_vec());
+        self.track_change(table, key)?;
+
+        self.undo.push(UndoRecord {
+            table: table.name().to_string(),
+            key: key.to_string(),
+            previous: previous.clone(),
+            written: Some(value.to_vec()),
+        });
+
+        Ok(previous)
+    }
+
+    /// Remove a row, returning the value it held
+    pub(crate) fn remove(
+        &mut self,
+        table: TableDefinition<&str, &[u8]>,
+        key: &str,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let mut handle = self.txn.open_table(table)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open {} table: {}", table.name(), e)))?;
+
+        let previous = handle.remove(key)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove from {}: {}", table.name(), e)))?
+            .map(|old| old.value().to_vec());
+        self.track_change(table, key)?;
+
+        self.undo.push(UndoRecord {
+            table: table.name().to_string(),
+            key: key.to_string(),
+            previous: previous.clone(),
+            written: None,
+        });
+
+        Ok(previous)
+    }
+
+    /// Knowledge writes are recorded for a reindex in progress
+    fn track_change(&self, table: TableDefinition<&str, &[u8]>, key: &str) -> Result<(), StorageError> {
+        if table.name() == KNOWLEDGE_TABLE.name() {
+            track_knowledge_change(self.txn, key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a row holding `current` still holds `expected`
+///
+/// Knowledge rows are compared decoded and without their embeddings: a reindex
+/// stores new embeddings in place, and sealing a row again changes its bytes.
+fn row_holds(
+    cipher: Option<&RowCipher>,
+    table_name: &str,
+    current: &Option<Vec<u8>>,
+    expected: &Option<Vec<u8>>,
+) -> bool {
+    if current == expected {
+        return true;
+    }
+    let (Some(current), Some(expected)) = (current, expected) else {
+        return false;
+    };
+    if table_name != KNOWLEDGE_TABLE.name() {
+        return false;
+    }
+
+    let without_embedding = |data: &[u8]| {
+        encryption::decode_row::<KnowledgeEntity>(cipher, EntityTable::Knowledge, data)
+            .ok()
+            .and_then(|knowledge| serde_json::to_value(KnowledgeEntity { embeddings: None, ..knowledge }).ok())
+    };
+    match (without_embedding(current), without_embedding(expected)) {
+        (Some(current), Some(expected)) => current == expected,
+        _ => false,
+    }
+}
+
+/// `(table, key)` of the rows in `undo` that no longer hold what the operation last wrote to them
+fn superseded_rows(
+    write_txn: &WriteTransaction,
+    cipher: Option<&RowCipher>,
+    undo: &[UndoRecord],
+) -> Result<HashSet<(String, String)>, StorageError> {
+    let mut written: HashMap<(&str, &str), &Option<Vec<u8>>> = HashMap::new();
+    for record in undo {
+        written.insert((record.table.as_str(), record.key.as_str()), &record.written);
+    }
+
+    let mut superseded = HashSet::new();
+    for ((table_name, key), written) in written {
+        let table = write_txn.open_table(TableDefinition::<&str, &[u8]>::new(table_name))
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open {} table: {}", table_name, e)))?;
+        let current = table.get(key)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read {} row: {}", table_name, e)))?
+            .map(|value| value.value().to_vec());
+        if !row_holds(cipher, table_name, &current, written) {
+            superseded.insert((table_name.to_string(), key.to_string()));
+        }
+    }
+    Ok(superseded)
+}
+
+/// `intents` without the points in `ids`, dropping intents left empty
+fn without_points(intents: Vec<VectorIntent>, ids: &HashSet<Uuid>) -> Vec<VectorIntent> {
+    intents.into_iter()
+        .filter_map(|intent| match intent {
+            VectorIntent::Upsert { collection, mut points } => {
+                points.retain(|point| !ids.contains(&point.id));
+                (!points.is_empty()).then_some(VectorIntent::Upsert { collection, points })
+            }
+            VectorIntent::Delete { collection, ids: mut deleted } => {
+                deleted.retain(|id| !ids.contains(id));
+                (!deleted.is_empty()).then_some(VectorIntent::Delete { collection, ids: deleted })
+            }
+        })
+        .collect()
+}
+
+impl HybridStorageCoordinator {
+    /// Execute coordinated transaction across both storage systems
+    ///
+    /// `operation` writes the entity rows and returns the compensating vector
+    /// intents needed to undo `vector_intents` should the operation be rolled back.
+    pub(crate) async fn execute_coordinated_transaction<F>(
+        &self,
+        operation_type: OperationType,
+        vector_intents: Vec<VectorIntent>,
+        operation: F,
+    ) -> Result<(), StorageError>
+    where
+        F: FnOnce(&mut JournaledWrite) -> Result<Vec<VectorIntent>, StorageError>,
+    {
+        let operation_id = Uuid::new_v4();
+
+        // Track the in-flight operation
+        {
+            let mut state = self.state.write().await;
+            state.pending_operations.insert(operation_id, PendingOperation {
+                operation_id,
+                operation_type,
+                redb_committed: false,
+                vector_committed: false,
+                timestamp: chrono::Utc::now(),
+            });
+        }
+
+        // Phase 1: Entity rows and journal entry (or outbox event) in one REDB transaction
+        let write_path = match self.config.consistency_mode {
+            ConsistencyMode::Immediate => WritePath::Journal(RecoveryPolicy::RollBack),
+            ConsistencyMode::Eventually => WritePath::Journal(RecoveryPolicy::RollForward),
+            ConsistencyMode::EventDriven => WritePath::Outbox,
+        };
+
+        let prepared = self.time_redb(|| {
+            self.prepare_operation(operation_id, operation_type, write_path, vector_intents, operation)
+        });
+        let (write_txn, prepared) = match prepared {
+            Ok(prepared) => prepared,
+            // Nothing was written: a rejected or invalid operation is not a storage failure
+            Err(e) => {
+                self.complete_operation(operation_id).await?;
+                return Err(e);
+            }
+        };
+
+        let committed = self.time_redb(|| {
+            write_txn.commit()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to commit REDB transaction: {}", e)))
+        });
+        if let Err(e) = committed {
+            self.rollback_operation(operation_id).await?;
+            return Err(e);
+        }
+
+        {
+            let mut state = self.state.write().await;
+            if let Some(pending_op) = state.pending_operations.get_mut(&operation_id) {
+                pending_op.redb_committed = true;
+            }
+        }
+
+        let entry = match prepared {
+            // Nothing to coordinate with the vector index
+            Prepared::RowsOnly => return self.complete_operation(operation_id).await,
+
+            // The write is durable in the outbox; the sync worker projects it
+            Prepared::Outboxed => {
+                self.complete_operation(operation_id).await?;
+                self.refresh_pending_count().await;
+                self.notify_sync_worker();
+                return Ok(());
+            }
+
+            // Eventual consistency: the sync worker applies the vector intents in batches
+            Prepared::Journaled(entry) if entry.policy == RecoveryPolicy::RollForward => {
+                self.complete_operation(operation_id).await?;
+                self.refresh_pending_count().await;
+                self.notify_sync_worker();
+                return Ok(());
+            }
+
+            Prepared::Journaled(entry) => entry,
+        };
+
+        // Phase 2: Apply vector intents
+        let failure = match self.apply_vector_intents(&entry.vector_intents).await {
+            Ok(()) => match self.time_redb(|| self.remove_journal_entry(operation_id)) {
+                Ok(()) => {
+                    {
+                        let mut state = self.state.write().await;
+                        if let Some(pending_op) = state.pending_operations.get_mut(&operation_id) {
+                            pending_op.vector_committed = true;
+                        }
+                    }
+                    return self.complete_operation(operation_id).await;
+                }
+                // Both writes are done; recovery clears the entry by rolling it forward
+                Err(e) => e,
+            },
+            Err(e) => match self.roll_back_entry(entry).await {
+                Ok(_) => e,
+                Err(compensation) => {
+                    tracing::warn!("Rollback of operation {} after a vector failure failed: {}", operation_id, e);
+                    compensation
+                }
+            },
+        };
+
+        // Whatever failed, the operation is over; recovery owns what is left in the journal
+        self.rollback_operation(operation_id).await?;
+        self.refresh_pending_count().await;
+        Err(failure)
+    }
+
+    /// Roll forward or roll back every operation left in the journal
+    ///
+    /// Entries of operations still running in this process belong to their
+    /// caller and are skipped.
+    pub async fn recover(&self) -> Result<RecoveryReport, StorageError> {
+        let _sync = self.sync_lock.lock().await;
+        let mut report = RecoveryReport::default();
+
+        for entry in self.load_journal()? {
+            if self.is_operation_live(entry.operation_id).await {
+                continue;
+            }
+            // A finished operation clears its entry before it stops being live; act on the current copy only
+            let Some(entry) = self.load_journal_entry(entry.operation_id)? else {
+                continue;
+            };
+
+            match entry.phase {
+                JournalPhase::Prepared => match self.apply_vector_intents(&entry.vector_intents).await {
+                    Ok(()) => {
+                        self.remove_journal_entry(entry.operation_id)?;
+                        report.rolled_forward += 1;
+                    }
+                    Err(e) if entry.policy == RecoveryPolicy::RollBack => {
+                        tracing::warn!("Rolling back operation {}: {}", entry.operation_id, e);
+                        if self.roll_back_entry(entry).await? {
+                            report.rolled_back += 1;
+                        } else {
+                            report.pending += 1;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("Operation {} still pending: {}", entry.operation_id, e);
+                        report.pending += 1;
+                    }
+                },
+                JournalPhase::RollingBack => {
+                    if self.finish_roll_back(&entry).await? {
+                        report.rolled_back += 1;
+                    } else {
+                        report.pending += 1;
+                    }
+                }
+            }
+        }
+
+        self.refresh_pending_count().await;
+        self.state.write().await.sync_status.last_sync = Some(chrono::Utc::now());
+
+        Ok(report)
+    }
+
+    /// Phase 1 up to the commit, which the caller makes so it can tell commit failures from rejected operations
+    fn prepare_operation<F>(
+        &self,
+        operation_id: Uuid,
+        operation_type: OperationType,
+        write_path: WritePath,
+        vector_intents: Vec<VectorIntent>,
+        operation: F,
+    ) -> Result<(WriteTransaction, Prepared), StorageError>
+    where
+        F: FnOnce(&mut JournaledWrite) -> Result<Vec<VectorIntent>, StorageError>,
+    {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+
+        // An error here drops the transaction, which aborts it
+        let mut journaled = JournaledWrite { txn: &write_txn, undo: Vec::new() };
+        let compensation = operation(&mut journaled)?;
+        let undo = journaled.undo;
+
+        let prepared = match write_path {
+            _ if vector_intents.is_empty() => Prepared::RowsOnly,
+            WritePath::Outbox => {
+                append_outbox_event(&write_txn, operation_type, vector_intents)?;
+                Prepared::Outboxed
+            }
+            WritePath::Journal(policy) => {
+                let entry = JournalEntry {
+                    operation_id,
+                    operation_type,
+                    phase: JournalPhase::Prepared,
+                    policy,
+                    undo,
+                    vector_intents,
+                    compensation,
+                    created_at: chrono::Utc::now(),
+                    failure: None,
+                };
+                write_journal_entry(&write_txn, &entry)?;
+                Prepared::Journaled(entry)
+            }
+        };
+
+        Ok((write_txn, prepared))
+    }
+
+    /// Restore before-images, then apply compensation. Returns false if compensation is still pending.
+    ///
+    /// Rows written again since the operation committed belong to the later
+    /// write: they are left as they are and their points are not compensated.
+    async fn roll_back_entry(&self, mut entry: JournalEntry) -> Result<bool, StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+
+        let superseded = superseded_rows(&write_txn, self.cipher(), &entry.undo)?;
+        if !superseded.is_empty() {
+            let ids: HashSet<Uuid> = superseded.iter().filter_map(|(_, key)| Uuid::parse_str(key).ok()).collect();
+            entry.undo.retain(|record| !superseded.contains(&(record.table.clone(), record.key.clone())));
+            entry.compensation = without_points(std::mem::take(&mut entry.compensation), &ids);
+        }
+
+        for record in entry.undo.iter().rev() {
+            let mut table = write_txn.open_table(TableDefinition::<&str, &[u8]>::new(&record.table))
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open {} table: {}", record.table, e)))?;
+
+            match &record.previous {
+                Some(previous) => table.insert(record.key.as_str(), previous.as_slice()).map(|_| ()),
+                None => table.remove(record.key.as_str()).map(|_| ()),
+            }
+            .map_err(|e| StorageError::TransactionError(format!("Failed to restore {} row: {}", record.table, e)))?;
+        }
+
+        // Derived indexes follow the restored knowledge rows
+        let mut knowledge_keys: Vec<&str> = entry.undo.iter()
+            .filter(|record| record.table == KNOWLEDGE_TABLE.name())
+            .map(|record| record.key.as_str())
+            .collect();
+        knowledge_keys.sort();
+        knowledge_keys.dedup();
+        for key in knowledge_keys {
+            super::reindex_derived_row(&write_txn, key, self.cipher())?;
+            track_knowledge_change(&write_txn, key)?;
+        }
+        let mut coordination_keys: Vec<&str> = entry.undo.iter()
+            .filter(|record| record.table == COORDINATION_TABLE.name())
+            .map(|record| record.key.as_str())
+            .collect();
+        coordination_keys.sort();
+        coordination_keys.dedup();
+        for key in coordination_keys {
+            reindex_coordination_row(&write_txn, key, self.cipher())?;
+        }
+
+        entry.phase = JournalPhase::RollingBack;
+        write_journal_entry(&write_txn, &entry)?;
+
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit rollback: {}", e)))?;
+
+        self.finish_roll_back(&entry).await
+    }
+
+    async fn finish_roll_back(&self, entry: &JournalEntry) -> Result<bool, StorageError> {
+        // A later write to the same rows owns the vector state now
+        if !self.is_superseded(entry)? {
+            if let Err(e) = self.apply_vector_intents(&entry.compensation).await {
+                tracing::warn!("Compensation for operation {} pending: {}", entry.operation_id, e);
+                return Ok(false);
+            }
+        }
+
+        self.remove_journal_entry(entry.operation_id)?;
+        Ok(true)
+    }
+
+    fn is_superseded(&self, entry: &JournalEntry) -> Result<bool, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        for record in &entry.undo {
+            let current = match read_txn.open_table(TableDefinition::<&str, &[u8]>::new(&record.table)) {
+                Ok(table) => table.get(record.key.as_str())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to read {} row: {}", record.table, e)))?
+                    .map(|value| value.value().to_vec()),
+                Err(redb::TableError::TableDoesNotExist(_)) => None,
+                Err(e) => return Err(StorageError::TransactionError(format!("Failed to open {} table: {}", record.table, e))),
+            };
+
+            if !row_holds(self.cipher(), &record.table, &current, &record.previous) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    pub(crate) async fn apply_vector_intents(&self, intents: &[VectorIntent]) -> Result<(), StorageError> {
+        for intent in intents {
+            match intent {
+                VectorIntent::Upsert { collection, points } => {
+                    self.vectors.upsert(collection, points.clone()).await?
+                }
+                VectorIntent::Delete { collection, ids } => {
+                    self.vectors.delete(collection, ids).await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn load_journal(&self) -> Result<Vec<JournalEntry>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(JOURNAL_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open journal table: {}", e))),
+        };
+
+        let mut entries = Vec::new();
+        for row in table.iter()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read journal: {}", e)))?
+        {
+            let (_, data) = row
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read journal: {}", e)))?;
+            let entry: JournalEntry = serde_json::from_slice(data.value())
+                .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize journal entry: {}", e)))?;
+            entries.push(entry);
+        }
+
+        // Replay in the order the operations were issued
+        entries.sort_by_key(|entry| entry.created_at);
+        Ok(entries)
+    }
+
+    /// Number of journal entries, without decoding them
+    pub(crate) fn journal_len(&self) -> Result<usize, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        match read_txn.open_table(JOURNAL_TABLE) {
+            Ok(table) => table.len()
+                .map(|len| len as usize)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read journal: {}", e))),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(0),
+            Err(e) => Err(StorageError::TransactionError(format!("Failed to open journal table: {}", e))),
+        }
+    }
+
+    fn load_journal_entry(&self, operation_id: Uuid) -> Result<Option<JournalEntry>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(JOURNAL_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open journal table: {}", e))),
+        };
+
+        let Some(data) = table.get(operation_id.to_string().as_str())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read journal: {}", e)))?
+        else {
+            return Ok(None);
+        };
+        serde_json::from_slice(data.value())
+            .map(Some)
+            .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize journal entry: {}", e)))
+    }
+
+    /// Whether a coordinated operation of this process is still between prepare and completion
+    async fn is_operation_live(&self, operation_id: Uuid) -> bool {
+        self.state.read().await.pending_operations.contains_key(&operation_id)
+    }
+
+    pub(crate) fn remove_journal_entry(&self, operation_id: Uuid) -> Result<(), StorageError> {
+        self.remove_journal_entries(&[operation_id])
+    }
+
+    pub(crate) fn remove_journal_entries(&self, operation_ids: &[Uuid]) -> Result<(), StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let mut table = write_txn.open_table(JOURNAL_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open journal table: {}", e)))?;
+            for operation_id in operation_ids {
+                table.remove(operation_id.to_string().as_str())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to clear journal entry: {}", e)))?;
+            }
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit journal update: {}", e)))
+    }
+
+    /// Move a journal entry to the dead-letter table, recording why it failed
+    pub(crate) fn quarantine_journal_entry(&self, entry: &JournalEntry, failure: &str) -> Result<(), StorageError> {
+        let quarantined = JournalEntry { failure: Some(failure.to_string()), ..entry.clone() };
+        let data = serde_json::to_vec(&quarantined)
+            .map_err(|e| StorageError::SerializationError(format!("Failed to serialize journal entry: {}", e)))?;
+
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let key = entry.operation_id.to_string();
+            let mut journal = write_txn.open_table(JOURNAL_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open journal table: {}", e)))?;
+            journal.remove(key.as_str())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to clear journal entry: {}", e)))?;
+            let mut dead_letter = write_txn.open_table(DEAD_LETTER_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open dead-letter table: {}", e)))?;
+            dead_letter.insert(key.as_str(), data.as_slice())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to write dead-letter entry: {}", e)))?;
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit journal update: {}", e)))
+    }
+
+    /// Move every dead-letter entry back into the journal, returning how many were moved
+    pub(crate) fn requeue_dead_letters(&self) -> Result<usize, StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        let requeued = {
+            let mut dead_letter = write_txn.open_table(DEAD_LETTER_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open dead-letter table: {}", e)))?;
+            let mut journal = write_txn.open_table(JOURNAL_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open journal table: {}", e)))?;
+
+            let mut requeued = 0;
+            while let Some((key, data)) = dead_letter.pop_first()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read dead-letter table: {}", e)))?
+            {
+                let mut entry: JournalEntry = serde_json::from_slice(data.value())
+                    .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize journal entry: {}", e)))?;
+                entry.failure = None;
+                let data = serde_json::to_vec(&entry)
+                    .map_err(|e| StorageError::SerializationError(format!("Failed to serialize journal entry: {}", e)))?;
+                journal.insert(key.value(), data.as_slice())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to write journal entry: {}", e)))?;
+                requeued += 1;
+            }
+            requeued
+        };
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit journal update: {}", e)))?;
+        Ok(requeued)
+    }
+
+    /// Number of dead-letter entries, without decoding them
+    pub(crate) fn dead_letter_len(&self) -> Result<usize, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        match read_txn.open_table(DEAD_LETTER_TABLE) {
+            Ok(table) => table.len()
+                .map(|len| len as usize)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read dead-letter table: {}", e))),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(0),
+            Err(e) => Err(StorageError::TransactionError(format!("Failed to open dead-letter table: {}", e))),
+        }
+    }
+
+    /// Forget an operation whose vector or commit step failed, counting the failure
+    async fn rollback_operation(&self, operation_id: Uuid) -> Result<(), StorageError> {
+        let mut state = self.state.write().await;
+        state.pending_operations.remove(&operation_id);
+        state.sync_status.error_count += 1;
+        Ok(())
+    }
+
+    async fn complete_operation(&self, operation_id: Uuid) -> Result<(), StorageError> {
+        let mut state = self.state.write().await;
+        state.pending_operations.remove(&operation_id);
+        Ok(())
+    }
+}
+
+fn write_journal_entry(write_txn: &WriteTransaction, entry: &JournalEntry) -> Result<(), StorageError> {
+    let data = serde_json::to_vec(entry)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to serialize journal entry: {}", e)))?;
+
+    let mut table = write_txn.open_table(JOURNAL_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open journal table: {}", e)))?;
+    table.insert(entry.operation_id.to_string().as_str(), data.as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write journal entry: {}", e)))?;
+
+    Ok(())
+}
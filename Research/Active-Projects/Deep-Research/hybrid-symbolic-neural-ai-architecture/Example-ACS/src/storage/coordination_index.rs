@@ -0,0 +1,307 @@
+//! Coordination Record Indexes
+//!
+//! Coordination rows are keyed by their own ID, so reading back what happened
+//! in a session needs secondary indexes. Each row is indexed three times, by
+//! session, by agent (when it names one) and by status, under
+//! `{value} \0 {timestamp} \0 {id}`. Timestamps are fixed-width RFC 3339, so
+//! every listing comes back oldest first and time ranges are key ranges.
+//!
+//! Like the lexical index the indexes are derived from the rows: they are
+//! written in the same transaction, re-derived for rows a rollback restores,
+//! and built once for databases that predate them. With encryption enabled,
+//! statuses are stored as keyed hashes; session and agent IDs and timestamps
+//! are stored as they are.
+
+use super::encryption::{self, RowCipher};
+use super::schema::EntityTable;
+use super::{CoordinationEntity, HybridStorageCoordinator, StorageError, COORDINATION_TABLE, METADATA_TABLE};
+use chrono::{DateTime, SecondsFormat, Utc};
+use redb::{ReadableTable, TableDefinition, TableHandle, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+/// Coordination IDs keyed by `session_id \0 timestamp \0 id`; values are empty
+pub(crate) const COORDINATION_BY_SESSION_TABLE: TableDefinition<&str, &[u8]> =
+    TableDefinition::new("coordination_by_session");
+
+/// Coordination IDs keyed by `agent_id \0 timestamp \0 id`; values are empty
+pub(crate) const COORDINATION_BY_AGENT_TABLE: TableDefinition<&str, &[u8]> =
+    TableDefinition::new("coordination_by_agent");
+
+/// Coordination IDs keyed by `status \0 timestamp \0 id`; values are empty
+pub(crate) const COORDINATION_BY_STATUS_TABLE: TableDefinition<&str, &[u8]> =
+    TableDefinition::new("coordination_by_status");
+
+/// Index keys of each coordination row, needed to remove them
+pub(crate) const COORDINATION_INDEX_KEYS_TABLE: TableDefinition<&str, &[u8]> =
+    TableDefinition::new("coordination_index_keys");
+
+/// Metadata key set once the coordination indexes have been built
+pub(crate) const COORDINATION_INDEX_KEY: &str = "coordination_index";
+
+/// Half-open time range: `from` inclusive, `until` exclusive; unset bounds are open
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub from: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Keys a coordination row is indexed under
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedCoordination {
+    session: String,
+    agent: Option<String>,
+    status: String,
+}
+
+/// Fixed width for years 0 to 9999, so keys sort chronologically
+fn timestamp_key(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn index_key(value: &str, timestamp: DateTime<Utc>, id: &str) -> String {
+    format!("{}\0{}\0{}", value, timestamp_key(timestamp), id)
+}
+
+fn status_value(cipher: Option<&RowCipher>, status: &str) -> String {
+    match cipher {
+        Some(cipher) => cipher.blind_term(status),
+        None => status.to_string(),
+    }
+}
+
+/// Index a coordination row, replacing whatever was indexed under its ID
+pub(crate) fn index_coordination(
+    write_txn: &WriteTransaction,
+    coordination: &CoordinationEntity,
+    cipher: Option<&RowCipher>,
+) -> Result<(), StorageError> {
+    let id = coordination.id.to_string();
+    unindex_coordination(write_txn, &id)?;
+
+    let indexed = IndexedCoordination {
+        session: index_key(&coordination.session_id.to_string(), coordination.timestamp, &id),
+        agent: coordination.agent_id
+            .map(|agent_id| index_key(&agent_id.to_string(), coordination.timestamp, &id)),
+        status: index_key(&status_value(cipher, &coordination.status), coordination.timestamp, &id),
+    };
+
+    for (table, key) in [
+        (COORDINATION_BY_SESSION_TABLE, Some(&indexed.session)),
+        (COORDINATION_BY_AGENT_TABLE, indexed.agent.as_ref()),
+        (COORDINATION_BY_STATUS_TABLE, Some(&indexed.status)),
+    ] {
+        let Some(key) = key else {
+            continue;
+        };
+        let mut index = write_txn.open_table(table)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open {} index: {}", table.name(), e)))?;
+        index.insert(key.as_str(), [].as_slice())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to write {} entry: {}", table.name(), e)))?;
+    }
+
+    let data = bincode::serialize(&indexed)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to serialize coordination index keys: {}", e)))?;
+    let mut keys = write_txn.open_table(COORDINATION_INDEX_KEYS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open coordination index keys: {}", e)))?;
+    keys.insert(id.as_str(), data.as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write coordination index keys: {}", e)))?;
+    Ok(())
+}
+
+/// Remove a coordination row from the indexes; unknown IDs are ignored
+pub(crate) fn unindex_coordination(write_txn: &WriteTransaction, id: &str) -> Result<(), StorageError> {
+    let removed = {
+        let mut keys = write_txn.open_table(COORDINATION_INDEX_KEYS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open coordination index keys: {}", e)))?;
+        let removed = keys.remove(id)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove coordination index keys: {}", e)))?;
+        removed.map(|data| data.value().to_vec())
+    };
+    let Some(data) = removed else {
+        return Ok(());
+    };
+    let indexed: IndexedCoordination = bincode::deserialize(&data)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize coordination index keys: {}", e)))?;
+
+    for (table, key) in [
+        (COORDINATION_BY_SESSION_TABLE, Some(&indexed.session)),
+        (COORDINATION_BY_AGENT_TABLE, indexed.agent.as_ref()),
+        (COORDINATION_BY_STATUS_TABLE, Some(&indexed.status)),
+    ] {
+        let Some(key) = key else {
+            continue;
+        };
+        let mut index = write_txn.open_table(table)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open {} index: {}", table.name(), e)))?;
+        index.remove(key.as_str())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove {} entry: {}", table.name(), e)))?;
+    }
+    Ok(())
+}
+
+/// Re-derive the index entries of one coordination row from its current value
+pub(crate) fn reindex_coordination_row(
+    write_txn: &WriteTransaction,
+    key: &str,
+    cipher: Option<&RowCipher>,
+) -> Result<(), StorageError> {
+    let row = {
+        let table = write_txn.open_table(COORDINATION_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open coordination table: {}", e)))?;
+        let row = table.get(key)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read coordination row: {}", e)))?;
+        row.map(|data| data.value().to_vec())
+    };
+
+    match row.map(|data| encryption::decode_row::<CoordinationEntity>(cipher, EntityTable::Coordination, &data)) {
+        Some(Ok(coordination)) => index_coordination(write_txn, &coordination, cipher),
+        Some(Err(e)) => {
+            warn!("Coordination row {} left out of the coordination indexes: {}", key, e);
+            unindex_coordination(write_txn, key)
+        }
+        None => unindex_coordination(write_txn, key),
+    }
+}
+
+/// Replace the coordination indexes with ones derived from the current rows and mark them as built
+pub(crate) fn rebuild_index(write_txn: &WriteTransaction, cipher: Option<&RowCipher>) -> Result<usize, StorageError> {
+    let keys: Vec<String> = {
+        let table = write_txn.open_table(COORDINATION_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open coordination table: {}", e)))?;
+        let mut keys = Vec::new();
+        for row in table.iter()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read coordination table: {}", e)))?
+        {
+            let (key, _) = row
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read coordination table: {}", e)))?;
+            keys.push(key.value().to_string());
+        }
+        keys
+    };
+
+    for table in [
+        COORDINATION_BY_SESSION_TABLE,
+        COORDINATION_BY_AGENT_TABLE,
+        COORDINATION_BY_STATUS_TABLE,
+        COORDINATION_INDEX_KEYS_TABLE,
+    ] {
+        write_txn.delete_table(table)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to clear {}: {}", table.name(), e)))?;
+    }
+
+    for key in &keys {
+        reindex_coordination_row(write_txn, key, cipher)?;
+    }
+
+    let mut metadata = write_txn.open_table(METADATA_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+    metadata.insert(COORDINATION_INDEX_KEY, [1u8].as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write coordination index marker: {}", e)))?;
+
+    Ok(keys.len())
+}
+
+impl HybridStorageCoordinator {
+    /// Coordination rows indexed under `value` within `range`, oldest first
+    fn list_indexed_coordination(
+        &self,
+        index: TableDefinition<'static, &'static str, &'static [u8]>,
+        value: &str,
+        range: TimeRange,
+    ) -> Result<Vec<CoordinationEntity>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let entries = match read_txn.open_table(index) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open {} index: {}", index.name(), e))),
+        };
+        let rows = match read_txn.open_table(COORDINATION_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open coordination table: {}", e))),
+        };
+
+        // Entries for `value` sort between "value\0" and "value\x01"; a bound narrows that to the range
+        let start = match range.from {
+            Some(from) => format!("{}\0{}", value, timestamp_key(from)),
+            None => format!("{}\0", value),
+        };
+        let end = match range.until {
+            Some(until) => format!("{}\0{}", value, timestamp_key(until)),
+            None => format!("{}\u{1}", value),
+        };
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut records = Vec::new();
+        for entry in entries.range(start.as_str()..end.as_str())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read {} index: {}", index.name(), e)))?
+        {
+            let (key, _) = entry
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read {} index: {}", index.name(), e)))?;
+            let Some(id) = key.value().rsplit('\0').next() else {
+                continue;
+            };
+
+            let data = rows.get(id)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read coordination row: {}", e)))?;
+            match data.map(|data| self.decode_row::<CoordinationEntity>(EntityTable::Coordination, data.value())) {
+                Some(Ok(record)) => records.push(record),
+                Some(Err(e)) => warn!("Coordination row {} left out of the listing: {}", id, e),
+                None => warn!("Coordination row {} is indexed but has no stored row", id),
+            }
+        }
+        Ok(records)
+    }
+
+    /// Coordination records of a session, oldest first
+    pub(crate) fn list_session_coordination(&self, session_id: &Uuid) -> Result<Vec<CoordinationEntity>, StorageError> {
+        self.list_indexed_coordination(COORDINATION_BY_SESSION_TABLE, &session_id.to_string(), TimeRange::default())
+    }
+
+    /// Coordination records naming an agent within `range`, oldest first
+    pub(crate) fn list_agent_coordination(
+        &self,
+        agent_id: &Uuid,
+        range: TimeRange,
+    ) -> Result<Vec<CoordinationEntity>, StorageError> {
+        self.list_indexed_coordination(COORDINATION_BY_AGENT_TABLE, &agent_id.to_string(), range)
+    }
+
+    /// Coordination records with a status, oldest first
+    pub(crate) fn list_status_coordination(&self, status: &str) -> Result<Vec<CoordinationEntity>, StorageError> {
+        let value = status_value(self.cipher(), status);
+        self.list_indexed_coordination(COORDINATION_BY_STATUS_TABLE, &value, TimeRange::default())
+    }
+
+    /// Rebuild the coordination indexes from `COORDINATION_TABLE`, returning the number of indexed rows
+    pub fn rebuild_coordination_index(&self) -> Result<usize, StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+
+        let indexed = rebuild_index(&write_txn, self.cipher())?;
+
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit coordination index rebuild: {}", e)))?;
+        Ok(indexed)
+    }
+
+    /// Whether the coordination indexes have been built for this database
+    pub(crate) fn coordination_index_exists(&self) -> Result<bool, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => Ok(table.get(COORDINATION_INDEX_KEY)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read coordination index marker: {}", e)))?
+                .is_some()),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(false),
+            Err(e) => Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Duplicate Detection on Ingest
+//!
+//! Every top-level knowledge entry is indexed by a hash of its normalized content: the
+//! lowercased alphanumeric tokens, so case, whitespace and punctuation do not
+//! matter. With encryption enabled the hash is keyed, like lexical terms. The
+//! index is maintained through the storage module's `index_derived` and
+//! `unindex_derived`, in the same transaction as the rows.
+//!
+//! `store_knowledge` looks for an entry with the same hash and, when
+//! `near_duplicate_threshold` is set, for the nearest vector at or above that
+//! cosine similarity. `DuplicateAction` decides what happens to a duplicate.
+//! Storing under an existing ID is an update, never a duplicate, and batch
+//! stores and snapshot imports write entries as given.
+//!
+//! The check runs before the write, so concurrent stores of the same content
+//! can both succeed, and near duplicates are only found among entries already
+//! applied to the vector index. Document chunks are never duplicates: they
+//! are left out of the hash index and skipped among the nearest vectors.
+
+use super::encryption::RowCipher;
+use super::lexical::tokenize;
+use super::{HybridStorage, HybridStorageCoordinator, KnowledgeEntity, StorageError, METADATA_TABLE};
+use redb::{TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Knowledge IDs keyed by `content_hash \0 knowledge_id`; values are empty
+pub(crate) const CONTENT_HASH_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("content_hashes");
+
+/// Content hash of each knowledge entry, needed to remove it from `CONTENT_HASH_TABLE`
+pub(crate) const CONTENT_HASH_DOCUMENTS_TABLE: TableDefinition<&str, &[u8]> =
+    TableDefinition::new("content_hash_documents");
+
+/// Metadata key set once the content hash index has been built; renamed when chunks stopped being hashed
+pub(crate) const CONTENT_HASH_INDEX_KEY: &str = "content_hash_index_v2";
+
+/// Nearest vectors examined for a near duplicate, so chunks ranked ahead of entries can be skipped
+const NEAR_DUPLICATE_CANDIDATES: usize = 8;
+
+/// What `store_knowledge` does when the entry duplicates an existing one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DuplicateAction {
+    #[default]
+    Skip,          // Keep the existing entry and store nothing
+    MergeMetadata, // Add the new metadata and tags to the existing entry; new values win on conflicts
+    NewRevision,   // Replace the existing entry's content, archiving its previous revision
+    Allow,         // Store a separate entry without checking
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeduplicationConfig {
+    pub action: DuplicateAction,
+    /// Cosine similarity at which an entry counts as a near duplicate; `None` only matches identical content
+    pub near_duplicate_threshold: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IngestOutcome {
+    Stored,         // Written as given
+    Skipped,        // Duplicate left as it was
+    MergedMetadata, // Metadata merged into the duplicate
+    NewRevision,    // Duplicate replaced by the new content
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateMatch {
+    pub id: Uuid,
+    /// Whether the normalized content is identical
+    pub exact: bool,
+    /// Cosine similarity of the embeddings; 1.0 for exact matches
+    pub similarity: f32,
+}
+
+/// Result of `store_knowledge`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestReport {
+    /// ID the content is stored under: the new entry's, or the duplicate's
+    pub id: Uuid,
+    pub outcome: IngestOutcome,
+    pub duplicate_of: Option<DuplicateMatch>,
+}
+
+/// Hash of the normalized content, keyed under the row key when encryption is enabled
+pub(crate) fn content_hash(cipher: Option<&RowCipher>, content: &str) -> String {
+    let normalized = tokenize(content).collect::<Vec<_>>().join(" ");
+    match cipher {
+        Some(cipher) => cipher.blind_term(&normalized),
+        None => format!("{:x}", Sha256::digest(normalized.as_bytes())),
+    }
+}
+
+fn hash_key(hash: &str, id: &str) -> String {
+    format!("{}\0{}", hash, id)
+}
+
+/// Index a knowledge entry's content hash, replacing whatever was indexed under its ID; chunks are not indexed
+pub(crate) fn index_content(
+    write_txn: &WriteTransaction,
+    knowledge: &KnowledgeEntity,
+    cipher: Option<&RowCipher>,
+) -> Result<(), StorageError> {
+    let id = knowledge.id.to_string();
+    unindex_content(write_txn, &id)?;
+    if knowledge.parent_id.is_some() {
+        return Ok(());
+    }
+
+    let hash = content_hash(cipher, &knowledge.content);
+    {
+        let mut hashes = write_txn.open_table(CONTENT_HASH_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open content hashes: {}", e)))?;
+        hashes.insert(hash_key(&hash, &id).as_str(), [].as_slice())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to write content hash: {}", e)))?;
+    }
+
+    let mut documents = write_txn.open_table(CONTENT_HASH_DOCUMENTS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open content hash documents: {}", e)))?;
+    documents.insert(id.as_str(), hash.as_bytes())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write content hash document: {}", e)))?;
+    Ok(())
+}
+
+/// Remove a knowledge entry's content hash; unknown IDs are ignored
+pub(crate) fn unindex_content(write_txn: &WriteTransaction, id: &str) -> Result<(), StorageError> {
+    let hash = {
+        let mut documents = write_txn.open_table(CONTENT_HASH_DOCUMENTS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open content hash documents: {}", e)))?;
+        let removed = documents.remove(id)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove content hash document: {}", e)))?;
+        removed.map(|data| String::from_utf8_lossy(data.value()).into_owned())
+    };
+
+    if let Some(hash) = hash {
+        let mut hashes = write_txn.open_table(CONTENT_HASH_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open content hashes: {}", e)))?;
+        hashes.remove(hash_key(&hash, id).as_str())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove content hash: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Drop the content hash index ahead of a rebuild and mark it as built
+pub(crate) fn clear_index(write_txn: &WriteTransaction) -> Result<(), StorageError> {
+    write_txn.delete_table(CONTENT_HASH_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to clear content hashes: {}", e)))?;
+    write_txn.delete_table(CONTENT_HASH_DOCUMENTS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to clear content hash documents: {}", e)))?;
+
+    let mut metadata = write_txn.open_table(METADATA_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+    metadata.insert(CONTENT_HASH_INDEX_KEY, [1u8].as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write content hash index marker: {}", e)))?;
+    Ok(())
+}
+
+impl HybridStorageCoordinator {
+    /// Store a knowledge entry unless it duplicates another, applying `deduplication` to duplicates
+    pub(crate) async fn run_ingest(
+        &self,
+        knowledge: &KnowledgeEntity,
+        deduplication: &DeduplicationConfig,
+    ) -> Result<IngestReport, StorageError> {
+        let stored = |outcome| IngestReport { id: knowledge.id, outcome, duplicate_of: None };
+
+        if deduplication.action == DuplicateAction::Allow || self.get_knowledge(&knowledge.id).await?.is_some() {
+            self.write_knowledge(knowledge).await?;
+            return Ok(stored(IngestOutcome::Stored));
+        }
+
+        // Embed once: the near-duplicate check and the write share it
+        let knowledge = &KnowledgeEntity {
+            embeddings: Some(self.stored_embedding(knowledge).await?),
+            ..knowledge.clone()
+        };
+
+        let Some(duplicate) = self.find_duplicate(knowledge, deduplication).await? else {
+            self.write_knowledge(knowledge).await?;
+            return Ok(stored(IngestOutcome::Stored));
+        };
+        // Removed since it was found: nothing left to duplicate
+        let Some(existing) = self.get_knowledge(&duplicate.id).await? else {
+            self.write_knowledge(knowledge).await?;
+            return Ok(stored(IngestOutcome::Stored));
+        };
+
+        let outcome = match deduplication.action {
+            DuplicateAction::Skip | DuplicateAction::Allow => IngestOutcome::Skipped,
+            DuplicateAction::MergeMetadata => {
+                let mut merged = existing.clone();
+                merged.metadata.extend(knowledge.metadata.clone());
+                for tag in &knowledge.tags {
+                    if !merged.tags.contains(tag) {
+                        merged.tags.push(tag.clone());
+                    }
+                }
+                if merged.metadata != existing.metadata || merged.tags != existing.tags {
+                    self.update_knowledge(&merged).await?;
+                }
+                IngestOutcome::MergedMetadata
+            }
+            DuplicateAction::NewRevision => {
+                self.update_knowledge(&KnowledgeEntity {
+                    id: existing.id,
+                    created_at: existing.created_at,
+                    ..knowledge.clone()
+                }).await?;
+                IngestOutcome::NewRevision
+            }
+        };
+
+        Ok(IngestReport { id: existing.id, outcome, duplicate_of: Some(duplicate) })
+    }
+
+    /// Another entry with the same normalized content, or else the nearest one above the threshold
+    async fn find_duplicate(
+        &self,
+        knowledge: &KnowledgeEntity,
+        deduplication: &DeduplicationConfig,
+    ) -> Result<Option<DuplicateMatch>, StorageError> {
+        if let Some(id) = self.time_redb(|| self.find_same_content(knowledge))? {
+            return Ok(Some(DuplicateMatch { id, exact: true, similarity: 1.0 }));
+        }
+
+        let (Some(threshold), Some(embedding)) = (deduplication.near_duplicate_threshold, &knowledge.embeddings) else {
+            return Ok(None);
+        };
+        let collection = self.knowledge_collection().await;
+        let nearest: Vec<_> = self.vectors.search(&collection, embedding, NEAR_DUPLICATE_CANDIDATES).await?
+            .into_iter()
+            .filter(|vector_match| vector_match.id != knowledge.id && vector_match.score >= threshold)
+            .collect();
+
+        // Points carry no parent, so chunks are told apart by their rows
+        let ids: Vec<Uuid> = nearest.iter().map(|vector_match| vector_match.id).collect();
+        let rows = self.time_redb(|| self.load_knowledge_entities(&ids))?;
+
+        Ok(nearest.into_iter()
+            .zip(rows)
+            .find(|(_, row)| row.as_ref().is_ok_and(|entity| entity.parent_id.is_none()))
+            .map(|(vector_match, _)| DuplicateMatch { id: vector_match.id, exact: false, similarity: vector_match.score }))
+    }
+
+    fn find_same_content(&self, knowledge: &KnowledgeEntity) -> Result<Option<Uuid>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+        let hashes = match read_txn.open_table(CONTENT_HASH_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open content hashes: {}", e))),
+        };
+
+        // All entries with a hash sort between "hash\0" and "hash\x01"
+        let hash = content_hash(self.cipher(), &knowledge.content);
+        let start = format!("{}\0", hash);
+        let end = format!("{}\u{1}", hash);
+
+        for row in hashes.range(start.as_str()..end.as_str())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read content hashes: {}", e)))?
+        {
+            let (key, _) = row
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read content hashes: {}", e)))?;
+            let id = Uuid::parse_str(&key.value()[start.len()..])
+                .map_err(|e| StorageError::SerializationError(format!("Invalid content hash entry: {}", e)))?;
+            if id != knowledge.id {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether the content hash index has been built for this database
+    pub(crate) fn content_index_exists(&self) -> Result<bool, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => Ok(table.get(CONTENT_HASH_INDEX_KEY)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read content hash index marker: {}", e)))?
+                .is_some()),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(false),
+            Err(e) => Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        }
+    }
+}
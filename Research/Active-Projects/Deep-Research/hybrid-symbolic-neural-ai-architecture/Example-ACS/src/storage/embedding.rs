@@ -0,0 +1,138 @@
+//! Embedding Providers
+//!
+//! Text is turned into vectors through the `EmbeddingProvider` trait so the
+//! coordinator does not depend on any particular model. The default
+//! `HashingEmbedder` needs no model files: it feature-hashes word tokens and
+//! character n-grams into a fixed-size vector, so cosine similarity between two
+//! texts tracks how much vocabulary and spelling they share.
+//!
+//! Unkeyed feature hashes are public, so anyone holding a vector can test
+//! guessed words against it. With encryption at rest the coordinator uses
+//! `HashingEmbedder::keyed` instead, which hashes features with HMAC-SHA256
+//! under a key derived from the data key.
+
+use super::lexical::tokenize;
+use super::StorageError;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Character n-gram length used by the hashing embedder
+const CHAR_NGRAM: usize = 3;
+
+/// Weight of a word token relative to one of its character n-grams
+const TOKEN_WEIGHT: f32 = 2.0;
+
+/// Converts text into fixed-size embedding vectors
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Length of every vector this provider returns
+    fn dimension(&self) -> usize;
+
+    /// Names the model, so vectors from different models are never mixed in one collection
+    ///
+    /// Two providers with the same identity must embed the same text alike.
+    fn identity(&self) -> String {
+        format!("{}:{}", std::any::type_name::<Self>(), self.dimension())
+    }
+
+    /// Embed a batch of texts, returning one vector per input in input order
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, StorageError>;
+}
+
+/// Prefix of the identity of a keyed `HashingEmbedder`
+pub(crate) const KEYED_IDENTITY_PREFIX: &str = "hashing-keyed:";
+
+/// Deterministic offline embedder based on feature hashing
+///
+/// Vectors only depend on the input text, the dimension and the key if any, so
+/// they are stable across processes and safe to persist.
+pub struct HashingEmbedder {
+    dimension: usize,
+    mac: Option<Hmac<Sha256>>,
+    identity: String,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimension: usize) -> Self {
+        Self { dimension, mac: None, identity: format!("hashing:{}", dimension) }
+    }
+
+    /// Embedder whose feature hashes are keyed, so vectors cannot be matched against guessed texts
+    pub fn keyed(dimension: usize, key: &[u8; 32]) -> Self {
+        let mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        let fingerprint = mac.clone().chain_update(b"acs-embedding-identity").finalize().into_bytes();
+        let fingerprint: String = fingerprint[..8].iter().map(|byte| format!("{:02x}", byte)).collect();
+        Self { dimension, mac: Some(mac), identity: format!("{}{}:{}", KEYED_IDENTITY_PREFIX, dimension, fingerprint) }
+    }
+
+    fn embed_text(&self, text: &str) -> Vec<f32> {
+        let mut embedding = vec![0.0f32; self.dimension];
+        if self.dimension == 0 {
+            return embedding;
+        }
+
+        for token in tokenize(text) {
+            self.add_feature(&mut embedding, "w", &token, TOKEN_WEIGHT);
+
+            // Pad so prefixes and suffixes get their own n-grams
+            let chars: Vec<char> = format!(" {} ", token).chars().collect();
+            for window in chars.windows(CHAR_NGRAM) {
+                let ngram: String = window.iter().collect();
+                self.add_feature(&mut embedding, "c", &ngram, 1.0);
+            }
+        }
+
+        let norm: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for component in &mut embedding {
+                *component /= norm;
+            }
+        }
+
+        embedding
+    }
+
+    /// Add a feature to its hashed bucket; the sign bit keeps collisions from biasing similarity
+    fn add_feature(&self, embedding: &mut [f32], namespace: &str, feature: &str, weight: f32) {
+        let hash = match &self.mac {
+            Some(mac) => {
+                let digest = mac.clone().chain_update(namespace).chain_update(b":").chain_update(feature).finalize();
+                u64::from_le_bytes(digest.into_bytes()[..8].try_into().expect("digest holds 32 bytes"))
+            }
+            None => fnv1a(namespace.as_bytes().iter().chain(b":").chain(feature.as_bytes())),
+        };
+        let bucket = (hash % self.dimension as u64) as usize;
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        embedding[bucket] += sign * weight;
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HashingEmbedder {
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn identity(&self) -> String {
+        self.identity.clone()
+    }
+
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, StorageError> {
+        Ok(texts.iter().map(|text| self.embed_text(text)).collect())
+    }
+}
+
+/// SHA-256 of a text, used as a cache key for computed embeddings
+#[cfg(feature = "candle")]
+pub(crate) fn content_hash(text: &str) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(text.as_bytes()).into()
+}
+
+/// 64-bit FNV-1a; unlike `DefaultHasher` its output is fixed across Rust releases
+fn fnv1a<'a>(bytes: impl Iterator<Item = &'a u8>) -> u64 {
+    bytes.fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
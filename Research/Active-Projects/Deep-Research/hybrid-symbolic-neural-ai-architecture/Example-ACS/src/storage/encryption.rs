@@ -0,0 +1,397 @@
+//! Encryption at Rest
+//!
+//! With `StorageConfig::encryption` set, rows of `KNOWLEDGE_TABLE`,
+//! `KNOWLEDGE_REVISIONS_TABLE`, `COORDINATION_TABLE`, `TASK_HISTORY_TABLE` and
+//! `RELATIONS_TABLE` are sealed with XChaCha20-Poly1305 around the versioned envelope: the magic
+//! bytes `ACSe`, an 8-byte key ID, a random 24-byte nonce, then the
+//! ciphertext. The magic bytes, key ID and table name are authenticated, so a
+//! row cannot be moved to another table unnoticed.
+//! Unsealed rows stay readable, which is how a database is first encrypted.
+//!
+//! Keys are 32 bytes, read from a file or an environment variable. Retired
+//! keys listed in `previous_keys` can still open rows; when the database was
+//! last encrypted with another key, every row is re-encrypted with the current
+//! one on open. The ID of that key is recorded under `encryption_key_id` in
+//! `METADATA_TABLE`.
+//!
+//! The lexical index stores terms, and the coordination status index
+//! statuses, as keyed hashes rather than plaintext, and vector payloads never
+//! carry content, so none of them leaks what rows hold.
+//! The default hashing embedder keys its feature hashes with a key derived
+//! from the current data key, so vectors in the index cannot be matched
+//! against embeddings of guessed texts. Changing the key changes every
+//! embedding: after opening with a new key, `reindex()` re-embeds knowledge
+//! under it. Vectors of other embedding providers are not keyed and may
+//! reveal what a row is about.
+//! Pages freed when plaintext rows are re-encrypted are not scrubbed by REDB;
+//! to leave no plaintext behind, export a snapshot and import it into a new
+//! encrypted database instead.
+
+use super::coordination_index;
+use super::embedding::KEYED_IDENTITY_PREFIX;
+use super::schema::{self, EntityTable, MigrationFailure};
+use super::{HybridStorageCoordinator, StorageError, METADATA_TABLE};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use redb::{ReadableTable, WriteTransaction};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Metadata key holding the ID (hex) of the key every row was last encrypted with
+pub(crate) const ENCRYPTION_KEY_ID_KEY: &str = "encryption_key_id";
+
+const SEALED_MAGIC: &[u8; 4] = b"ACSe";
+const KEY_ID_LEN: usize = 8;
+const NONCE_LEN: usize = 24;
+const SEALED_HEADER_LEN: usize = SEALED_MAGIC.len() + KEY_ID_LEN + NONCE_LEN;
+const KEY_LEN: usize = 32;
+
+type KeyId = [u8; KEY_ID_LEN];
+
+/// Where encryption keys come from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    /// Key new rows are sealed with
+    pub key: KeySource,
+    /// Retired keys, still accepted when opening rows that have not been re-encrypted
+    #[serde(default)]
+    pub previous_keys: Vec<KeySource>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KeySource {
+    File(String),  // Path to a file holding 32 raw bytes or 64 hex digits
+    Env(String),   // Name of an environment variable holding 64 hex digits
+}
+
+/// Outcome of re-encrypting the rows of a database
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyRotationReport {
+    /// ID (hex) of the key rows are now encrypted with
+    pub key_id: String,
+    pub reencrypted: usize,
+    /// Rows that could not be opened with any configured key; they are left untouched
+    pub failures: Vec<MigrationFailure>,
+}
+
+/// Seals and opens rows with the configured keys
+pub(crate) struct RowCipher {
+    current: KeyId,
+    keys: HashMap<KeyId, XChaCha20Poly1305>,
+    lexical_key: [u8; KEY_LEN],
+}
+
+impl RowCipher {
+    pub(crate) fn load(config: &EncryptionConfig) -> Result<Self, StorageError> {
+        let current_key = read_key(&config.key)?;
+        let current = key_id(&current_key);
+
+        let mut keys = HashMap::new();
+        for key in std::iter::once(Ok(current_key)).chain(config.previous_keys.iter().map(read_key)) {
+            let key = key?;
+            keys.insert(key_id(&key), XChaCha20Poly1305::new((&key).into()));
+        }
+
+        Ok(Self {
+            current,
+            keys,
+            lexical_key: derive_key(b"acs-lexical", &current_key),
+        })
+    }
+
+    pub(crate) fn key_id(&self) -> String {
+        to_hex(&self.current)
+    }
+
+    fn seal(&self, table: EntityTable, row: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let header = sealed_header(&self.current);
+        let ciphertext = self.keys[&self.current]
+            .encrypt(&nonce, Payload { msg: row, aad: &associated_data(&header, table) })
+            .map_err(|_| StorageError::SerializationError(format!("Failed to encrypt {} row", table.name())))?;
+
+        let mut sealed = Vec::with_capacity(SEALED_HEADER_LEN + ciphertext.len());
+        sealed.extend_from_slice(&header);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    fn open(&self, table: EntityTable, data: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let id = sealed_key_id(data);
+        let cipher = self.keys.get(&id).ok_or_else(|| {
+            StorageError::SerializationError(format!(
+                "{} row is encrypted with key {}, which is not configured", table.name(), to_hex(&id)
+            ))
+        })?;
+
+        let nonce = XNonce::from_slice(&data[SEALED_MAGIC.len() + KEY_ID_LEN..SEALED_HEADER_LEN]);
+        cipher
+            .decrypt(nonce, Payload {
+                msg: &data[SEALED_HEADER_LEN..],
+                aad: &associated_data(&data[..SEALED_MAGIC.len() + KEY_ID_LEN], table),
+            })
+            .map_err(|_| StorageError::SerializationError(format!("Failed to decrypt {} row: authentication failed", table.name())))
+    }
+
+    /// Key of the hashing embedder, derived from the current key
+    pub(crate) fn embedding_key(config: &EncryptionConfig) -> Result<[u8; KEY_LEN], StorageError> {
+        Ok(derive_key(b"acs-embedding", &read_key(&config.key)?))
+    }
+
+    /// Keyed hash of a lexical term, stored in place of the term itself
+    pub(crate) fn blind_term(&self, term: &str) -> String {
+        to_hex(&hmac_sha256(&self.lexical_key, term.as_bytes())[..16])
+    }
+}
+
+/// Tables whose rows are sealed when a key is configured
+fn is_encrypted(table: EntityTable) -> bool {
+    !matches!(table, EntityTable::Agents)
+}
+
+fn is_sealed(data: &[u8]) -> bool {
+    data.len() >= SEALED_HEADER_LEN && data.starts_with(SEALED_MAGIC)
+}
+
+fn sealed_key_id(data: &[u8]) -> KeyId {
+    let mut id = [0u8; KEY_ID_LEN];
+    id.copy_from_slice(&data[SEALED_MAGIC.len()..SEALED_MAGIC.len() + KEY_ID_LEN]);
+    id
+}
+
+fn sealed_header(id: &KeyId) -> Vec<u8> {
+    let mut header = Vec::with_capacity(SEALED_MAGIC.len() + KEY_ID_LEN);
+    header.extend_from_slice(SEALED_MAGIC);
+    header.extend_from_slice(id);
+    header
+}
+
+fn associated_data(header: &[u8], table: EntityTable) -> Vec<u8> {
+    [header, table.name().as_bytes()].concat()
+}
+
+/// Encode an entity as a row of `table`, sealed if the table is encrypted and a key is configured
+pub(crate) fn encode_row<T: Serialize>(
+    cipher: Option<&RowCipher>,
+    table: EntityTable,
+    entity: &T,
+) -> Result<Vec<u8>, StorageError> {
+    seal_row(cipher, table, schema::encode(entity)?)
+}
+
+/// Decode a row of `table`, opening it first if it is sealed
+pub(crate) fn decode_row<T: DeserializeOwned>(
+    cipher: Option<&RowCipher>,
+    table: EntityTable,
+    data: &[u8],
+) -> Result<T, StorageError> {
+    schema::decode(table, &open_row(cipher, table, data)?)
+}
+
+/// Envelope inside a row, which is the row itself unless it is sealed
+pub(crate) fn open_row<'a>(
+    cipher: Option<&RowCipher>,
+    table: EntityTable,
+    data: &'a [u8],
+) -> Result<Cow<'a, [u8]>, StorageError> {
+    if !is_sealed(data) {
+        return Ok(Cow::Borrowed(data));
+    }
+    match cipher {
+        Some(cipher) => cipher.open(table, data).map(Cow::Owned),
+        None => Err(StorageError::SerializationError(format!(
+            "{} row is encrypted but no encryption key is configured", table.name()
+        ))),
+    }
+}
+
+/// Seal an envelope for `table` if the table is encrypted and a key is configured
+pub(crate) fn seal_row(cipher: Option<&RowCipher>, table: EntityTable, row: Vec<u8>) -> Result<Vec<u8>, StorageError> {
+    match cipher {
+        Some(cipher) if is_encrypted(table) => cipher.seal(table, &row),
+        _ => Ok(row),
+    }
+}
+
+impl HybridStorageCoordinator {
+    pub(crate) fn cipher(&self) -> Option<&RowCipher> {
+        self.cipher.as_deref()
+    }
+
+    pub(crate) fn encode_row<T: Serialize>(&self, table: EntityTable, entity: &T) -> Result<Vec<u8>, StorageError> {
+        encode_row(self.cipher(), table, entity)
+    }
+
+    pub(crate) fn decode_row<T: DeserializeOwned>(&self, table: EntityTable, data: &[u8]) -> Result<T, StorageError> {
+        decode_row(self.cipher(), table, data)
+    }
+
+    /// Re-encrypt rows if the database was last encrypted with another key, or not at all
+    pub(crate) fn open_encryption(&self) -> Result<(), StorageError> {
+        let recorded = self.recorded_key_id()?;
+        match (self.cipher(), recorded) {
+            (None, None) => Ok(()),
+            (None, Some(key_id)) => Err(StorageError::InitializationError(format!(
+                "Database is encrypted with key {}; configure StorageConfig::encryption to open it", key_id
+            ))),
+            (Some(cipher), Some(key_id)) if key_id == cipher.key_id() => Ok(()),
+            (Some(_), _) => {
+                let report = self.rotate_encryption_key()?;
+                tracing::info!(
+                    "Encrypted {} rows with key {}, {} failed",
+                    report.reencrypted, report.key_id, report.failures.len()
+                );
+                for failure in &report.failures {
+                    tracing::warn!("Could not re-encrypt {} row {}: {}", failure.table, failure.key, failure.error);
+                }
+                if report.reencrypted > 0 && self.embedder.identity().starts_with(KEYED_IDENTITY_PREFIX) {
+                    tracing::warn!(
+                        "Knowledge embedded before key {} was configured no longer matches queries; call reindex() to re-embed it",
+                        report.key_id
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Re-encrypt every row of the encrypted tables with the current key
+    ///
+    /// Rows sealed with a previous key or not sealed at all are rewritten, and
+    /// the lexical index is rebuilt with terms hashed under the current key.
+    /// The key is recorded only once every row has been re-encrypted, so rows
+    /// that failed are retried the next time the database is opened.
+    pub fn rotate_encryption_key(&self) -> Result<KeyRotationReport, StorageError> {
+        let cipher = self.cipher()
+            .ok_or_else(|| StorageError::ConfigurationError("No encryption key configured".to_string()))?;
+        let mut report = KeyRotationReport { key_id: cipher.key_id(), ..Default::default() };
+
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+
+        for entity_table in EntityTable::ALL.into_iter().filter(|table| is_encrypted(*table)) {
+            let mut table = write_txn.open_table(entity_table.definition())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open {} table: {}", entity_table.name(), e)))?;
+
+            let mut resealed = Vec::new();
+            for row in table.iter()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read {} table: {}", entity_table.name(), e)))?
+            {
+                let (key, data) = row
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to read {} table: {}", entity_table.name(), e)))?;
+                let data = data.value();
+                if is_sealed(data) && sealed_key_id(data) == cipher.current {
+                    continue;
+                }
+
+                match open_row(Some(cipher), entity_table, data).and_then(|row| cipher.seal(entity_table, &row)) {
+                    Ok(row) => resealed.push((key.value().to_string(), row)),
+                    Err(e) => report.failures.push(MigrationFailure {
+                        table: entity_table.name().to_string(),
+                        key: key.value().to_string(),
+                        error: e.to_string(),
+                    }),
+                }
+            }
+
+            for (key, row) in &resealed {
+                table.insert(key.as_str(), row.as_slice())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to write {} row: {}", entity_table.name(), e)))?;
+            }
+            report.reencrypted += resealed.len();
+        }
+
+        super::rebuild_derived(&write_txn, Some(cipher))?;
+        coordination_index::rebuild_index(&write_txn, Some(cipher))?;
+        if report.failures.is_empty() {
+            write_key_id(&write_txn, &report.key_id)?;
+        }
+
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit key rotation: {}", e)))?;
+
+        Ok(report)
+    }
+
+    fn recorded_key_id(&self) -> Result<Option<String>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        };
+
+        let key_id = table.get(ENCRYPTION_KEY_ID_KEY)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read encryption key ID: {}", e)))?;
+        Ok(key_id.map(|data| String::from_utf8_lossy(data.value()).into_owned()))
+    }
+}
+
+fn write_key_id(write_txn: &WriteTransaction, key_id: &str) -> Result<(), StorageError> {
+    let mut table = write_txn.open_table(METADATA_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+    table.insert(ENCRYPTION_KEY_ID_KEY, key_id.as_bytes())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write encryption key ID: {}", e)))?;
+    Ok(())
+}
+
+fn read_key(source: &KeySource) -> Result<[u8; KEY_LEN], StorageError> {
+    let (hex, origin) = match source {
+        KeySource::File(path) => {
+            let bytes = std::fs::read(path)
+                .map_err(|e| StorageError::ConfigurationError(format!("Failed to read key file {}: {}", path, e)))?;
+            if let Ok(key) = <[u8; KEY_LEN]>::try_from(bytes.as_slice()) {
+                return Ok(key);
+            }
+            (String::from_utf8_lossy(&bytes).into_owned(), format!("Key file {}", path))
+        }
+        KeySource::Env(name) => {
+            let value = std::env::var(name)
+                .map_err(|e| StorageError::ConfigurationError(format!("Failed to read key variable {}: {}", name, e)))?;
+            (value, format!("Key variable {}", name))
+        }
+    };
+
+    parse_hex_key(hex.trim()).ok_or_else(|| {
+        StorageError::ConfigurationError(format!("{} must hold a 32-byte key as 64 hex digits", origin))
+    })
+}
+
+fn parse_hex_key(hex: &str) -> Option<[u8; KEY_LEN]> {
+    if hex.len() != KEY_LEN * 2 {
+        return None;
+    }
+    let mut key = [0u8; KEY_LEN];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(key)
+}
+
+fn key_id(key: &[u8; KEY_LEN]) -> KeyId {
+    let mut id = [0u8; KEY_ID_LEN];
+    id.copy_from_slice(&derive_key(b"acs-key-id", key)[..KEY_ID_LEN]);
+    id
+}
+
+/// Independent key for `purpose`, so key IDs and term hashes reveal nothing about the row key
+fn derive_key(purpose: &[u8], key: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    hmac_sha256(key, purpose)
+}
+
+fn hmac_sha256(key: &[u8; KEY_LEN], message: &[u8]) -> [u8; KEY_LEN] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
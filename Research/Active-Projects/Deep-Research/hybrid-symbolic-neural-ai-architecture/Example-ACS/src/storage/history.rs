@@ -0,0 +1,194 @@
+//! Task History
+//!
+//! Tasks executed through the coordination hub are recorded in
+//! `TASK_HISTORY_TABLE` rather than the knowledge base, so they neither show
+//! up in knowledge search nor crowd out real entries. Each record keeps the
+//! embedding of its summary; routing compares a query against every record,
+//! which stays cheap because retention caps the table (see `retention`).
+//!
+//! Older versions stored these records as knowledge entries from source
+//! `coordination_hub`. They are moved to task history once, on open.
+
+use super::local_index::cosine_similarity;
+use super::schema::EntityTable;
+use super::snapshot::for_each_row;
+use super::{
+    knowledge_point, HybridStorageCoordinator, KnowledgeEntity, OperationType, StorageError, TaskHistoryEntity,
+    VectorIntent, KNOWLEDGE_TABLE, METADATA_TABLE, TASK_HISTORY_TABLE,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Source of the task records older versions wrote to the knowledge base
+const LEGACY_TASK_SOURCE: &str = "coordination_hub";
+
+/// Metadata key set once legacy task records have been moved out of the knowledge base
+pub(crate) const TASK_HISTORY_MOVED_KEY: &str = "task_history_moved";
+
+/// Task history record with its similarity to the query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredTaskHistory {
+    pub entity: TaskHistoryEntity,
+    pub score: f32,
+}
+
+impl HybridStorageCoordinator {
+    pub(crate) async fn store_task_history(&self, entry: &TaskHistoryEntity) -> Result<(), StorageError> {
+        let embedding = match &entry.embeddings {
+            Some(embedding) => {
+                self.check_embedding(embedding)?;
+                embedding.clone()
+            }
+            None => self.generate_embedding(&entry.summary).await?,
+        };
+        let stored = TaskHistoryEntity { embeddings: Some(embedding), ..entry.clone() };
+        let entry_key = entry.id.to_string();
+        let entry_data = self.encode_row(EntityTable::TaskHistory, &stored)?;
+
+        self.execute_coordinated_transaction(OperationType::Insert, vec![], |txn| {
+            txn.insert(TASK_HISTORY_TABLE, &entry_key, &entry_data)?;
+            Ok(vec![])
+        }).await
+    }
+
+    /// Score every task history record against `query`, best first
+    pub(crate) async fn rank_task_history(&self, query: &str, limit: usize) -> Result<Vec<ScoredTaskHistory>, StorageError> {
+        let query_embedding = self.generate_embedding(query).await?;
+
+        let mut scored = Vec::new();
+        self.time_redb(|| {
+            let read_txn = self.redb.begin_read()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+            for_each_row(&read_txn, TASK_HISTORY_TABLE, |key, data| {
+                match self.decode_row::<TaskHistoryEntity>(EntityTable::TaskHistory, data) {
+                    Ok(entity) => {
+                        if let Some(embedding) = &entity.embeddings {
+                            let score = cosine_similarity(&query_embedding, embedding);
+                            scored.push(ScoredTaskHistory { entity, score });
+                        }
+                    }
+                    Err(e) => tracing::warn!("Task history row {} left out of the search: {}", key, e),
+                }
+                Ok(())
+            })
+        })?;
+
+        scored.sort_by(|a, b| {
+            b.score.partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b.entity.created_at.cmp(&a.entity.created_at))
+        });
+        scored.truncate(limit);
+        Ok(scored)
+    }
+
+    /// Move task records written by older versions from the knowledge base to task history
+    pub(crate) async fn move_task_knowledge(&self) -> Result<(), StorageError> {
+        if self.task_knowledge_moved()? {
+            return Ok(());
+        }
+
+        let mut legacy = Vec::new();
+        {
+            let read_txn = self.redb.begin_read()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+            for_each_row(&read_txn, KNOWLEDGE_TABLE, |_, data| {
+                if let Ok(knowledge) = self.decode_row::<KnowledgeEntity>(EntityTable::Knowledge, data) {
+                    if let Some(entry) = legacy_task_entry(&knowledge) {
+                        legacy.push((knowledge, entry));
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        let active = self.knowledge_collection().await;
+        let collection = active.clone();
+
+        for chunk in legacy.chunks(self.config.sync_batch_size.max(1)) {
+            let mut rows = Vec::with_capacity(chunk.len());
+            let mut restored = Vec::with_capacity(chunk.len());
+            for (knowledge, entry) in chunk {
+                rows.push((knowledge.id.to_string(), entry.id.to_string(), self.encode_row(EntityTable::TaskHistory, entry)?));
+                if let Some(embedding) = &knowledge.embeddings {
+                    restored.push(knowledge_point(knowledge, embedding.clone()));
+                }
+            }
+            let ids = chunk.iter().map(|(knowledge, _)| knowledge.id).collect();
+
+            self.execute_coordinated_transaction(
+                OperationType::Batch,
+                vec![VectorIntent::Delete { collection: collection.clone(), ids }],
+                |txn| {
+                    for (knowledge_key, entry_key, entry_data) in &rows {
+                        txn.remove(KNOWLEDGE_TABLE, knowledge_key)?;
+                        super::unindex_derived(txn.transaction(), knowledge_key)?;
+                        txn.insert(TASK_HISTORY_TABLE, entry_key, entry_data)?;
+                    }
+                    Ok(if restored.is_empty() {
+                        vec![]
+                    } else {
+                        vec![VectorIntent::Upsert { collection: collection.clone(), points: restored }]
+                    })
+                },
+            ).await?;
+        }
+        drop(active);
+
+        if !legacy.is_empty() {
+            tracing::info!("Moved {} task records from the knowledge base to task history", legacy.len());
+        }
+        self.mark_task_knowledge_moved()
+    }
+
+    fn task_knowledge_moved(&self) -> Result<bool, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => Ok(table.get(TASK_HISTORY_MOVED_KEY)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read task history marker: {}", e)))?
+                .is_some()),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(false),
+            Err(e) => Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        }
+    }
+
+    fn mark_task_knowledge_moved(&self) -> Result<(), StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let mut table = write_txn.open_table(METADATA_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+            table.insert(TASK_HISTORY_MOVED_KEY, [1u8].as_slice())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to write task history marker: {}", e)))?;
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit task history marker: {}", e)))
+    }
+}
+
+/// Task history record for a knowledge entry written by an older coordination hub
+fn legacy_task_entry(knowledge: &KnowledgeEntity) -> Option<TaskHistoryEntity> {
+    if knowledge.source != LEGACY_TASK_SOURCE {
+        return None;
+    }
+    let uuid = |field: &str| knowledge.metadata.get(field)?.as_str().and_then(|id| Uuid::parse_str(id).ok());
+    let text = |field: &str| knowledge.metadata.get(field).map(|value| match value.as_str() {
+        Some(text) => text.to_string(),
+        None => value.to_string(),
+    });
+
+    Some(TaskHistoryEntity {
+        id: knowledge.id,
+        session_id: Uuid::nil(),
+        task_id: uuid("task_id")?,
+        agent_id: uuid("agent_id")?,
+        action_type: text("action_type").unwrap_or_default(),
+        status: text("status").unwrap_or_default(),
+        summary: knowledge.content.clone(),
+        execution_time_ms: knowledge.metadata.get("execution_time_ms").and_then(|value| value.as_u64()).unwrap_or(0),
+        embeddings: knowledge.embeddings.clone(),
+        created_at: knowledge.created_at,
+    })
+}
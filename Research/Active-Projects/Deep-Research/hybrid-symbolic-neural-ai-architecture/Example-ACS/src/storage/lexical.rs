@@ -0,0 +1,349 @@
+//! Lexical Index - BM25 over REDB
+//!
+//! Inverted index over knowledge entries, kept in the same REDB transaction as
+//! `KNOWLEDGE_TABLE`, so exact terms such as crate names, standard IDs
+//! ("PRISMA 2020") or credibility ratings ("A1") can be found even when vector
+//! similarity misses them. The index is derived from the knowledge rows: a
+//! rollback restores the rows and then re-derives their index entries.
+//!
+//! Hybrid search fuses the BM25 and vector rankings, either with reciprocal
+//! rank fusion or with a weighted sum of normalized scores.
+//!
+//! When rows are encrypted, terms are stored as keyed hashes and queries are
+//! hashed the same way, so the index matches exact terms without holding them.
+//!
+//! Writes maintain it through `index_derived` and `unindex_derived` in the
+//! storage module, together with the content hash, chunk and tag indexes.
+
+use super::encryption::RowCipher;
+use super::{HybridStorageCoordinator, KnowledgeEntity, StorageError, METADATA_TABLE};
+use redb::{ReadableTable, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Postings keyed by `term \0 knowledge_id`; value is term frequency and document length (u32 LE each)
+pub(crate) const LEXICAL_POSTINGS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("lexical_postings");
+
+/// Terms indexed for each knowledge entry, needed to remove its postings
+pub(crate) const LEXICAL_DOCUMENTS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("lexical_documents");
+
+/// Metadata key holding the corpus statistics used for length normalization
+pub(crate) const LEXICAL_STATS_KEY: &str = "lexical_stats";
+
+/// BM25 term-frequency saturation and length normalization parameters
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+/// How to combine the lexical and vector rankings
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum FusionStrategy {
+    /// Sum of `1 / (k + rank)` over both rankings; robust to incomparable scores
+    ReciprocalRank { k: f32 },
+    /// Weighted sum of scores, each normalized by the best score in its ranking
+    Weighted { lexical: f32, vector: f32 },
+}
+
+impl Default for FusionStrategy {
+    fn default() -> Self {
+        FusionStrategy::ReciprocalRank { k: 60.0 }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LexicalDocument {
+    length: u32,
+    terms: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LexicalStats {
+    documents: u64,
+    total_length: u64,
+}
+
+/// Lowercased alphanumeric tokens
+pub(crate) fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+}
+
+/// Fields of a knowledge entry that are searchable by exact term
+fn indexed_text(knowledge: &KnowledgeEntity) -> String {
+    let mut text = format!("{} {} {}", knowledge.content, knowledge.source, knowledge.credibility_rating);
+    for value in knowledge.title.iter().chain(&knowledge.tags) {
+        text.push(' ');
+        text.push_str(value);
+    }
+    for value in knowledge.metadata.values() {
+        if let Some(value) = value.as_str() {
+            text.push(' ');
+            text.push_str(value);
+        }
+    }
+    text
+}
+
+/// Form a term takes in the index, hashed under the row key when encryption is enabled
+fn stored_term(cipher: Option<&RowCipher>, term: String) -> String {
+    match cipher {
+        Some(cipher) => cipher.blind_term(&term),
+        None => term,
+    }
+}
+
+fn posting_key(term: &str, id: &str) -> String {
+    format!("{}\0{}", term, id)
+}
+
+/// Index a knowledge entry, replacing whatever was indexed under its ID
+pub(crate) fn index_knowledge(
+    write_txn: &WriteTransaction,
+    knowledge: &KnowledgeEntity,
+    cipher: Option<&RowCipher>,
+) -> Result<(), StorageError> {
+    let id = knowledge.id.to_string();
+    unindex_knowledge(write_txn, &id)?;
+
+    let mut frequencies: HashMap<String, u32> = HashMap::new();
+    for token in tokenize(&indexed_text(knowledge)) {
+        *frequencies.entry(stored_term(cipher, token)).or_default() += 1;
+    }
+    let length: u32 = frequencies.values().sum();
+
+    {
+        let mut postings = write_txn.open_table(LEXICAL_POSTINGS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open lexical postings: {}", e)))?;
+        for (term, frequency) in &frequencies {
+            let mut value = [0u8; 8];
+            value[..4].copy_from_slice(&frequency.to_le_bytes());
+            value[4..].copy_from_slice(&length.to_le_bytes());
+            postings.insert(posting_key(term, &id).as_str(), value.as_slice())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to write lexical posting: {}", e)))?;
+        }
+    }
+
+    let document = LexicalDocument { length, terms: frequencies.into_keys().collect() };
+    let data = bincode::serialize(&document)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to serialize lexical document: {}", e)))?;
+    {
+        let mut documents = write_txn.open_table(LEXICAL_DOCUMENTS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open lexical documents: {}", e)))?;
+        documents.insert(id.as_str(), data.as_slice())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to write lexical document: {}", e)))?;
+    }
+
+    update_stats(write_txn, 1, length as i64)
+}
+
+/// Remove a knowledge entry from the index; unknown IDs are ignored
+pub(crate) fn unindex_knowledge(write_txn: &WriteTransaction, id: &str) -> Result<(), StorageError> {
+    let removed = {
+        let mut documents = write_txn.open_table(LEXICAL_DOCUMENTS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open lexical documents: {}", e)))?;
+        let removed = documents.remove(id)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove lexical document: {}", e)))?;
+        removed.map(|data| data.value().to_vec())
+    };
+
+    let document: LexicalDocument = match removed {
+        Some(data) => bincode::deserialize(&data)
+            .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize lexical document: {}", e)))?,
+        None => return Ok(()),
+    };
+
+    {
+        let mut postings = write_txn.open_table(LEXICAL_POSTINGS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open lexical postings: {}", e)))?;
+        for term in &document.terms {
+            postings.remove(posting_key(term, id).as_str())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to remove lexical posting: {}", e)))?;
+        }
+    }
+
+    update_stats(write_txn, -1, -(document.length as i64))
+}
+
+fn update_stats(write_txn: &WriteTransaction, documents: i64, length: i64) -> Result<(), StorageError> {
+    let mut table = write_txn.open_table(METADATA_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+
+    let mut stats: LexicalStats = match table.get(LEXICAL_STATS_KEY)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to read lexical stats: {}", e)))?
+    {
+        Some(data) => bincode::deserialize(data.value())
+            .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize lexical stats: {}", e)))?,
+        None => LexicalStats::default(),
+    };
+
+    stats.documents = stats.documents.saturating_add_signed(documents);
+    stats.total_length = stats.total_length.saturating_add_signed(length);
+
+    let data = bincode::serialize(&stats)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to serialize lexical stats: {}", e)))?;
+    table.insert(LEXICAL_STATS_KEY, data.as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write lexical stats: {}", e)))?;
+
+    Ok(())
+}
+
+impl HybridStorageCoordinator {
+    /// Rank knowledge entries by BM25 against `query`, best first
+    pub fn search_lexical(&self, query: &str, limit: usize) -> Result<Vec<(Uuid, f32)>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let stats: LexicalStats = match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => match table.get(LEXICAL_STATS_KEY)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read lexical stats: {}", e)))?
+            {
+                Some(data) => bincode::deserialize(data.value())
+                    .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize lexical stats: {}", e)))?,
+                None => return Ok(Vec::new()),
+            },
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        };
+        if stats.documents == 0 {
+            return Ok(Vec::new());
+        }
+
+        let postings = match read_txn.open_table(LEXICAL_POSTINGS_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open lexical postings: {}", e))),
+        };
+
+        let documents = stats.documents as f32;
+        let average_length = (stats.total_length as f32 / documents).max(1.0);
+
+        let mut terms: Vec<String> = tokenize(query).map(|term| stored_term(self.cipher(), term)).collect();
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: HashMap<Uuid, f32> = HashMap::new();
+        for term in &terms {
+            // All postings for a term sort between "term\0" and "term\x01"
+            let start = format!("{}\0", term);
+            let end = format!("{}\u{1}", term);
+
+            let mut matches = Vec::new();
+            for row in postings.range(start.as_str()..end.as_str())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read lexical postings: {}", e)))?
+            {
+                let (key, value) = row
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to read lexical postings: {}", e)))?;
+                let id = Uuid::parse_str(&key.value()[start.len()..])
+                    .map_err(|e| StorageError::SerializationError(format!("Invalid lexical posting: {}", e)))?;
+                let value = value.value();
+                let frequency = u32::from_le_bytes([value[0], value[1], value[2], value[3]]) as f32;
+                let length = u32::from_le_bytes([value[4], value[5], value[6], value[7]]) as f32;
+                matches.push((id, frequency, length));
+            }
+
+            let document_frequency = matches.len() as f32;
+            let idf = (1.0 + (documents - document_frequency + 0.5) / (document_frequency + 0.5)).ln();
+
+            for (id, frequency, length) in matches {
+                let normalization = BM25_K1 * (1.0 - BM25_B + BM25_B * length / average_length);
+                *scores.entry(id).or_default() += idf * frequency * (BM25_K1 + 1.0) / (frequency + normalization);
+            }
+        }
+
+        let mut ranked: Vec<(Uuid, f32)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        ranked.truncate(limit);
+
+        Ok(ranked)
+    }
+
+    /// Rebuild the lexical index, and the indexes maintained with it, from `KNOWLEDGE_TABLE`
+    ///
+    /// Returns the number of knowledge rows indexed.
+    pub fn rebuild_lexical_index(&self) -> Result<usize, StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+
+        let indexed = super::rebuild_derived(&write_txn, self.cipher())?;
+
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit lexical index rebuild: {}", e)))?;
+
+        Ok(indexed)
+    }
+
+    /// Whether the lexical index has been built for this database
+    pub(crate) fn lexical_index_exists(&self) -> Result<bool, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => Ok(table.get(LEXICAL_STATS_KEY)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read lexical stats: {}", e)))?
+                .is_some()),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(false),
+            Err(e) => Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        }
+    }
+}
+
+/// Empty the lexical index and reset its statistics
+pub(crate) fn clear_index(write_txn: &WriteTransaction) -> Result<(), StorageError> {
+    write_txn.delete_table(LEXICAL_POSTINGS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to clear lexical postings: {}", e)))?;
+    write_txn.delete_table(LEXICAL_DOCUMENTS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to clear lexical documents: {}", e)))?;
+
+    let mut metadata = write_txn.open_table(METADATA_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+    let stats = bincode::serialize(&LexicalStats::default())
+        .map_err(|e| StorageError::SerializationError(format!("Failed to serialize lexical stats: {}", e)))?;
+    metadata.insert(LEXICAL_STATS_KEY, stats.as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to reset lexical stats: {}", e)))?;
+    Ok(())
+}
+
+/// Fuse two rankings (best first) into one, returning at most `limit` IDs with fused scores
+pub(crate) fn fuse_rankings(
+    lexical: &[(Uuid, f32)],
+    vector: &[(Uuid, f32)],
+    fusion: FusionStrategy,
+    limit: usize,
+) -> Vec<(Uuid, f32)> {
+    let mut fused: HashMap<Uuid, f32> = HashMap::new();
+
+    match fusion {
+        FusionStrategy::ReciprocalRank { k } => {
+            for ranking in [lexical, vector] {
+                for (rank, (id, _)) in ranking.iter().enumerate() {
+                    *fused.entry(*id).or_default() += 1.0 / (k + rank as f32 + 1.0);
+                }
+            }
+        }
+        FusionStrategy::Weighted { lexical: lexical_weight, vector: vector_weight } => {
+            for (ranking, weight) in [(lexical, lexical_weight), (vector, vector_weight)] {
+                let best = ranking.iter().map(|(_, score)| *score).fold(0.0f32, f32::max);
+                if best <= 0.0 {
+                    continue;
+                }
+                for (id, score) in ranking {
+                    *fused.entry(*id).or_default() += weight * score.max(0.0) / best;
+                }
+            }
+        }
+    }
+
+    let mut ranked: Vec<(Uuid, f32)> = fused.into_iter().collect();
+    ranked.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    ranked.truncate(limit);
+    ranked
+}
@@ -0,0 +1,392 @@
+//! In-Process Vector Index
+//!
+//! Pure-Rust `VectorIndex` for laptops, CI and offline build boxes where no
+//! Qdrant server is available. Points are kept in memory for brute-force cosine
+//! search and persisted to a dedicated REDB file next to the main database,
+//! one table per collection, or to REDB's in-memory backend for storage that
+//! should leave nothing behind.
+//!
+//! REDB commits and brute-force scans block, so every call runs on tokio's
+//! blocking pool; the in-memory points sit behind a std lock that is never
+//! held across an await.
+
+use super::vector_index::{VectorIndex, VectorMatch, VectorPoint};
+use super::StorageError;
+use async_trait::async_trait;
+use redb::backends::InMemoryBackend;
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use uuid::Uuid;
+
+/// Registry of collections and their vector dimension
+const COLLECTIONS_TABLE: TableDefinition<&str, u64> = TableDefinition::new("collections");
+
+/// In-process vector index persisted in its own REDB file
+pub struct LocalVectorIndex {
+    inner: Arc<IndexState>,
+}
+
+/// Index file and loaded points, shared with the blocking tasks that use them
+struct IndexState {
+    db: Database,
+    collections: RwLock<HashMap<String, Collection>>,
+}
+
+#[derive(Debug, Default)]
+struct Collection {
+    dimension: usize,
+    points: BTreeMap<Uuid, StoredPoint>,
+}
+
+/// On-disk point representation (JSON, since payloads are `serde_json::Value`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredPoint {
+    vector: Vec<f32>,
+    payload: HashMap<String, serde_json::Value>,
+}
+
+impl LocalVectorIndex {
+    /// Open (or create) the index file and load every collection into memory
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let db = Database::create(path.as_ref())
+            .map_err(|e| StorageError::InitializationError(format!("Vector index init failed: {}", e)))?;
+        Self::load(db)
+    }
+
+    /// Create an empty index that is never written to disk
+    pub fn in_memory() -> Result<Self, StorageError> {
+        let db = Database::builder()
+            .create_with_backend(InMemoryBackend::new())
+            .map_err(|e| StorageError::InitializationError(format!("Vector index init failed: {}", e)))?;
+        Self::load(db)
+    }
+
+    fn load(db: Database) -> Result<Self, StorageError> {
+        let mut collections = HashMap::new();
+
+        let read_txn = db.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        // A fresh file has no registry table yet
+        if let Ok(registry) = read_txn.open_table(COLLECTIONS_TABLE) {
+            let entries = registry.iter()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read collection registry: {}", e)))?;
+
+            for entry in entries {
+                let (name, dimension) = entry
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to read collection registry: {}", e)))?;
+                let name = name.value().to_string();
+
+                let mut collection = Collection {
+                    dimension: dimension.value() as usize,
+                    points: BTreeMap::new(),
+                };
+
+                let table_name = points_table_name(&name);
+                let table = read_txn.open_table(points_table(&table_name))
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to open collection {}: {}", name, e)))?;
+
+                for point in table.iter()
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to read collection {}: {}", name, e)))?
+                {
+                    let (id, data) = point
+                        .map_err(|e| StorageError::TransactionError(format!("Failed to read collection {}: {}", name, e)))?;
+                    let id = Uuid::parse_str(id.value())
+                        .map_err(|e| StorageError::SerializationError(format!("Invalid point ID: {}", e)))?;
+                    let stored: StoredPoint = serde_json::from_slice(data.value())
+                        .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize point: {}", e)))?;
+                    collection.points.insert(id, stored);
+                }
+
+                collections.insert(name, collection);
+            }
+        }
+
+        Ok(Self {
+            inner: Arc::new(IndexState {
+                db,
+                collections: RwLock::new(collections),
+            }),
+        })
+    }
+
+    /// Run `work` on the blocking pool
+    async fn blocking<T, F>(&self, work: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&IndexState) -> Result<T, StorageError> + Send + 'static,
+    {
+        let state = self.inner.clone();
+        tokio::task::spawn_blocking(move || work(&state))
+            .await
+            .map_err(|e| StorageError::VectorError(format!("Vector index task failed: {}", e)))?
+    }
+}
+
+#[async_trait]
+impl VectorIndex for LocalVectorIndex {
+    async fn ensure_collection(&self, collection: &str, dimension: usize) -> Result<(), StorageError> {
+        let collection = collection.to_string();
+        self.blocking(move |state| state.ensure_collection(&collection, dimension)).await
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<(), StorageError> {
+        let collection = collection.to_string();
+        self.blocking(move |state| state.upsert(&collection, points)).await
+    }
+
+    async fn delete(&self, collection: &str, ids: &[Uuid]) -> Result<(), StorageError> {
+        let collection = collection.to_string();
+        let ids = ids.to_vec();
+        self.blocking(move |state| state.delete(&collection, &ids)).await
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<(), StorageError> {
+        let collection = collection.to_string();
+        self.blocking(move |state| state.delete_collection(&collection)).await
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        offset: Option<Uuid>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<Uuid>), StorageError> {
+        let collection = collection.to_string();
+        self.blocking(move |state| state.scroll(&collection, offset, limit)).await
+    }
+
+    async fn search(&self, collection: &str, vector: &[f32], limit: usize) -> Result<Vec<VectorMatch>, StorageError> {
+        let collection = collection.to_string();
+        let vector = vector.to_vec();
+        self.blocking(move |state| state.search(&collection, &vector, limit)).await
+    }
+}
+
+impl IndexState {
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Collection>> {
+        self.collections.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Collection>> {
+        self.collections.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn ensure_collection(&self, collection: &str, dimension: usize) -> Result<(), StorageError> {
+        let mut collections = self.write();
+
+        if let Some(existing) = collections.get(collection) {
+            if existing.dimension != dimension {
+                return Err(StorageError::ConfigurationError(format!(
+                    "Collection {} has dimension {}, expected {}",
+                    collection, existing.dimension, dimension
+                )));
+            }
+            return Ok(());
+        }
+
+        let write_txn = self.db.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let mut registry = write_txn.open_table(COLLECTIONS_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open collection registry: {}", e)))?;
+            registry.insert(collection, dimension as u64)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to register collection: {}", e)))?;
+
+            let table_name = points_table_name(collection);
+            write_txn.open_table(points_table(&table_name))
+                .map_err(|e| StorageError::TransactionError(format!("Failed to create collection table: {}", e)))?;
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit collection creation: {}", e)))?;
+
+        collections.insert(collection.to_string(), Collection {
+            dimension,
+            points: BTreeMap::new(),
+        });
+
+        Ok(())
+    }
+
+    fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<(), StorageError> {
+        let mut collections = self.write();
+        let target = collections.get_mut(collection)
+            .ok_or_else(|| StorageError::VectorError(format!("Unknown collection: {}", collection)))?;
+
+        if let Some(point) = points.iter().find(|p| p.vector.len() != target.dimension) {
+            return Err(StorageError::VectorError(format!(
+                "Point {} has dimension {}, collection {} expects {}",
+                point.id, point.vector.len(), collection, target.dimension
+            )));
+        }
+
+        let write_txn = self.db.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let table_name = points_table_name(collection);
+            let mut table = write_txn.open_table(points_table(&table_name))
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open collection table: {}", e)))?;
+
+            for point in &points {
+                let data = serde_json::to_vec(&StoredPoint {
+                    vector: point.vector.clone(),
+                    payload: point.payload.clone(),
+                })
+                .map_err(|e| StorageError::SerializationError(format!("Failed to serialize point: {}", e)))?;
+
+                table.insert(point.id.to_string().as_str(), data.as_slice())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to insert point: {}", e)))?;
+            }
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit points: {}", e)))?;
+
+        for point in points {
+            target.points.insert(point.id, StoredPoint {
+                vector: point.vector,
+                payload: point.payload,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self, collection: &str, ids: &[Uuid]) -> Result<(), StorageError> {
+        let mut collections = self.write();
+        let target = collections.get_mut(collection)
+            .ok_or_else(|| StorageError::VectorError(format!("Unknown collection: {}", collection)))?;
+
+        let write_txn = self.db.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let table_name = points_table_name(collection);
+            let mut table = write_txn.open_table(points_table(&table_name))
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open collection table: {}", e)))?;
+
+            for id in ids {
+                table.remove(id.to_string().as_str())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to remove point: {}", e)))?;
+            }
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit point removal: {}", e)))?;
+
+        for id in ids {
+            target.points.remove(id);
+        }
+
+        Ok(())
+    }
+
+    fn delete_collection(&self, collection: &str) -> Result<(), StorageError> {
+        let mut collections = self.write();
+        if !collections.contains_key(collection) {
+            return Ok(());
+        }
+
+        let write_txn = self.db.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let mut registry = write_txn.open_table(COLLECTIONS_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open collection registry: {}", e)))?;
+            registry.remove(collection)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to unregister collection: {}", e)))?;
+
+            let table_name = points_table_name(collection);
+            write_txn.delete_table(points_table(&table_name))
+                .map_err(|e| StorageError::TransactionError(format!("Failed to delete collection table: {}", e)))?;
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit collection removal: {}", e)))?;
+
+        collections.remove(collection);
+
+        Ok(())
+    }
+
+    fn scroll(
+        &self,
+        collection: &str,
+        offset: Option<Uuid>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<Uuid>), StorageError> {
+        let collections = self.read();
+        let target = collections.get(collection)
+            .ok_or_else(|| StorageError::VectorError(format!("Unknown collection: {}", collection)))?;
+
+        let mut page = target.points.range(offset.unwrap_or(Uuid::nil())..);
+        let points = page.by_ref()
+            .take(limit)
+            .map(|(id, point)| VectorPoint {
+                id: *id,
+                vector: point.vector.clone(),
+                payload: point.payload.clone(),
+            })
+            .collect();
+
+        Ok((points, page.next().map(|(id, _)| *id)))
+    }
+
+    fn search(&self, collection: &str, vector: &[f32], limit: usize) -> Result<Vec<VectorMatch>, StorageError> {
+        let collections = self.read();
+        let target = collections.get(collection)
+            .ok_or_else(|| StorageError::VectorError(format!("Unknown collection: {}", collection)))?;
+
+        if vector.len() != target.dimension {
+            return Err(StorageError::VectorError(format!(
+                "Query has dimension {}, collection {} expects {}",
+                vector.len(), collection, target.dimension
+            )));
+        }
+
+        // Rank by score first; payloads are only cloned for the points returned
+        let mut scored: Vec<(f32, &Uuid)> = target.points.iter()
+            .map(|(id, point)| (cosine_similarity(vector, &point.vector), id))
+            .collect();
+
+        // Highest score first; ties broken by ID so results are deterministic
+        let ranking = |a: &(f32, &Uuid), b: &(f32, &Uuid)| {
+            b.0.partial_cmp(&a.0)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.1.cmp(b.1))
+        };
+        if limit < scored.len() {
+            scored.select_nth_unstable_by(limit, ranking);
+            scored.truncate(limit);
+        }
+        scored.sort_by(ranking);
+
+        let matches = scored.into_iter()
+            .map(|(score, id)| VectorMatch {
+                id: *id,
+                score,
+                payload: target.points[id].payload.clone(),
+            })
+            .collect();
+
+        Ok(matches)
+    }
+}
+
+fn points_table_name(collection: &str) -> String {
+    format!("points::{}", collection)
+}
+
+fn points_table(table_name: &str) -> TableDefinition<'_, &'static str, &'static [u8]> {
+    TableDefinition::new(table_name)
+}
+
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
@@ -0,0 +1,29 @@
+//! In-Memory Storage
+//!
+//! A `HybridStorageCoordinator` over REDB's in-memory backend and an
+//! in-process vector index that is never written to disk. It is the same
+//! coordinator, so journaling, deduplication, indexes, retention and the
+//! consistency modes behave exactly as with file-backed storage; everything
+//! is dropped with the last clone. Meant for tests and for embedding the
+//! framework in processes that keep no state of their own.
+
+use super::local_index::LocalVectorIndex;
+use super::{HybridStorageCoordinator, StorageConfig, StorageError};
+use redb::backends::InMemoryBackend;
+use redb::Database;
+use std::sync::Arc;
+
+impl HybridStorageCoordinator {
+    /// Initialize storage that lives only in memory
+    ///
+    /// `redb_path`, `qdrant_url` and `vector_backend` are ignored; the
+    /// embedding backend, encryption and every other setting apply as usual.
+    pub async fn in_memory(config: StorageConfig) -> Result<Self, StorageError> {
+        let redb = Database::builder()
+            .create_with_backend(InMemoryBackend::new())
+            .map_err(|e| StorageError::InitializationError(format!("REDB init failed: {}", e)))?;
+        let vectors = Arc::new(LocalVectorIndex::in_memory()?);
+
+        Self::with_database(config, redb, vectors).await
+    }
+}
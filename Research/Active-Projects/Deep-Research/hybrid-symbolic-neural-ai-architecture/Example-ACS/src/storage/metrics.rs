@@ -0,0 +1,383 @@
+//! Storage Metrics
+//!
+//! Every `HybridStorage` operation is timed end to end, and the time it spends
+//! in each backend is recorded separately: REDB transactions and vector index
+//! calls. The operation in progress is carried in a task-local, so backend
+//! calls made deep inside the coordinator are attributed to the operation that
+//! issued them; calls made outside any operation (the sync worker, recovery,
+//! maintenance such as reindexing) are recorded under `background`.
+//!
+//! REDB only reports page statistics to write transactions, so the space
+//! figures in `StorageUsage` are sampled when the database is opened and after
+//! each retention pass rather than on every read of the metrics.
+
+use super::chunking::CHUNK_KEYS_TABLE;
+use super::vector_index::{VectorIndex, VectorMatch, VectorPoint};
+use super::{HybridStorageCoordinator, StorageError, KNOWLEDGE_TABLE};
+use async_trait::async_trait;
+use redb::{ReadTransaction, ReadableTableMetadata, TableDefinition, TableHandle};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Upper bounds of the latency histogram buckets, in milliseconds; a final bucket holds slower samples
+pub const LATENCY_BUCKETS_MS: [f64; 12] = [0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 5000.0];
+
+/// Operation name for backend calls made outside any `HybridStorage` operation
+pub const BACKGROUND_OPERATION: &str = "background";
+
+tokio::task_local! {
+    static CURRENT_OPERATION: &'static str;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Backend {
+    Redb,
+    Vector,
+}
+
+/// Latency distribution with success and error counts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    /// Samples recorded, errors included
+    pub count: u64,
+    pub errors: u64,
+    pub sum_ms: f64,
+    pub max_ms: f64,
+    /// Samples per bucket of `LATENCY_BUCKETS_MS`, plus one overflow bucket
+    pub buckets: Vec<u64>,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            errors: 0,
+            sum_ms: 0.0,
+            max_ms: 0.0,
+            buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, elapsed: Duration, success: bool) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let bucket = LATENCY_BUCKETS_MS.iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+
+        self.count += 1;
+        if !success {
+            self.errors += 1;
+        }
+        self.sum_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn mean_ms(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum_ms / self.count as f64
+        }
+    }
+
+    /// Upper bound of the bucket holding the `quantile` sample (e.g. 0.99), capped at the slowest sample
+    pub fn percentile_ms(&self, quantile: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+
+        let rank = (quantile.clamp(0.0, 1.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, samples) in self.buckets.iter().enumerate() {
+            seen += samples;
+            if seen >= rank {
+                return LATENCY_BUCKETS_MS.get(bucket).map_or(self.max_ms, |bound| bound.min(self.max_ms));
+            }
+        }
+        self.max_ms
+    }
+}
+
+/// Metrics of one `HybridStorage` operation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OperationMetrics {
+    /// End-to-end latency of each call
+    pub latency: LatencyHistogram,
+    /// REDB transactions made by the operation
+    pub redb: LatencyHistogram,
+    /// Vector index calls made by the operation
+    pub vector: LatencyHistogram,
+}
+
+/// Knowledge base size when metrics were read, and REDB space when it was last sampled
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageUsage {
+    /// Top-level knowledge entries; document chunks are counted separately
+    pub knowledge_entries: u64,
+    pub knowledge_chunks: u64,
+    /// When the space figures below were sampled
+    pub space_sampled_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub allocated_bytes: u64,
+    /// Keys and values stored, excluding index overhead
+    pub stored_bytes: u64,
+    /// Allocated but unused space, including free pages in the file
+    pub fragmented_bytes: u64,
+}
+
+impl StorageUsage {
+    /// Fraction of the allocated REDB pages holding data
+    pub fn utilization(&self) -> f64 {
+        if self.allocated_bytes == 0 {
+            0.0
+        } else {
+            self.stored_bytes as f64 / self.allocated_bytes as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageMetrics {
+    /// Keyed by operation name, e.g. `store_knowledge`, plus `background`
+    pub operations: BTreeMap<String, OperationMetrics>,
+    pub usage: StorageUsage,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub last_updated: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl StorageMetrics {
+    /// Operations completed, successfully or not, excluding background work
+    pub fn operations_total(&self) -> u64 {
+        self.foreground().map(|metrics| metrics.latency.count).sum()
+    }
+
+    pub fn errors_total(&self) -> u64 {
+        self.foreground().map(|metrics| metrics.latency.errors).sum()
+    }
+
+    /// Operations per second since the coordinator started
+    pub fn throughput(&self) -> f64 {
+        let elapsed = (chrono::Utc::now() - self.started_at).num_milliseconds() as f64 / 1000.0;
+        if elapsed <= 0.0 {
+            0.0
+        } else {
+            self.operations_total() as f64 / elapsed
+        }
+    }
+
+    fn foreground(&self) -> impl Iterator<Item = &OperationMetrics> {
+        self.operations.iter()
+            .filter(|(name, _)| name.as_str() != BACKGROUND_OPERATION)
+            .map(|(_, metrics)| metrics)
+    }
+}
+
+/// Shared, lock-protected metrics; recording never awaits
+#[derive(Debug)]
+pub(crate) struct MetricsRegistry {
+    started_at: chrono::DateTime<chrono::Utc>,
+    state: Mutex<RegistryState>,
+}
+
+#[derive(Debug, Default)]
+struct RegistryState {
+    operations: BTreeMap<String, OperationMetrics>,
+    last_updated: Option<chrono::DateTime<chrono::Utc>>,
+    space: SpaceSample,
+}
+
+/// REDB page statistics from the last sample
+#[derive(Debug, Clone, Copy, Default)]
+struct SpaceSample {
+    sampled_at: Option<chrono::DateTime<chrono::Utc>>,
+    allocated_bytes: u64,
+    stored_bytes: u64,
+    fragmented_bytes: u64,
+}
+
+impl Default for MetricsRegistry {
+    fn default() -> Self {
+        Self {
+            started_at: chrono::Utc::now(),
+            state: Mutex::new(RegistryState::default()),
+        }
+    }
+}
+
+impl MetricsRegistry {
+    fn record_operation(&self, operation: &str, elapsed: Duration, success: bool) {
+        self.update(operation, |metrics| metrics.latency.record(elapsed, success));
+    }
+
+    pub(crate) fn record_backend(&self, backend: Backend, elapsed: Duration, success: bool) {
+        let operation = CURRENT_OPERATION.try_with(|operation| *operation).unwrap_or(BACKGROUND_OPERATION);
+        self.update(operation, |metrics| match backend {
+            Backend::Redb => metrics.redb.record(elapsed, success),
+            Backend::Vector => metrics.vector.record(elapsed, success),
+        });
+    }
+
+    fn update(&self, operation: &str, record: impl FnOnce(&mut OperationMetrics)) {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        record(state.operations.entry(operation.to_string()).or_default());
+        state.last_updated = Some(chrono::Utc::now());
+    }
+
+    fn record_space(&self, space: SpaceSample) {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).space = space;
+    }
+
+    /// Metrics with the given entry counts and the last space sample
+    fn snapshot(&self, knowledge_entries: u64, knowledge_chunks: u64) -> StorageMetrics {
+        let state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        StorageMetrics {
+            operations: state.operations.clone(),
+            usage: StorageUsage {
+                knowledge_entries,
+                knowledge_chunks,
+                space_sampled_at: state.space.sampled_at,
+                allocated_bytes: state.space.allocated_bytes,
+                stored_bytes: state.space.stored_bytes,
+                fragmented_bytes: state.space.fragmented_bytes,
+            },
+            started_at: self.started_at,
+            last_updated: state.last_updated,
+        }
+    }
+}
+
+/// Vector index decorator recording the latency of every call
+pub(crate) struct InstrumentedIndex {
+    inner: Arc<dyn VectorIndex>,
+    metrics: Arc<MetricsRegistry>,
+}
+
+impl InstrumentedIndex {
+    pub(crate) fn new(inner: Arc<dyn VectorIndex>, metrics: Arc<MetricsRegistry>) -> Self {
+        Self { inner, metrics }
+    }
+
+    async fn timed<T>(&self, call: impl Future<Output = Result<T, StorageError>>) -> Result<T, StorageError> {
+        let start = Instant::now();
+        let result = call.await;
+        self.metrics.record_backend(Backend::Vector, start.elapsed(), result.is_ok());
+        result
+    }
+}
+
+#[async_trait]
+impl VectorIndex for InstrumentedIndex {
+    async fn ensure_collection(&self, collection: &str, dimension: usize) -> Result<(), StorageError> {
+        self.timed(self.inner.ensure_collection(collection, dimension)).await
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<(), StorageError> {
+        self.timed(self.inner.upsert(collection, points)).await
+    }
+
+    async fn delete(&self, collection: &str, ids: &[Uuid]) -> Result<(), StorageError> {
+        self.timed(self.inner.delete(collection, ids)).await
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<(), StorageError> {
+        self.timed(self.inner.delete_collection(collection)).await
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        offset: Option<Uuid>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<Uuid>), StorageError> {
+        self.timed(self.inner.scroll(collection, offset, limit)).await
+    }
+
+    async fn search(&self, collection: &str, vector: &[f32], limit: usize) -> Result<Vec<VectorMatch>, StorageError> {
+        self.timed(self.inner.search(collection, vector, limit)).await
+    }
+}
+
+impl HybridStorageCoordinator {
+    /// Run a `HybridStorage` operation under `name`, timing it and attributing its backend calls to it
+    ///
+    /// Operations called from within another operation count towards the outer one.
+    pub(crate) async fn instrument<T>(
+        &self,
+        name: &'static str,
+        operation: impl Future<Output = Result<T, StorageError>>,
+    ) -> Result<T, StorageError> {
+        if CURRENT_OPERATION.try_with(|_| ()).is_ok() {
+            return operation.await;
+        }
+
+        let start = Instant::now();
+        let result = CURRENT_OPERATION.scope(name, operation).await;
+        self.metrics.record_operation(name, start.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Time REDB work for the current operation
+    pub(crate) fn time_redb<T>(&self, work: impl FnOnce() -> Result<T, StorageError>) -> Result<T, StorageError> {
+        let start = Instant::now();
+        let result = work();
+        self.metrics.record_backend(Backend::Redb, start.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Current metrics; entry counts are left at zero if they cannot be read
+    pub(crate) fn metrics_snapshot(&self, counts: Result<(u64, u64), StorageError>) -> StorageMetrics {
+        let (knowledge_entries, knowledge_chunks) = counts.unwrap_or_else(|e| {
+            tracing::warn!("Failed to count knowledge entries: {}", e);
+            (0, 0)
+        });
+        self.metrics.snapshot(knowledge_entries, knowledge_chunks)
+    }
+
+    /// Top-level knowledge entries and document chunks
+    pub(crate) fn knowledge_counts(&self) -> Result<(u64, u64), StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+        let rows = table_len(&read_txn, KNOWLEDGE_TABLE)?;
+        let chunks = table_len(&read_txn, CHUNK_KEYS_TABLE)?;
+        Ok((rows.saturating_sub(chunks), chunks))
+    }
+
+    /// Sample REDB page statistics for `StorageUsage`
+    ///
+    /// Statistics are only available to write transactions, so this waits for
+    /// any writer; the transaction is never committed.
+    pub(crate) fn sample_space_usage(&self) -> Result<(), StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        let stats = write_txn.stats()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read database statistics: {}", e)))?;
+        write_txn.abort()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to abort statistics transaction: {}", e)))?;
+
+        self.metrics.record_space(SpaceSample {
+            sampled_at: Some(chrono::Utc::now()),
+            allocated_bytes: stats.allocated_pages() * stats.page_size() as u64,
+            stored_bytes: stats.stored_bytes(),
+            fragmented_bytes: stats.fragmented_bytes(),
+        });
+        Ok(())
+    }
+}
+
+fn table_len(
+    read_txn: &ReadTransaction,
+    table: TableDefinition<&str, &[u8]>,
+) -> Result<u64, StorageError> {
+    match read_txn.open_table(table) {
+        Ok(handle) => handle.len()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read {} table: {}", table.name(), e))),
+        Err(redb::TableError::TableDoesNotExist(_)) => Ok(0),
+        Err(e) => Err(StorageError::TransactionError(format!("Failed to open {} table: {}", table.name(), e))),
+    }
+}
@@ -2,35 +2,114 @@
 //!
 //! This module demonstrates the hybrid storage architecture identified in the research:
 //! - REDB for structured state management and agent coordination
-//! - Qdrant for semantic embeddings and similarity search
+//! - Qdrant (or the in-process index) for semantic embeddings and similarity search
 //! - Coordinated access patterns with shared entity management
 
 use async_trait::async_trait;
-use qdrant_client::{client::QdrantClient, qdrant::*};
-use redb::{Database, ReadableTable, WriteTransaction, ReadTransaction, TableDefinition};
+use redb::{Database, ReadableTable, TableDefinition};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use schema::EntityTable;
 use uuid::Uuid;
 
+pub mod chunking;
 pub mod coordination;
-pub mod redb_integration;
+pub mod coordination_index;
+pub mod dedup;
+pub mod embedding;
+pub mod encryption;
+pub mod history;
+pub mod lexical;
+pub mod metrics;
+pub mod outbox;
+pub mod query;
+pub mod reindex;
+pub mod relations;
+pub mod retention;
+pub mod revisions;
+pub mod schema;
+pub mod search;
+pub mod snapshot;
+pub mod sync;
+pub mod tags;
+pub mod vector_index;
+pub mod verify;
+pub mod local_index;
+pub mod memory;
+#[cfg(feature = "qdrant")]
 pub mod qdrant_integration;
-
-/// Hybrid storage coordinator managing both REDB and Qdrant
+#[cfg(feature = "candle")]
+pub mod candle_embedding;
+
+pub use chunking::{
+    ChunkStrategy, ChunkingConfig, DocumentMatch, DocumentPassage, DocumentReport, DocumentSearch,
+    SECTION_METADATA_KEY,
+};
+pub use coordination::{RecoveryReport, VectorIntent};
+pub use coordination_index::TimeRange;
+pub use dedup::{DeduplicationConfig, DuplicateAction, DuplicateMatch, IngestOutcome, IngestReport};
+pub use embedding::{EmbeddingProvider, HashingEmbedder};
+pub use encryption::{EncryptionConfig, KeyRotationReport, KeySource};
+pub use history::ScoredTaskHistory;
+pub use lexical::FusionStrategy;
+pub use metrics::{LatencyHistogram, OperationMetrics, StorageMetrics, StorageUsage};
+pub use outbox::{OutboxEvent, ProjectionReport};
+pub use query::{KnowledgeFacets, KnowledgeQuery, KnowledgeQueryResult};
+pub use reindex::{ReindexOptions, ReindexPhase, ReindexProgress, ReindexReport};
+pub use relations::{Direction, EntityKind, EntityRef, PathQuery, Relation, RelationKind};
+pub use retention::{RetentionConfig, RetentionPolicy, RetentionReport};
+pub use revisions::KnowledgeRevision;
+pub use schema::{MigrationFailure, MigrationReport, SchemaMigration, CURRENT_SCHEMA_VERSION};
+pub use snapshot::{
+    ExportOptions, ExportReport, ImportMode, ImportOptions, ImportReport, SnapshotCounts, SnapshotManifest,
+};
+pub use search::{ConsistencyWarning, ConsistencyWarningKind, KnowledgeSearch, ScoredKnowledge, SearchPage};
+pub use tags::TagMatch;
+pub use vector_index::{VectorIndex, VectorMatch, VectorPoint};
+pub use verify::{
+    ConsistencyIssue, ConsistencyIssueKind, ConsistencyReport, MissingVectorRepair, OrphanedVectorRepair,
+    PayloadMismatchRepair, RepairFailure, RepairPolicy, RepairReport, UnreadableRowRepair,
+};
+
+/// Hybrid storage coordinator managing REDB and a vector index
 #[derive(Clone)]
 pub struct HybridStorageCoordinator {
     // REDB for structured state and coordination
     redb: Arc<Database>,
 
-    // Qdrant for semantic embeddings and similarity
-    qdrant: Arc<QdrantClient>,
+    // Vector index (Qdrant or in-process) for semantic embeddings and similarity
+    vectors: Arc<dyn VectorIndex>,
+
+    // Text embedding model
+    embedder: Arc<dyn EmbeddingProvider>,
+
+    // Keys sealing knowledge and coordination rows, if encryption is enabled
+    cipher: Option<Arc<encryption::RowCipher>>,
+
+    // Collection serving knowledge; writes hold it shared, a reindex swaps it exclusively
+    active_collection: Arc<RwLock<String>>,
 
     // Coordination state
     state: Arc<RwLock<CoordinationState>>,
 
+    // Serializes journal draining and outbox projection between the worker and callers
+    sync_lock: Arc<tokio::sync::Mutex<()>>,
+
+    // Background worker applying deferred vector writes
+    sync_worker: Arc<sync::SyncWorker>,
+
+    // Background worker enforcing retention policies
+    compactor: Arc<retention::Compactor>,
+
+    // Shared by user handles only; background tasks run on a copy without it
+    _owner: Option<Arc<OwnerGuard>>,
+
+    // Per-operation latency and error counts, split by backend
+    metrics: Arc<metrics::MetricsRegistry>,
+
     // Configuration
     config: StorageConfig,
 }
@@ -44,13 +123,47 @@ pub struct StorageConfig {
     pub embedding_dimension: usize,
     pub sync_batch_size: usize,
     pub consistency_mode: ConsistencyMode,
+    /// Projected outbox events kept for `replay_outbox` before they are pruned
+    #[serde(default = "default_outbox_retained_events")]
+    pub outbox_retained_events: usize,
+    #[serde(default)]
+    pub vector_backend: VectorBackend,
+    #[serde(default)]
+    pub embedding_backend: EmbeddingBackend,
+    #[serde(default)]
+    pub embedding_model_dir: Option<String>,
+    #[serde(default = "default_embedding_batch_size")]
+    pub embedding_batch_size: usize,
+    #[serde(default)]
+    pub schema_migration: SchemaMigration,
+    #[serde(default)]
+    pub encryption: Option<EncryptionConfig>,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    /// What `store_knowledge` does with content that duplicates an existing entry
+    #[serde(default)]
+    pub deduplication: DeduplicationConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConsistencyMode {
     Immediate,     // Two-phase commit for critical operations
     Eventually,    // Async synchronization for performance
-    EventDriven,   // Event sourcing pattern
+    EventDriven,   // Transactional outbox projected to the vector index
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum VectorBackend {
+    #[default]
+    Qdrant,        // Remote Qdrant server at `qdrant_url`
+    InProcess,     // Pure-Rust index persisted next to the REDB file
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum EmbeddingBackend {
+    #[default]
+    Hashing,       // Offline feature-hashed tokens and character n-grams
+    Candle,        // Local sentence-transformer from `embedding_model_dir`, run on the CPU
 }
 
 /// Coordination state for hybrid storage operations
@@ -58,39 +171,52 @@ pub enum ConsistencyMode {
 struct CoordinationState {
     pending_operations: HashMap<Uuid, PendingOperation>,
     sync_status: SyncStatus,
-    performance_metrics: StorageMetrics,
+}
+
+/// Signals the background tasks to exit once the last user handle is dropped
+///
+/// The tasks notice on their next wake-up and release the database when they
+/// return, so a path can be reopened shortly after its coordinator is dropped.
+struct OwnerGuard {
+    sync_worker: Arc<sync::SyncWorker>,
+    compactor: Arc<retention::Compactor>,
+}
+
+impl Drop for OwnerGuard {
+    fn drop(&mut self) {
+        self.sync_worker.signal_shutdown();
+        self.compactor.signal_shutdown();
+    }
 }
 
 #[derive(Debug)]
+#[allow(dead_code)] // Fields other than the commit flags are kept for `Debug` output
 struct PendingOperation {
     operation_id: Uuid,
     operation_type: OperationType,
     redb_committed: bool,
-    qdrant_committed: bool,
+    vector_committed: bool,
     timestamp: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(Debug)]
-enum OperationType {
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum OperationType {
     Insert,
     Update,
     Delete,
     Batch,
 }
 
-#[derive(Debug, Default)]
-struct SyncStatus {
-    last_sync: Option<chrono::DateTime<chrono::Utc>>,
-    pending_count: usize,
-    error_count: usize,
-}
-
-#[derive(Debug, Default)]
-struct StorageMetrics {
-    operations_total: u64,
-    operations_success: u64,
-    average_latency_ms: f64,
-    last_updated: chrono::DateTime<chrono::Utc>,
+/// Synchronization status between REDB and the vector index
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncStatus {
+    pub last_sync: Option<chrono::DateTime<chrono::Utc>>,
+    pub pending_count: usize,
+    /// Deferred vector writes the index kept rejecting, set aside so the rest could apply (see `sync`)
+    #[serde(default)]
+    pub quarantined_count: usize,
+    pub error_count: usize,
+    pub last_error: Option<String>,
 }
 
 /// Entity types for hybrid storage
@@ -107,12 +233,23 @@ pub struct AgentEntity {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KnowledgeEntity {
     pub id: Uuid,
+    #[serde(default)]
+    pub title: Option<String>,
     pub content: String,
     pub metadata: HashMap<String, serde_json::Value>,
     pub embeddings: Option<Vec<f32>>,
     pub source: String,
     pub credibility_rating: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Indexed for `list_tags` and `find_by_tags` (see `tags`)
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Document this entry is a chunk of (see `chunking`)
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+    /// Position of the chunk within its parent document
+    #[serde(default)]
+    pub chunk_index: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -123,12 +260,34 @@ pub struct CoordinationEntity {
     pub status: String,
     pub data: serde_json::Value,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Agent the record concerns, if any
+    #[serde(default)]
+    pub agent_id: Option<Uuid>,
+}
+
+/// Task executed through the coordination hub, kept apart from the knowledge base
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskHistoryEntity {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub task_id: Uuid,
+    pub agent_id: Uuid,
+    pub action_type: String,
+    pub status: String,
+    /// Text embedded for routing similar tasks
+    pub summary: String,
+    pub execution_time_ms: u64,
+    pub embeddings: Option<Vec<f32>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
 /// REDB table definitions
 const AGENTS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("agents");
 const KNOWLEDGE_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("knowledge");
 const COORDINATION_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("coordination");
+const TASK_HISTORY_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("task_history");
+const KNOWLEDGE_REVISIONS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("knowledge_revisions");
+const RELATIONS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("relations");
 const METADATA_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("metadata");
 
 /// Hybrid storage trait for coordinated operations
@@ -137,315 +296,1060 @@ pub trait HybridStorage: Send + Sync {
     /// Store agent with coordinated REDB + Qdrant operation
     async fn store_agent(&self, agent: &AgentEntity) -> Result<(), StorageError>;
 
-    /// Store knowledge with semantic embeddings
-    async fn store_knowledge(&self, knowledge: &KnowledgeEntity) -> Result<(), StorageError>;
+    /// Store knowledge with semantic embeddings, handling duplicates as `StorageConfig::deduplication` says
+    async fn store_knowledge(&self, knowledge: &KnowledgeEntity) -> Result<IngestReport, StorageError>;
+
+    /// Store knowledge, handling duplicates as `deduplication` says
+    async fn store_knowledge_with(
+        &self,
+        knowledge: &KnowledgeEntity,
+        deduplication: &DeduplicationConfig,
+    ) -> Result<IngestReport, StorageError>;
+
+    /// Store many knowledge entries in one coordinated operation, reporting per-item outcomes
+    async fn store_knowledge_batch(&self, knowledge: &[KnowledgeEntity]) -> Result<BatchReport, StorageError>;
+
+    /// Store a document and its overlapping chunks as child entries, replacing chunks from a previous store
+    async fn store_document(
+        &self,
+        document: &KnowledgeEntity,
+        chunking: &ChunkingConfig,
+    ) -> Result<DocumentReport, StorageError>;
+
+    /// Chunks of a document in document order
+    async fn get_document_chunks(&self, id: &Uuid) -> Result<Vec<KnowledgeEntity>, StorageError>;
 
     /// Retrieve agent by ID
     async fn get_agent(&self, id: &Uuid) -> Result<Option<AgentEntity>, StorageError>;
 
+    /// Delete agent by ID, returning whether it existed
+    async fn delete_agent(&self, id: &Uuid) -> Result<bool, StorageError>;
+
+    /// Retrieve knowledge by ID
+    async fn get_knowledge(&self, id: &Uuid) -> Result<Option<KnowledgeEntity>, StorageError>;
+
+    /// Replace an existing knowledge entry, re-embedding it if its content changed
+    async fn update_knowledge(&self, knowledge: &KnowledgeEntity) -> Result<(), StorageError>;
+
+    /// Delete knowledge, its chunks and their vector points, returning whether it existed
+    async fn delete_knowledge(&self, id: &Uuid) -> Result<bool, StorageError>;
+
     /// Search knowledge by semantic similarity
-    async fn search_knowledge(&self, query: &str, limit: usize) -> Result<Vec<KnowledgeEntity>, StorageError>;
+    async fn search_knowledge(&self, query: &str, limit: usize) -> Result<Vec<ScoredKnowledge>, StorageError>;
+
+    /// Revisions of a knowledge entry, oldest first, ending with the current one unless it was deleted
+    async fn get_knowledge_history(&self, id: &Uuid) -> Result<Vec<KnowledgeRevision>, StorageError>;
+
+    /// Search knowledge as it was at `as_of`, ranked by semantic similarity
+    async fn search_knowledge_as_of(
+        &self,
+        query: &str,
+        as_of: chrono::DateTime<chrono::Utc>,
+        limit: usize,
+    ) -> Result<Vec<ScoredKnowledge>, StorageError>;
+
+    /// Search knowledge by fusing BM25 (exact terms) and semantic similarity rankings
+    async fn search_knowledge_hybrid(
+        &self,
+        query: &str,
+        limit: usize,
+        fusion: FusionStrategy,
+    ) -> Result<Vec<ScoredKnowledge>, StorageError>;
+
+    /// Search one page of knowledge with offset or cursor pagination and a minimum score
+    async fn search_knowledge_page(&self, search: &KnowledgeSearch) -> Result<SearchPage, StorageError>;
+
+    /// Search document chunks, grouped by document with their neighbouring chunks as context
+    async fn search_documents(&self, search: &DocumentSearch) -> Result<Vec<DocumentMatch>, StorageError>;
+
+    /// Search knowledge with source, credibility, date, metadata and tag filters, plus facet counts
+    async fn query_knowledge(&self, query: &KnowledgeQuery) -> Result<KnowledgeQueryResult, StorageError>;
+
+    /// Every tag in use with the number of entries carrying it; chunks are not counted
+    async fn list_tags(&self) -> Result<BTreeMap<String, usize>, StorageError>;
+
+    /// Entries carrying all or any of `tags`, oldest first; chunks are not returned
+    async fn find_by_tags(&self, tags: &[String], matching: TagMatch) -> Result<Vec<KnowledgeEntity>, StorageError>;
+
+    /// Store a relation between two existing entities, replacing one of the same kind between them
+    async fn add_relation(&self, relation: &Relation) -> Result<(), StorageError>;
+
+    /// Remove a relation, returning whether it existed
+    async fn remove_relation(&self, from: &EntityRef, kind: RelationKind, to: &EntityRef) -> Result<bool, StorageError>;
+
+    /// Relations of an entity in a direction, limited to `kinds` unless empty
+    async fn neighbors(
+        &self,
+        entity: &EntityRef,
+        direction: Direction,
+        kinds: &[RelationKind],
+    ) -> Result<Vec<Relation>, StorageError>;
+
+    /// Shortest chain of relations from one entity to another, if any within the query's depth
+    async fn find_path(
+        &self,
+        from: &EntityRef,
+        to: &EntityRef,
+        query: &PathQuery,
+    ) -> Result<Option<Vec<Relation>>, StorageError>;
 
     /// Update coordination state
     async fn update_coordination(&self, coordination: &CoordinationEntity) -> Result<(), StorageError>;
 
-    /// Synchronize state between storage systems
+    /// Coordination records of a session, oldest first
+    async fn list_coordination(&self, session_id: &Uuid) -> Result<Vec<CoordinationEntity>, StorageError>;
+
+    /// Coordination records naming an agent within a time range, oldest first
+    async fn list_by_agent(&self, agent_id: &Uuid, range: TimeRange) -> Result<Vec<CoordinationEntity>, StorageError>;
+
+    /// Coordination records with a status, oldest first
+    async fn list_by_status(&self, status: &str) -> Result<Vec<CoordinationEntity>, StorageError>;
+
+    /// Record an executed task, embedding its summary for similarity routing
+    async fn record_task_history(&self, entry: &TaskHistoryEntity) -> Result<(), StorageError>;
+
+    /// Find recorded tasks whose summaries are most similar to `query`
+    async fn search_task_history(&self, query: &str, limit: usize) -> Result<Vec<ScoredTaskHistory>, StorageError>;
+
+    /// Synchronize state between storage systems, waiting until pending vector writes are flushed
     async fn synchronize(&self) -> Result<SyncResult, StorageError>;
 
-    /// Get storage metrics
+    /// Get synchronization status (pending writes, errors, last successful sync)
+    async fn sync_status(&self) -> SyncStatus;
+
+    /// Get per-operation latency and error metrics and current storage usage
     async fn get_metrics(&self) -> StorageMetrics;
+
+    /// Compare stored rows with the vector index and check that every row decodes
+    async fn verify(&self) -> Result<ConsistencyReport, StorageError>;
+
+    /// Verify, then fix every issue found as `policy` directs
+    async fn repair(&self, policy: &RepairPolicy) -> Result<RepairReport, StorageError>;
+
+    /// Stop background workers; call after a final `synchronize`
+    async fn shutdown(&self);
 }
 
 impl HybridStorageCoordinator {
-    /// Initialize hybrid storage coordinator
+    /// Initialize hybrid storage coordinator with the configured vector and embedding backends
     pub async fn new(config: StorageConfig) -> Result<Self, StorageError> {
+        let vectors = Self::open_vector_index(&config)?;
+        Self::with_vector_index(config, vectors).await
+    }
+
+    /// Initialize hybrid storage coordinator with a caller-provided vector index
+    pub async fn with_vector_index(
+        config: StorageConfig,
+        vectors: Arc<dyn VectorIndex>,
+    ) -> Result<Self, StorageError> {
+        let embedder = Self::open_embedding_provider(&config)?;
+        Self::with_backends(config, vectors, embedder).await
+    }
+
+    /// Initialize hybrid storage coordinator over a caller-opened REDB database, such as one with a custom storage backend
+    pub async fn with_database(
+        config: StorageConfig,
+        redb: Database,
+        vectors: Arc<dyn VectorIndex>,
+    ) -> Result<Self, StorageError> {
+        let embedder = Self::open_embedding_provider(&config)?;
+        Self::check_embedding_dimension(&config, embedder.as_ref())?;
+        Self::open(config, redb, vectors, embedder).await
+    }
+
+    /// Initialize hybrid storage coordinator with a caller-provided vector index and embedding provider
+    pub async fn with_backends(
+        config: StorageConfig,
+        vectors: Arc<dyn VectorIndex>,
+        embedder: Arc<dyn EmbeddingProvider>,
+    ) -> Result<Self, StorageError> {
+        Self::check_embedding_dimension(&config, embedder.as_ref())?;
+
         // Initialize REDB
         let redb = Database::create(&config.redb_path)
             .map_err(|e| StorageError::InitializationError(format!("REDB init failed: {}", e)))?;
 
-        // Initialize Qdrant connection
-        let qdrant = QdrantClient::from_url(&config.qdrant_url)
-            .build()
-            .map_err(|e| StorageError::InitializationError(format!("Qdrant connection failed: {}", e)))?;
-
-        // Create collection if it doesn't exist
-        let collections = qdrant.list_collections().await
-            .map_err(|e| StorageError::InitializationError(format!("Failed to list collections: {}", e)))?;
-
-        if !collections.collections.iter().any(|c| c.name == config.collection_name) {
-            qdrant.create_collection(&CreateCollection {
-                collection_name: config.collection_name.clone(),
-                vectors_config: Some(VectorConfig {
-                    config: Some(Config::Params(VectorParams {
-                        size: config.embedding_dimension as u64,
-                        distance: Distance::Cosine as i32,
-                    })),
-                }),
-                ..Default::default()
-            }).await
-            .map_err(|e| StorageError::InitializationError(format!("Failed to create collection: {}", e)))?;
+        Self::open(config, redb, vectors, embedder).await
+    }
+
+    fn check_embedding_dimension(config: &StorageConfig, embedder: &dyn EmbeddingProvider) -> Result<(), StorageError> {
+        if embedder.dimension() != config.embedding_dimension {
+            return Err(StorageError::ConfigurationError(format!(
+                "Embedding provider produces {}-dimensional vectors, embedding_dimension is {}",
+                embedder.dimension(), config.embedding_dimension
+            )));
         }
+        Ok(())
+    }
+
+    /// Open the coordinator over an initialized REDB database, upgrading and recovering it as needed
+    async fn open(
+        config: StorageConfig,
+        redb: Database,
+        vectors: Arc<dyn VectorIndex>,
+        embedder: Arc<dyn EmbeddingProvider>,
+    ) -> Result<Self, StorageError> {
+        let cipher = match &config.encryption {
+            Some(encryption) => Some(Arc::new(encryption::RowCipher::load(encryption)?)),
+            None => None,
+        };
 
-        Ok(Self {
+        let metrics = Arc::new(metrics::MetricsRegistry::default());
+        let sync_worker = Arc::new(sync::SyncWorker::default());
+        let compactor = Arc::new(retention::Compactor::default());
+        let coordinator = Self {
             redb: Arc::new(redb),
-            qdrant: Arc::new(qdrant),
+            vectors: Arc::new(metrics::InstrumentedIndex::new(vectors, metrics.clone())),
+            embedder,
+            cipher,
+            active_collection: Arc::new(RwLock::new(config.collection_name.clone())),
             state: Arc::new(RwLock::new(CoordinationState::default())),
+            sync_lock: Arc::new(tokio::sync::Mutex::new(())),
+            sync_worker: sync_worker.clone(),
+            compactor: compactor.clone(),
+            _owner: Some(Arc::new(OwnerGuard { sync_worker, compactor })),
+            metrics,
             config,
-        })
+        };
+
+        // Refuse databases from newer builds and upgrade rows from older ones
+        coordinator.open_schema()?;
+
+        // Use the collection of the last completed reindex, creating it if it doesn't exist
+        let collection = coordinator.stored_active_collection()?;
+        coordinator.vectors.ensure_collection(&collection, coordinator.config.embedding_dimension).await?;
+        *coordinator.active_collection.write().await = collection;
+
+        if let Some(progress) = coordinator.reindex_progress()? {
+            tracing::info!(
+                "Reindex into {} interrupted at {}/{} entries; call reindex() to resume",
+                progress.collection, progress.indexed, progress.total
+            );
+        }
+
+        // Finish any coordinated operation interrupted by a crash
+        let report = coordinator.recover().await?;
+        if report.rolled_forward + report.rolled_back + report.pending > 0 {
+            tracing::info!(
+                "Journal recovery: {} rolled forward, {} rolled back, {} pending",
+                report.rolled_forward, report.rolled_back, report.pending
+            );
+        }
+
+        // Encrypt rows written without encryption or with a retired key
+        coordinator.open_encryption()?;
+
+        // Databases written before the lexical, content hash or tag index existed are indexed once
+        if !coordinator.lexical_index_exists()? || !coordinator.content_index_exists()? || !coordinator.tag_index_exists()? {
+            let indexed = coordinator.rebuild_lexical_index()?;
+            tracing::info!("Built lexical index for {} knowledge entries", indexed);
+        }
+        if !coordinator.coordination_index_exists()? {
+            let indexed = coordinator.rebuild_coordination_index()?;
+            tracing::info!("Built coordination indexes for {} records", indexed);
+        }
+
+        // Task records written to the knowledge base by older versions move to task history once
+        coordinator.move_task_knowledge().await?;
+
+        if let Err(e) = coordinator.sample_space_usage() {
+            tracing::warn!("Failed to sample storage usage: {}", e);
+        }
+
+        // Eventual consistency modes apply vector writes in the background
+        coordinator.start_sync_worker();
+        coordinator.start_compactor();
+
+        Ok(coordinator)
+    }
+
+    /// Copy of this coordinator for a background task, which must not keep the tasks alive itself
+    pub(crate) fn background_handle(&self) -> Self {
+        Self { _owner: None, ..self.clone() }
+    }
+
+    /// Build the vector index selected by `StorageConfig::vector_backend`
+    fn open_vector_index(config: &StorageConfig) -> Result<Arc<dyn VectorIndex>, StorageError> {
+        match config.vector_backend {
+            #[cfg(feature = "qdrant")]
+            VectorBackend::Qdrant => Ok(Arc::new(qdrant_integration::QdrantIndex::connect(&config.qdrant_url)?)),
+            #[cfg(not(feature = "qdrant"))]
+            VectorBackend::Qdrant => Err(StorageError::ConfigurationError(
+                "Qdrant backend requested but the `qdrant` feature is disabled".to_string(),
+            )),
+            VectorBackend::InProcess => Ok(Arc::new(local_index::LocalVectorIndex::open(config.vector_index_path())?)),
+        }
+    }
+
+    /// Build the embedding provider selected by `StorageConfig::embedding_backend`
+    fn open_embedding_provider(config: &StorageConfig) -> Result<Arc<dyn EmbeddingProvider>, StorageError> {
+        match config.embedding_backend {
+            EmbeddingBackend::Hashing => match &config.encryption {
+                Some(encryption) => {
+                    let key = encryption::RowCipher::embedding_key(encryption)?;
+                    Ok(Arc::new(HashingEmbedder::keyed(config.embedding_dimension, &key)))
+                }
+                None => Ok(Arc::new(HashingEmbedder::new(config.embedding_dimension))),
+            },
+            #[cfg(feature = "candle")]
+            EmbeddingBackend::Candle => {
+                let model_dir = config.embedding_model_dir.as_deref().ok_or_else(|| {
+                    StorageError::ConfigurationError("Candle embeddings require embedding_model_dir".to_string())
+                })?;
+                Ok(Arc::new(candle_embedding::CandleEmbedder::load(model_dir, config.embedding_batch_size)?))
+            }
+            #[cfg(not(feature = "candle"))]
+            EmbeddingBackend::Candle => Err(StorageError::ConfigurationError(
+                "Candle embeddings requested but the `candle` feature is disabled".to_string(),
+            )),
+        }
     }
 
-    /// Generate embedding for text content (simplified implementation)
+    /// Name of the collection currently serving knowledge search
+    pub async fn active_collection(&self) -> String {
+        self.active_collection.read().await.clone()
+    }
+
+    /// Active knowledge collection; writers hold the guard until their vector intents are recorded
+    /// so a reindex cannot swap collections underneath them
+    async fn knowledge_collection(&self) -> tokio::sync::RwLockReadGuard<'_, String> {
+        self.active_collection.read().await
+    }
+
+    /// Generate embedding for text content
     async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>, StorageError> {
-        // In production, this would use a local embedding model
-        // For demo purposes, we'll create a simple hash-based embedding
-        use std::hash::{Hash, Hasher};
-        use std::collections::hash_map::DefaultHasher;
-
-        let mut hasher = DefaultHasher::new();
-        text.hash(&mut hasher);
-        let hash = hasher.finish();
-
-        // Convert hash to normalized embedding vector
-        let mut embedding = Vec::with_capacity(self.config.embedding_dimension);
-        for i in 0..self.config.embedding_dimension {
-            let component = ((hash.wrapping_mul(i as u64 + 1)) as f32) / (u64::MAX as f32);
-            embedding.push(component * 2.0 - 1.0); // Normalize to [-1, 1]
+        let mut embeddings = self.generate_embeddings(&[text]).await?;
+        embeddings.pop()
+            .ok_or_else(|| StorageError::VectorError("Embedding provider returned no vector".to_string()))
+    }
+
+    /// Generate embeddings for a batch of texts, checking each against `embedding_dimension`
+    async fn generate_embeddings(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, StorageError> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let embeddings = self.embedder.embed(texts).await?;
+        if embeddings.len() != texts.len() {
+            return Err(StorageError::VectorError(format!(
+                "Embedding provider returned {} vectors for {} texts",
+                embeddings.len(), texts.len()
+            )));
+        }
+        for embedding in &embeddings {
+            self.check_embedding(embedding)?;
         }
 
-        Ok(embedding)
+        Ok(embeddings)
     }
 
-    /// Execute coordinated transaction across both storage systems
-    async fn execute_coordinated_transaction<F>(&self, operation: F) -> Result<(), StorageError>
-    where
-        F: FnOnce(&WriteTransaction) -> Result<(), StorageError>,
-    {
-        let operation_id = Uuid::new_v4();
+    /// Reject a vector whose length is not `embedding_dimension`; the vector index would never accept it
+    pub(crate) fn check_embedding(&self, embedding: &[f32]) -> Result<(), StorageError> {
+        if embedding.len() != self.config.embedding_dimension {
+            return Err(StorageError::ConfigurationError(format!(
+                "Embedding has dimension {}, expected {}",
+                embedding.len(), self.config.embedding_dimension
+            )));
+        }
+        Ok(())
+    }
 
-        // Phase 1: Prepare operation
-        {
-            let mut state = self.state.write().await;
-            state.pending_operations.insert(operation_id, PendingOperation {
-                operation_id,
-                operation_type: OperationType::Insert,
-                redb_committed: false,
-                qdrant_committed: false,
-                timestamp: chrono::Utc::now(),
+    /// Embed a batch of texts for a batch write, one result per text
+    ///
+    /// If the batch fails, each text is embedded alone so one bad input only
+    /// fails its own item.
+    async fn generate_batch_embeddings(&self, texts: &[&str]) -> Vec<Result<Vec<f32>, String>> {
+        let e = match self.generate_embeddings(texts).await {
+            Ok(embeddings) => return embeddings.into_iter().map(Ok).collect(),
+            Err(e) => e,
+        };
+        tracing::warn!("Embedding a batch of {} texts failed, retrying one by one: {}", texts.len(), e);
+
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for text in texts {
+            embeddings.push(self.generate_embedding(text).await.map_err(|e| e.to_string()));
+        }
+        embeddings
+    }
+
+    /// Rank knowledge IDs for a query, best first: vector similarity, or fused with BM25
+    async fn rank_knowledge(
+        &self,
+        query: &str,
+        limit: usize,
+        fusion: Option<FusionStrategy>,
+    ) -> Result<Vec<(Uuid, f32)>, StorageError> {
+        let query_embedding = self.generate_embedding(query).await?;
+        let collection = self.knowledge_collection().await;
+
+        let Some(fusion) = fusion else {
+            let matches = self.vectors
+                .search(&collection, &query_embedding, limit)
+                .await?;
+            return Ok(matches.into_iter().map(|vector_match| (vector_match.id, vector_match.score)).collect());
+        };
+
+        // Rank a deeper candidate pool on each side so fusion can promote items from either
+        let candidates = limit.saturating_mul(4).max(20);
+
+        let lexical = self.time_redb(|| self.search_lexical(query, candidates))?;
+        let vector: Vec<(Uuid, f32)> = self.vectors
+            .search(&collection, &query_embedding, candidates)
+            .await?
+            .into_iter()
+            .map(|vector_match| (vector_match.id, vector_match.score))
+            .collect();
+
+        Ok(lexical::fuse_rankings(&lexical, &vector, fusion, limit))
+    }
+
+    /// Load knowledge entities by ID, one result per ID in order; missing or unreadable rows become warnings
+    fn load_knowledge_entities(
+        &self,
+        ids: &[Uuid],
+    ) -> Result<Vec<Result<KnowledgeEntity, ConsistencyWarning>>, StorageError> {
+        let missing = |id: &Uuid| Err(ConsistencyWarning { id: *id, kind: ConsistencyWarningKind::Missing });
+
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(KNOWLEDGE_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(ids.iter().map(missing).collect()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open knowledge table: {}", e))),
+        };
+
+        let mut knowledge_entities = Vec::with_capacity(ids.len());
+        for id in ids {
+            let data = table.get(id.to_string().as_str())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge: {}", e)))?;
+            knowledge_entities.push(match data {
+                Some(data) => self.decode_row::<KnowledgeEntity>(EntityTable::Knowledge, data.value())
+                    .map_err(|e| ConsistencyWarning {
+                        id: *id,
+                        kind: ConsistencyWarningKind::Unreadable(e.to_string()),
+                    }),
+                None => missing(id),
             });
         }
 
-        // Phase 2: Execute REDB transaction
-        let write_txn = self.redb.begin_write()
-            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        Ok(knowledge_entities)
+    }
+
+    /// Write a knowledge entry and its vector as one coordinated operation, replacing any row with its ID
+    pub(crate) async fn write_knowledge(&self, knowledge: &KnowledgeEntity) -> Result<(), StorageError> {
+        let knowledge_key = knowledge.id.to_string();
 
-        match operation(&write_txn) {
-            Ok(_) => {
-                write_txn.commit()
-                    .map_err(|e| StorageError::TransactionError(format!("Failed to commit REDB transaction: {}", e)))?;
+        // Generate embedding for semantic search
+        let embedding = self.stored_embedding(knowledge).await?;
+
+        // Keep the embedding with the row so updates and deletes can restore the point
+        let stored = KnowledgeEntity { embeddings: Some(embedding.clone()), ..knowledge.clone() };
+        let knowledge_data = self.encode_row(EntityTable::Knowledge, &stored)?;
+
+        let point = knowledge_point(knowledge, embedding);
+        let active = self.knowledge_collection().await;
+        let collection = active.clone();
+
+        // Store in REDB and the vector index as one coordinated operation
+        self.execute_coordinated_transaction(
+            OperationType::Insert,
+            vec![VectorIntent::Upsert { collection: collection.clone(), points: vec![point] }],
+            |txn| {
+                let previous = txn.insert(KNOWLEDGE_TABLE, &knowledge_key, &knowledge_data)?;
+                revisions::archive_replaced(txn, self.cipher(), previous.as_deref(), Some(knowledge))?;
+                index_derived(txn.transaction(), knowledge, self.cipher())?;
+
+                // Restore the replaced row's point, or remove the new one
+                Ok(match replaced_knowledge_point(self.cipher(), previous) {
+                    Some(old_point) => vec![VectorIntent::Upsert { collection, points: vec![old_point] }],
+                    None => vec![VectorIntent::Delete { collection, ids: vec![knowledge.id] }],
+                })
+            },
+        ).await
+    }
 
-                // Mark REDB as committed
-                {
-                    let mut state = self.state.write().await;
-                    if let Some(pending_op) = state.pending_operations.get_mut(&operation_id) {
-                        pending_op.redb_committed = true;
-                    }
-                }
-            }
-            Err(e) => {
-                // Rollback operation
-                self.rollback_operation(operation_id).await?;
-                return Err(e);
+    /// Embedding stored with an existing entry, regenerated for rows written before embeddings were kept
+    async fn stored_embedding(&self, knowledge: &KnowledgeEntity) -> Result<Vec<f32>, StorageError> {
+        match &knowledge.embeddings {
+            Some(embedding) => {
+                self.check_embedding(embedding)?;
+                Ok(embedding.clone())
             }
+            None => self.generate_embedding(&knowledge.content).await,
         }
+    }
+}
 
-        // Phase 3: Complete coordination
-        self.complete_operation(operation_id).await?;
+/// Point to restore when a knowledge row is overwritten, if the old row kept its embedding
+fn replaced_knowledge_point(cipher: Option<&encryption::RowCipher>, previous: Option<Vec<u8>>) -> Option<VectorPoint> {
+    previous
+        .and_then(|data| encryption::decode_row::<KnowledgeEntity>(cipher, EntityTable::Knowledge, &data).ok())
+        .and_then(|old| old.embeddings.clone().map(|embedding| knowledge_point(&old, embedding)))
+}
 
-        Ok(())
+/// Vector point for a knowledge entry; the payload carries the fields used for filtering
+fn knowledge_point(knowledge: &KnowledgeEntity, embedding: Vec<f32>) -> VectorPoint {
+    VectorPoint {
+        id: knowledge.id,
+        vector: embedding,
+        payload: [
+            ("source".to_string(), knowledge.source.clone().into()),
+            ("credibility".to_string(), knowledge.credibility_rating.clone().into()),
+            ("created_at".to_string(), knowledge.created_at.to_rfc3339().into()),
+        ].into_iter().collect(),
     }
+}
 
-    async fn rollback_operation(&self, operation_id: Uuid) -> Result<(), StorageError> {
-        let mut state = self.state.write().await;
-        state.pending_operations.remove(&operation_id);
-        state.sync_status.error_count += 1;
-        Ok(())
+/// Add a knowledge row to every index derived from it: lexical, content hash, chunk and tag
+///
+/// Each index replaces whatever it held under the row's ID.
+pub(crate) fn index_derived(
+    write_txn: &redb::WriteTransaction,
+    knowledge: &KnowledgeEntity,
+    cipher: Option<&encryption::RowCipher>,
+) -> Result<(), StorageError> {
+    lexical::index_knowledge(write_txn, knowledge, cipher)?;
+    dedup::index_content(write_txn, knowledge, cipher)?;
+    chunking::index_chunk(write_txn, knowledge)?;
+    tags::index_tags(write_txn, knowledge, cipher)
+}
+
+/// Remove a knowledge row from every derived index; unknown IDs are ignored
+pub(crate) fn unindex_derived(write_txn: &redb::WriteTransaction, id: &str) -> Result<(), StorageError> {
+    lexical::unindex_knowledge(write_txn, id)?;
+    dedup::unindex_content(write_txn, id)?;
+    chunking::unindex_chunk(write_txn, id)?;
+    tags::unindex_tags(write_txn, id)
+}
+
+/// Re-derive the index entries for a knowledge row from its current contents
+pub(crate) fn reindex_derived_row(
+    write_txn: &redb::WriteTransaction,
+    key: &str,
+    cipher: Option<&encryption::RowCipher>,
+) -> Result<(), StorageError> {
+    let row = {
+        let table = write_txn.open_table(KNOWLEDGE_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge table: {}", e)))?;
+        let row = table.get(key)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge row: {}", e)))?;
+        row.map(|data| data.value().to_vec())
+    };
+
+    match row.map(|data| encryption::decode_row::<KnowledgeEntity>(cipher, EntityTable::Knowledge, &data)) {
+        Some(Ok(knowledge)) => index_derived(write_txn, &knowledge, cipher),
+        Some(Err(e)) => {
+            tracing::warn!("Knowledge row {} left out of the derived indexes: {}", key, e);
+            unindex_derived(write_txn, key)
+        }
+        None => unindex_derived(write_txn, key),
     }
+}
 
-    async fn complete_operation(&self, operation_id: Uuid) -> Result<(), StorageError> {
-        let mut state = self.state.write().await;
-        state.pending_operations.remove(&operation_id);
-        state.performance_metrics.operations_success += 1;
-        Ok(())
+/// Replace every derived index with one built from the current knowledge rows
+pub(crate) fn rebuild_derived(
+    write_txn: &redb::WriteTransaction,
+    cipher: Option<&encryption::RowCipher>,
+) -> Result<usize, StorageError> {
+    let keys: Vec<String> = {
+        let table = write_txn.open_table(KNOWLEDGE_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge table: {}", e)))?;
+        let mut keys = Vec::new();
+        for row in table.iter()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge table: {}", e)))?
+        {
+            let (key, _) = row
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge table: {}", e)))?;
+            keys.push(key.value().to_string());
+        }
+        keys
+    };
+
+    lexical::clear_index(write_txn)?;
+    dedup::clear_index(write_txn)?;
+    chunking::clear_index(write_txn)?;
+    tags::clear_index(write_txn)?;
+
+    for key in &keys {
+        reindex_derived_row(write_txn, key, cipher)?;
     }
+
+    Ok(keys.len())
 }
 
 #[async_trait]
 impl HybridStorage for HybridStorageCoordinator {
     async fn store_agent(&self, agent: &AgentEntity) -> Result<(), StorageError> {
-        let agent_key = agent.id.to_string();
-        let agent_data = bincode::serialize(agent)
-            .map_err(|e| StorageError::SerializationError(format!("Failed to serialize agent: {}", e)))?;
+        self.instrument("store_agent", async {
+            let agent_key = agent.id.to_string();
+            let agent_data = self.encode_row(EntityTable::Agents, agent)?;
 
-        self.execute_coordinated_transaction(|write_txn| {
-            let mut table = write_txn.open_table(AGENTS_TABLE)
-                .map_err(|e| StorageError::TransactionError(format!("Failed to open agents table: {}", e)))?;
+            self.execute_coordinated_transaction(OperationType::Insert, vec![], |txn| {
+                txn.insert(AGENTS_TABLE, &agent_key, &agent_data)?;
+                Ok(vec![])
+            }).await
+        }).await
+    }
 
-            table.insert(agent_key.as_str(), agent_data.as_slice())
-                .map_err(|e| StorageError::TransactionError(format!("Failed to insert agent: {}", e)))?;
+    async fn store_knowledge(&self, knowledge: &KnowledgeEntity) -> Result<IngestReport, StorageError> {
+        self.instrument("store_knowledge", self.run_ingest(knowledge, &self.config.deduplication)).await
+    }
 
-            Ok(())
-        }).await?;
+    async fn store_knowledge_with(
+        &self,
+        knowledge: &KnowledgeEntity,
+        deduplication: &DeduplicationConfig,
+    ) -> Result<IngestReport, StorageError> {
+        self.instrument("store_knowledge", self.run_ingest(knowledge, deduplication)).await
+    }
 
-        // Update metrics
-        {
-            let mut state = self.state.write().await;
-            state.performance_metrics.operations_total += 1;
-            state.performance_metrics.last_updated = chrono::Utc::now();
-        }
+    async fn store_knowledge_batch(&self, knowledge: &[KnowledgeEntity]) -> Result<BatchReport, StorageError> {
+        self.instrument("store_knowledge_batch", async {
+            let chunk_size = self.config.sync_batch_size.max(1);
+            let mut items: Vec<BatchItemResult> = knowledge.iter()
+                .map(|entry| BatchItemResult { id: entry.id, success: false, error: None })
+                .collect();
+
+            // Validate and embed in chunks; a failing item, including one that fails to embed, is reported and skipped
+            let mut seen = std::collections::HashSet::new();
+            let mut rows = Vec::new();
+            let mut points = Vec::new();
+
+            for (chunk_index, chunk) in knowledge.chunks(chunk_size).enumerate() {
+                let missing: Vec<&str> = chunk.iter()
+                    .filter(|entry| entry.embeddings.is_none())
+                    .map(|entry| entry.content.as_str())
+                    .collect();
+                let mut generated = self.generate_batch_embeddings(&missing).await.into_iter();
+
+                for (offset, entry) in chunk.iter().enumerate() {
+                    let index = chunk_index * chunk_size + offset;
+                    let embedding = match &entry.embeddings {
+                        Some(embedding) => Ok(embedding.clone()),
+                        None => generated.next().unwrap_or_else(|| Ok(Vec::new())),
+                    };
+
+                    let problem = match &embedding {
+                        _ if !seen.insert(entry.id) => Some(format!("Duplicate knowledge ID {} in batch", entry.id)),
+                        Err(e) => Some(e.clone()),
+                        Ok(embedding) if embedding.len() != self.config.embedding_dimension => Some(format!(
+                            "Embedding has dimension {}, expected {}",
+                            embedding.len(), self.config.embedding_dimension
+                        )),
+                        Ok(_) => None,
+                    };
+                    let embedding = match (problem, embedding) {
+                        (None, Ok(embedding)) => embedding,
+                        (problem, _) => {
+                            items[index].error = problem;
+                            continue;
+                        }
+                    };
+
+                    let stored = KnowledgeEntity { embeddings: Some(embedding.clone()), ..entry.clone() };
+                    match self.encode_row(EntityTable::Knowledge, &stored) {
+                        Ok(data) => {
+                            rows.push((index, entry, data));
+                            points.push(knowledge_point(entry, embedding));
+                        }
+                        Err(e) => items[index].error = Some(e.to_string()),
+                    }
+                }
+            }
 
-        Ok(())
+            if !rows.is_empty() {
+                let active = self.knowledge_collection().await;
+                let collection = active.clone();
+                let vector_intents = points.chunks(chunk_size)
+                    .map(|points| VectorIntent::Upsert { collection: collection.clone(), points: points.to_vec() })
+                    .collect();
+
+                // All rows in one REDB transaction; vectors upserted chunk by chunk
+                self.execute_coordinated_transaction(OperationType::Batch, vector_intents, |txn| {
+                    let mut restored = Vec::new();
+                    let mut removed = Vec::new();
+
+                    for (_, entry, data) in &rows {
+                        let previous = txn.insert(KNOWLEDGE_TABLE, &entry.id.to_string(), data)?;
+                        revisions::archive_replaced(txn, self.cipher(), previous.as_deref(), Some(entry))?;
+                        index_derived(txn.transaction(), entry, self.cipher())?;
+                        match replaced_knowledge_point(self.cipher(), previous) {
+                            Some(old_point) => restored.push(old_point),
+                            None => removed.push(entry.id),
+                        }
+                    }
+
+                    let mut compensation = Vec::new();
+                    if !removed.is_empty() {
+                        compensation.push(VectorIntent::Delete { collection: collection.clone(), ids: removed });
+                    }
+                    if !restored.is_empty() {
+                        compensation.push(VectorIntent::Upsert { collection: collection.clone(), points: restored });
+                    }
+                    Ok(compensation)
+                }).await?;
+
+                for (index, _, _) in &rows {
+                    items[*index].success = true;
+                }
+            }
+
+            let succeeded = items.iter().filter(|item| item.success).count();
+            Ok(BatchReport {
+                succeeded,
+                failed: items.len() - succeeded,
+                items,
+            })
+        }).await
     }
 
-    async fn store_knowledge(&self, knowledge: &KnowledgeEntity) -> Result<(), StorageError> {
-        let knowledge_key = knowledge.id.to_string();
+    async fn store_document(
+        &self,
+        document: &KnowledgeEntity,
+        chunking: &ChunkingConfig,
+    ) -> Result<DocumentReport, StorageError> {
+        self.instrument("store_document", async {
+            self.write_document(document, chunking).await
+        }).await
+    }
 
-        // Generate embedding for semantic search
-        let embedding = if knowledge.embeddings.is_some() {
-            knowledge.embeddings.clone().unwrap()
-        } else {
-            self.generate_embedding(&knowledge.content).await?
-        };
+    async fn get_document_chunks(&self, id: &Uuid) -> Result<Vec<KnowledgeEntity>, StorageError> {
+        self.instrument("get_document_chunks", async {
+            self.time_redb(|| self.load_document_chunks(id))
+        }).await
+    }
 
-        // Store in REDB
-        let knowledge_data = bincode::serialize(knowledge)
-            .map_err(|e| StorageError::SerializationError(format!("Failed to serialize knowledge: {}", e)))?;
+    async fn get_agent(&self, id: &Uuid) -> Result<Option<AgentEntity>, StorageError> {
+        self.instrument("get_agent", async {
+            self.time_redb(|| {
+                let read_txn = self.redb.begin_read()
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+                let table = read_txn.open_table(AGENTS_TABLE)
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to open agents table: {}", e)))?;
+
+                let agent_key = id.to_string();
+                match table.get(agent_key.as_str()) {
+                    Ok(Some(data)) => {
+                        let agent: AgentEntity = self.decode_row(EntityTable::Agents, data.value())?;
+                        Ok(Some(agent))
+                    }
+                    Ok(None) => Ok(None),
+                    Err(e) => Err(StorageError::TransactionError(format!("Failed to get agent: {}", e))),
+                }
+            })
+        }).await
+    }
 
-        self.execute_coordinated_transaction(|write_txn| {
-            let mut table = write_txn.open_table(KNOWLEDGE_TABLE)
-                .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge table: {}", e)))?;
+    async fn delete_agent(&self, id: &Uuid) -> Result<bool, StorageError> {
+        self.instrument("delete_agent", async {
+            let agent_key = id.to_string();
+            let mut existed = false;
 
-            table.insert(knowledge_key.as_str(), knowledge_data.as_slice())
-                .map_err(|e| StorageError::TransactionError(format!("Failed to insert knowledge: {}", e)))?;
+            self.execute_coordinated_transaction(OperationType::Delete, vec![], |txn| {
+                existed = txn.remove(AGENTS_TABLE, &agent_key)?.is_some();
+                relations::remove_entity_relations(txn, &EntityRef::agent(*id))?;
+                Ok(vec![])
+            }).await?;
 
-            Ok(())
-        }).await?;
-
-        // Store embedding in Qdrant
-        let point = PointStruct::new(
-            knowledge.id.to_string(),
-            embedding,
-            Some(
-                [
-                    ("source".to_string(), knowledge.source.clone().into()),
-                    ("credibility".to_string(), knowledge.credibility_rating.clone().into()),
-                    ("created_at".to_string(), knowledge.created_at.to_rfc3339().into()),
-                ].into_iter().collect()
-            ),
-        );
-
-        self.qdrant.upsert_points_blocking(&UpsertPoints {
-            collection_name: self.config.collection_name.clone(),
-            points: vec![point],
-            ..Default::default()
+            Ok(existed)
         }).await
-        .map_err(|e| StorageError::VectorError(format!("Failed to store vector: {}", e)))?;
+    }
 
-        Ok(())
+    async fn get_knowledge(&self, id: &Uuid) -> Result<Option<KnowledgeEntity>, StorageError> {
+        self.instrument("get_knowledge", async {
+            self.time_redb(|| {
+                let read_txn = self.redb.begin_read()
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+                let table = match read_txn.open_table(KNOWLEDGE_TABLE) {
+                    Ok(table) => table,
+                    Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+                    Err(e) => return Err(StorageError::TransactionError(format!("Failed to open knowledge table: {}", e))),
+                };
+
+                let knowledge_key = id.to_string();
+                match table.get(knowledge_key.as_str()) {
+                    Ok(Some(data)) => {
+                        let knowledge: KnowledgeEntity = self.decode_row(EntityTable::Knowledge, data.value())?;
+                        Ok(Some(knowledge))
+                    }
+                    Ok(None) => Ok(None),
+                    Err(e) => Err(StorageError::TransactionError(format!("Failed to get knowledge: {}", e))),
+                }
+            })
+        }).await
     }
 
-    async fn get_agent(&self, id: &Uuid) -> Result<Option<AgentEntity>, StorageError> {
-        let read_txn = self.redb.begin_read()
-            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+    async fn update_knowledge(&self, knowledge: &KnowledgeEntity) -> Result<(), StorageError> {
+        self.instrument("update_knowledge", async {
+            let knowledge_key = knowledge.id.to_string();
 
-        let table = read_txn.open_table(AGENTS_TABLE)
-            .map_err(|e| StorageError::TransactionError(format!("Failed to open agents table: {}", e)))?;
+            let existing = self.get_knowledge(&knowledge.id).await?
+                .ok_or_else(|| StorageError::NotFound(format!("Knowledge {} does not exist", knowledge.id)))?;
+            let previous_embedding = self.stored_embedding(&existing).await?;
 
-        let agent_key = id.to_string();
-        match table.get(agent_key.as_str()) {
-            Ok(Some(data)) => {
-                let agent: AgentEntity = bincode::deserialize(data.value())
-                    .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize agent: {}", e)))?;
-                Ok(Some(agent))
-            }
-            Ok(None) => Ok(None),
-            Err(e) => Err(StorageError::TransactionError(format!("Failed to get agent: {}", e))),
-        }
+            // Re-embed changed content unless the caller supplied a new vector
+            let embedding = match &knowledge.embeddings {
+                Some(embedding) if existing.content == knowledge.content || *embedding != previous_embedding => {
+                    self.check_embedding(embedding)?;
+                    embedding.clone()
+                }
+                _ if existing.content == knowledge.content => previous_embedding.clone(),
+                _ => self.generate_embedding(&knowledge.content).await?,
+            };
+
+            let stored = KnowledgeEntity { embeddings: Some(embedding.clone()), ..knowledge.clone() };
+            let knowledge_data = self.encode_row(EntityTable::Knowledge, &stored)?;
+
+            let point = knowledge_point(knowledge, embedding);
+            let previous_point = knowledge_point(&existing, previous_embedding);
+            let active = self.knowledge_collection().await;
+            let collection = active.clone();
+
+            self.execute_coordinated_transaction(
+                OperationType::Update,
+                vec![VectorIntent::Upsert { collection: collection.clone(), points: vec![point] }],
+                |txn| {
+                    // Deleted since it was read: abort rather than resurrect it
+                    let Some(previous) = txn.insert(KNOWLEDGE_TABLE, &knowledge_key, &knowledge_data)? else {
+                        return Err(StorageError::NotFound(format!("Knowledge {} does not exist", knowledge.id)));
+                    };
+                    revisions::archive_replaced(txn, self.cipher(), Some(&previous), Some(knowledge))?;
+                    index_derived(txn.transaction(), knowledge, self.cipher())?;
+                    Ok(vec![VectorIntent::Upsert { collection, points: vec![previous_point] }])
+                },
+            ).await
+        }).await
     }
 
-    async fn search_knowledge(&self, query: &str, limit: usize) -> Result<Vec<KnowledgeEntity>, StorageError> {
-        // Generate query embedding
-        let query_embedding = self.generate_embedding(query).await?;
+    async fn delete_knowledge(&self, id: &Uuid) -> Result<bool, StorageError> {
+        self.instrument("delete_knowledge", async {
+            let knowledge_key = id.to_string();
+
+            let existing = match self.get_knowledge(id).await? {
+                Some(existing) => existing,
+                None => return Ok(false),
+            };
+            let previous_point = knowledge_point(&existing, self.stored_embedding(&existing).await?);
+            let chunks = self.time_redb(|| self.load_chunk_ids(id))?;
+            let active = self.knowledge_collection().await;
+            let collection = active.clone();
+
+            let ids = std::iter::once(*id).chain(chunks.iter().copied()).collect();
+            self.execute_coordinated_transaction(
+                OperationType::Delete,
+                vec![VectorIntent::Delete { collection: collection.clone(), ids }],
+                |txn| {
+                    let Some(previous) = txn.remove(KNOWLEDGE_TABLE, &knowledge_key)? else {
+                        return Err(StorageError::NotFound(format!("Knowledge {} does not exist", id)));
+                    };
+                    revisions::archive_replaced(txn, self.cipher(), Some(&previous), None)?;
+                    unindex_derived(txn.transaction(), &knowledge_key)?;
+                    relations::remove_entity_relations(txn, &EntityRef::knowledge(*id))?;
+
+                    let mut points = self.remove_document_chunks(txn, *id, &chunks)?;
+                    points.insert(0, previous_point);
+                    Ok(vec![VectorIntent::Upsert { collection, points }])
+                },
+            ).await?;
+
+            Ok(true)
+        }).await
+    }
 
-        // Search in Qdrant
-        let search_result = self.qdrant.search_points(&SearchPoints {
-            collection_name: self.config.collection_name.clone(),
-            vector: query_embedding,
-            limit: limit as u64,
-            with_payload: Some(WithPayloadSelector {
-                selector_options: Some(with_payload_selector::SelectorOptions::Enable(true)),
-            }),
-            ..Default::default()
+    async fn search_knowledge(&self, query: &str, limit: usize) -> Result<Vec<ScoredKnowledge>, StorageError> {
+        self.instrument("search_knowledge", async {
+            let search = KnowledgeSearch { query: query.to_string(), limit, ..Default::default() };
+            Ok(self.run_knowledge_search(&search).await?.results)
         }).await
-        .map_err(|e| StorageError::VectorError(format!("Failed to search vectors: {}", e)))?;
+    }
 
-        // Retrieve full knowledge entities from REDB
-        let mut knowledge_entities = Vec::new();
-        let read_txn = self.redb.begin_read()
-            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+    async fn get_knowledge_history(&self, id: &Uuid) -> Result<Vec<KnowledgeRevision>, StorageError> {
+        self.instrument("get_knowledge_history", async {
+            self.time_redb(|| self.load_knowledge_history(id))
+        }).await
+    }
 
-        let table = read_txn.open_table(KNOWLEDGE_TABLE)
-            .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge table: {}", e)))?;
+    async fn search_knowledge_as_of(
+        &self,
+        query: &str,
+        as_of: chrono::DateTime<chrono::Utc>,
+        limit: usize,
+    ) -> Result<Vec<ScoredKnowledge>, StorageError> {
+        self.instrument("search_knowledge_as_of", async {
+            self.rank_knowledge_as_of(query, as_of, limit).await
+        }).await
+    }
 
-        for scored_point in search_result.result {
-            if let Ok(Some(data)) = table.get(scored_point.id.unwrap().point_id_options.unwrap().to_string().as_str()) {
-                if let Ok(knowledge) = bincode::deserialize::<KnowledgeEntity>(data.value()) {
-                    knowledge_entities.push(knowledge);
-                }
-            }
-        }
+    async fn search_knowledge_hybrid(
+        &self,
+        query: &str,
+        limit: usize,
+        fusion: FusionStrategy,
+    ) -> Result<Vec<ScoredKnowledge>, StorageError> {
+        self.instrument("search_knowledge_hybrid", async {
+            let search = KnowledgeSearch { query: query.to_string(), limit, fusion: Some(fusion), ..Default::default() };
+            Ok(self.run_knowledge_search(&search).await?.results)
+        }).await
+    }
 
-        Ok(knowledge_entities)
+    async fn search_knowledge_page(&self, search: &KnowledgeSearch) -> Result<SearchPage, StorageError> {
+        self.instrument("search_knowledge_page", async {
+            self.run_knowledge_search(search).await
+        }).await
+    }
+
+    async fn search_documents(&self, search: &DocumentSearch) -> Result<Vec<DocumentMatch>, StorageError> {
+        self.instrument("search_documents", async {
+            self.run_document_search(search).await
+        }).await
+    }
+
+    async fn query_knowledge(&self, query: &KnowledgeQuery) -> Result<KnowledgeQueryResult, StorageError> {
+        self.instrument("query_knowledge", async {
+            self.run_knowledge_query(query).await
+        }).await
+    }
+
+    async fn list_tags(&self) -> Result<BTreeMap<String, usize>, StorageError> {
+        self.instrument("list_tags", async {
+            self.time_redb(|| self.load_tags())
+        }).await
+    }
+
+    async fn find_by_tags(&self, tags: &[String], matching: TagMatch) -> Result<Vec<KnowledgeEntity>, StorageError> {
+        self.instrument("find_by_tags", async {
+            self.time_redb(|| self.find_tagged_knowledge(tags, matching))
+        }).await
+    }
+
+    async fn add_relation(&self, relation: &Relation) -> Result<(), StorageError> {
+        self.instrument("add_relation", async {
+            self.store_relation(relation).await
+        }).await
+    }
+
+    async fn remove_relation(&self, from: &EntityRef, kind: RelationKind, to: &EntityRef) -> Result<bool, StorageError> {
+        self.instrument("remove_relation", async {
+            self.delete_relation(from, kind, to).await
+        }).await
+    }
+
+    async fn neighbors(
+        &self,
+        entity: &EntityRef,
+        direction: Direction,
+        kinds: &[RelationKind],
+    ) -> Result<Vec<Relation>, StorageError> {
+        self.instrument("neighbors", async {
+            self.time_redb(|| self.load_neighbors(entity, direction, kinds))
+        }).await
+    }
+
+    async fn find_path(
+        &self,
+        from: &EntityRef,
+        to: &EntityRef,
+        query: &PathQuery,
+    ) -> Result<Option<Vec<Relation>>, StorageError> {
+        self.instrument("find_path", async {
+            self.time_redb(|| self.shortest_path(from, to, query))
+        }).await
     }
 
     async fn update_coordination(&self, coordination: &CoordinationEntity) -> Result<(), StorageError> {
-        let coordination_key = coordination.id.to_string();
-        let coordination_data = bincode::serialize(coordination)
-            .map_err(|e| StorageError::SerializationError(format!("Failed to serialize coordination: {}", e)))?;
+        self.instrument("update_coordination", async {
+            let coordination_key = coordination.id.to_string();
+            let coordination_data = self.encode_row(EntityTable::Coordination, coordination)?;
+
+            self.execute_coordinated_transaction(OperationType::Insert, vec![], |txn| {
+                txn.insert(COORDINATION_TABLE, &coordination_key, &coordination_data)?;
+                coordination_index::index_coordination(txn.transaction(), coordination, self.cipher())?;
+                Ok(vec![])
+            }).await
+        }).await
+    }
 
-        self.execute_coordinated_transaction(|write_txn| {
-            let mut table = write_txn.open_table(COORDINATION_TABLE)
-                .map_err(|e| StorageError::TransactionError(format!("Failed to open coordination table: {}", e)))?;
+    async fn list_coordination(&self, session_id: &Uuid) -> Result<Vec<CoordinationEntity>, StorageError> {
+        self.instrument("list_coordination", async {
+            self.time_redb(|| self.list_session_coordination(session_id))
+        }).await
+    }
 
-            table.insert(coordination_key.as_str(), coordination_data.as_slice())
-                .map_err(|e| StorageError::TransactionError(format!("Failed to insert coordination: {}", e)))?;
+    async fn list_by_agent(&self, agent_id: &Uuid, range: TimeRange) -> Result<Vec<CoordinationEntity>, StorageError> {
+        self.instrument("list_by_agent", async {
+            self.time_redb(|| self.list_agent_coordination(agent_id, range))
+        }).await
+    }
 
-            Ok(())
+    async fn list_by_status(&self, status: &str) -> Result<Vec<CoordinationEntity>, StorageError> {
+        self.instrument("list_by_status", async {
+            self.time_redb(|| self.list_status_coordination(status))
+        }).await
+    }
+
+    async fn record_task_history(&self, entry: &TaskHistoryEntity) -> Result<(), StorageError> {
+        self.instrument("record_task_history", async {
+            self.store_task_history(entry).await
+        }).await
+    }
+
+    async fn search_task_history(&self, query: &str, limit: usize) -> Result<Vec<ScoredTaskHistory>, StorageError> {
+        self.instrument("search_task_history", async {
+            self.rank_task_history(query, limit).await
         }).await
     }
 
     async fn synchronize(&self) -> Result<SyncResult, StorageError> {
-        let mut state = self.state.write().await;
+        self.instrument("synchronize", async {
+            let start_time = chrono::Utc::now();
 
-        let pending_count = state.pending_operations.len();
-        let start_time = chrono::Utc::now();
+            // Drain deferred vector writes (journal and outbox) in batches
+            let flushed = self.flush_until_empty().await?;
 
-        // Process pending operations (simplified)
-        state.pending_operations.clear();
-        state.sync_status.last_sync = Some(chrono::Utc::now());
-        state.sync_status.pending_count = 0;
+            // Settle anything left in the journal, e.g. interrupted rollbacks
+            let report = self.recover().await?;
 
-        let duration = chrono::Utc::now() - start_time;
+            let duration = chrono::Utc::now() - start_time;
 
-        Ok(SyncResult {
-            operations_processed: pending_count,
-            duration_ms: duration.num_milliseconds() as u64,
-            success: true,
-        })
+            Ok(SyncResult {
+                operations_processed: flushed.applied + report.rolled_forward + report.rolled_back,
+                duration_ms: duration.num_milliseconds() as u64,
+                success: flushed.remaining == 0 && report.pending == 0,
+            })
+        }).await
+    }
+
+    async fn sync_status(&self) -> SyncStatus {
+        let status = self.instrument("sync_status", async {
+            Ok(self.state.read().await.sync_status.clone())
+        }).await;
+        status.unwrap_or_default()
     }
 
     async fn get_metrics(&self) -> StorageMetrics {
-        let state = self.state.read().await;
-        state.performance_metrics.clone()
+        let counts = self.instrument("get_metrics", async {
+            self.time_redb(|| self.knowledge_counts())
+        }).await;
+        self.metrics_snapshot(counts)
+    }
+
+    async fn verify(&self) -> Result<ConsistencyReport, StorageError> {
+        self.instrument("verify", self.run_verify()).await
+    }
+
+    async fn repair(&self, policy: &RepairPolicy) -> Result<RepairReport, StorageError> {
+        self.instrument("repair", self.run_repair(policy)).await
+    }
+
+    async fn shutdown(&self) {
+        let stopped = self.instrument("shutdown", async {
+            self.stop_sync_worker().await;
+            Ok(())
+        }).await;
+        stopped.unwrap_or_default()
     }
 }
 
@@ -469,6 +1373,27 @@ pub enum StorageError {
 
     #[error("Configuration error: {0}")]
     ConfigurationError(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("I/O error: {0}")]
+    IoError(String),
+}
+
+/// Outcome of a batch write, one entry per input item in input order
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchReport {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub items: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    pub id: Uuid,
+    pub success: bool,
+    pub error: Option<String>,
 }
 
 /// Synchronization result
@@ -488,6 +1413,30 @@ impl Default for StorageConfig {
             embedding_dimension: 384, // Common embedding dimension
             sync_batch_size: 100,
             consistency_mode: ConsistencyMode::Eventually,
+            outbox_retained_events: default_outbox_retained_events(),
+            vector_backend: VectorBackend::Qdrant,
+            embedding_backend: EmbeddingBackend::Hashing,
+            embedding_model_dir: None,
+            embedding_batch_size: default_embedding_batch_size(),
+            schema_migration: SchemaMigration::OnOpen,
+            encryption: None,
+            retention: RetentionConfig::default(),
+            deduplication: DeduplicationConfig::default(),
         }
     }
+}
+
+fn default_embedding_batch_size() -> usize {
+    32
+}
+
+fn default_outbox_retained_events() -> usize {
+    10_000
+}
+
+impl StorageConfig {
+    /// Location of the in-process vector index, next to the REDB file
+    pub fn vector_index_path(&self) -> PathBuf {
+        Path::new(&self.redb_path).with_extension("vectors.redb")
+    }
 }
\ No newline at end of file
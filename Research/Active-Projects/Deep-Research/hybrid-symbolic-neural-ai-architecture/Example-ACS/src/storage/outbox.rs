@@ -0,0 +1,381 @@
+//! Transactional Outbox - Event-Driven Consistency
+//!
+//! Under `ConsistencyMode::EventDriven` knowledge writes append an event to
+//! `OUTBOX_TABLE` in the same REDB transaction as the entity rows. A projector
+//! applies the events to the vector index in sequence order and records its
+//! progress as a checkpoint in `METADATA_TABLE`. Vector operations are
+//! idempotent, so the log can be replayed from any event still retained: the
+//! transaction that advances the checkpoint prunes projected events beyond the
+//! last `StorageConfig::outbox_retained_events`.
+//!
+//! An event that fails `MAX_EVENT_ATTEMPTS` times while the index accepts the
+//! event after it is moved to `OUTBOX_DEAD_LETTER_TABLE` and projection moves
+//! on; an index outage leaves events in place. Dead-lettered events are
+//! counted in `SyncStatus::quarantined_count` and `requeue_quarantined`
+//! appends them to the log again.
+
+use super::coordination::VectorIntent;
+use super::{HybridStorageCoordinator, OperationType, StorageError, METADATA_TABLE};
+use redb::{ReadableTable, ReadableTableMetadata, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+
+/// Append-only event log, keyed by sequence number (starting at 1)
+pub(crate) const OUTBOX_TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new("knowledge_outbox");
+
+/// Events taken out of the log after failing repeatedly, keyed by their sequence number
+const OUTBOX_DEAD_LETTER_TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new("knowledge_outbox_dead_letter");
+
+/// Metadata key holding the sequence number of the last projected event
+const OUTBOX_CHECKPOINT_KEY: &str = "outbox_checkpoint";
+
+/// Metadata key holding the sequence number of the last pruned event
+const OUTBOX_PRUNED_KEY: &str = "outbox_pruned_through";
+
+/// Failed projections of one event before it can be dead-lettered
+const MAX_EVENT_ATTEMPTS: u32 = 3;
+
+/// Vector index change recorded alongside an entity write
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEvent {
+    pub sequence: u64,
+    pub operation_type: String,
+    pub intents: Vec<VectorIntent>,
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+    /// Failed projections so far
+    #[serde(default)]
+    pub attempts: u32,
+    /// Error of the last failed projection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+}
+
+/// Outcome of a projection pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectionReport {
+    pub events_applied: usize,
+    /// Events moved to the dead-letter table
+    #[serde(default)]
+    pub events_quarantined: usize,
+    pub checkpoint: u64,
+    pub lag: usize,
+}
+
+/// Append an event inside the caller's write transaction, returning its sequence number
+pub(crate) fn append_outbox_event(
+    write_txn: &WriteTransaction,
+    operation_type: OperationType,
+    intents: Vec<VectorIntent>,
+) -> Result<u64, StorageError> {
+    let event = OutboxEvent {
+        sequence: 0,
+        operation_type: format!("{:?}", operation_type),
+        intents,
+        recorded_at: chrono::Utc::now(),
+        attempts: 0,
+        failure: None,
+    };
+    append_event(write_txn, event)
+}
+
+/// Append `event` under the next sequence number, which it is given
+fn append_event(write_txn: &WriteTransaction, mut event: OutboxEvent) -> Result<u64, StorageError> {
+    // Projected events are pruned, so numbering continues from the checkpoint when the log is empty
+    let checkpoint = {
+        let metadata = write_txn.open_table(METADATA_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+        read_sequence(&metadata, OUTBOX_CHECKPOINT_KEY)?
+    };
+
+    let mut table = write_txn.open_table(OUTBOX_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open outbox table: {}", e)))?;
+
+    let last = table.last()
+        .map_err(|e| StorageError::TransactionError(format!("Failed to read outbox: {}", e)))?
+        .map(|(key, _)| key.value())
+        .unwrap_or(0);
+    event.sequence = last.max(checkpoint) + 1;
+
+    table.insert(event.sequence, encode_event(&event)?.as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to append outbox event: {}", e)))?;
+
+    Ok(event.sequence)
+}
+
+fn encode_event(event: &OutboxEvent) -> Result<Vec<u8>, StorageError> {
+    serde_json::to_vec(event)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to serialize outbox event: {}", e)))
+}
+
+impl HybridStorageCoordinator {
+    /// Apply every outbox event after the checkpoint to the vector index
+    ///
+    /// Stops at the first event that fails so ordering is preserved; the
+    /// checkpoint only advances past events that were applied. An event that
+    /// has failed `MAX_EVENT_ATTEMPTS` times is dead-lettered once the event
+    /// after it applies.
+    pub async fn project_outbox(&self) -> Result<ProjectionReport, StorageError> {
+        let _sync = self.sync_lock.lock().await;
+        let batch_size = self.config.sync_batch_size.max(1);
+
+        let mut checkpoint = self.outbox_checkpoint()?;
+        let mut cursor = checkpoint;
+        let mut events_applied = 0;
+        let mut events_quarantined = 0;
+        // Event out of attempts, waiting for the next one to show whether the index is up
+        let mut held: Option<OutboxEvent> = None;
+        let mut failure = None;
+
+        'batches: loop {
+            let events = self.read_outbox(cursor, batch_size)?;
+            let batch_len = events.len();
+
+            for event in events {
+                cursor = event.sequence;
+                match self.apply_vector_intents(&event.intents).await {
+                    Ok(()) => {
+                        if let Some(stuck) = held.take() {
+                            tracing::warn!(
+                                "Quarantining outbox event {}: {}",
+                                stuck.sequence, stuck.failure.as_deref().unwrap_or_default()
+                            );
+                            self.dead_letter_outbox_event(&stuck)?;
+                            events_quarantined += 1;
+                        }
+                        checkpoint = event.sequence;
+                        events_applied += 1;
+                    }
+                    Err(e) => {
+                        if held.is_none() {
+                            let failed = OutboxEvent { attempts: event.attempts + 1, failure: Some(e.to_string()), ..event };
+                            if failed.attempts >= MAX_EVENT_ATTEMPTS {
+                                held = Some(failed);
+                                continue;
+                            }
+                            self.record_outbox_failure(&failed)?;
+                        }
+                        failure = Some(e);
+                        break 'batches;
+                    }
+                }
+            }
+
+            self.write_outbox_checkpoint(checkpoint)?;
+            if batch_len < batch_size {
+                break;
+            }
+        }
+
+        // Nothing after the held event applied, so it stays for the next pass
+        if let Some(stuck) = held {
+            self.record_outbox_failure(&stuck)?;
+            failure.get_or_insert(StorageError::VectorError(stuck.failure.unwrap_or_default()));
+        }
+        self.write_outbox_checkpoint(checkpoint)?;
+
+        if let Some(e) = failure {
+            tracing::warn!("Outbox projection stopped after event {}: {}", checkpoint, e);
+            let mut state = self.state.write().await;
+            state.sync_status.error_count += 1;
+        }
+
+        let lag = self.outbox_lag(checkpoint)?;
+        self.refresh_pending_count().await;
+        self.state.write().await.sync_status.last_sync = Some(chrono::Utc::now());
+
+        Ok(ProjectionReport {
+            events_applied,
+            events_quarantined,
+            checkpoint,
+            lag,
+        })
+    }
+
+    /// Rewind the checkpoint and re-apply the log starting at `from_sequence`
+    ///
+    /// Fails with `NotFound` if events from `from_sequence` on were already pruned.
+    pub async fn replay_outbox(&self, from_sequence: u64) -> Result<ProjectionReport, StorageError> {
+        {
+            let _sync = self.sync_lock.lock().await;
+            let pruned_through = self.read_outbox_metadata(OUTBOX_PRUNED_KEY)?;
+            if from_sequence <= pruned_through {
+                return Err(StorageError::NotFound(format!(
+                    "Outbox events up to {} were pruned; replay from {} or later", pruned_through, pruned_through + 1
+                )));
+            }
+            let checkpoint = self.outbox_checkpoint()?;
+            self.write_outbox_checkpoint(checkpoint.min(from_sequence.saturating_sub(1)))?;
+        }
+        self.project_outbox().await
+    }
+
+    /// Read up to `limit` events with a sequence number greater than `after`
+    pub fn read_outbox(&self, after: u64, limit: usize) -> Result<Vec<OutboxEvent>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(OUTBOX_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open outbox table: {}", e))),
+        };
+
+        let mut events = Vec::new();
+        for row in table.range((after + 1)..)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read outbox: {}", e)))?
+            .take(limit)
+        {
+            let (_, data) = row
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read outbox: {}", e)))?;
+            let event: OutboxEvent = serde_json::from_slice(data.value())
+                .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize outbox event: {}", e)))?;
+            events.push(event);
+        }
+
+        Ok(events)
+    }
+
+    /// Sequence number of the last event applied to the vector index
+    pub fn outbox_checkpoint(&self) -> Result<u64, StorageError> {
+        self.read_outbox_metadata(OUTBOX_CHECKPOINT_KEY)
+    }
+
+    fn read_outbox_metadata(&self, key: &str) -> Result<u64, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(0),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        };
+
+        read_sequence(&table, key)
+    }
+
+    /// Move the checkpoint and prune projected events beyond the retained ones
+    fn write_outbox_checkpoint(&self, checkpoint: u64) -> Result<(), StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let mut table = write_txn.open_table(METADATA_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+            table.insert(OUTBOX_CHECKPOINT_KEY, checkpoint.to_le_bytes().as_slice())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to write outbox checkpoint: {}", e)))?;
+
+            let prune_through = checkpoint.saturating_sub(self.config.outbox_retained_events as u64);
+            if prune_through > read_sequence(&table, OUTBOX_PRUNED_KEY)? {
+                let mut outbox = write_txn.open_table(OUTBOX_TABLE)
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to open outbox table: {}", e)))?;
+                outbox.retain_in(..=prune_through, |_, _| false)
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to prune outbox: {}", e)))?;
+                table.insert(OUTBOX_PRUNED_KEY, prune_through.to_le_bytes().as_slice())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to write outbox checkpoint: {}", e)))?;
+            }
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit outbox checkpoint: {}", e)))
+    }
+
+    /// Store the attempt count and error of an event that failed to project
+    fn record_outbox_failure(&self, event: &OutboxEvent) -> Result<(), StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let mut outbox = write_txn.open_table(OUTBOX_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open outbox table: {}", e)))?;
+            outbox.insert(event.sequence, encode_event(event)?.as_slice())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to write outbox event: {}", e)))?;
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit outbox event: {}", e)))
+    }
+
+    /// Move an event from the log to the dead-letter table
+    fn dead_letter_outbox_event(&self, event: &OutboxEvent) -> Result<(), StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let mut outbox = write_txn.open_table(OUTBOX_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open outbox table: {}", e)))?;
+            outbox.remove(event.sequence)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to remove outbox event: {}", e)))?;
+            let mut dead_letter = write_txn.open_table(OUTBOX_DEAD_LETTER_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open outbox dead-letter table: {}", e)))?;
+            dead_letter.insert(event.sequence, encode_event(event)?.as_slice())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to write outbox dead-letter event: {}", e)))?;
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit outbox dead letter: {}", e)))
+    }
+
+    /// Append every dead-lettered event to the log again with its attempts reset, returning how many
+    pub(crate) fn requeue_outbox_dead_letters(&self) -> Result<usize, StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        let mut requeued = Vec::new();
+        {
+            let mut dead_letter = write_txn.open_table(OUTBOX_DEAD_LETTER_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open outbox dead-letter table: {}", e)))?;
+            while let Some((_, data)) = dead_letter.pop_first()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read outbox dead-letter table: {}", e)))?
+            {
+                let event: OutboxEvent = serde_json::from_slice(data.value())
+                    .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize outbox event: {}", e)))?;
+                requeued.push(OutboxEvent { attempts: 0, failure: None, ..event });
+            }
+        }
+        let count = requeued.len();
+        for event in requeued {
+            append_event(&write_txn, event)?;
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit outbox requeue: {}", e)))?;
+        Ok(count)
+    }
+
+    pub(crate) fn outbox_dead_letter_len(&self) -> Result<usize, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        match read_txn.open_table(OUTBOX_DEAD_LETTER_TABLE) {
+            Ok(table) => table.len()
+                .map(|len| len as usize)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read outbox dead-letter table: {}", e))),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(0),
+            Err(e) => Err(StorageError::TransactionError(format!("Failed to open outbox dead-letter table: {}", e))),
+        }
+    }
+
+    pub(crate) fn outbox_lag(&self, checkpoint: u64) -> Result<usize, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(OUTBOX_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(0),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open outbox table: {}", e))),
+        };
+
+        let last = table.last()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read outbox: {}", e)))?
+            .map(|(key, _)| key.value())
+            .unwrap_or(0);
+
+        Ok(last.saturating_sub(checkpoint) as usize)
+    }
+}
+
+/// Sequence number stored under `key` in the metadata table, 0 if unset
+fn read_sequence(metadata: &impl ReadableTable<&'static str, &'static [u8]>, key: &str) -> Result<u64, StorageError> {
+    let Some(value) = metadata.get(key)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to read {}: {}", key, e)))?
+    else {
+        return Ok(0);
+    };
+
+    <[u8; 8]>::try_from(value.value())
+        .map(u64::from_le_bytes)
+        .map_err(|_| StorageError::SerializationError(format!(
+            "{} is {} bytes, expected 8", key, value.value().len()
+        )))
+}
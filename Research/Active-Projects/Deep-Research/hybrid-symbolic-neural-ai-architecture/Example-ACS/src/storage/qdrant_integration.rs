@@ -0,0 +1,243 @@
+//! Qdrant Vector Index
+//!
+//! `VectorIndex` implementation backed by a Qdrant server, the production
+//! vector store recommended by the research. Payloads are converted between
+//! `serde_json::Value` and Qdrant's protobuf values at this boundary so the
+//! rest of the storage layer never sees Qdrant types.
+
+use super::vector_index::{VectorIndex, VectorMatch, VectorPoint};
+use super::StorageError;
+use async_trait::async_trait;
+use ::qdrant_client::{client::QdrantClient, qdrant::*};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Vector index stored in a remote Qdrant instance
+pub struct QdrantIndex {
+    client: QdrantClient,
+}
+
+impl QdrantIndex {
+    /// Connect to the Qdrant server at `url`
+    pub fn connect(url: &str) -> Result<Self, StorageError> {
+        let client = QdrantClient::from_url(url)
+            .build()
+            .map_err(|e| StorageError::InitializationError(format!("Qdrant connection failed: {}", e)))?;
+
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl VectorIndex for QdrantIndex {
+    async fn ensure_collection(&self, collection: &str, dimension: usize) -> Result<(), StorageError> {
+        let collections = self.client.list_collections().await
+            .map_err(|e| StorageError::InitializationError(format!("Failed to list collections: {}", e)))?;
+
+        if collections.collections.iter().any(|c| c.name == collection) {
+            let info = self.client.collection_info(collection).await
+                .map_err(|e| StorageError::InitializationError(format!("Failed to read collection info: {}", e)))?;
+            let existing = info.result
+                .and_then(|info| info.config)
+                .and_then(|config| config.params)
+                .and_then(|params| params.vectors_config)
+                .and_then(|vectors| vectors.config);
+
+            // Points are stored as one unnamed vector, so a collection of named vectors cannot hold them either
+            return match existing {
+                Some(vectors_config::Config::Params(params)) if params.size == dimension as u64 => Ok(()),
+                Some(vectors_config::Config::Params(params)) => Err(StorageError::ConfigurationError(format!(
+                    "Collection {} has dimension {}, expected {}",
+                    collection, params.size, dimension
+                ))),
+                _ => Err(StorageError::ConfigurationError(format!(
+                    "Collection {} has no unnamed vector of dimension {}",
+                    collection, dimension
+                ))),
+            };
+        }
+
+        self.client.create_collection(&CreateCollection {
+            collection_name: collection.to_string(),
+            vectors_config: Some(VectorsConfig {
+                config: Some(vectors_config::Config::Params(VectorParams {
+                    size: dimension as u64,
+                    distance: Distance::Cosine as i32,
+                    ..Default::default()
+                })),
+            }),
+            ..Default::default()
+        }).await
+        .map_err(|e| StorageError::InitializationError(format!("Failed to create collection: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<(), StorageError> {
+        let points = points.into_iter()
+            .map(|point| PointStruct {
+                id: Some(PointId::from(point.id.to_string())),
+                payload: point.payload.into_iter()
+                    .map(|(key, value)| (key, json_to_qdrant(value)))
+                    .collect(),
+                vectors: Some(Vectors::from(point.vector)),
+            })
+            .collect();
+
+        self.client.upsert_points_blocking(collection, None, points, None).await
+            .map_err(|e| StorageError::VectorError(format!("Failed to store vectors: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, collection: &str, ids: &[Uuid]) -> Result<(), StorageError> {
+        let selector = PointsSelector {
+            points_selector_one_of: Some(points_selector::PointsSelectorOneOf::Points(PointsIdsList {
+                ids: ids.iter().map(|id| PointId::from(id.to_string())).collect(),
+            })),
+        };
+
+        self.client.delete_points_blocking(collection, None, &selector, None).await
+            .map_err(|e| StorageError::VectorError(format!("Failed to delete vectors: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<(), StorageError> {
+        let collections = self.client.list_collections().await
+            .map_err(|e| StorageError::VectorError(format!("Failed to list collections: {}", e)))?;
+
+        if collections.collections.iter().any(|c| c.name == collection) {
+            self.client.delete_collection(collection).await
+                .map_err(|e| StorageError::VectorError(format!("Failed to delete collection: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        offset: Option<Uuid>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<Uuid>), StorageError> {
+        let response = self.client.scroll(&ScrollPoints {
+            collection_name: collection.to_string(),
+            offset: offset.map(|id| PointId::from(id.to_string())),
+            limit: Some(limit as u32),
+            with_payload: Some(WithPayloadSelector {
+                selector_options: Some(with_payload_selector::SelectorOptions::Enable(true)),
+            }),
+            with_vectors: Some(WithVectorsSelector {
+                selector_options: Some(with_vectors_selector::SelectorOptions::Enable(true)),
+            }),
+            ..Default::default()
+        }).await
+        .map_err(|e| StorageError::VectorError(format!("Failed to scroll vectors: {}", e)))?;
+
+        let points = response.result.into_iter()
+            .map(|point| {
+                Ok(VectorPoint {
+                    id: point_uuid(point.id)?,
+                    vector: point_vector(point.vectors),
+                    payload: payload_to_json(point.payload),
+                })
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        let next_offset = response.next_page_offset
+            .map(|id| point_uuid(Some(id)))
+            .transpose()?;
+
+        Ok((points, next_offset))
+    }
+
+    async fn search(&self, collection: &str, vector: &[f32], limit: usize) -> Result<Vec<VectorMatch>, StorageError> {
+        let search_result = self.client.search_points(&SearchPoints {
+            collection_name: collection.to_string(),
+            vector: vector.to_vec(),
+            limit: limit as u64,
+            with_payload: Some(WithPayloadSelector {
+                selector_options: Some(with_payload_selector::SelectorOptions::Enable(true)),
+            }),
+            ..Default::default()
+        }).await
+        .map_err(|e| StorageError::VectorError(format!("Failed to search vectors: {}", e)))?;
+
+        search_result.result.into_iter()
+            .map(|scored_point| {
+                Ok(VectorMatch {
+                    id: point_uuid(scored_point.id)?,
+                    score: scored_point.score,
+                    payload: payload_to_json(scored_point.payload),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Extract the UUID from a Qdrant point ID (this index only writes UUID IDs)
+fn point_uuid(id: Option<PointId>) -> Result<Uuid, StorageError> {
+    match id.and_then(|id| id.point_id_options) {
+        Some(point_id::PointIdOptions::Uuid(uuid)) => Uuid::parse_str(&uuid)
+            .map_err(|e| StorageError::VectorError(format!("Invalid point ID {}: {}", uuid, e))),
+        Some(point_id::PointIdOptions::Num(num)) => Err(StorageError::VectorError(format!(
+            "Unexpected numeric point ID: {}", num
+        ))),
+        None => Err(StorageError::VectorError("Point without ID".to_string())),
+    }
+}
+
+/// Extract the unnamed vector of a retrieved point (this index only writes unnamed vectors)
+fn point_vector(vectors: Option<Vectors>) -> Vec<f32> {
+    match vectors.and_then(|vectors| vectors.vectors_options) {
+        #[allow(deprecated)]
+        Some(vectors::VectorsOptions::Vector(vector)) => vector.data,
+        _ => Vec::new(),
+    }
+}
+
+fn payload_to_json(payload: HashMap<String, Value>) -> HashMap<String, serde_json::Value> {
+    payload.into_iter()
+        .map(|(key, value)| (key, qdrant_to_json(value)))
+        .collect()
+}
+
+fn json_to_qdrant(value: serde_json::Value) -> Value {
+    let kind = match value {
+        serde_json::Value::Null => value::Kind::NullValue(0),
+        serde_json::Value::Bool(b) => value::Kind::BoolValue(b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => value::Kind::IntegerValue(i),
+            None => value::Kind::DoubleValue(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => value::Kind::StringValue(s),
+        serde_json::Value::Array(values) => value::Kind::ListValue(ListValue {
+            values: values.into_iter().map(json_to_qdrant).collect(),
+        }),
+        serde_json::Value::Object(fields) => value::Kind::StructValue(Struct {
+            fields: fields.into_iter()
+                .map(|(key, value)| (key, json_to_qdrant(value)))
+                .collect(),
+        }),
+    };
+
+    Value { kind: Some(kind) }
+}
+
+fn qdrant_to_json(value: Value) -> serde_json::Value {
+    match value.kind {
+        None | Some(value::Kind::NullValue(_)) => serde_json::Value::Null,
+        Some(value::Kind::BoolValue(b)) => b.into(),
+        Some(value::Kind::IntegerValue(i)) => i.into(),
+        Some(value::Kind::DoubleValue(d)) => d.into(),
+        Some(value::Kind::StringValue(s)) => s.into(),
+        Some(value::Kind::ListValue(list)) => serde_json::Value::Array(
+            list.values.into_iter().map(qdrant_to_json).collect(),
+        ),
+        Some(value::Kind::StructValue(object)) => serde_json::Value::Object(
+            object.fields.into_iter()
+                .map(|(key, value)| (key, qdrant_to_json(value)))
+                .collect(),
+        ),
+    }
+}
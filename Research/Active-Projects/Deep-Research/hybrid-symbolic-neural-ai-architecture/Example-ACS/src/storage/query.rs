@@ -0,0 +1,217 @@
+//! Filtered and Faceted Knowledge Queries
+//!
+//! `KnowledgeQuery` combines a ranking query (semantic or hybrid) with filters
+//! evaluated against the REDB entities: minimum Admiralty rating, source
+//! allow/deny lists, a `created_at` range, metadata equality and tags. Ranked
+//! candidates are fetched in growing pools until enough of them pass the
+//! filters, so restrictive filters never starve the result set. A query without
+//! text browses every entry, oldest `created_at` first.
+//!
+//! Facets count the candidates that passed the filters. For a ranked query that
+//! is the top-N candidate pool, not every entry that would pass; only a query
+//! without text facets the whole knowledge base.
+
+use super::lexical::FusionStrategy;
+use super::schema::EntityTable;
+use super::search::{ConsistencyWarning, ConsistencyWarningKind};
+use super::{HybridStorageCoordinator, KnowledgeEntity, StorageError, KNOWLEDGE_TABLE};
+use redb::ReadableTable;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Smallest candidate pool ranked for a filtered query
+const MIN_CANDIDATE_POOL: usize = 50;
+
+/// Knowledge search with storage-level filters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KnowledgeQuery {
+    /// Ranking text; empty to list every entry that passes the filters, oldest first
+    pub text: String,
+    pub limit: usize,
+    /// `None` ranks by vector similarity only
+    pub fusion: Option<FusionStrategy>,
+    /// Minimum Admiralty rating, e.g. "B3" (reliability B or better and credibility 3 or better)
+    pub min_credibility: Option<String>,
+    /// Only these sources, if non-empty
+    pub sources: Vec<String>,
+    pub excluded_sources: Vec<String>,
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+    /// Every key must be present in `metadata` with an equal value
+    pub metadata: HashMap<String, serde_json::Value>,
+    /// Every tag must be present in the entry's `tags`
+    pub tags: Vec<String>,
+}
+
+/// Filtered results plus facet counts over the candidates that passed the filters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KnowledgeQueryResult {
+    pub results: Vec<KnowledgeEntity>,
+    /// Facets over the top-N ranked candidates, or over every entry when the query has no text
+    pub candidate_facets: KnowledgeFacets,
+    /// Ranked IDs whose stored row could not be returned, or unreadable rows when browsing
+    pub warnings: Vec<ConsistencyWarning>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KnowledgeFacets {
+    /// Candidates counted; less than the number of matching entries when the pool was truncated
+    pub candidates: usize,
+    pub sources: BTreeMap<String, usize>,
+    pub credibility: BTreeMap<String, usize>,
+    pub tags: BTreeMap<String, usize>,
+}
+
+/// Admiralty (NATO) rating: source reliability A-F and information credibility 1-6, lower is better
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AdmiraltyRating {
+    reliability: u8,
+    credibility: u8,
+}
+
+impl AdmiraltyRating {
+    fn parse(rating: &str) -> Option<Self> {
+        let mut chars = rating.trim().chars();
+        let reliability = chars.next()?.to_ascii_uppercase();
+        let credibility = chars.next()?.to_digit(10)?;
+        if chars.next().is_some() || !('A'..='F').contains(&reliability) || !(1..=6).contains(&credibility) {
+            return None;
+        }
+        Some(Self {
+            reliability: reliability as u8 - b'A',
+            credibility: credibility as u8,
+        })
+    }
+
+    fn at_least(&self, minimum: &AdmiraltyRating) -> bool {
+        self.reliability <= minimum.reliability && self.credibility <= minimum.credibility
+    }
+}
+
+impl KnowledgeQuery {
+    fn matches(&self, knowledge: &KnowledgeEntity, minimum: Option<&AdmiraltyRating>) -> bool {
+        // Chunks are part of their document, not entries of their own
+        if knowledge.parent_id.is_some() {
+            return false;
+        }
+
+        if let Some(minimum) = minimum {
+            match AdmiraltyRating::parse(&knowledge.credibility_rating) {
+                Some(rating) if rating.at_least(minimum) => {}
+                _ => return false,
+            }
+        }
+
+        if !self.sources.is_empty() && !self.sources.contains(&knowledge.source) {
+            return false;
+        }
+        if self.excluded_sources.contains(&knowledge.source) {
+            return false;
+        }
+
+        if self.created_after.is_some_and(|after| knowledge.created_at < after) {
+            return false;
+        }
+        if self.created_before.is_some_and(|before| knowledge.created_at >= before) {
+            return false;
+        }
+
+        if !self.metadata.iter().all(|(key, value)| knowledge.metadata.get(key) == Some(value)) {
+            return false;
+        }
+
+        self.tags.iter().all(|tag| knowledge.tags.contains(tag))
+    }
+}
+
+impl KnowledgeFacets {
+    fn count(&mut self, knowledge: &KnowledgeEntity) {
+        self.candidates += 1;
+        *self.sources.entry(knowledge.source.clone()).or_default() += 1;
+        *self.credibility.entry(knowledge.credibility_rating.clone()).or_default() += 1;
+        for tag in &knowledge.tags {
+            *self.tags.entry(tag.clone()).or_default() += 1;
+        }
+    }
+}
+
+impl HybridStorageCoordinator {
+    pub(crate) async fn run_knowledge_query(&self, query: &KnowledgeQuery) -> Result<KnowledgeQueryResult, StorageError> {
+        let minimum = match &query.min_credibility {
+            Some(rating) => Some(AdmiraltyRating::parse(rating).ok_or_else(|| {
+                StorageError::ConfigurationError(format!("Invalid Admiralty rating: {}", rating))
+            })?),
+            None => None,
+        };
+
+        let (candidates, warnings) = if query.text.trim().is_empty() {
+            let (mut entities, warnings) = self.time_redb(|| self.scan_knowledge())?;
+            entities.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+            (entities, warnings)
+        } else {
+            let mut pool = query.limit.saturating_mul(4).max(MIN_CANDIDATE_POOL);
+            loop {
+                let ranked = self.rank_knowledge(&query.text, pool, query.fusion).await?;
+                let exhausted = ranked.len() < pool;
+
+                let ids: Vec<Uuid> = ranked.iter().map(|(id, _)| *id).collect();
+                let (entities, missing): (Vec<_>, Vec<_>) = self
+                    .time_redb(|| self.load_knowledge_entities(&ids))?
+                    .into_iter()
+                    .partition(Result::is_ok);
+                let entities: Vec<KnowledgeEntity> = entities.into_iter().filter_map(Result::ok).collect();
+                let passing = entities.iter().filter(|k| query.matches(k, minimum.as_ref())).count();
+
+                if passing >= query.limit || exhausted {
+                    break (entities, missing.into_iter().filter_map(Result::err).collect());
+                }
+                pool = pool.saturating_mul(4);
+            }
+        };
+
+        for warning in &warnings {
+            tracing::warn!("Query consistency warning: {}", warning);
+        }
+
+        let mut result = KnowledgeQueryResult { warnings, ..Default::default() };
+        for knowledge in candidates.into_iter().filter(|k| query.matches(k, minimum.as_ref())) {
+            result.candidate_facets.count(&knowledge);
+            if result.results.len() < query.limit {
+                result.results.push(knowledge);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Every readable knowledge entry in key order, plus a warning for each row that is not
+    fn scan_knowledge(&self) -> Result<(Vec<KnowledgeEntity>, Vec<ConsistencyWarning>), StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(KNOWLEDGE_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok((Vec::new(), Vec::new())),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open knowledge table: {}", e))),
+        };
+
+        let mut knowledge_entities = Vec::new();
+        let mut warnings = Vec::new();
+        for row in table.iter()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge table: {}", e)))?
+        {
+            let (key, data) = row
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge table: {}", e)))?;
+            match self.decode_row::<KnowledgeEntity>(EntityTable::Knowledge, data.value()) {
+                Ok(knowledge) => knowledge_entities.push(knowledge),
+                Err(e) => match Uuid::parse_str(key.value()) {
+                    Ok(id) => warnings.push(ConsistencyWarning { id, kind: ConsistencyWarningKind::Unreadable(e.to_string()) }),
+                    Err(_) => tracing::warn!("Knowledge row {} could not be read: {}", key.value(), e),
+                },
+            }
+        }
+
+        Ok((knowledge_entities, warnings))
+    }
+}
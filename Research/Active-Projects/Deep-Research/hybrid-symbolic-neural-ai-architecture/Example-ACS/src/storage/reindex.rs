@@ -0,0 +1,540 @@
+//! Vector Collection Reindexing
+//!
+//! REDB is the source of truth for knowledge, so the vector collection can be
+//! rebuilt from it at any time: after the collection was lost, or when the
+//! embedding model changed. A reindex:
+//!
+//! 1. Creates a fresh collection named after the configured one plus a
+//!    timestamp, and records a checkpoint in `METADATA_TABLE`.
+//! 2. Scans `KNOWLEDGE_TABLE` in key order, re-embedding each batch with the
+//!    configured provider, upserting it into the new collection and storing
+//!    the new embedding with the row. The checkpoint advances with each batch,
+//!    so an interrupted reindex resumes after the last completed batch.
+//! 3. Blocks knowledge writes, re-embeds the entries written while it was
+//!    scanning, and swaps the active collection in one REDB transaction.
+//!
+//! Searches and writes keep using the previous collection until the swap.
+//! While a checkpoint exists, every knowledge write also records its key in
+//! `REINDEX_CHANGES_TABLE`, in the same transaction, for step 3.
+
+use super::schema::{EntityTable, MigrationFailure};
+use super::{knowledge_point, HybridStorageCoordinator, KnowledgeEntity, StorageError, KNOWLEDGE_TABLE, METADATA_TABLE};
+use redb::{ReadableTable, ReadableTableMetadata, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Collection currently serving knowledge search, once it differs from `collection_name`
+pub(crate) const ACTIVE_COLLECTION_KEY: &str = "active_collection";
+
+/// JSON `ReindexCheckpoint` of the reindex in progress
+pub(crate) const REINDEX_CHECKPOINT_KEY: &str = "reindex_checkpoint";
+
+/// Knowledge keys written since the reindex in progress started
+const REINDEX_CHANGES_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("reindex_changes");
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReindexOptions {
+    /// Entries embedded per batch; defaults to `embedding_batch_size`
+    pub batch_size: Option<usize>,
+    /// Drop the previous collection once the new one is active
+    pub drop_previous: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReindexPhase {
+    Indexing,      // Scanning the knowledge table into the new collection
+    CatchingUp,    // Writes blocked; re-embedding entries changed during the scan
+    Swapped,       // The new collection is active
+}
+
+/// Progress of a reindex, reported after every batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReindexProgress {
+    pub collection: String,
+    pub phase: ReindexPhase,
+    pub indexed: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReindexReport {
+    pub previous_collection: String,
+    pub collection: String,
+    /// Entries embedded into the new collection, including those of an interrupted run
+    pub indexed: usize,
+    /// Entries re-embedded because they were written during the scan
+    pub caught_up: usize,
+    /// Whether this run continued an interrupted reindex
+    pub resumed: bool,
+    pub previous_dropped: bool,
+    /// Rows that could not be decoded; they are missing from the new collection
+    pub failures: Vec<MigrationFailure>,
+}
+
+/// Durable state of a reindex, rewritten after every batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReindexCheckpoint {
+    target_collection: String,
+    previous_collection: String,
+    embedding_dimension: usize,
+    /// `EmbeddingProvider::identity` of the model embedding into the target collection
+    #[serde(default)]
+    embedder: Option<String>,
+    /// Last knowledge key embedded into the target collection
+    last_key: Option<String>,
+    indexed: usize,
+    /// Checkpoints written before the phase was recorded were all taken while indexing
+    #[serde(default = "indexing_phase")]
+    phase: ReindexPhase,
+    failures: Vec<MigrationFailure>,
+    started_at: chrono::DateTime<chrono::Utc>,
+}
+
+fn indexing_phase() -> ReindexPhase {
+    ReindexPhase::Indexing
+}
+
+/// Knowledge row read for re-embedding, with the bytes it was decoded from
+struct ScannedRow {
+    key: String,
+    data: Vec<u8>,
+    knowledge: KnowledgeEntity,
+}
+
+/// Record a knowledge write for the reindex in progress, if any
+///
+/// Called from every transaction that writes a knowledge row, so entries the
+/// scan has already passed are re-embedded before the swap.
+pub(crate) fn track_knowledge_change(write_txn: &WriteTransaction, key: &str) -> Result<(), StorageError> {
+    let reindexing = {
+        let metadata = write_txn.open_table(METADATA_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+        let checkpoint = metadata.get(REINDEX_CHECKPOINT_KEY)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read reindex checkpoint: {}", e)))?;
+        checkpoint.is_some()
+    };
+
+    if reindexing {
+        let mut changes = write_txn.open_table(REINDEX_CHANGES_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open reindex changes table: {}", e)))?;
+        changes.insert(key, [].as_slice())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to record reindex change: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+impl HybridStorageCoordinator {
+    /// Rebuild the vector collection from `KNOWLEDGE_TABLE` and make it the active one
+    ///
+    /// Resumes an interrupted reindex from its checkpoint unless the embedding
+    /// model changed since, in which case it starts over: resuming would mix
+    /// vectors of two models in one collection. Checkpoints that predate the
+    /// recorded model identity start over too. `on_progress` is called after
+    /// every batch.
+    pub async fn reindex<F>(&self, options: &ReindexOptions, mut on_progress: F) -> Result<ReindexReport, StorageError>
+    where
+        F: FnMut(&ReindexProgress) + Send,
+    {
+        let batch_size = options.batch_size.unwrap_or(self.config.embedding_batch_size).max(1);
+
+        let embedder = self.embedder.identity();
+        let (mut checkpoint, resumed) = match self.reindex_checkpoint()? {
+            Some(checkpoint)
+                if checkpoint.embedding_dimension == self.config.embedding_dimension
+                    && checkpoint.embedder.as_deref() == Some(embedder.as_str()) =>
+            {
+                tracing::info!(
+                    "Resuming reindex into {} after {} entries",
+                    checkpoint.target_collection, checkpoint.indexed
+                );
+                (checkpoint, true)
+            }
+            abandoned => {
+                if let Some(abandoned) = abandoned {
+                    tracing::info!(
+                        "Abandoning reindex into {}: embedding model changed from {} to {}",
+                        abandoned.target_collection,
+                        abandoned.embedder.as_deref().unwrap_or("an unrecorded model"),
+                        embedder
+                    );
+                    self.vectors.delete_collection(&abandoned.target_collection).await?;
+                }
+                (self.start_reindex().await?, false)
+            }
+        };
+
+        self.vectors.ensure_collection(&checkpoint.target_collection, checkpoint.embedding_dimension).await?;
+
+        let total = checkpoint.indexed + self.count_knowledge_after(checkpoint.last_key.as_deref())?;
+        let mut progress = ReindexProgress {
+            collection: checkpoint.target_collection.clone(),
+            phase: ReindexPhase::Indexing,
+            indexed: checkpoint.indexed,
+            total,
+        };
+        on_progress(&progress);
+
+        // Phase 1: scan in key order, checkpointing after every batch
+        checkpoint.phase = ReindexPhase::Indexing;
+        loop {
+            let (rows, last_key) = self.scan_knowledge_batch(checkpoint.last_key.as_deref(), batch_size, &mut checkpoint.failures)?;
+            let Some(last_key) = last_key else {
+                break;
+            };
+
+            self.reembed_rows(&checkpoint.target_collection, &rows, false).await?;
+
+            checkpoint.last_key = Some(last_key);
+            checkpoint.indexed += rows.len();
+            self.write_reindex_checkpoint(&checkpoint)?;
+
+            progress.indexed = checkpoint.indexed;
+            progress.total = progress.total.max(progress.indexed);
+            tracing::info!("Reindexed {}/{} entries into {}", progress.indexed, progress.total, progress.collection);
+            on_progress(&progress);
+        }
+
+        // Phase 2: with knowledge writes blocked, catch up on changes and swap
+        let mut active = self.active_collection.write().await;
+        let previous_collection = active.clone();
+
+        checkpoint.phase = ReindexPhase::CatchingUp;
+        self.write_reindex_checkpoint(&checkpoint)?;
+        progress.phase = ReindexPhase::CatchingUp;
+        on_progress(&progress);
+
+        // Deferred writes name the previous collection; apply them before it can be dropped
+        let drain_previous = options.drop_previous && previous_collection != checkpoint.target_collection;
+        let previous_drained = drain_previous && self.flush_until_empty().await?.remaining == 0;
+
+        let changed = self.reindex_changes()?;
+        for keys in changed.chunks(batch_size) {
+            let rows = self.load_changed_rows(keys, &mut checkpoint.failures)?;
+            self.reembed_rows(&checkpoint.target_collection, &rows, true).await?;
+
+            let present: Vec<&str> = rows.iter().map(|row| row.key.as_str()).collect();
+            let deleted: Vec<Uuid> = keys.iter()
+                .filter(|key| !present.contains(&key.as_str()))
+                .filter_map(|key| Uuid::parse_str(key).ok())
+                .collect();
+            if !deleted.is_empty() {
+                self.vectors.delete(&checkpoint.target_collection, &deleted).await?;
+            }
+        }
+
+        self.finish_reindex(&checkpoint.target_collection)?;
+        *active = checkpoint.target_collection.clone();
+        drop(active);
+
+        progress.phase = ReindexPhase::Swapped;
+        tracing::info!(
+            "Knowledge collection swapped from {} to {} ({} entries, {} caught up)",
+            previous_collection, checkpoint.target_collection, checkpoint.indexed, changed.len()
+        );
+        on_progress(&progress);
+
+        let previous_dropped = if previous_drained {
+            self.vectors.delete_collection(&previous_collection).await?;
+            true
+        } else {
+            if drain_previous {
+                tracing::warn!("Keeping collection {}: deferred vector writes are still pending", previous_collection);
+            }
+            false
+        };
+
+        for failure in &checkpoint.failures {
+            tracing::warn!("Knowledge {} was not reindexed: {}", failure.key, failure.error);
+        }
+
+        Ok(ReindexReport {
+            previous_collection,
+            collection: checkpoint.target_collection,
+            indexed: checkpoint.indexed,
+            caught_up: changed.len(),
+            resumed,
+            previous_dropped,
+            failures: checkpoint.failures,
+        })
+    }
+
+    /// Progress of an interrupted or running reindex, if one is in progress
+    pub fn reindex_progress(&self) -> Result<Option<ReindexProgress>, StorageError> {
+        let Some(checkpoint) = self.reindex_checkpoint()? else {
+            return Ok(None);
+        };
+
+        Ok(Some(ReindexProgress {
+            total: checkpoint.indexed + self.count_knowledge_after(checkpoint.last_key.as_deref())?,
+            collection: checkpoint.target_collection,
+            phase: checkpoint.phase,
+            indexed: checkpoint.indexed,
+        }))
+    }
+
+    /// Collection recorded as active, or `collection_name` if no reindex has completed
+    pub(crate) fn stored_active_collection(&self) -> Result<String, StorageError> {
+        Ok(self.read_metadata(ACTIVE_COLLECTION_KEY)?
+            .map(|data| String::from_utf8_lossy(&data).into_owned())
+            .unwrap_or_else(|| self.config.collection_name.clone()))
+    }
+
+    /// Record a fresh checkpoint and forget changes tracked by an abandoned run
+    async fn start_reindex(&self) -> Result<ReindexCheckpoint, StorageError> {
+        let checkpoint = ReindexCheckpoint {
+            target_collection: format!(
+                "{}_{}",
+                self.config.collection_name,
+                chrono::Utc::now().format("%Y%m%d%H%M%S%3f")
+            ),
+            previous_collection: self.active_collection.read().await.clone(),
+            embedding_dimension: self.config.embedding_dimension,
+            embedder: Some(self.embedder.identity()),
+            last_key: None,
+            indexed: 0,
+            phase: ReindexPhase::Indexing,
+            failures: Vec::new(),
+            started_at: chrono::Utc::now(),
+        };
+
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        write_txn.delete_table(REINDEX_CHANGES_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to clear reindex changes: {}", e)))?;
+        put_checkpoint(&write_txn, &checkpoint)?;
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit reindex checkpoint: {}", e)))?;
+
+        tracing::info!("Reindexing knowledge into {}", checkpoint.target_collection);
+        Ok(checkpoint)
+    }
+
+    /// Embed rows into `collection` and store the new embeddings with them
+    ///
+    /// Outside the catch-up phase a row may change between the scan and the
+    /// write-back; such rows keep their new value, and the change tracking
+    /// re-embeds them before the swap. The write-back is not journaled: a
+    /// rollback ignores embeddings when it checks whether a row was written
+    /// again, so it still restores a row whose write failed (see `coordination`).
+    async fn reembed_rows(&self, collection: &str, rows: &[ScannedRow], writes_blocked: bool) -> Result<(), StorageError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let contents: Vec<&str> = rows.iter().map(|row| row.knowledge.content.as_str()).collect();
+        let embeddings = self.generate_embeddings(&contents).await?;
+
+        let points = rows.iter()
+            .zip(&embeddings)
+            .map(|(row, embedding)| knowledge_point(&row.knowledge, embedding.clone()))
+            .collect();
+        self.vectors.upsert(collection, points).await?;
+
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let mut table = write_txn.open_table(KNOWLEDGE_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge table: {}", e)))?;
+
+            for (row, embedding) in rows.iter().zip(embeddings) {
+                if !writes_blocked {
+                    let current = table.get(row.key.as_str())
+                        .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge: {}", e)))?
+                        .map(|data| data.value().to_vec());
+                    if current.as_deref() != Some(row.data.as_slice()) {
+                        continue;
+                    }
+                }
+
+                let stored = KnowledgeEntity { embeddings: Some(embedding), ..row.knowledge.clone() };
+                table.insert(row.key.as_str(), self.encode_row(EntityTable::Knowledge, &stored)?.as_slice())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to store embedding: {}", e)))?;
+            }
+        }
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit embeddings: {}", e)))
+    }
+
+    /// Read up to `limit` knowledge rows after `after`, returning them with the last key examined
+    fn scan_knowledge_batch(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+        failures: &mut Vec<MigrationFailure>,
+    ) -> Result<(Vec<ScannedRow>, Option<String>), StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(KNOWLEDGE_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok((Vec::new(), None)),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open knowledge table: {}", e))),
+        };
+
+        let range = match after {
+            Some(after) => table.range::<&str>((std::ops::Bound::Excluded(after), std::ops::Bound::Unbounded)),
+            None => table.range::<&str>(..),
+        }
+        .map_err(|e| StorageError::TransactionError(format!("Failed to scan knowledge: {}", e)))?;
+
+        let mut rows = Vec::new();
+        let mut last_key = None;
+        for row in range.take(limit) {
+            let (key, data) = row
+                .map_err(|e| StorageError::TransactionError(format!("Failed to scan knowledge: {}", e)))?;
+            let key = key.value().to_string();
+            last_key = Some(key.clone());
+
+            match self.decode_row::<KnowledgeEntity>(EntityTable::Knowledge, data.value()) {
+                Ok(knowledge) => rows.push(ScannedRow { key, data: data.value().to_vec(), knowledge }),
+                Err(e) => failures.push(MigrationFailure {
+                    table: EntityTable::Knowledge.name().to_string(),
+                    key,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        Ok((rows, last_key))
+    }
+
+    /// Current rows for changed keys; deleted keys are left out
+    fn load_changed_rows(&self, keys: &[String], failures: &mut Vec<MigrationFailure>) -> Result<Vec<ScannedRow>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(KNOWLEDGE_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open knowledge table: {}", e))),
+        };
+
+        let mut rows = Vec::new();
+        for key in keys {
+            let Some(data) = table.get(key.as_str())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge: {}", e)))?
+            else {
+                continue;
+            };
+
+            // An earlier failure for this key is superseded by its latest write
+            failures.retain(|failure| failure.key != *key);
+            match self.decode_row::<KnowledgeEntity>(EntityTable::Knowledge, data.value()) {
+                Ok(knowledge) => rows.push(ScannedRow { key: key.clone(), data: data.value().to_vec(), knowledge }),
+                Err(e) => failures.push(MigrationFailure {
+                    table: EntityTable::Knowledge.name().to_string(),
+                    key: key.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        Ok(rows)
+    }
+
+    /// Knowledge rows with a key after `after`
+    fn count_knowledge_after(&self, after: Option<&str>) -> Result<usize, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(KNOWLEDGE_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(0),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open knowledge table: {}", e))),
+        };
+
+        match after {
+            Some(after) => Ok(table.range::<&str>((std::ops::Bound::Excluded(after), std::ops::Bound::Unbounded))
+                .map_err(|e| StorageError::TransactionError(format!("Failed to scan knowledge: {}", e)))?
+                .count()),
+            None => Ok(table.len()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge table: {}", e)))? as usize),
+        }
+    }
+
+    /// Keys recorded in `REINDEX_CHANGES_TABLE`
+    fn reindex_changes(&self) -> Result<Vec<String>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(REINDEX_CHANGES_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open reindex changes table: {}", e))),
+        };
+
+        let mut keys = Vec::new();
+        for row in table.iter()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read reindex changes: {}", e)))?
+        {
+            let (key, _) = row
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read reindex changes: {}", e)))?;
+            keys.push(key.value().to_string());
+        }
+
+        Ok(keys)
+    }
+
+    /// Make `collection` active, clearing the checkpoint and change tracking
+    fn finish_reindex(&self, collection: &str) -> Result<(), StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        {
+            let mut metadata = write_txn.open_table(METADATA_TABLE)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+            metadata.insert(ACTIVE_COLLECTION_KEY, collection.as_bytes())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to record active collection: {}", e)))?;
+            metadata.remove(REINDEX_CHECKPOINT_KEY)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to clear reindex checkpoint: {}", e)))?;
+        }
+        write_txn.delete_table(REINDEX_CHANGES_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to clear reindex changes: {}", e)))?;
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit collection swap: {}", e)))
+    }
+
+    fn reindex_checkpoint(&self) -> Result<Option<ReindexCheckpoint>, StorageError> {
+        self.read_metadata(REINDEX_CHECKPOINT_KEY)?
+            .map(|data| {
+                serde_json::from_slice(&data)
+                    .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize reindex checkpoint: {}", e)))
+            })
+            .transpose()
+    }
+
+    fn write_reindex_checkpoint(&self, checkpoint: &ReindexCheckpoint) -> Result<(), StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        put_checkpoint(&write_txn, checkpoint)?;
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit reindex checkpoint: {}", e)))
+    }
+
+    fn read_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        };
+
+        let value = table.get(key)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read {}: {}", key, e)))?
+            .map(|data| data.value().to_vec());
+        Ok(value)
+    }
+}
+
+fn put_checkpoint(write_txn: &WriteTransaction, checkpoint: &ReindexCheckpoint) -> Result<(), StorageError> {
+    let data = serde_json::to_vec(checkpoint)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to serialize reindex checkpoint: {}", e)))?;
+
+    let mut metadata = write_txn.open_table(METADATA_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+    metadata.insert(REINDEX_CHECKPOINT_KEY, data.as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write reindex checkpoint: {}", e)))?;
+    Ok(())
+}
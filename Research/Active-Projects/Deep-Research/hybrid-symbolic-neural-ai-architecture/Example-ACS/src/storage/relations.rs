@@ -0,0 +1,403 @@
+//! Entity Relations
+//!
+//! Typed, directed edges between knowledge, agent and coordination entities,
+//! such as a finding that `contradicts` another or an entry `produced_by` an
+//! agent. Each relation is an entity row in `RELATIONS_TABLE` keyed
+//! `from \0 kind \0 to`, where an endpoint is written `kind:id`, so at most one
+//! relation of each kind joins two entities and storing it again replaces its
+//! metadata. `RELATION_TARGETS_TABLE` holds the same key reversed so incoming
+//! edges are found without a scan. Both rows are written in the same journaled
+//! transaction, so a rollback restores them together.
+//!
+//! Both endpoints must exist when a relation is added. Deleting an agent, a
+//! knowledge entry (or its chunks) or an expired coordination record removes
+//! its relations with it.
+
+use super::coordination::JournaledWrite;
+use super::schema::EntityTable;
+use super::{
+    HybridStorageCoordinator, OperationType, StorageError, AGENTS_TABLE, COORDINATION_TABLE, KNOWLEDGE_TABLE,
+    RELATIONS_TABLE,
+};
+use redb::{ReadTransaction, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use uuid::Uuid;
+
+/// Relation keys reversed (`to \0 kind \0 from`), for incoming edges; values are empty
+pub(crate) const RELATION_TARGETS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("relation_targets");
+
+/// Default `PathQuery::max_depth`
+pub const DEFAULT_PATH_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationKind {
+    Cites,         // The source references the target
+    Supports,      // The source is evidence for the target
+    Contradicts,   // The source conflicts with the target
+    DerivedFrom,   // The source was produced from the target
+    ProducedBy,    // The source was created by the target, usually an agent
+}
+
+impl RelationKind {
+    pub const ALL: [RelationKind; 5] = [
+        RelationKind::Cites,
+        RelationKind::Supports,
+        RelationKind::Contradicts,
+        RelationKind::DerivedFrom,
+        RelationKind::ProducedBy,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RelationKind::Cites => "cites",
+            RelationKind::Supports => "supports",
+            RelationKind::Contradicts => "contradicts",
+            RelationKind::DerivedFrom => "derived_from",
+            RelationKind::ProducedBy => "produced_by",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Knowledge,
+    Agent,
+    Coordination,
+}
+
+impl EntityKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            EntityKind::Knowledge => "knowledge",
+            EntityKind::Agent => "agent",
+            EntityKind::Coordination => "coordination",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        [EntityKind::Knowledge, EntityKind::Agent, EntityKind::Coordination]
+            .into_iter()
+            .find(|kind| kind.as_str() == name)
+    }
+
+    fn table(self) -> TableDefinition<'static, &'static str, &'static [u8]> {
+        match self {
+            EntityKind::Knowledge => KNOWLEDGE_TABLE,
+            EntityKind::Agent => AGENTS_TABLE,
+            EntityKind::Coordination => COORDINATION_TABLE,
+        }
+    }
+}
+
+/// One endpoint of a relation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EntityRef {
+    pub kind: EntityKind,
+    pub id: Uuid,
+}
+
+impl EntityRef {
+    pub fn knowledge(id: Uuid) -> Self {
+        Self { kind: EntityKind::Knowledge, id }
+    }
+
+    pub fn agent(id: Uuid) -> Self {
+        Self { kind: EntityKind::Agent, id }
+    }
+
+    pub fn coordination(id: Uuid) -> Self {
+        Self { kind: EntityKind::Coordination, id }
+    }
+
+    fn parse(key: &str) -> Option<Self> {
+        let (kind, id) = key.split_once(':')?;
+        Some(Self { kind: EntityKind::parse(kind)?, id: Uuid::parse_str(id).ok()? })
+    }
+}
+
+impl fmt::Display for EntityRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.kind.as_str(), self.id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relation {
+    pub from: EntityRef,
+    pub to: EntityRef,
+    pub kind: RelationKind,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+impl Relation {
+    pub fn new(from: EntityRef, kind: RelationKind, to: EntityRef) -> Self {
+        Self { from, to, kind, created_at: chrono::Utc::now(), metadata: HashMap::new() }
+    }
+
+    fn key(&self) -> String {
+        relation_key(&self.from, self.kind, &self.to)
+    }
+}
+
+/// Which edges of an entity `neighbors` and `find_path` follow
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    #[default]
+    Outgoing,      // Relations the entity is the source of
+    Incoming,      // Relations the entity is the target of
+    Both,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathQuery {
+    /// Relation kinds to follow; empty follows every kind
+    pub kinds: Vec<RelationKind>,
+    pub direction: Direction,
+    /// Longest path, in relations, that is searched for
+    pub max_depth: usize,
+}
+
+impl Default for PathQuery {
+    fn default() -> Self {
+        Self { kinds: Vec::new(), direction: Direction::Outgoing, max_depth: DEFAULT_PATH_DEPTH }
+    }
+}
+
+pub(crate) fn relation_key(from: &EntityRef, kind: RelationKind, to: &EntityRef) -> String {
+    format!("{}\0{}\0{}", from, kind.as_str(), to)
+}
+
+/// Endpoints and kind of a `RELATIONS_TABLE` or `RELATION_TARGETS_TABLE` key, in key order
+fn parse_key(key: &str) -> Option<(EntityRef, RelationKind, EntityRef)> {
+    let mut parts = key.split('\0');
+    let first = EntityRef::parse(parts.next()?)?;
+    let kind = RelationKind::parse(parts.next()?)?;
+    let second = EntityRef::parse(parts.next()?)?;
+    parts.next().is_none().then_some((first, kind, second))
+}
+
+/// `RELATION_TARGETS_TABLE` key of the relation stored under `key`
+pub(crate) fn target_key(key: &str) -> Option<String> {
+    parse_key(key).map(|(from, kind, to)| relation_key(&to, kind, &from))
+}
+
+/// Write a relation row and its reversed key
+pub(crate) fn insert_relation(txn: &mut JournaledWrite, key: &str, data: &[u8]) -> Result<(), StorageError> {
+    let reversed = target_key(key)
+        .ok_or_else(|| StorageError::SerializationError(format!("Invalid relation key {:?}", key)))?;
+    txn.insert(RELATIONS_TABLE, key, data)?;
+    txn.insert(RELATION_TARGETS_TABLE, &reversed, &[])?;
+    Ok(())
+}
+
+/// Remove a relation row and its reversed key, returning whether the relation existed
+pub(crate) fn remove_relation(txn: &mut JournaledWrite, key: &str) -> Result<bool, StorageError> {
+    let existed = txn.remove(RELATIONS_TABLE, key)?.is_some();
+    if let Some(reversed) = target_key(key) {
+        txn.remove(RELATION_TARGETS_TABLE, &reversed)?;
+    }
+    Ok(existed)
+}
+
+/// Remove every relation `entity` takes part in, returning how many were removed
+pub(crate) fn remove_entity_relations(txn: &mut JournaledWrite, entity: &EntityRef) -> Result<usize, StorageError> {
+    let keys = {
+        let outgoing = txn.transaction().open_table(RELATIONS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open relations table: {}", e)))?;
+        let incoming = txn.transaction().open_table(RELATION_TARGETS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open relation targets: {}", e)))?;
+
+        let mut keys: BTreeSet<String> = prefixed_keys(&outgoing, entity)?.into_iter().collect();
+        for reversed in prefixed_keys(&incoming, entity)? {
+            keys.extend(target_key(&reversed));
+        }
+        keys
+    };
+
+    for key in &keys {
+        remove_relation(txn, key)?;
+    }
+    Ok(keys.len())
+}
+
+/// Keys of a relation table starting with `entity`, in key order
+fn prefixed_keys(
+    table: &impl ReadableTable<&'static str, &'static [u8]>,
+    entity: &EntityRef,
+) -> Result<Vec<String>, StorageError> {
+    let prefix = format!("{}\0", entity);
+    let mut keys = Vec::new();
+    for row in table.range(prefix.as_str()..)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to read relations: {}", e)))?
+    {
+        let (key, _) = row.map_err(|e| StorageError::TransactionError(format!("Failed to read relations: {}", e)))?;
+        if !key.value().starts_with(prefix.as_str()) {
+            break;
+        }
+        keys.push(key.value().to_string());
+    }
+    Ok(keys)
+}
+
+impl HybridStorageCoordinator {
+    /// Store a relation, replacing one of the same kind between the same entities
+    pub(crate) async fn store_relation(&self, relation: &Relation) -> Result<(), StorageError> {
+        let key = relation.key();
+        let data = self.encode_row(EntityTable::Relations, relation)?;
+
+        self.execute_coordinated_transaction(OperationType::Insert, vec![], |txn| {
+            for endpoint in [&relation.from, &relation.to] {
+                let table = txn.transaction().open_table(endpoint.kind.table())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to open {} table: {}", endpoint.kind.as_str(), e)))?;
+                let exists = table.get(endpoint.id.to_string().as_str())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to read {}: {}", endpoint, e)))?
+                    .is_some();
+                if !exists {
+                    return Err(StorageError::NotFound(format!("Relation endpoint {} does not exist", endpoint)));
+                }
+            }
+            insert_relation(txn, &key, &data)?;
+            Ok(vec![])
+        }).await
+    }
+
+    /// Remove a relation, returning whether it existed
+    pub(crate) async fn delete_relation(
+        &self,
+        from: &EntityRef,
+        kind: RelationKind,
+        to: &EntityRef,
+    ) -> Result<bool, StorageError> {
+        let key = relation_key(from, kind, to);
+        let mut existed = false;
+
+        self.execute_coordinated_transaction(OperationType::Delete, vec![], |txn| {
+            existed = remove_relation(txn, &key)?;
+            Ok(vec![])
+        }).await?;
+
+        Ok(existed)
+    }
+
+    /// Relations of `entity` of the given kinds (all when empty), outgoing before incoming
+    pub(crate) fn load_neighbors(
+        &self,
+        entity: &EntityRef,
+        direction: Direction,
+        kinds: &[RelationKind],
+    ) -> Result<Vec<Relation>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+        self.neighbors_in(&read_txn, entity, direction, kinds)
+    }
+
+    /// Shortest chain of relations leading from `from` to `to`, if one is within `query.max_depth`
+    ///
+    /// Relations are returned as stored, so with `Direction::Incoming` or
+    /// `Both` a step may run from a relation's target to its source.
+    pub(crate) fn shortest_path(
+        &self,
+        from: &EntityRef,
+        to: &EntityRef,
+        query: &PathQuery,
+    ) -> Result<Option<Vec<Relation>>, StorageError> {
+        if from == to {
+            return Ok(Some(Vec::new()));
+        }
+
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        // Breadth first, remembering the relation each entity was reached by
+        let mut reached: HashMap<EntityRef, (EntityRef, Relation)> = HashMap::new();
+        let mut visited = HashSet::from([*from]);
+        let mut frontier = VecDeque::from([(*from, 0)]);
+
+        while let Some((entity, depth)) = frontier.pop_front() {
+            if depth >= query.max_depth {
+                continue;
+            }
+            for relation in self.neighbors_in(&read_txn, &entity, query.direction, &query.kinds)? {
+                let next = if relation.from == entity { relation.to } else { relation.from };
+                if !visited.insert(next) {
+                    continue;
+                }
+                reached.insert(next, (entity, relation));
+
+                if next == *to {
+                    let mut path = Vec::new();
+                    let mut current = next;
+                    while let Some((previous, relation)) = reached.remove(&current) {
+                        path.push(relation);
+                        current = previous;
+                    }
+                    path.reverse();
+                    return Ok(Some(path));
+                }
+                frontier.push_back((next, depth + 1));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn neighbors_in(
+        &self,
+        read_txn: &ReadTransaction,
+        entity: &EntityRef,
+        direction: Direction,
+        kinds: &[RelationKind],
+    ) -> Result<Vec<Relation>, StorageError> {
+        let relations = match read_txn.open_table(RELATIONS_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open relations table: {}", e))),
+        };
+
+        let wanted = |kind: RelationKind| kinds.is_empty() || kinds.contains(&kind);
+        let mut keys = Vec::new();
+        if matches!(direction, Direction::Outgoing | Direction::Both) {
+            keys.extend(prefixed_keys(&relations, entity)?.into_iter()
+                .filter(|key| parse_key(key).is_some_and(|(_, kind, _)| wanted(kind))));
+        }
+        if matches!(direction, Direction::Incoming | Direction::Both) {
+            let targets = match read_txn.open_table(RELATION_TARGETS_TABLE) {
+                Ok(table) => Some(table),
+                Err(redb::TableError::TableDoesNotExist(_)) => None,
+                Err(e) => return Err(StorageError::TransactionError(format!("Failed to open relation targets: {}", e))),
+            };
+            if let Some(targets) = targets {
+                for reversed in prefixed_keys(&targets, entity)? {
+                    match parse_key(&reversed) {
+                        // A relation from the entity to itself was already found as outgoing
+                        Some((_, _, from)) if from == *entity && direction == Direction::Both => {}
+                        Some((to, kind, from)) if wanted(kind) => keys.push(relation_key(&from, kind, &to)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            let data = relations.get(key.as_str())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read relation: {}", e)))?;
+            match data.map(|data| self.decode_row::<Relation>(EntityTable::Relations, data.value())) {
+                Some(Ok(relation)) => found.push(relation),
+                Some(Err(e)) => tracing::warn!("Relation {:?} skipped: {}", key, e),
+                None => tracing::warn!("Relation target entry {:?} has no matching row", key),
+            }
+        }
+        Ok(found)
+    }
+}
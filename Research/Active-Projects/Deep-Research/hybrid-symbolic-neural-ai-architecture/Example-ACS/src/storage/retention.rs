@@ -0,0 +1,248 @@
+//! Retention Policies
+//!
+//! Coordination records and task history grow with every task the hub runs.
+//! `StorageConfig::retention` bounds each class by age, by total count and by
+//! count per session; every limit that is set applies, and the newest records
+//! are the ones kept. Records without a session (a nil `session_id`) only
+//! count towards the total. A background compactor enforces the policies every
+//! `compaction_interval_secs`, and `compact_retention` runs a pass on demand.
+//!
+//! Knowledge entries are never expired: they are what users stored. Expired
+//! coordination records take their relations with them.
+
+use super::coordination_index::unindex_coordination;
+use super::relations::{self, EntityRef};
+use super::schema::EntityTable;
+use super::snapshot::for_each_row;
+use super::{
+    CoordinationEntity, HybridStorageCoordinator, OperationType, StorageError, TaskHistoryEntity, COORDINATION_TABLE,
+    TASK_HISTORY_TABLE,
+};
+use redb::{ReadableTable, TableDefinition, TableHandle};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Limits for one class of records; `None` means unlimited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub max_age_secs: Option<u64>,
+    pub max_count: Option<usize>,
+    pub max_per_session: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    pub coordination: RetentionPolicy,
+    pub task_history: RetentionPolicy,
+    /// Seconds between compactor passes; 0 disables the background compactor
+    pub compaction_interval_secs: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            coordination: RetentionPolicy::default(),
+            // Routing scans every task history record, so the table is capped by default
+            task_history: RetentionPolicy { max_count: Some(10_000), ..Default::default() },
+            compaction_interval_secs: 3600,
+        }
+    }
+}
+
+/// Outcome of a compaction pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionReport {
+    pub coordination_removed: usize,
+    pub task_history_removed: usize,
+}
+
+/// Shared handle between the coordinator and its background compactor
+#[derive(Default)]
+pub(crate) struct Compactor {
+    notify: Notify,
+    shutdown: AtomicBool,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Compactor {
+    pub(crate) fn signal_shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+}
+
+/// What a policy needs to know about one record
+struct RetainedRecord {
+    key: String,
+    session_id: Uuid,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    data: Vec<u8>,
+}
+
+impl HybridStorageCoordinator {
+    /// Spawn the background compactor unless it is disabled
+    pub(crate) fn start_compactor(&self) {
+        if self.config.retention.compaction_interval_secs == 0 {
+            return;
+        }
+
+        let coordinator = self.background_handle();
+        let interval = Duration::from_secs(self.config.retention.compaction_interval_secs);
+        let handle = tokio::spawn(async move { coordinator.run_compactor(interval).await });
+        *self.compactor.handle.lock().unwrap() = Some(handle);
+    }
+
+    pub(crate) async fn stop_compactor(&self) {
+        self.compactor.signal_shutdown();
+
+        let handle = self.compactor.handle.lock().unwrap().take();
+        if let Some(handle) = handle {
+            let _ = handle.await;
+        }
+    }
+
+    async fn run_compactor(self, interval: Duration) {
+        loop {
+            let _ = tokio::time::timeout(interval, self.compactor.notify.notified()).await;
+
+            // Exit once shut down, or when every user handle is gone
+            if self.compactor.shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+
+            match self.compact_retention().await {
+                Ok(report) if report.coordination_removed + report.task_history_removed > 0 => tracing::info!(
+                    "Retention removed {} coordination and {} task history records",
+                    report.coordination_removed, report.task_history_removed
+                ),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Retention compaction failed: {}", e),
+            }
+        }
+    }
+
+    /// Remove coordination and task history records that exceed their retention policy
+    pub async fn compact_retention(&self) -> Result<RetentionReport, StorageError> {
+        let now = chrono::Utc::now();
+        let retention = &self.config.retention;
+
+        let coordination = self.retained_records(EntityTable::Coordination, |data| {
+            self.decode_row::<CoordinationEntity>(EntityTable::Coordination, data)
+                .map(|entity| (entity.session_id, entity.timestamp))
+        })?;
+        let coordination = expired_records(coordination, &retention.coordination, now);
+
+        let task_history = self.retained_records(EntityTable::TaskHistory, |data| {
+            self.decode_row::<TaskHistoryEntity>(EntityTable::TaskHistory, data)
+                .map(|entity| (entity.session_id, entity.created_at))
+        })?;
+        let task_history = expired_records(task_history, &retention.task_history, now);
+
+        let report = RetentionReport {
+            coordination_removed: self.remove_expired(COORDINATION_TABLE, &coordination).await?,
+            task_history_removed: self.remove_expired(TASK_HISTORY_TABLE, &task_history).await?,
+        };
+
+        // Storage usage reports the space left after this pass
+        self.sample_space_usage()?;
+        Ok(report)
+    }
+
+    /// Session and timestamp of every readable row of `table`; unreadable rows are left to `verify`
+    fn retained_records<F>(&self, table: EntityTable, mut describe: F) -> Result<Vec<RetainedRecord>, StorageError>
+    where
+        F: FnMut(&[u8]) -> Result<(Uuid, chrono::DateTime<chrono::Utc>), StorageError>,
+    {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let mut records = Vec::new();
+        for_each_row(&read_txn, table.definition(), |key, data| {
+            if let Ok((session_id, timestamp)) = describe(data) {
+                records.push(RetainedRecord { key: key.to_string(), session_id, timestamp, data: data.to_vec() });
+            }
+            Ok(())
+        })?;
+        Ok(records)
+    }
+
+    /// Delete expired rows in batches, skipping rows rewritten since they were read
+    async fn remove_expired(
+        &self,
+        table: TableDefinition<'static, &'static str, &'static [u8]>,
+        expired: &[RetainedRecord],
+    ) -> Result<usize, StorageError> {
+        let mut removed = 0;
+
+        for chunk in expired.chunks(self.config.sync_batch_size.max(1)) {
+            let mut chunk_removed = 0;
+            self.execute_coordinated_transaction(OperationType::Delete, vec![], |txn| {
+                let current = txn.transaction().open_table(table)
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to open table: {}", e)))?;
+                let unchanged: Vec<&str> = chunk.iter()
+                    .filter(|record| {
+                        current.get(record.key.as_str()).ok().flatten()
+                            .is_some_and(|data| data.value() == record.data.as_slice())
+                    })
+                    .map(|record| record.key.as_str())
+                    .collect();
+                drop(current);
+
+                for key in unchanged {
+                    txn.remove(table, key)?;
+                    if table.name() == COORDINATION_TABLE.name() {
+                        unindex_coordination(txn.transaction(), key)?;
+                        if let Ok(id) = Uuid::parse_str(key) {
+                            relations::remove_entity_relations(txn, &EntityRef::coordination(id))?;
+                        }
+                    }
+                    chunk_removed += 1;
+                }
+                Ok(vec![])
+            }).await?;
+            removed += chunk_removed;
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Records `policy` does not keep: too old, or beyond the newest `max_per_session` or `max_count`
+fn expired_records(
+    mut records: Vec<RetainedRecord>,
+    policy: &RetentionPolicy,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<RetainedRecord> {
+    records.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.key.cmp(&b.key)));
+
+    let cutoff = policy.max_age_secs
+        .and_then(|secs| chrono::Duration::try_seconds(secs.min(i64::MAX as u64) as i64))
+        .and_then(|max_age| now.checked_sub_signed(max_age));
+
+    let mut per_session: HashMap<Uuid, usize> = HashMap::new();
+    let mut kept = 0;
+    let mut expired = Vec::new();
+
+    for record in records {
+        let too_old = cutoff.is_some_and(|cutoff| record.timestamp < cutoff);
+        let session_count = per_session.entry(record.session_id).or_default();
+        let over_session_cap = !record.session_id.is_nil()
+            && policy.max_per_session.is_some_and(|cap| *session_count >= cap);
+        let over_count = policy.max_count.is_some_and(|cap| kept >= cap);
+
+        if too_old || over_session_cap || over_count {
+            expired.push(record);
+        } else {
+            *session_count += 1;
+            kept += 1;
+        }
+    }
+
+    expired
+}
@@ -0,0 +1,239 @@
+//! Knowledge Revisions
+//!
+//! `KNOWLEDGE_TABLE` holds the current revision of every entry. When a write
+//! replaces or deletes a row, the row is archived to
+//! `KNOWLEDGE_REVISIONS_TABLE` under `{id}/{revision}` with the time it stopped
+//! being current as `valid_to`. Each revision is valid from the `valid_to` of
+//! the one before it, the first from the entry's `created_at`. Rewrites that
+//! leave content, title, tags, metadata, source, credibility and creation time
+//! unchanged are not archived.
+//!
+//! The vector and lexical indexes only cover current rows, so
+//! `search_knowledge_as_of` scans both tables and ranks the revisions valid at
+//! the requested time by cosine similarity of their stored embeddings. It is
+//! meant for reproducing past research runs, not for interactive search.
+
+use super::coordination::JournaledWrite;
+use super::encryption::{self, RowCipher};
+use super::local_index::cosine_similarity;
+use super::schema::EntityTable;
+use super::snapshot::for_each_row;
+use super::{
+    HybridStorageCoordinator, KnowledgeEntity, ScoredKnowledge, StorageError, KNOWLEDGE_REVISIONS_TABLE,
+    KNOWLEDGE_TABLE,
+};
+use chrono::{DateTime, Utc};
+use redb::{ReadableTable, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// One revision of a knowledge entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeRevision {
+    /// 1-based position in the entry's chain
+    pub revision: u32,
+    pub valid_from: DateTime<Utc>,
+    /// When the revision was replaced or deleted; `None` while it is current
+    pub valid_to: Option<DateTime<Utc>>,
+    pub entity: KnowledgeEntity,
+}
+
+impl KnowledgeRevision {
+    fn valid_at(&self, at: DateTime<Utc>) -> bool {
+        self.valid_from <= at && self.valid_to.is_none_or(|valid_to| at < valid_to)
+    }
+}
+
+/// Archive the knowledge row a write replaced, unless `replacement` leaves it unchanged
+///
+/// `replacement` is `None` for deletes. A replaced row that cannot be decoded
+/// is overwritten without being archived.
+pub(crate) fn archive_replaced(
+    txn: &mut JournaledWrite,
+    cipher: Option<&RowCipher>,
+    previous: Option<&[u8]>,
+    replacement: Option<&KnowledgeEntity>,
+) -> Result<(), StorageError> {
+    let Some(previous) = previous else {
+        return Ok(());
+    };
+    let previous = match encryption::decode_row::<KnowledgeEntity>(cipher, EntityTable::Knowledge, previous) {
+        Ok(previous) => previous,
+        Err(e) => {
+            tracing::warn!("Replaced knowledge row could not be archived: {}", e);
+            return Ok(());
+        }
+    };
+    if replacement.is_some_and(|replacement| same_revision(&previous, replacement)) {
+        return Ok(());
+    }
+
+    let last = last_revision(txn.transaction(), cipher, previous.id)?;
+    let revision = KnowledgeRevision {
+        revision: last.as_ref().map_or(1, |last| last.revision + 1),
+        valid_from: last.and_then(|last| last.valid_to).unwrap_or(previous.created_at),
+        valid_to: Some(Utc::now()),
+        entity: previous,
+    };
+
+    let key = revision_key(revision.entity.id, revision.revision);
+    let data = encryption::encode_row(cipher, EntityTable::KnowledgeRevisions, &revision)?;
+    txn.insert(KNOWLEDGE_REVISIONS_TABLE, &key, &data)?;
+    Ok(())
+}
+
+/// Fields a revision is read and cited by; the stored embedding is derived from them
+fn same_revision(a: &KnowledgeEntity, b: &KnowledgeEntity) -> bool {
+    a.content == b.content
+        && a.title == b.title
+        && a.tags == b.tags
+        && a.metadata == b.metadata
+        && a.source == b.source
+        && a.credibility_rating == b.credibility_rating
+        && a.created_at == b.created_at
+}
+
+/// Zero-padded so revisions of an entry sort in order
+pub(crate) fn revision_key(id: Uuid, revision: u32) -> String {
+    format!("{}/{:010}", id, revision)
+}
+
+/// Key range covering every revision of `id`: `/` sorts just before `0`
+fn revision_range(id: Uuid) -> (String, String) {
+    (format!("{}/", id), format!("{}0", id))
+}
+
+fn last_revision(
+    write_txn: &WriteTransaction,
+    cipher: Option<&RowCipher>,
+    id: Uuid,
+) -> Result<Option<KnowledgeRevision>, StorageError> {
+    let table = write_txn.open_table(KNOWLEDGE_REVISIONS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge revisions table: {}", e)))?;
+
+    let (start, end) = revision_range(id);
+    let last = table.range(start.as_str()..end.as_str())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge revisions: {}", e)))?
+        .next_back()
+        .transpose()
+        .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge revisions: {}", e)))?;
+
+    last.map(|(_, data)| encryption::decode_row(cipher, EntityTable::KnowledgeRevisions, data.value()))
+        .transpose()
+}
+
+impl HybridStorageCoordinator {
+    /// Archived revisions of `id` followed by the current row, if it still exists
+    pub(crate) fn load_knowledge_history(&self, id: &Uuid) -> Result<Vec<KnowledgeRevision>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let mut history: Vec<KnowledgeRevision> = Vec::new();
+        match read_txn.open_table(KNOWLEDGE_REVISIONS_TABLE) {
+            Ok(table) => {
+                let (start, end) = revision_range(*id);
+                for row in table.range(start.as_str()..end.as_str())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge revisions: {}", e)))?
+                {
+                    let (_, data) = row
+                        .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge revisions: {}", e)))?;
+                    history.push(self.decode_row(EntityTable::KnowledgeRevisions, data.value())?);
+                }
+            }
+            Err(redb::TableError::TableDoesNotExist(_)) => {}
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open knowledge revisions table: {}", e))),
+        }
+
+        let current = match read_txn.open_table(KNOWLEDGE_TABLE) {
+            Ok(table) => table.get(id.to_string().as_str())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to get knowledge: {}", e)))?
+                .map(|data| self.decode_row::<KnowledgeEntity>(EntityTable::Knowledge, data.value()))
+                .transpose()?,
+            Err(redb::TableError::TableDoesNotExist(_)) => None,
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open knowledge table: {}", e))),
+        };
+
+        if let Some(entity) = current {
+            history.push(current_revision(history.last(), entity));
+        }
+        Ok(history)
+    }
+
+    /// Rank the knowledge revisions valid at `as_of` against `query`, best first
+    pub(crate) async fn rank_knowledge_as_of(
+        &self,
+        query: &str,
+        as_of: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<ScoredKnowledge>, StorageError> {
+        let query_embedding = self.generate_embedding(query).await?;
+
+        let mut candidates = Vec::new();
+        self.time_redb(|| {
+            let read_txn = self.redb.begin_read()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+            // Archived revisions come first so current rows know when they became current
+            let mut latest: HashMap<Uuid, KnowledgeRevision> = HashMap::new();
+            for_each_row(&read_txn, KNOWLEDGE_REVISIONS_TABLE, |key, data| {
+                match self.decode_row::<KnowledgeRevision>(EntityTable::KnowledgeRevisions, data) {
+                    Ok(revision) => {
+                        if revision.valid_at(as_of) && revision.entity.parent_id.is_none() {
+                            candidates.push(revision.entity.clone());
+                        }
+                        latest.insert(revision.entity.id, revision);
+                    }
+                    Err(e) => tracing::warn!("Knowledge revision {} left out of the search: {}", key, e),
+                }
+                Ok(())
+            })?;
+
+            for_each_row(&read_txn, KNOWLEDGE_TABLE, |key, data| {
+                match self.decode_row::<KnowledgeEntity>(EntityTable::Knowledge, data) {
+                    Ok(entity) => {
+                        let revision = current_revision(latest.get(&entity.id), entity);
+                        if revision.valid_at(as_of) && revision.entity.parent_id.is_none() {
+                            candidates.push(revision.entity);
+                        }
+                    }
+                    Err(e) => tracing::warn!("Knowledge row {} left out of the search: {}", key, e),
+                }
+                Ok(())
+            })
+        })?;
+
+        // Rows written before embeddings were stored are embedded now
+        let missing: Vec<&str> = candidates.iter()
+            .filter(|entity| entity.embeddings.is_none())
+            .map(|entity| entity.content.as_str())
+            .collect();
+        let mut generated = self.generate_embeddings(&missing).await?.into_iter();
+
+        let mut scored: Vec<(f32, KnowledgeEntity)> = Vec::with_capacity(candidates.len());
+        for entity in candidates {
+            let score = match &entity.embeddings {
+                Some(embedding) => cosine_similarity(&query_embedding, embedding),
+                None => cosine_similarity(&query_embedding, &generated.next().unwrap_or_default()),
+            };
+            scored.push((score, entity));
+        }
+
+        scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(scored.into_iter()
+            .take(limit)
+            .enumerate()
+            .map(|(index, (score, entity))| ScoredKnowledge { entity, score, rank: index + 1 })
+            .collect())
+    }
+}
+
+/// Revision for the current row, following the last archived one
+fn current_revision(last: Option<&KnowledgeRevision>, entity: KnowledgeEntity) -> KnowledgeRevision {
+    KnowledgeRevision {
+        revision: last.map_or(1, |last| last.revision + 1),
+        valid_from: last.and_then(|last| last.valid_to).unwrap_or(entity.created_at),
+        valid_to: None,
+        entity,
+    }
+}
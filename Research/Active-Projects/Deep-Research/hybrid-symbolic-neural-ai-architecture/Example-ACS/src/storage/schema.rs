@@ -0,0 +1,463 @@
+//! Versioned Storage Schema
+//!
+//! Entity rows are wrapped in an envelope: the magic bytes `ACSv`, the schema
+//! version the payload was written with (u32 LE), then the payload. Rows written
+//! before the envelope existed are raw `bincode` and count as version 1. The
+//! database records its schema version under `schema_version` in
+//! `METADATA_TABLE`.
+//!
+//! Older rows are upgraded through the migration registry, either all at once
+//! when the database is opened or one row at a time as it is read, depending on
+//! `StorageConfig::schema_migration`. Version 2 payloads are JSON, which, unlike
+//! bincode, can decode the `serde_json::Value` fields of the entities. Version
+//! 3 moves knowledge titles and tags out of metadata into their own fields.
+
+use super::encryption;
+use super::{
+    HybridStorageCoordinator, KnowledgeEntity, StorageError, AGENTS_TABLE, COORDINATION_TABLE, KNOWLEDGE_TABLE,
+    METADATA_TABLE, KNOWLEDGE_REVISIONS_TABLE, RELATIONS_TABLE, TASK_HISTORY_TABLE,
+};
+use redb::{ReadableTable, ReadableTableMetadata, TableDefinition};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Schema version written by this build
+pub const CURRENT_SCHEMA_VERSION: u32 = 3;
+
+/// Metadata key holding the database schema version (u32 LE)
+pub(crate) const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+const ENVELOPE_MAGIC: &[u8; 4] = b"ACSv";
+const ENVELOPE_HEADER_LEN: usize = 8;
+
+/// When rows older than the current schema are upgraded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SchemaMigration {
+    #[default]
+    OnOpen,        // Rewrite every old row when the database is opened
+    Lazy,          // Upgrade rows in memory on read; they are rewritten on their next write
+}
+
+/// Tables whose rows are stored in the versioned envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntityTable {
+    Agents,
+    Knowledge,
+    Coordination,
+    TaskHistory,
+    KnowledgeRevisions,
+    Relations,
+}
+
+impl EntityTable {
+    pub(crate) const ALL: [EntityTable; 6] = [
+        EntityTable::Agents,
+        EntityTable::Knowledge,
+        EntityTable::Coordination,
+        EntityTable::TaskHistory,
+        EntityTable::KnowledgeRevisions,
+        EntityTable::Relations,
+    ];
+
+    pub(crate) fn definition(self) -> TableDefinition<'static, &'static str, &'static [u8]> {
+        match self {
+            EntityTable::Agents => AGENTS_TABLE,
+            EntityTable::Knowledge => KNOWLEDGE_TABLE,
+            EntityTable::Coordination => COORDINATION_TABLE,
+            EntityTable::TaskHistory => TASK_HISTORY_TABLE,
+            EntityTable::KnowledgeRevisions => KNOWLEDGE_REVISIONS_TABLE,
+            EntityTable::Relations => RELATIONS_TABLE,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            EntityTable::Agents => "agents",
+            EntityTable::Knowledge => "knowledge",
+            EntityTable::Coordination => "coordination",
+            EntityTable::TaskHistory => "task_history",
+            EntityTable::KnowledgeRevisions => "knowledge_revisions",
+            EntityTable::Relations => "relations",
+        }
+    }
+}
+
+/// Upgrades a row payload from the previous schema version to `version`
+struct Migration {
+    version: u32,
+    description: &'static str,
+    upgrade: fn(EntityTable, &[u8]) -> Result<Vec<u8>, StorageError>,
+}
+
+/// Every migration, in version order
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        description: "bincode payloads to JSON",
+        upgrade: v1::to_json,
+    },
+    Migration {
+        version: 3,
+        description: "knowledge title and tags metadata to fields",
+        upgrade: v2::lift_title_and_tags,
+    },
+];
+
+/// Outcome of upgrading the rows of a database
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub migrated: usize,
+    pub failures: Vec<MigrationFailure>,
+}
+
+/// Row that could not be upgraded; it is left untouched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationFailure {
+    pub table: String,
+    pub key: String,
+    pub error: String,
+}
+
+/// Encode an entity as a row at the current schema version
+pub(crate) fn encode<T: Serialize>(entity: &T) -> Result<Vec<u8>, StorageError> {
+    let mut data = Vec::with_capacity(256);
+    data.extend_from_slice(ENVELOPE_MAGIC);
+    data.extend_from_slice(&CURRENT_SCHEMA_VERSION.to_le_bytes());
+    serde_json::to_writer(&mut data, entity)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to encode {}: {}", std::any::type_name::<T>(), e)))?;
+    Ok(data)
+}
+
+/// Decode a row of `table`, upgrading it in memory if it predates the current schema
+pub(crate) fn decode<T: DeserializeOwned>(table: EntityTable, data: &[u8]) -> Result<T, StorageError> {
+    let (version, payload) = split_envelope(data);
+    let payload = upgrade_payload(table, version, payload)?;
+    serde_json::from_slice(&payload)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to decode {} row: {}", table.name(), e)))
+}
+
+/// Schema version and payload of a row; rows without an envelope are version 1
+fn split_envelope(data: &[u8]) -> (u32, &[u8]) {
+    if data.len() >= ENVELOPE_HEADER_LEN && data.starts_with(ENVELOPE_MAGIC) {
+        let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        (version, &data[ENVELOPE_HEADER_LEN..])
+    } else {
+        (1, data)
+    }
+}
+
+fn upgrade_payload(table: EntityTable, version: u32, payload: &[u8]) -> Result<Cow<'_, [u8]>, StorageError> {
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(StorageError::SerializationError(format!(
+            "{} row has schema version {}, newer than supported version {}",
+            table.name(), version, CURRENT_SCHEMA_VERSION
+        )));
+    }
+
+    let mut payload = Cow::Borrowed(payload);
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > version) {
+        payload = Cow::Owned((migration.upgrade)(table, &payload).map_err(|e| {
+            StorageError::SerializationError(format!(
+                "Migration to version {} ({}) failed: {}", migration.version, migration.description, e
+            ))
+        })?);
+    }
+    Ok(payload)
+}
+
+/// Row re-encoded at the current version, or `None` if it is already current
+fn upgrade_row(table: EntityTable, data: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+    let (version, payload) = split_envelope(data);
+    if version == CURRENT_SCHEMA_VERSION {
+        return Ok(None);
+    }
+
+    let payload = upgrade_payload(table, version, payload)?;
+    let mut row = Vec::with_capacity(ENVELOPE_HEADER_LEN + payload.len());
+    row.extend_from_slice(ENVELOPE_MAGIC);
+    row.extend_from_slice(&CURRENT_SCHEMA_VERSION.to_le_bytes());
+    row.extend_from_slice(&payload);
+    Ok(Some(row))
+}
+
+impl HybridStorageCoordinator {
+    /// Schema version recorded in the database
+    pub fn schema_version(&self) -> Result<u32, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        let table = match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return self.unversioned_schema(&read_txn),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        };
+
+        let version = table.get(SCHEMA_VERSION_KEY)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read schema version: {}", e)))?;
+        match version {
+            Some(data) => {
+                let bytes: [u8; 4] = data.value().try_into()
+                    .map_err(|_| StorageError::SerializationError("Invalid schema version".to_string()))?;
+                Ok(u32::from_le_bytes(bytes))
+            }
+            None => self.unversioned_schema(&read_txn),
+        }
+    }
+
+    /// Databases without a recorded version are version 1 if they hold any entity, otherwise new
+    fn unversioned_schema(&self, read_txn: &redb::ReadTransaction) -> Result<u32, StorageError> {
+        for entity_table in EntityTable::ALL {
+            match read_txn.open_table(entity_table.definition()) {
+                Ok(table) => {
+                    let rows = table.len()
+                        .map_err(|e| StorageError::TransactionError(format!("Failed to read {} table: {}", entity_table.name(), e)))?;
+                    if rows > 0 {
+                        return Ok(1);
+                    }
+                }
+                Err(redb::TableError::TableDoesNotExist(_)) => {}
+                Err(e) => {
+                    return Err(StorageError::TransactionError(format!(
+                        "Failed to open {} table: {}", entity_table.name(), e
+                    )))
+                }
+            }
+        }
+        Ok(CURRENT_SCHEMA_VERSION)
+    }
+
+    /// Check the schema version on open and upgrade old rows if configured to
+    pub(crate) fn open_schema(&self) -> Result<(), StorageError> {
+        let version = self.schema_version()?;
+        if version > CURRENT_SCHEMA_VERSION {
+            return Err(StorageError::InitializationError(format!(
+                "Database schema version {} is newer than supported version {}",
+                version, CURRENT_SCHEMA_VERSION
+            )));
+        }
+
+        if version == CURRENT_SCHEMA_VERSION {
+            return self.record_schema_version(CURRENT_SCHEMA_VERSION);
+        }
+
+        match self.config.schema_migration {
+            SchemaMigration::OnOpen => {
+                let report = self.migrate_schema()?;
+                tracing::info!(
+                    "Schema migration {} -> {}: {} rows upgraded, {} failed",
+                    report.from_version, report.to_version, report.migrated, report.failures.len()
+                );
+                for failure in &report.failures {
+                    tracing::warn!("Could not migrate {} row {}: {}", failure.table, failure.key, failure.error);
+                }
+            }
+            SchemaMigration::Lazy => {
+                tracing::info!("Schema version {} rows will be upgraded as they are read", version);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrite every row older than the current schema and record the new version
+    ///
+    /// Rows that cannot be upgraded are reported and left as they are; retrying
+    /// would not help, since the migrations are deterministic.
+    pub fn migrate_schema(&self) -> Result<MigrationReport, StorageError> {
+        let mut report = MigrationReport {
+            from_version: self.schema_version()?,
+            to_version: CURRENT_SCHEMA_VERSION,
+            ..Default::default()
+        };
+
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+
+        for entity_table in EntityTable::ALL {
+            let mut table = write_txn.open_table(entity_table.definition())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to open {} table: {}", entity_table.name(), e)))?;
+
+            let mut upgraded = Vec::new();
+            for row in table.iter()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read {} table: {}", entity_table.name(), e)))?
+            {
+                let (key, data) = row
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to read {} table: {}", entity_table.name(), e)))?;
+                // Encrypted rows are upgraded inside their seal and sealed again
+                let upgrade = encryption::open_row(self.cipher(), entity_table, data.value())
+                    .and_then(|row| upgrade_row(entity_table, &row))
+                    .and_then(|row| row.map(|row| encryption::seal_row(self.cipher(), entity_table, row)).transpose());
+                match upgrade {
+                    Ok(Some(row)) => upgraded.push((key.value().to_string(), row)),
+                    Ok(None) => {}
+                    Err(e) => report.failures.push(MigrationFailure {
+                        table: entity_table.name().to_string(),
+                        key: key.value().to_string(),
+                        error: e.to_string(),
+                    }),
+                }
+            }
+
+            for (key, row) in &upgraded {
+                table.insert(key.as_str(), row.as_slice())
+                    .map_err(|e| StorageError::TransactionError(format!("Failed to write {} row: {}", entity_table.name(), e)))?;
+            }
+            report.migrated += upgraded.len();
+        }
+
+        write_schema_version(&write_txn, CURRENT_SCHEMA_VERSION)?;
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit schema migration: {}", e)))?;
+
+        Ok(report)
+    }
+
+    fn record_schema_version(&self, version: u32) -> Result<(), StorageError> {
+        let write_txn = self.redb.begin_write()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin write transaction: {}", e)))?;
+        write_schema_version(&write_txn, version)?;
+        write_txn.commit()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to commit schema version: {}", e)))
+    }
+}
+
+/// Move a title and tags kept in metadata before schema version 3 into their fields
+///
+/// For entities that bypass row migration, such as snapshot records.
+pub(crate) fn lift_legacy_title_and_tags(knowledge: &mut KnowledgeEntity) {
+    if let Some(serde_json::Value::String(title)) = knowledge.metadata.get("title") {
+        knowledge.title = Some(title.clone());
+        knowledge.metadata.remove("title");
+    }
+    let tags: Option<Vec<String>> = knowledge.metadata.get("tags")
+        .and_then(|tags| tags.as_array())
+        .and_then(|tags| tags.iter().map(|tag| tag.as_str().map(String::from)).collect());
+    if let Some(tags) = tags {
+        knowledge.tags = tags;
+        knowledge.metadata.remove("tags");
+    }
+}
+
+fn write_schema_version(write_txn: &redb::WriteTransaction, version: u32) -> Result<(), StorageError> {
+    let mut table = write_txn.open_table(METADATA_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+    table.insert(SCHEMA_VERSION_KEY, version.to_le_bytes().as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write schema version: {}", e)))?;
+    Ok(())
+}
+
+/// Version 1: raw bincode rows
+///
+/// The layouts are frozen copies of the entities as they were at version 1, so
+/// later changes to the entities do not break decoding of old rows. Bincode
+/// cannot decode `serde_json::Value`, so version 1 rows whose JSON fields hold
+/// anything were never readable and fail to migrate.
+mod v1 {
+    use super::EntityTable;
+    use crate::storage::StorageError;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    #[derive(Serialize, Deserialize)]
+    struct AgentEntity {
+        id: Uuid,
+        agent_type: String,
+        state: serde_json::Value,
+        capabilities: Vec<String>,
+        created_at: chrono::DateTime<chrono::Utc>,
+        updated_at: chrono::DateTime<chrono::Utc>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct KnowledgeEntity {
+        id: Uuid,
+        content: String,
+        metadata: HashMap<String, serde_json::Value>,
+        embeddings: Option<Vec<f32>>,
+        source: String,
+        credibility_rating: String,
+        created_at: chrono::DateTime<chrono::Utc>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct CoordinationEntity {
+        id: Uuid,
+        session_id: Uuid,
+        operation_type: String,
+        status: String,
+        data: serde_json::Value,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    }
+
+    pub(super) fn to_json(table: EntityTable, payload: &[u8]) -> Result<Vec<u8>, StorageError> {
+        match table {
+            EntityTable::Agents => transcode::<AgentEntity>(payload),
+            EntityTable::Knowledge => transcode::<KnowledgeEntity>(payload),
+            EntityTable::Coordination => transcode::<CoordinationEntity>(payload),
+            EntityTable::TaskHistory | EntityTable::KnowledgeRevisions | EntityTable::Relations => Err(StorageError::SerializationError(
+                format!("The {} table did not exist at version 1", table.name()),
+            )),
+        }
+    }
+
+    fn transcode<T: Serialize + for<'de> Deserialize<'de>>(payload: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let entity: T = bincode::deserialize(payload)
+            .map_err(|e| StorageError::SerializationError(format!("Failed to decode bincode row: {}", e)))?;
+        serde_json::to_vec(&entity)
+            .map_err(|e| StorageError::SerializationError(format!("Failed to encode JSON row: {}", e)))
+    }
+}
+
+/// Version 2: JSON rows, with knowledge titles and tags kept in metadata
+///
+/// The framework stored tags as a `tags` array of strings and callers put
+/// titles under `title`; either is moved only if it has that shape, so
+/// unrelated metadata under the same key is left alone.
+mod v2 {
+    use super::EntityTable;
+    use crate::storage::StorageError;
+    use serde_json::Value;
+
+    pub(super) fn lift_title_and_tags(table: EntityTable, payload: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let mut row: Value = serde_json::from_slice(payload)
+            .map_err(|e| StorageError::SerializationError(format!("Failed to decode JSON row: {}", e)))?;
+        match table {
+            EntityTable::Knowledge => lift(&mut row),
+            EntityTable::KnowledgeRevisions => {
+                if let Some(entity) = row.get_mut("entity") {
+                    lift(entity);
+                }
+            }
+            EntityTable::Agents | EntityTable::Coordination | EntityTable::TaskHistory | EntityTable::Relations => {
+                return Ok(payload.to_vec())
+            }
+        }
+        serde_json::to_vec(&row)
+            .map_err(|e| StorageError::SerializationError(format!("Failed to encode JSON row: {}", e)))
+    }
+
+    fn lift(knowledge: &mut Value) {
+        let Some(metadata) = knowledge.get_mut("metadata").and_then(Value::as_object_mut) else {
+            return;
+        };
+
+        let title = metadata.get("title").filter(|title| title.is_string()).cloned();
+        let tags = metadata.get("tags")
+            .filter(|tags| tags.as_array().is_some_and(|tags| tags.iter().all(Value::is_string)))
+            .cloned();
+        if title.is_some() {
+            metadata.remove("title");
+        }
+        if tags.is_some() {
+            metadata.remove("tags");
+        }
+
+        if let Some(knowledge) = knowledge.as_object_mut() {
+            knowledge.insert("title".to_string(), title.unwrap_or(Value::Null));
+            knowledge.insert("tags".to_string(), tags.unwrap_or_else(|| Value::Array(Vec::new())));
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Scored and Paginated Knowledge Search
+//!
+//! Search results keep the score that ranked them and their 1-based rank, so
+//! callers can threshold relevance. Pages are addressed either by offset or by
+//! an opaque cursor taken from the previous page; a cursor records the score,
+//! ID and rank of the last result, so paging stays stable when entries are
+//! added ahead of it. Ranked IDs whose REDB row is missing or unreadable are
+//! reported as consistency warnings instead of being dropped silently.
+//! Document chunks are found through `search_documents`, not here.
+
+use super::lexical::FusionStrategy;
+use super::{HybridStorageCoordinator, KnowledgeEntity, StorageError};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Smallest candidate pool ranked for a page
+const MIN_SEARCH_POOL: usize = 20;
+
+/// Knowledge entry with the score and rank it was returned at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredKnowledge {
+    pub entity: KnowledgeEntity,
+    /// Cosine similarity for semantic search, fused score for hybrid search
+    pub score: f32,
+    /// 1-based position in the full result list
+    pub rank: usize,
+}
+
+/// Paginated knowledge search
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KnowledgeSearch {
+    pub query: String,
+    pub limit: usize,
+    /// Results to skip; ignored when `cursor` is set
+    pub offset: usize,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    /// Drop results scoring below this value
+    pub min_score: Option<f32>,
+    /// `None` ranks by vector similarity only
+    pub fusion: Option<FusionStrategy>,
+}
+
+/// One page of search results
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchPage {
+    pub results: Vec<ScoredKnowledge>,
+    /// Cursor for the following page; `None` on the last page
+    pub next_cursor: Option<String>,
+    /// Ranked IDs within this page whose stored row could not be returned
+    pub warnings: Vec<ConsistencyWarning>,
+}
+
+/// Vector index entry without a usable REDB row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsistencyWarning {
+    pub id: Uuid,
+    pub kind: ConsistencyWarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ConsistencyWarningKind {
+    Missing,             // Indexed but no REDB row
+    Unreadable(String),  // Row exists but failed to deserialize
+}
+
+impl std::fmt::Display for ConsistencyWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ConsistencyWarningKind::Missing => write!(f, "knowledge {} is indexed but has no stored row", self.id),
+            ConsistencyWarningKind::Unreadable(error) => {
+                write!(f, "knowledge {} could not be read: {}", self.id, error)
+            }
+        }
+    }
+}
+
+/// Position of the last result of a page
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SearchCursor {
+    score: f32,
+    id: Uuid,
+    rank: usize,
+}
+
+impl SearchCursor {
+    fn encode(&self) -> String {
+        format!("{:08x}.{}.{}", self.score.to_bits(), self.rank, self.id)
+    }
+
+    fn decode(cursor: &str) -> Result<Self, StorageError> {
+        let invalid = || StorageError::ConfigurationError(format!("Invalid search cursor: {}", cursor));
+
+        let mut parts = cursor.splitn(3, '.');
+        let score = parts.next()
+            .and_then(|bits| u32::from_str_radix(bits, 16).ok())
+            .map(f32::from_bits)
+            .ok_or_else(invalid)?;
+        let rank = parts.next().and_then(|rank| rank.parse().ok()).ok_or_else(invalid)?;
+        let id = parts.next().and_then(|id| Uuid::parse_str(id).ok()).ok_or_else(invalid)?;
+
+        Ok(Self { score, id, rank })
+    }
+
+    /// Whether a ranked entry comes after this cursor in result order
+    fn precedes(&self, id: &Uuid, score: f32) -> bool {
+        result_order(&(self.id, self.score), &(*id, score)) == Ordering::Less
+    }
+}
+
+/// Result order: score descending, ties broken by ID so pages are deterministic
+fn result_order(a: &(Uuid, f32), b: &(Uuid, f32)) -> Ordering {
+    b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then_with(|| a.0.cmp(&b.0))
+}
+
+impl ScoredKnowledge {
+    /// Cursor continuing a search after this result
+    pub fn cursor(&self) -> String {
+        SearchCursor { score: self.score, id: self.entity.id, rank: self.rank }.encode()
+    }
+}
+
+impl HybridStorageCoordinator {
+    pub(crate) async fn run_knowledge_search(&self, search: &KnowledgeSearch) -> Result<SearchPage, StorageError> {
+        let cursor = search.cursor.as_deref().map(SearchCursor::decode).transpose()?;
+        let (skip, first_rank) = match &cursor {
+            Some(cursor) => (0, cursor.rank + 1),
+            None => (search.offset, search.offset + 1),
+        };
+
+        if search.limit == 0 {
+            return Ok(SearchPage::default());
+        }
+
+        // Rank one extra result so we know whether another page follows
+        let wanted = skip + search.limit + 1;
+        let mut pool = wanted.saturating_mul(2).max(MIN_SEARCH_POOL);
+
+        let page = loop {
+            let mut ranked = self.rank_knowledge(&search.query, pool, search.fusion).await?;
+            let exhausted = ranked.len() < pool;
+            ranked.sort_by(result_order);
+
+            let mut candidates: Vec<(Uuid, f32)> = ranked.into_iter()
+                .filter(|(id, score)| cursor.is_none_or(|cursor| cursor.precedes(id, *score)))
+                .collect();
+            let below_cutoff = search.min_score.is_some_and(|min_score| {
+                let before = candidates.len();
+                candidates.retain(|(_, score)| *score >= min_score);
+                candidates.len() < before
+            });
+
+            let ids: Vec<Uuid> = candidates.iter().map(|(id, _)| *id).collect();
+            let rows = self.time_redb(|| self.load_knowledge_entities(&ids))?;
+
+            let mut page = SearchPage::default();
+            let mut served = 0;
+            let mut more = false;
+            for ((_, score), row) in candidates.into_iter().zip(rows) {
+                match row {
+                    Ok(entity) if entity.parent_id.is_some() => {}
+                    Ok(entity) => {
+                        served += 1;
+                        if served <= skip {
+                            continue;
+                        }
+                        if page.results.len() == search.limit {
+                            more = true;
+                            break;
+                        }
+                        page.results.push(ScoredKnowledge {
+                            entity,
+                            score,
+                            rank: first_rank + page.results.len(),
+                        });
+                    }
+                    Err(warning) => {
+                        if served >= skip && page.results.len() < search.limit {
+                            page.warnings.push(warning);
+                        }
+                    }
+                }
+            }
+
+            // Everything ranked has been examined if the index ran dry or the score cutoff was reached
+            if more || exhausted || below_cutoff {
+                if more {
+                    page.next_cursor = page.results.last().map(ScoredKnowledge::cursor);
+                }
+                break page;
+            }
+            pool = pool.saturating_mul(4);
+        };
+
+        for warning in &page.warnings {
+            warn!("Search consistency warning: {}", warning);
+        }
+
+        Ok(page)
+    }
+}
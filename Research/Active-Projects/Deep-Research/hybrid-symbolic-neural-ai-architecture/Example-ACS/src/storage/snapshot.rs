@@ -0,0 +1,600 @@
+//! Knowledge Base Snapshots
+//!
+//! A snapshot is a JSON Lines file, one record per line, each tagged by `type`:
+//!
+//! 1. `manifest`: format name and version, creation time, schema version,
+//!    collection name, embedding dimension and whether vectors are included.
+//! 2. `agent`, `knowledge`, `coordination`, `task_history`,
+//!    `knowledge_revision`, `relation`: one entity per line, as JSON. Knowledge, task
+//!    history and archived knowledge revisions carry their stored embedding in
+//!    `embeddings` when vectors are included, `null` otherwise.
+//! 3. `metadata`: a `METADATA_TABLE` row, its value hex-encoded.
+//! 4. `footer`: record counts per type and the SHA-256 of every preceding line
+//!    (newlines included).
+//!
+//! Import verifies the checksum and counts before touching the database. In
+//! `Replace` mode existing entities are removed first; in `Merge` mode snapshot
+//! entities overwrite those with the same ID. Knowledge is re-embedded when the
+//! snapshot has no vectors or its embedding dimension differs from the target.
+//! Metadata derived by the storage layer (schema version, lexical statistics,
+//! active collection, reindex checkpoint, encryption key ID, task history
+//! migration marker, content hash, coordination and tag index markers) is
+//! exported for reference but never imported.
+
+use super::coordination_index::{self, COORDINATION_INDEX_KEY};
+use super::dedup::CONTENT_HASH_INDEX_KEY;
+use super::encryption::ENCRYPTION_KEY_ID_KEY;
+use super::history::TASK_HISTORY_MOVED_KEY;
+use super::lexical::LEXICAL_STATS_KEY;
+use super::reindex::{ACTIVE_COLLECTION_KEY, REINDEX_CHECKPOINT_KEY};
+use super::relations::{self, Relation};
+use super::revisions::{revision_key, KnowledgeRevision};
+use super::schema::{lift_legacy_title_and_tags, EntityTable, SCHEMA_VERSION_KEY};
+use super::tags::TAG_INDEX_KEY;
+use super::{
+    AgentEntity, BatchItemResult, CoordinationEntity, HybridStorage, HybridStorageCoordinator, KnowledgeEntity,
+    OperationType, StorageError, TaskHistoryEntity, VectorIntent, AGENTS_TABLE, COORDINATION_TABLE,
+    KNOWLEDGE_REVISIONS_TABLE, KNOWLEDGE_TABLE, METADATA_TABLE, RELATIONS_TABLE, TASK_HISTORY_TABLE,
+};
+use redb::{ReadableTable, TableDefinition, TableHandle};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Value of `SnapshotManifest::format`
+pub const SNAPSHOT_FORMAT: &str = "acs-snapshot";
+
+/// Version of the snapshot file layout described above
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub format: String,
+    pub format_version: u32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub schema_version: u32,
+    pub collection_name: String,
+    pub embedding_dimension: usize,
+    pub includes_vectors: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotCounts {
+    pub agents: usize,
+    pub knowledge: usize,
+    pub coordination: usize,
+    /// Absent from snapshots written before task history had its own table
+    #[serde(default)]
+    pub task_history: usize,
+    #[serde(default)]
+    pub knowledge_revisions: usize,
+    #[serde(default)]
+    pub relations: usize,
+    pub metadata: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotFooter {
+    pub counts: SnapshotCounts,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SnapshotRecord {
+    Manifest(SnapshotManifest),
+    Agent(AgentEntity),
+    Knowledge(KnowledgeEntity),
+    Coordination(CoordinationEntity),
+    TaskHistory(TaskHistoryEntity),
+    KnowledgeRevision(KnowledgeRevision),
+    Relation(Relation),
+    Metadata { key: String, value: String },
+    Footer(SnapshotFooter),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportOptions {
+    /// Include stored embeddings; without them the importer re-embeds every entry
+    pub include_vectors: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self { include_vectors: true }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportOptions {
+    pub mode: ImportMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImportMode {
+    #[default]
+    Merge,         // Keep existing entities; snapshot entities win on ID conflicts
+    Replace,       // Remove every existing entity before importing
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportReport {
+    pub manifest: SnapshotManifest,
+    pub counts: SnapshotCounts,
+    /// Rows left out because they could not be decoded
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub imported: SnapshotCounts,
+    /// Existing entities removed in `Replace` mode
+    pub removed: usize,
+    pub re_embedded: bool,
+    /// Knowledge entries that could not be stored
+    pub failed: Vec<BatchItemResult>,
+}
+
+/// Agent, coordination, task history, knowledge revision, relation or metadata row ready to be written: table, key, encoded value
+type ImportRow = (TableDefinition<'static, &'static str, &'static [u8]>, String, Vec<u8>);
+
+/// Metadata rows the storage layer derives itself
+fn is_derived_metadata(key: &str) -> bool {
+    [
+        SCHEMA_VERSION_KEY, LEXICAL_STATS_KEY, ACTIVE_COLLECTION_KEY, REINDEX_CHECKPOINT_KEY, ENCRYPTION_KEY_ID_KEY,
+        TASK_HISTORY_MOVED_KEY, CONTENT_HASH_INDEX_KEY, COORDINATION_INDEX_KEY, TAG_INDEX_KEY,
+    ].contains(&key)
+}
+
+impl HybridStorageCoordinator {
+    /// Write every entity and metadata row to a snapshot file
+    ///
+    /// The snapshot is written next to `path` and renamed into place once complete.
+    pub async fn export_snapshot(
+        &self,
+        path: impl AsRef<Path>,
+        options: &ExportOptions,
+    ) -> Result<ExportReport, StorageError> {
+        let path = path.as_ref();
+        let partial_path = partial_path(path);
+
+        let manifest = SnapshotManifest {
+            format: SNAPSHOT_FORMAT.to_string(),
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            created_at: chrono::Utc::now(),
+            schema_version: self.schema_version()?,
+            collection_name: self.active_collection().await,
+            embedding_dimension: self.config.embedding_dimension,
+            includes_vectors: options.include_vectors,
+        };
+
+        let file = std::fs::File::create(&partial_path)
+            .map_err(|e| StorageError::IoError(format!("Failed to create {}: {}", partial_path.display(), e)))?;
+        let mut writer = SnapshotWriter { out: BufWriter::new(file), hasher: Sha256::new(), path: &partial_path };
+        let mut counts = SnapshotCounts::default();
+        let mut skipped = Vec::new();
+
+        writer.write(&SnapshotRecord::Manifest(manifest.clone()))?;
+
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        for entity_table in EntityTable::ALL {
+            for_each_row(&read_txn, entity_table.definition(), |key, data| {
+                let record = match entity_table {
+                    EntityTable::Agents => self.decode_row(entity_table, data).map(SnapshotRecord::Agent),
+                    EntityTable::Knowledge => self.decode_row::<KnowledgeEntity>(entity_table, data).map(|knowledge| {
+                        SnapshotRecord::Knowledge(KnowledgeEntity {
+                            embeddings: knowledge.embeddings.filter(|_| options.include_vectors),
+                            ..knowledge
+                        })
+                    }),
+                    EntityTable::Coordination => self.decode_row(entity_table, data).map(SnapshotRecord::Coordination),
+                    EntityTable::TaskHistory => self.decode_row::<TaskHistoryEntity>(entity_table, data).map(|entry| {
+                        SnapshotRecord::TaskHistory(TaskHistoryEntity {
+                            embeddings: entry.embeddings.filter(|_| options.include_vectors),
+                            ..entry
+                        })
+                    }),
+                    EntityTable::KnowledgeRevisions => self.decode_row::<KnowledgeRevision>(entity_table, data).map(|revision| {
+                        SnapshotRecord::KnowledgeRevision(KnowledgeRevision {
+                            entity: KnowledgeEntity {
+                                embeddings: revision.entity.embeddings.filter(|_| options.include_vectors),
+                                ..revision.entity
+                            },
+                            ..revision
+                        })
+                    }),
+                    EntityTable::Relations => self.decode_row(entity_table, data).map(SnapshotRecord::Relation),
+                };
+
+                match record {
+                    Ok(record) => {
+                        writer.write(&record)?;
+                        match entity_table {
+                            EntityTable::Agents => counts.agents += 1,
+                            EntityTable::Knowledge => counts.knowledge += 1,
+                            EntityTable::Coordination => counts.coordination += 1,
+                            EntityTable::TaskHistory => counts.task_history += 1,
+                            EntityTable::KnowledgeRevisions => counts.knowledge_revisions += 1,
+                            EntityTable::Relations => counts.relations += 1,
+                        }
+                    }
+                    Err(e) => skipped.push(format!("{} row {}: {}", entity_table.name(), key, e)),
+                }
+                Ok(())
+            })?;
+        }
+
+        for_each_row(&read_txn, METADATA_TABLE, |key, data| {
+            writer.write(&SnapshotRecord::Metadata { key: key.to_string(), value: to_hex(data) })?;
+            counts.metadata += 1;
+            Ok(())
+        })?;
+
+        writer.finish(counts.clone())?;
+        std::fs::rename(&partial_path, path)
+            .map_err(|e| StorageError::IoError(format!("Failed to move snapshot to {}: {}", path.display(), e)))?;
+
+        for skipped_row in &skipped {
+            tracing::warn!("Snapshot export skipped {}", skipped_row);
+        }
+
+        Ok(ExportReport { manifest, counts, skipped })
+    }
+
+    /// Load a snapshot file written by `export_snapshot`
+    pub async fn import_snapshot(
+        &self,
+        path: impl AsRef<Path>,
+        options: &ImportOptions,
+    ) -> Result<ImportReport, StorageError> {
+        let path = path.as_ref();
+
+        // Check integrity before changing anything
+        let manifest = verify_snapshot(path)?;
+        let re_embed = !manifest.includes_vectors || manifest.embedding_dimension != self.config.embedding_dimension;
+        // Snapshots from before schema version 3 keep knowledge titles and tags in metadata
+        let legacy_tags = manifest.schema_version < 3;
+
+        let mut report = ImportReport { re_embedded: re_embed, ..Default::default() };
+        if options.mode == ImportMode::Replace {
+            report.removed = self.remove_all_entities().await?;
+        }
+
+        let chunk_size = self.config.sync_batch_size.max(1);
+        let mut knowledge = Vec::with_capacity(chunk_size);
+        let mut rows: Vec<ImportRow> = Vec::with_capacity(chunk_size);
+
+        for line in open_lines(path)? {
+            let line = line?;
+            match parse_record(&line)? {
+                SnapshotRecord::Agent(agent) => {
+                    rows.push((AGENTS_TABLE, agent.id.to_string(), self.encode_row(EntityTable::Agents, &agent)?));
+                    report.imported.agents += 1;
+                }
+                SnapshotRecord::Coordination(coordination) => {
+                    rows.push((COORDINATION_TABLE, coordination.id.to_string(), self.encode_row(EntityTable::Coordination, &coordination)?));
+                    report.imported.coordination += 1;
+                }
+                SnapshotRecord::TaskHistory(entry) => {
+                    let embedding = match entry.embeddings.clone().filter(|_| !re_embed) {
+                        Some(embedding) => embedding,
+                        None => self.generate_embedding(&entry.summary).await?,
+                    };
+                    let entry = TaskHistoryEntity { embeddings: Some(embedding), ..entry };
+                    rows.push((TASK_HISTORY_TABLE, entry.id.to_string(), self.encode_row(EntityTable::TaskHistory, &entry)?));
+                    report.imported.task_history += 1;
+                }
+                SnapshotRecord::KnowledgeRevision(mut revision) => {
+                    // Embedded again by `search_knowledge_as_of` when needed
+                    if re_embed {
+                        revision.entity.embeddings = None;
+                    }
+                    if legacy_tags {
+                        lift_legacy_title_and_tags(&mut revision.entity);
+                    }
+                    let key = revision_key(revision.entity.id, revision.revision);
+                    rows.push((KNOWLEDGE_REVISIONS_TABLE, key, self.encode_row(EntityTable::KnowledgeRevisions, &revision)?));
+                    report.imported.knowledge_revisions += 1;
+                }
+                SnapshotRecord::Relation(relation) => {
+                    let key = relations::relation_key(&relation.from, relation.kind, &relation.to);
+                    rows.push((RELATIONS_TABLE, key, self.encode_row(EntityTable::Relations, &relation)?));
+                    report.imported.relations += 1;
+                }
+                SnapshotRecord::Knowledge(mut entry) => {
+                    if legacy_tags {
+                        lift_legacy_title_and_tags(&mut entry);
+                    }
+                    knowledge.push(KnowledgeEntity {
+                        embeddings: if re_embed { None } else { entry.embeddings.clone() },
+                        ..entry
+                    });
+                }
+                SnapshotRecord::Metadata { key, value } => {
+                    if !is_derived_metadata(&key) {
+                        rows.push((METADATA_TABLE, key, from_hex(&value)?));
+                        report.imported.metadata += 1;
+                    }
+                }
+                SnapshotRecord::Manifest(_) | SnapshotRecord::Footer(_) => {}
+            }
+
+            if rows.len() >= chunk_size {
+                self.import_rows(std::mem::take(&mut rows)).await?;
+            }
+            if knowledge.len() >= chunk_size {
+                self.import_knowledge(std::mem::take(&mut knowledge), &mut report).await?;
+            }
+        }
+
+        self.import_rows(rows).await?;
+        self.import_knowledge(knowledge, &mut report).await?;
+
+        Ok(report)
+    }
+
+    async fn import_rows(&self, rows: Vec<ImportRow>) -> Result<(), StorageError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        self.execute_coordinated_transaction(OperationType::Batch, vec![], |txn| {
+            for (table, key, data) in &rows {
+                if table.name() == RELATIONS_TABLE.name() {
+                    relations::insert_relation(txn, key, data)?;
+                    continue;
+                }
+                txn.insert(*table, key, data)?;
+                if table.name() == COORDINATION_TABLE.name() {
+                    coordination_index::reindex_coordination_row(txn.transaction(), key, self.cipher())?;
+                }
+            }
+            Ok(vec![])
+        }).await
+    }
+
+    async fn import_knowledge(&self, knowledge: Vec<KnowledgeEntity>, report: &mut ImportReport) -> Result<(), StorageError> {
+        if knowledge.is_empty() {
+            return Ok(());
+        }
+
+        let batch = self.store_knowledge_batch(&knowledge).await?;
+        report.imported.knowledge += batch.succeeded;
+        report.failed.extend(batch.items.into_iter().filter(|item| !item.success));
+        Ok(())
+    }
+
+    /// Remove every entity row and the knowledge vectors, as one coordinated operation
+    async fn remove_all_entities(&self) -> Result<usize, StorageError> {
+        let (agent_keys, knowledge_keys, coordination_keys, task_history_keys, revision_keys, relation_keys) = {
+            let read_txn = self.redb.begin_read()
+                .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+            let keys = |table| -> Result<Vec<String>, StorageError> {
+                let mut keys = Vec::new();
+                for_each_row(&read_txn, table, |key, _| {
+                    keys.push(key.to_string());
+                    Ok(())
+                })?;
+                Ok(keys)
+            };
+            (
+                keys(AGENTS_TABLE)?,
+                keys(KNOWLEDGE_TABLE)?,
+                keys(COORDINATION_TABLE)?,
+                keys(TASK_HISTORY_TABLE)?,
+                keys(KNOWLEDGE_REVISIONS_TABLE)?,
+                keys(RELATIONS_TABLE)?,
+            )
+        };
+
+        let removed = agent_keys.len()
+            + knowledge_keys.len()
+            + coordination_keys.len()
+            + task_history_keys.len()
+            + revision_keys.len()
+            + relation_keys.len();
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        let active = self.knowledge_collection().await;
+        let collection = active.clone();
+        let knowledge_ids: Vec<uuid::Uuid> = knowledge_keys.iter()
+            .filter_map(|key| uuid::Uuid::parse_str(key).ok())
+            .collect();
+        let vector_intents = knowledge_ids.chunks(self.config.sync_batch_size.max(1))
+            .map(|ids| VectorIntent::Delete { collection: collection.clone(), ids: ids.to_vec() })
+            .collect();
+
+        self.execute_coordinated_transaction(OperationType::Batch, vector_intents, |txn| {
+            for key in &agent_keys {
+                txn.remove(AGENTS_TABLE, key)?;
+            }
+            for key in &coordination_keys {
+                txn.remove(COORDINATION_TABLE, key)?;
+                coordination_index::unindex_coordination(txn.transaction(), key)?;
+            }
+            for key in &task_history_keys {
+                txn.remove(TASK_HISTORY_TABLE, key)?;
+            }
+            for key in &revision_keys {
+                txn.remove(KNOWLEDGE_REVISIONS_TABLE, key)?;
+            }
+            for key in &relation_keys {
+                relations::remove_relation(txn, key)?;
+            }
+
+            let mut restored = Vec::new();
+            for key in &knowledge_keys {
+                if let Some(point) = super::replaced_knowledge_point(self.cipher(), txn.remove(KNOWLEDGE_TABLE, key)?) {
+                    restored.push(point);
+                }
+                super::unindex_derived(txn.transaction(), key)?;
+            }
+
+            Ok(if restored.is_empty() {
+                vec![]
+            } else {
+                vec![VectorIntent::Upsert { collection, points: restored }]
+            })
+        }).await?;
+
+        Ok(removed)
+    }
+}
+
+struct SnapshotWriter<'a> {
+    out: BufWriter<std::fs::File>,
+    hasher: Sha256,
+    path: &'a Path,
+}
+
+impl SnapshotWriter<'_> {
+    fn write(&mut self, record: &SnapshotRecord) -> Result<(), StorageError> {
+        let mut line = serde_json::to_vec(record)
+            .map_err(|e| StorageError::SerializationError(format!("Failed to serialize snapshot record: {}", e)))?;
+        line.push(b'\n');
+        self.hasher.update(&line);
+        self.out.write_all(&line)
+            .map_err(|e| StorageError::IoError(format!("Failed to write {}: {}", self.path.display(), e)))
+    }
+
+    fn finish(mut self, counts: SnapshotCounts) -> Result<(), StorageError> {
+        let footer = SnapshotRecord::Footer(SnapshotFooter {
+            counts,
+            sha256: format!("{:x}", self.hasher.clone().finalize()),
+        });
+        self.write(&footer)?;
+
+        let file = self.out.into_inner()
+            .map_err(|e| StorageError::IoError(format!("Failed to write {}: {}", self.path.display(), e)))?;
+        file.sync_all()
+            .map_err(|e| StorageError::IoError(format!("Failed to sync {}: {}", self.path.display(), e)))
+    }
+}
+
+/// Check the manifest, record counts and checksum of a snapshot file
+fn verify_snapshot(path: &Path) -> Result<SnapshotManifest, StorageError> {
+    let mut hasher = Sha256::new();
+    let mut manifest = None;
+    let mut footer = None;
+    let mut counts = SnapshotCounts::default();
+
+    for line in open_lines(path)? {
+        let line = line?;
+        if footer.is_some() {
+            return Err(StorageError::SerializationError("Snapshot has records after its footer".to_string()));
+        }
+
+        let record = parse_record(&line)?;
+        if manifest.is_none() && !matches!(record, SnapshotRecord::Manifest(_)) {
+            return Err(StorageError::SerializationError("Snapshot does not start with a manifest".to_string()));
+        }
+
+        match record {
+            SnapshotRecord::Manifest(found) => {
+                if manifest.is_some() {
+                    return Err(StorageError::SerializationError("Snapshot has more than one manifest".to_string()));
+                }
+                if found.format != SNAPSHOT_FORMAT || found.format_version != SNAPSHOT_FORMAT_VERSION {
+                    return Err(StorageError::SerializationError(format!(
+                        "Unsupported snapshot format {} version {}", found.format, found.format_version
+                    )));
+                }
+                manifest = Some(found);
+            }
+            SnapshotRecord::Agent(_) => counts.agents += 1,
+            SnapshotRecord::Knowledge(_) => counts.knowledge += 1,
+            SnapshotRecord::Coordination(_) => counts.coordination += 1,
+            SnapshotRecord::TaskHistory(_) => counts.task_history += 1,
+            SnapshotRecord::KnowledgeRevision(_) => counts.knowledge_revisions += 1,
+            SnapshotRecord::Relation(_) => counts.relations += 1,
+            SnapshotRecord::Metadata { .. } => counts.metadata += 1,
+            SnapshotRecord::Footer(found) => {
+                footer = Some(found);
+                continue;
+            }
+        }
+
+        hasher.update(line.as_bytes());
+        hasher.update(b"\n");
+    }
+
+    let manifest = manifest
+        .ok_or_else(|| StorageError::SerializationError("Snapshot has no manifest".to_string()))?;
+    let footer = footer
+        .ok_or_else(|| StorageError::SerializationError("Snapshot is truncated: no footer".to_string()))?;
+
+    let checksum = format!("{:x}", hasher.finalize());
+    if checksum != footer.sha256 {
+        return Err(StorageError::SerializationError(format!(
+            "Snapshot checksum mismatch: expected {}, found {}", footer.sha256, checksum
+        )));
+    }
+    if counts != footer.counts {
+        return Err(StorageError::SerializationError(format!(
+            "Snapshot record counts {:?} do not match footer {:?}", counts, footer.counts
+        )));
+    }
+
+    Ok(manifest)
+}
+
+fn open_lines(path: &Path) -> Result<impl Iterator<Item = Result<String, StorageError>> + '_, StorageError> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| StorageError::IoError(format!("Failed to open {}: {}", path.display(), e)))?;
+    Ok(BufReader::new(file).lines().map(move |line| {
+        line.map_err(|e| StorageError::IoError(format!("Failed to read {}: {}", path.display(), e)))
+    }))
+}
+
+fn parse_record(line: &str) -> Result<SnapshotRecord, StorageError> {
+    serde_json::from_str(line)
+        .map_err(|e| StorageError::SerializationError(format!("Invalid snapshot record: {}", e)))
+}
+
+/// Visit every row of a table in key order; a missing table has no rows
+pub(crate) fn for_each_row(
+    read_txn: &redb::ReadTransaction,
+    definition: TableDefinition<&str, &[u8]>,
+    mut visit: impl FnMut(&str, &[u8]) -> Result<(), StorageError>,
+) -> Result<(), StorageError> {
+    let table = match read_txn.open_table(definition) {
+        Ok(table) => table,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(()),
+        Err(e) => return Err(StorageError::TransactionError(format!("Failed to open {} table: {}", definition, e))),
+    };
+
+    for row in table.iter()
+        .map_err(|e| StorageError::TransactionError(format!("Failed to read {} table: {}", definition, e)))?
+    {
+        let (key, value) = row
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read {} table: {}", definition, e)))?;
+        visit(key.value(), value.value())?;
+    }
+    Ok(())
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, StorageError> {
+    let invalid = || StorageError::SerializationError(format!("Invalid hex value in snapshot: {}", hex));
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()).ok_or_else(invalid))
+        .collect()
+}
@@ -0,0 +1,318 @@
+//! Background Synchronization Worker
+//!
+//! Under `ConsistencyMode::Eventually` and `ConsistencyMode::EventDriven` writes
+//! return as soon as REDB commits. A tokio task then brings the vector index up
+//! to date, draining the journal (or projecting the outbox) in batches of
+//! `StorageConfig::sync_batch_size` and retrying failures with exponential backoff.
+//!
+//! A journal batch the vector index rejects is retried one entry at a time, so
+//! one bad entry cannot hold back the rest. Entries that still fail while
+//! others apply, and entries whose points do not have `embedding_dimension`
+//! components, are moved to a dead-letter table and counted in
+//! `SyncStatus::quarantined_count`. Their rows stay in REDB: `repair` rebuilds
+//! the missing points from them, or `requeue_quarantined` retries the entries.
+
+use super::coordination::{JournalEntry, JournalPhase, RecoveryPolicy, VectorIntent};
+use super::{ConsistencyMode, HybridStorageCoordinator, StorageError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// How long the worker sleeps when idle before re-checking the queue
+const SYNC_IDLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Retry backoff bounds after a failed batch
+const MIN_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Number of flush passes `synchronize` attempts before reporting failure
+const FLUSH_ATTEMPTS: u32 = 5;
+
+/// Consecutive journal batches in which no entry applies before the index is taken to be unavailable
+const MAX_FAILED_BATCHES: usize = 3;
+
+/// Shared handle between the coordinator and its background worker
+#[derive(Default)]
+pub(crate) struct SyncWorker {
+    notify: Notify,
+    shutdown: AtomicBool,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl SyncWorker {
+    pub(crate) fn signal_shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+}
+
+/// Outcome of draining the pending vector writes
+#[derive(Debug, Default)]
+pub(crate) struct FlushOutcome {
+    pub(crate) applied: usize,
+    pub(crate) quarantined: usize,
+    pub(crate) remaining: usize,
+    pub(crate) error: Option<StorageError>,
+}
+
+impl HybridStorageCoordinator {
+    /// Spawn the background worker for the eventual consistency modes
+    pub(crate) fn start_sync_worker(&self) {
+        if matches!(self.config.consistency_mode, ConsistencyMode::Immediate) {
+            return;
+        }
+
+        let coordinator = self.background_handle();
+        let handle = tokio::spawn(async move { coordinator.run_sync_worker().await });
+        *self.sync_worker.handle.lock().unwrap() = Some(handle);
+    }
+
+    /// Stop the background worker after it finishes its current batch, along with the retention compactor
+    pub async fn stop_sync_worker(&self) {
+        self.stop_compactor().await;
+
+        self.sync_worker.signal_shutdown();
+
+        let handle = self.sync_worker.handle.lock().unwrap().take();
+        if let Some(handle) = handle {
+            let _ = handle.await;
+        }
+    }
+
+    /// Wake the worker after new pending writes were committed
+    pub(crate) fn notify_sync_worker(&self) {
+        self.sync_worker.notify.notify_one();
+    }
+
+    async fn run_sync_worker(self) {
+        let mut backoff = MIN_RETRY_BACKOFF;
+
+        loop {
+            // Exit once shut down, or when every user handle is gone
+            if self.sync_worker.shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+
+            match self.flush_pending().await {
+                Ok(outcome) if outcome.error.is_none() => {
+                    backoff = MIN_RETRY_BACKOFF;
+                    if outcome.remaining == 0 {
+                        let _ = tokio::time::timeout(SYNC_IDLE_INTERVAL, self.sync_worker.notify.notified()).await;
+                    }
+                }
+                Ok(_) | Err(_) => {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+                }
+            }
+        }
+    }
+
+    /// Flush pending writes, retrying with backoff, until the queue is empty
+    pub(crate) async fn flush_until_empty(&self) -> Result<FlushOutcome, StorageError> {
+        let mut total = FlushOutcome::default();
+        let mut backoff = MIN_RETRY_BACKOFF;
+
+        for attempt in 0..FLUSH_ATTEMPTS {
+            let outcome = self.flush_pending().await?;
+            total.applied += outcome.applied;
+            total.remaining = outcome.remaining;
+            total.error = outcome.error;
+
+            if total.remaining == 0 || attempt + 1 == FLUSH_ATTEMPTS {
+                break;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+        }
+
+        Ok(total)
+    }
+
+    /// Drain the journal and project the outbox once, batch by batch
+    async fn flush_pending(&self) -> Result<FlushOutcome, StorageError> {
+        let mut outcome = self.drain_journal().await?;
+
+        let projection = self.project_outbox().await?;
+        outcome.applied += projection.events_applied;
+        outcome.quarantined += projection.events_quarantined;
+        if outcome.error.is_none() && projection.lag > 0 && projection.events_applied == 0 {
+            outcome.error = Some(StorageError::VectorError(format!(
+                "Outbox projection stalled at event {}", projection.checkpoint
+            )));
+        }
+
+        outcome.remaining += projection.lag;
+
+        self.refresh_pending_count().await;
+        {
+            let mut state = self.state.write().await;
+            match &outcome.error {
+                Some(e) => state.sync_status.last_error = Some(e.to_string()),
+                None => {
+                    state.sync_status.last_sync = Some(chrono::Utc::now());
+                    state.sync_status.last_error = None;
+                }
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    /// Apply roll-forward journal entries in batches of `sync_batch_size`
+    ///
+    /// The journal is read once per drain; entries journaled meanwhile wait for
+    /// the next one. A failed batch is retried entry by entry, and entries that
+    /// fail alone are quarantined if any other entry applied during the drain.
+    /// Otherwise the index is taken to be unavailable and they stay pending.
+    async fn drain_journal(&self) -> Result<FlushOutcome, StorageError> {
+        let _sync = self.sync_lock.lock().await;
+        let batch_size = self.config.sync_batch_size.max(1);
+        let mut outcome = FlushOutcome::default();
+
+        let entries: Vec<JournalEntry> = self.load_journal()?
+            .into_iter()
+            .filter(|entry| entry.phase == JournalPhase::Prepared && entry.policy == RecoveryPolicy::RollForward)
+            .collect();
+
+        let mut failed = Vec::new();
+        let mut failed_batches = 0;
+        for batch in entries.chunks(batch_size) {
+            if failed_batches == MAX_FAILED_BATCHES {
+                break;
+            }
+
+            let mut ready = Vec::with_capacity(batch.len());
+            for entry in batch {
+                match self.invalid_intent(entry) {
+                    Some(problem) => {
+                        tracing::warn!("Quarantining operation {}: {}", entry.operation_id, problem);
+                        self.quarantine_journal_entry(entry, &problem)?;
+                        outcome.quarantined += 1;
+                    }
+                    None => ready.push(entry),
+                }
+            }
+            if ready.is_empty() {
+                continue;
+            }
+
+            let intents = coalesce_intents(ready.iter().flat_map(|entry| entry.vector_intents.iter().cloned()));
+            let Err(e) = self.apply_vector_intents(&intents).await else {
+                let operation_ids: Vec<_> = ready.iter().map(|entry| entry.operation_id).collect();
+                self.remove_journal_entries(&operation_ids)?;
+                outcome.applied += ready.len();
+                failed_batches = 0;
+                continue;
+            };
+
+            tracing::warn!("Sync batch of {} operations failed, retrying one by one: {}", ready.len(), e);
+            self.state.write().await.sync_status.error_count += 1;
+
+            let mut batch_applied = false;
+            for entry in ready {
+                match self.apply_vector_intents(&entry.vector_intents).await {
+                    Ok(()) => {
+                        self.remove_journal_entry(entry.operation_id)?;
+                        outcome.applied += 1;
+                        batch_applied = true;
+                    }
+                    Err(e) => failed.push((entry, e)),
+                }
+            }
+            failed_batches = if batch_applied { 0 } else { failed_batches + 1 };
+        }
+
+        // The index accepted other writes, so these entries are the problem
+        if outcome.applied > 0 {
+            for (entry, e) in failed.drain(..) {
+                tracing::warn!("Quarantining operation {}: {}", entry.operation_id, e);
+                self.quarantine_journal_entry(entry, &e.to_string())?;
+                outcome.quarantined += 1;
+            }
+        }
+
+        outcome.remaining = entries.len() - outcome.applied - outcome.quarantined;
+        outcome.error = failed.into_iter().next().map(|(_, e)| e);
+        Ok(outcome)
+    }
+
+    /// Why an entry can never apply, if it cannot: an upserted point of the wrong dimension
+    fn invalid_intent(&self, entry: &JournalEntry) -> Option<String> {
+        entry.vector_intents.iter()
+            .flat_map(|intent| match intent {
+                VectorIntent::Upsert { points, .. } => points.as_slice(),
+                VectorIntent::Delete { .. } => &[],
+            })
+            .find(|point| point.vector.len() != self.config.embedding_dimension)
+            .map(|point| format!(
+                "Point {} has dimension {}, expected {}",
+                point.id, point.vector.len(), self.config.embedding_dimension
+            ))
+    }
+
+    /// Move quarantined vector writes back into the journal or outbox and wake the sync worker, returning how many
+    ///
+    /// Apply them only while the entries they concern are unchanged since; otherwise
+    /// `repair` rebuilds the missing points from the current rows instead.
+    pub async fn requeue_quarantined(&self) -> Result<usize, StorageError> {
+        let requeued = {
+            let _sync = self.sync_lock.lock().await;
+            self.time_redb(|| Ok(self.requeue_dead_letters()? + self.requeue_outbox_dead_letters()?))?
+        };
+        self.refresh_pending_count().await;
+        self.notify_sync_worker();
+        Ok(requeued)
+    }
+
+    /// Vector writes not applied yet: every journal entry plus the outbox events past the checkpoint
+    pub(crate) fn pending_writes(&self) -> Result<usize, StorageError> {
+        Ok(self.journal_len()? + self.outbox_lag(self.outbox_checkpoint()?)?)
+    }
+
+    /// Recompute `SyncStatus::pending_count` and `quarantined_count`; every path that changes them calls this
+    pub(crate) async fn refresh_pending_count(&self) {
+        let quarantined = || Ok(self.dead_letter_len()? + self.outbox_dead_letter_len()?);
+        match self.pending_writes().and_then(|pending| Ok((pending, quarantined()?))) {
+            Ok((pending, quarantined)) => {
+                let mut state = self.state.write().await;
+                state.sync_status.pending_count = pending;
+                state.sync_status.quarantined_count = quarantined;
+            }
+            Err(e) => tracing::warn!("Failed to count pending vector writes: {}", e),
+        }
+    }
+}
+
+/// Merge consecutive operations on the same collection into single calls
+///
+/// Order is preserved across kinds so an upsert followed by a delete of the
+/// same point still ends with the point deleted.
+fn coalesce_intents(intents: impl Iterator<Item = VectorIntent>) -> Vec<VectorIntent> {
+    let mut merged: Vec<VectorIntent> = Vec::new();
+
+    for intent in intents {
+        match (merged.last_mut(), intent) {
+            (
+                Some(VectorIntent::Upsert { collection, points }),
+                VectorIntent::Upsert { collection: next_collection, points: next_points },
+            ) if *collection == next_collection => {
+                for point in next_points {
+                    points.retain(|existing| existing.id != point.id);
+                    points.push(point);
+                }
+            }
+            (
+                Some(VectorIntent::Delete { collection, ids }),
+                VectorIntent::Delete { collection: next_collection, ids: next_ids },
+            ) if *collection == next_collection => {
+                ids.extend(next_ids);
+            }
+            (_, intent) => merged.push(intent),
+        }
+    }
+
+    merged
+}
@@ -0,0 +1,246 @@
+//! Knowledge Tags
+//!
+//! `KnowledgeEntity::tags` are indexed in `TAG_TABLE` under `tag \0 id`, so
+//! `find_by_tags` reads one key range per tag instead of scanning every row.
+//! Chunks inherit their document's tags but are left out of the index, so
+//! tags count and find documents, not passages. Like the lexical index, tags
+//! are hashed under the row key when encryption is enabled; `list_tags` then
+//! recovers each name from one entry carrying it.
+//!
+//! The index is derived from the rows and maintained through the storage
+//! module's `index_derived` and `unindex_derived`. Databases written before
+//! it existed are indexed once on open.
+
+use super::encryption::RowCipher;
+use super::schema::EntityTable;
+use super::{HybridStorageCoordinator, KnowledgeEntity, StorageError, KNOWLEDGE_TABLE, METADATA_TABLE};
+use redb::{ReadableTable, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use uuid::Uuid;
+
+/// Tagged entry IDs keyed by `tag \0 id`; values are empty
+pub(crate) const TAG_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("knowledge_tags");
+
+/// Indexed tags of each entry (bincode `Vec<String>`), needed to remove them
+pub(crate) const TAG_KEYS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("knowledge_tag_keys");
+
+/// Metadata key marking that the tag index has been built
+pub(crate) const TAG_INDEX_KEY: &str = "tag_index";
+
+/// How `find_by_tags` combines several tags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TagMatch {
+    #[default]
+    All, // Entries carrying every tag
+    Any, // Entries carrying at least one of the tags
+}
+
+/// Form a tag takes in the index, hashed under the row key when encryption is enabled
+fn stored_tag(cipher: Option<&RowCipher>, tag: &str) -> String {
+    match cipher {
+        Some(cipher) => cipher.blind_term(tag),
+        None => tag.to_string(),
+    }
+}
+
+fn tag_key(tag: &str, id: &str) -> String {
+    format!("{}\0{}", tag, id)
+}
+
+/// Index the tags of a knowledge entry, replacing whatever was indexed under its ID
+pub(crate) fn index_tags(
+    write_txn: &WriteTransaction,
+    knowledge: &KnowledgeEntity,
+    cipher: Option<&RowCipher>,
+) -> Result<(), StorageError> {
+    let id = knowledge.id.to_string();
+    unindex_tags(write_txn, &id)?;
+    if knowledge.parent_id.is_some() || knowledge.tags.is_empty() {
+        return Ok(());
+    }
+
+    let tags: BTreeSet<String> = knowledge.tags.iter().map(|tag| stored_tag(cipher, tag)).collect();
+    {
+        let mut table = write_txn.open_table(TAG_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open tag index: {}", e)))?;
+        for tag in &tags {
+            table.insert(tag_key(tag, &id).as_str(), [].as_slice())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to write tag index entry: {}", e)))?;
+        }
+    }
+
+    let data = bincode::serialize(&tags)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to serialize indexed tags: {}", e)))?;
+    let mut keys = write_txn.open_table(TAG_KEYS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open tag keys: {}", e)))?;
+    keys.insert(id.as_str(), data.as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write tag keys: {}", e)))?;
+    Ok(())
+}
+
+/// Remove the tags of a knowledge entry from the index; unknown IDs are ignored
+pub(crate) fn unindex_tags(write_txn: &WriteTransaction, id: &str) -> Result<(), StorageError> {
+    let removed = {
+        let mut keys = write_txn.open_table(TAG_KEYS_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open tag keys: {}", e)))?;
+        let removed = keys.remove(id)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove tag keys: {}", e)))?;
+        removed.map(|data| data.value().to_vec())
+    };
+    let Some(data) = removed else {
+        return Ok(());
+    };
+
+    let tags: Vec<String> = bincode::deserialize(&data)
+        .map_err(|e| StorageError::SerializationError(format!("Failed to deserialize indexed tags: {}", e)))?;
+    let mut table = write_txn.open_table(TAG_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open tag index: {}", e)))?;
+    for tag in &tags {
+        table.remove(tag_key(tag, id).as_str())
+            .map_err(|e| StorageError::TransactionError(format!("Failed to remove tag index entry: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Drop the tag index ahead of a rebuild and mark it as built
+pub(crate) fn clear_index(write_txn: &WriteTransaction) -> Result<(), StorageError> {
+    write_txn.delete_table(TAG_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to clear tag index: {}", e)))?;
+    write_txn.delete_table(TAG_KEYS_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to clear tag keys: {}", e)))?;
+
+    let mut metadata = write_txn.open_table(METADATA_TABLE)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to open metadata table: {}", e)))?;
+    metadata.insert(TAG_INDEX_KEY, [1u8].as_slice())
+        .map_err(|e| StorageError::TransactionError(format!("Failed to write tag index marker: {}", e)))?;
+    Ok(())
+}
+
+/// IDs indexed under one stored tag, in ID order
+fn tagged_ids(table: &impl ReadableTable<&'static str, &'static [u8]>, tag: &str) -> Result<Vec<String>, StorageError> {
+    let prefix = format!("{}\0", tag);
+    let mut ids = Vec::new();
+    for row in table.range(prefix.as_str()..)
+        .map_err(|e| StorageError::TransactionError(format!("Failed to read tag index: {}", e)))?
+    {
+        let (key, _) = row.map_err(|e| StorageError::TransactionError(format!("Failed to read tag index: {}", e)))?;
+        match key.value().strip_prefix(prefix.as_str()) {
+            Some(id) => ids.push(id.to_string()),
+            None => break,
+        }
+    }
+    Ok(ids)
+}
+
+impl HybridStorageCoordinator {
+    /// Every tag in use with the number of entries carrying it
+    pub(crate) fn load_tags(&self) -> Result<BTreeMap<String, usize>, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+        let table = match read_txn.open_table(TAG_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(BTreeMap::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open tag index: {}", e))),
+        };
+
+        // Stored tag -> (entries, one of them)
+        let mut counts: BTreeMap<String, (usize, String)> = BTreeMap::new();
+        for row in table.iter()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to read tag index: {}", e)))?
+        {
+            let (key, _) = row.map_err(|e| StorageError::TransactionError(format!("Failed to read tag index: {}", e)))?;
+            let Some((tag, id)) = key.value().split_once('\0') else {
+                continue;
+            };
+            counts.entry(tag.to_string()).or_insert_with(|| (0, id.to_string())).0 += 1;
+        }
+
+        let Some(cipher) = self.cipher() else {
+            return Ok(counts.into_iter().map(|(tag, (count, _))| (tag, count)).collect());
+        };
+
+        let knowledge = read_txn.open_table(KNOWLEDGE_TABLE)
+            .map_err(|e| StorageError::TransactionError(format!("Failed to open knowledge table: {}", e)))?;
+        let mut tags = BTreeMap::new();
+        for (stored, (count, id)) in counts {
+            let data = knowledge.get(id.as_str())
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read knowledge: {}", e)))?;
+            let name = data
+                .map(|data| self.decode_row::<KnowledgeEntity>(EntityTable::Knowledge, data.value()))
+                .transpose()?
+                .and_then(|entity| entity.tags.into_iter().find(|tag| cipher.blind_term(tag) == stored));
+            match name {
+                Some(name) => {
+                    tags.insert(name, count);
+                }
+                None => tracing::warn!("Tag index entry for knowledge {} has no matching row", id),
+            }
+        }
+        Ok(tags)
+    }
+
+    /// IDs of entries carrying all or any of `tags`, in ID order
+    pub(crate) fn tagged_knowledge_ids(&self, tags: &[String], matching: TagMatch) -> Result<Vec<Uuid>, StorageError> {
+        if tags.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+        let table = match read_txn.open_table(TAG_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::TransactionError(format!("Failed to open tag index: {}", e))),
+        };
+
+        let mut matched: Option<BTreeSet<String>> = None;
+        for tag in tags.iter().collect::<HashSet<_>>() {
+            let ids: BTreeSet<String> = tagged_ids(&table, &stored_tag(self.cipher(), tag))?.into_iter().collect();
+            matched = Some(match (matched, matching) {
+                (None, _) => ids,
+                (Some(matched), TagMatch::All) => matched.intersection(&ids).cloned().collect(),
+                (Some(matched), TagMatch::Any) => matched.union(&ids).cloned().collect(),
+            });
+        }
+
+        matched.unwrap_or_default()
+            .into_iter()
+            .map(|id| Uuid::parse_str(&id)
+                .map_err(|e| StorageError::SerializationError(format!("Invalid tag index entry {}: {}", id, e))))
+            .collect()
+    }
+
+    /// Entries carrying all or any of `tags`, oldest first; unreadable rows are skipped with a warning
+    pub(crate) fn find_tagged_knowledge(
+        &self,
+        tags: &[String],
+        matching: TagMatch,
+    ) -> Result<Vec<KnowledgeEntity>, StorageError> {
+        let ids = self.tagged_knowledge_ids(tags, matching)?;
+
+        let mut found = Vec::with_capacity(ids.len());
+        for entity in self.load_knowledge_entities(&ids)? {
+            match entity {
+                Ok(entity) => found.push(entity),
+                Err(warning) => tracing::warn!("Tagged knowledge skipped: {}", warning),
+            }
+        }
+        found.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(found)
+    }
+
+    /// Whether the tag index has been built for this database
+    pub(crate) fn tag_index_exists(&self) -> Result<bool, StorageError> {
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        match read_txn.open_table(METADATA_TABLE) {
+            Ok(table) => Ok(table.get(TAG_INDEX_KEY)
+                .map_err(|e| StorageError::TransactionError(format!("Failed to read tag index marker: {}", e)))?
+                .is_some()),
+            Err(redb::TableError::TableDoesNotExist(_)) => Ok(false),
+            Err(e) => Err(StorageError::TransactionError(format!("Failed to open metadata table: {}", e))),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Vector Index Abstraction
+//!
+//! Decouples the hybrid storage coordinator from any particular vector database.
+//! REDB stays the source of truth for entities; a `VectorIndex` only holds the
+//! embeddings and a small payload used for filtering and consistency checks.
+
+use super::StorageError;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// A single embedding stored in a vector index collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorPoint {
+    pub id: Uuid,
+    pub vector: Vec<f32>,
+    pub payload: HashMap<String, serde_json::Value>,
+}
+
+/// A similarity search hit, ordered by descending score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorMatch {
+    pub id: Uuid,
+    pub score: f32,
+    pub payload: HashMap<String, serde_json::Value>,
+}
+
+/// Backend-agnostic vector index operations
+///
+/// Every operation is scoped to a named collection so that callers can keep
+/// several independent indexes (e.g. during a rebuild) in the same backend.
+#[async_trait]
+pub trait VectorIndex: Send + Sync {
+    /// Create the collection if it does not exist yet
+    async fn ensure_collection(&self, collection: &str, dimension: usize) -> Result<(), StorageError>;
+
+    /// Insert or replace points by ID
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<(), StorageError>;
+
+    /// Remove points by ID; missing IDs are ignored
+    async fn delete(&self, collection: &str, ids: &[Uuid]) -> Result<(), StorageError>;
+
+    /// Drop a collection and all of its points; a missing collection is ignored
+    async fn delete_collection(&self, collection: &str) -> Result<(), StorageError>;
+
+    /// Return up to `limit` points in ID order starting at `offset`, with the offset of the next page
+    async fn scroll(
+        &self,
+        collection: &str,
+        offset: Option<Uuid>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<Uuid>), StorageError>;
+
+    /// Return the `limit` points most similar to `vector` (cosine similarity)
+    async fn search(&self, collection: &str, vector: &[f32], limit: usize) -> Result<Vec<VectorMatch>, StorageError>;
+}
@@ -0,0 +1,458 @@
+//! Storage Consistency Checking and Repair
+//!
+//! `verify` compares every knowledge row with the active vector collection
+//! and reports four classes of drift:
+//!
+//! - a knowledge row without a vector point;
+//! - a vector point without a knowledge row;
+//! - a point whose payload (source, credibility, created_at) disagrees with
+//!   its row;
+//! - an entity row (agent, knowledge, coordination, task history, knowledge
+//!   revision or relation) that cannot be decoded.
+//!
+//! `repair` applies pending vector writes, then verifies and fixes each class
+//! according to a `RepairPolicy` while knowledge writes are blocked, so the
+//! issues it fixes cannot change underneath it. Unreadable rows can be moved
+//! to `QUARANTINE_TABLE` rather than deleted, keeping their bytes for
+//! inspection.
+
+use super::coordination_index;
+use super::relations::{self, EntityRef, Relation};
+use super::revisions::KnowledgeRevision;
+use super::schema::EntityTable;
+use super::snapshot::for_each_row;
+use super::{
+    knowledge_point, AgentEntity, CoordinationEntity, HybridStorageCoordinator, KnowledgeEntity, OperationType,
+    StorageError, TaskHistoryEntity, VectorIntent, KNOWLEDGE_TABLE,
+};
+use redb::TableDefinition;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Raw bytes of rows removed by `UnreadableRowRepair::Quarantine`, keyed by `table/key`
+pub(crate) const QUARANTINE_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("quarantine");
+
+/// Payload fields compared between a knowledge row and its vector point
+const CHECKED_PAYLOAD_FIELDS: [&str; 3] = ["source", "credibility", "created_at"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    /// Vector collection checked against `KNOWLEDGE_TABLE`
+    pub collection: String,
+    pub rows_checked: usize,
+    pub points_checked: usize,
+    /// Deferred vector writes not applied yet; entries they touch may be reported until synchronized
+    pub pending_writes: usize,
+    pub issues: Vec<ConsistencyIssue>,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsistencyIssue {
+    pub table: String,
+    pub key: String,
+    pub kind: ConsistencyIssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ConsistencyIssueKind {
+    MissingVector,                           // Knowledge row without a vector point
+    OrphanedVector,                          // Vector point without a knowledge row
+    PayloadMismatch { fields: Vec<String> }, // Point payload fields that differ from the row
+    Unreadable(String),                      // Row that failed to decode
+}
+
+impl std::fmt::Display for ConsistencyIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ConsistencyIssueKind::MissingVector => write!(f, "{} {} has no vector point", self.table, self.key),
+            ConsistencyIssueKind::OrphanedVector => write!(f, "vector point {} has no {} row", self.key, self.table),
+            ConsistencyIssueKind::PayloadMismatch { fields } => {
+                write!(f, "{} {} payload differs in {}", self.table, self.key, fields.join(", "))
+            }
+            ConsistencyIssueKind::Unreadable(error) => {
+                write!(f, "{} {} could not be read: {}", self.table, self.key, error)
+            }
+        }
+    }
+}
+
+/// How `repair` fixes each class of issue
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepairPolicy {
+    pub missing_vector: MissingVectorRepair,
+    pub orphaned_vector: OrphanedVectorRepair,
+    pub payload_mismatch: PayloadMismatchRepair,
+    pub unreadable_row: UnreadableRowRepair,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MissingVectorRepair {
+    #[default]
+    Reindex,       // Upsert the point from the row's stored embedding, re-embedding if it has none
+    DeleteRow,     // Treat the row as the stray half of a failed write
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrphanedVectorRepair {
+    #[default]
+    DeletePoint,
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PayloadMismatchRepair {
+    #[default]
+    RewritePoint,  // Upsert the point with the row's payload
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnreadableRowRepair {
+    #[default]
+    Quarantine,    // Move the row's bytes to the quarantine table and drop its point
+    Delete,        // Remove the row and its point
+    Ignore,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairReport {
+    /// Issues found before repairing
+    pub found: ConsistencyReport,
+    pub repaired: usize,
+    pub ignored: usize,
+    pub failures: Vec<RepairFailure>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairFailure {
+    pub issue: ConsistencyIssue,
+    pub error: String,
+}
+
+impl HybridStorageCoordinator {
+    /// Compare knowledge rows with the active vector collection and check that every entity row decodes
+    pub(crate) async fn run_verify(&self) -> Result<ConsistencyReport, StorageError> {
+        let collection = self.knowledge_collection().await;
+        self.verify_collection(&collection).await
+    }
+
+    /// Verify, then fix every issue found as `policy` directs
+    ///
+    /// Pending vector writes are applied first so that in-flight operations
+    /// are not mistaken for drift. Knowledge writes wait until the repair is done.
+    pub(crate) async fn run_repair(&self, policy: &RepairPolicy) -> Result<RepairReport, StorageError> {
+        let flushed = self.flush_until_empty().await?;
+        if flushed.remaining > 0 {
+            tracing::warn!("Repairing with {} vector writes still pending", flushed.remaining);
+        }
+
+        // Exclusive: no knowledge write or collection swap while repairing
+        let collection = self.active_collection.write().await;
+        let found = self.verify_collection(&collection).await?;
+
+        let mut missing = Vec::new();
+        let mut mismatched = Vec::new();
+        let mut orphaned = Vec::new();
+        let mut unreadable = Vec::new();
+        let mut ignored = 0;
+        for issue in &found.issues {
+            let queue = match issue.kind {
+                ConsistencyIssueKind::MissingVector => match policy.missing_vector {
+                    MissingVectorRepair::Ignore => None,
+                    _ => Some(&mut missing),
+                },
+                ConsistencyIssueKind::OrphanedVector => match policy.orphaned_vector {
+                    OrphanedVectorRepair::Ignore => None,
+                    OrphanedVectorRepair::DeletePoint => Some(&mut orphaned),
+                },
+                ConsistencyIssueKind::PayloadMismatch { .. } => match policy.payload_mismatch {
+                    PayloadMismatchRepair::Ignore => None,
+                    PayloadMismatchRepair::RewritePoint => Some(&mut mismatched),
+                },
+                ConsistencyIssueKind::Unreadable(_) => match policy.unreadable_row {
+                    UnreadableRowRepair::Ignore => None,
+                    _ => Some(&mut unreadable),
+                },
+            };
+            match queue {
+                Some(queue) => queue.push(issue.clone()),
+                None => ignored += 1,
+            }
+        }
+
+        let mut report = RepairReport { found: found.clone(), repaired: 0, ignored, failures: Vec::new() };
+
+        if policy.missing_vector == MissingVectorRepair::DeleteRow {
+            let outcome = self.delete_knowledge_rows(&missing).await;
+            report.record(&missing, outcome);
+        } else {
+            mismatched.append(&mut missing);
+        }
+
+        let outcome = self.rewrite_knowledge_points(&collection, &mismatched).await;
+        report.record(&mismatched, outcome);
+
+        let outcome = self.delete_orphaned_points(&collection, &orphaned).await;
+        report.record(&orphaned, outcome);
+
+        let quarantine = policy.unreadable_row == UnreadableRowRepair::Quarantine;
+        let outcome = self.remove_unreadable_rows(&collection, &unreadable, quarantine).await;
+        report.record(&unreadable, outcome);
+
+        tracing::info!(
+            "Repaired {} of {} storage issues ({} ignored, {} failed)",
+            report.repaired, found.issues.len(), report.ignored, report.failures.len()
+        );
+
+        Ok(report)
+    }
+
+    async fn verify_collection(&self, collection: &str) -> Result<ConsistencyReport, StorageError> {
+        let page_size = self.config.sync_batch_size.max(1);
+
+        // Payload of every point; vectors are not needed for the comparison
+        let mut points = HashMap::new();
+        let mut offset = None;
+        loop {
+            let (page, next) = self.vectors.scroll(collection, offset, page_size).await?;
+            points.extend(page.into_iter().map(|point| (point.id, point.payload)));
+            match next {
+                Some(next) => offset = Some(next),
+                None => break,
+            }
+        }
+
+        let mut report = ConsistencyReport {
+            collection: collection.to_string(),
+            rows_checked: 0,
+            points_checked: points.len(),
+            pending_writes: self.pending_writes()?,
+            issues: Vec::new(),
+        };
+
+        let read_txn = self.redb.begin_read()
+            .map_err(|e| StorageError::TransactionError(format!("Failed to begin read transaction: {}", e)))?;
+
+        for_each_row(&read_txn, KNOWLEDGE_TABLE, |key, data| {
+            report.rows_checked += 1;
+            let id = Uuid::parse_str(key).ok();
+            let point = id.and_then(|id| points.remove(&id));
+
+            let knowledge = match self.decode_row::<KnowledgeEntity>(EntityTable::Knowledge, data) {
+                Ok(knowledge) => knowledge,
+                Err(e) => {
+                    report.issues.push(issue(EntityTable::Knowledge, key, ConsistencyIssueKind::Unreadable(e.to_string())));
+                    return Ok(());
+                }
+            };
+
+            let Some(payload) = point else {
+                report.issues.push(issue(EntityTable::Knowledge, key, ConsistencyIssueKind::MissingVector));
+                return Ok(());
+            };
+
+            let expected = knowledge_point(&knowledge, Vec::new()).payload;
+            let fields: Vec<String> = CHECKED_PAYLOAD_FIELDS.iter()
+                .filter(|field| expected.get(**field) != payload.get(**field))
+                .map(|field| field.to_string())
+                .collect();
+            if !fields.is_empty() {
+                report.issues.push(issue(EntityTable::Knowledge, key, ConsistencyIssueKind::PayloadMismatch { fields }));
+            }
+            Ok(())
+        })?;
+
+        // Sorted so reports are stable between runs
+        let orphaned: BTreeMap<Uuid, _> = points.into_iter().collect();
+        for id in orphaned.keys() {
+            report.issues.push(issue(EntityTable::Knowledge, &id.to_string(), ConsistencyIssueKind::OrphanedVector));
+        }
+
+        for_each_row(&read_txn, EntityTable::Agents.definition(), |key, data| {
+            report.rows_checked += 1;
+            if let Err(e) = self.decode_row::<AgentEntity>(EntityTable::Agents, data) {
+                report.issues.push(issue(EntityTable::Agents, key, ConsistencyIssueKind::Unreadable(e.to_string())));
+            }
+            Ok(())
+        })?;
+        for_each_row(&read_txn, EntityTable::Coordination.definition(), |key, data| {
+            report.rows_checked += 1;
+            if let Err(e) = self.decode_row::<CoordinationEntity>(EntityTable::Coordination, data) {
+                report.issues.push(issue(EntityTable::Coordination, key, ConsistencyIssueKind::Unreadable(e.to_string())));
+            }
+            Ok(())
+        })?;
+        for_each_row(&read_txn, EntityTable::TaskHistory.definition(), |key, data| {
+            report.rows_checked += 1;
+            if let Err(e) = self.decode_row::<TaskHistoryEntity>(EntityTable::TaskHistory, data) {
+                report.issues.push(issue(EntityTable::TaskHistory, key, ConsistencyIssueKind::Unreadable(e.to_string())));
+            }
+            Ok(())
+        })?;
+        for_each_row(&read_txn, EntityTable::KnowledgeRevisions.definition(), |key, data| {
+            report.rows_checked += 1;
+            if let Err(e) = self.decode_row::<KnowledgeRevision>(EntityTable::KnowledgeRevisions, data) {
+                report.issues.push(issue(EntityTable::KnowledgeRevisions, key, ConsistencyIssueKind::Unreadable(e.to_string())));
+            }
+            Ok(())
+        })?;
+        for_each_row(&read_txn, EntityTable::Relations.definition(), |key, data| {
+            report.rows_checked += 1;
+            if let Err(e) = self.decode_row::<Relation>(EntityTable::Relations, data) {
+                report.issues.push(issue(EntityTable::Relations, key, ConsistencyIssueKind::Unreadable(e.to_string())));
+            }
+            Ok(())
+        })?;
+
+        for issue in &report.issues {
+            tracing::warn!("Storage consistency issue: {}", issue);
+        }
+
+        Ok(report)
+    }
+
+    /// Upsert the points of knowledge rows, from their stored embeddings where usable
+    async fn rewrite_knowledge_points(&self, collection: &str, issues: &[ConsistencyIssue]) -> Result<(), StorageError> {
+        let ids: Vec<Uuid> = issues.iter().filter_map(|issue| Uuid::parse_str(&issue.key).ok()).collect();
+
+        for chunk in ids.chunks(self.config.sync_batch_size.max(1)) {
+            let mut points = Vec::with_capacity(chunk.len());
+            for row in self.load_knowledge_entities(chunk)? {
+                // Rows deleted or broken since verification are left for the next run
+                let Ok(knowledge) = row else {
+                    continue;
+                };
+                let embedding = match &knowledge.embeddings {
+                    Some(embedding) if embedding.len() == self.config.embedding_dimension => embedding.clone(),
+                    _ => self.generate_embedding(&knowledge.content).await?,
+                };
+                points.push(knowledge_point(&knowledge, embedding));
+            }
+            self.vectors.upsert(collection, points).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn delete_orphaned_points(&self, collection: &str, issues: &[ConsistencyIssue]) -> Result<(), StorageError> {
+        let ids: Vec<Uuid> = issues.iter().filter_map(|issue| Uuid::parse_str(&issue.key).ok()).collect();
+
+        for chunk in ids.chunks(self.config.sync_batch_size.max(1)) {
+            self.vectors.delete(collection, chunk).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Delete knowledge rows that have no point, so there is no vector to remove
+    async fn delete_knowledge_rows(&self, issues: &[ConsistencyIssue]) -> Result<(), StorageError> {
+        if issues.is_empty() {
+            return Ok(());
+        }
+
+        self.execute_coordinated_transaction(OperationType::Delete, vec![], |txn| {
+            for issue in issues {
+                txn.remove(KNOWLEDGE_TABLE, &issue.key)?;
+                super::unindex_derived(txn.transaction(), &issue.key)?;
+                if let Ok(id) = Uuid::parse_str(&issue.key) {
+                    relations::remove_entity_relations(txn, &EntityRef::knowledge(id))?;
+                }
+            }
+            Ok(vec![])
+        }).await
+    }
+
+    /// Remove undecodable rows, optionally keeping their bytes in the quarantine table
+    async fn remove_unreadable_rows(
+        &self,
+        collection: &str,
+        issues: &[ConsistencyIssue],
+        quarantine: bool,
+    ) -> Result<(), StorageError> {
+        if issues.is_empty() {
+            return Ok(());
+        }
+
+        let knowledge_ids: Vec<Uuid> = issues.iter()
+            .filter(|issue| issue.table == EntityTable::Knowledge.name())
+            .filter_map(|issue| Uuid::parse_str(&issue.key).ok())
+            .collect();
+        let vector_intents = if knowledge_ids.is_empty() {
+            vec![]
+        } else {
+            vec![VectorIntent::Delete { collection: collection.to_string(), ids: knowledge_ids }]
+        };
+
+        // An unreadable row's point cannot be rebuilt, so there is no compensation
+        self.execute_coordinated_transaction(OperationType::Delete, vector_intents, |txn| {
+            for issue in issues {
+                let table = entity_table(&issue.table)?;
+                let Some(data) = txn.remove(table.definition(), &issue.key)? else {
+                    continue;
+                };
+                if quarantine {
+                    txn.insert(QUARANTINE_TABLE, &format!("{}/{}", issue.table, issue.key), &data)?;
+                }
+                let id = Uuid::parse_str(&issue.key).ok();
+                match table {
+                    EntityTable::Agents => {
+                        if let Some(id) = id {
+                            relations::remove_entity_relations(txn, &EntityRef::agent(id))?;
+                        }
+                    }
+                    EntityTable::Knowledge => {
+                        super::unindex_derived(txn.transaction(), &issue.key)?;
+                        if let Some(id) = id {
+                            relations::remove_entity_relations(txn, &EntityRef::knowledge(id))?;
+                        }
+                    }
+                    EntityTable::Coordination => {
+                        coordination_index::unindex_coordination(txn.transaction(), &issue.key)?;
+                        if let Some(id) = id {
+                            relations::remove_entity_relations(txn, &EntityRef::coordination(id))?;
+                        }
+                    }
+                    EntityTable::Relations => {
+                        relations::remove_relation(txn, &issue.key)?;
+                    }
+                    EntityTable::TaskHistory | EntityTable::KnowledgeRevisions => {}
+                }
+            }
+            Ok(vec![])
+        }).await
+    }
+}
+
+impl RepairReport {
+    fn record(&mut self, issues: &[ConsistencyIssue], outcome: Result<(), StorageError>) {
+        match outcome {
+            Ok(()) => self.repaired += issues.len(),
+            Err(e) => {
+                tracing::warn!("Failed to repair {} storage issues: {}", issues.len(), e);
+                self.failures.extend(issues.iter().map(|issue| RepairFailure {
+                    issue: issue.clone(),
+                    error: e.to_string(),
+                }));
+            }
+        }
+    }
+}
+
+fn issue(table: EntityTable, key: &str, kind: ConsistencyIssueKind) -> ConsistencyIssue {
+    ConsistencyIssue { table: table.name().to_string(), key: key.to_string(), kind }
+}
+
+fn entity_table(name: &str) -> Result<EntityTable, StorageError> {
+    EntityTable::ALL
+        .into_iter()
+        .find(|table| table.name() == name)
+        .ok_or_else(|| StorageError::NotFound(format!("Unknown entity table {}", name)))
+}
@@ -0,0 +1,124 @@
+//! Candle embedding tests
+//!
+//! Built with the `candle` feature only. Pooling and batching run against a
+//! tiny randomly initialized BERT written to a temporary directory. The cache
+//! tests need a real sentence-transformer (for example all-MiniLM-L6-v2): set
+//! `ACS_TEST_CANDLE_MODEL_DIR` to its directory and run them with `--ignored`.
+#![cfg(feature = "candle")]
+
+use acs_example::storage::candle_embedding::CandleEmbedder;
+use acs_example::storage::EmbeddingProvider;
+use candle_core::{DType, Device};
+use candle_nn::{VarBuilder, VarMap};
+use candle_transformers::models::bert::{BertModel, Config};
+use std::path::Path;
+
+const TINY_VOCAB: [&str; 10] = ["[PAD]", "[UNK]", "glaciers", "carve", "valleys", "rivers", "carry", "sediment", "to", "the"];
+
+fn load(batch_size: usize) -> CandleEmbedder {
+    let model_dir = std::env::var("ACS_TEST_CANDLE_MODEL_DIR").expect("ACS_TEST_CANDLE_MODEL_DIR is not set");
+    CandleEmbedder::load(model_dir, batch_size).unwrap()
+}
+
+/// Write a one-layer BERT with random weights and a whitespace word-level tokenizer to `dir`
+fn write_tiny_model(dir: &Path) {
+    let config = serde_json::json!({
+        "vocab_size": TINY_VOCAB.len(),
+        "hidden_size": 16,
+        "num_hidden_layers": 1,
+        "num_attention_heads": 2,
+        "intermediate_size": 32,
+        "hidden_act": "gelu",
+        "hidden_dropout_prob": 0.0,
+        "max_position_embeddings": 32,
+        "type_vocab_size": 2,
+        "initializer_range": 0.02,
+        "layer_norm_eps": 1e-12,
+        "pad_token_id": 0,
+        "classifier_dropout": null,
+        "model_type": null,
+    });
+    std::fs::write(dir.join("config.json"), config.to_string()).unwrap();
+
+    let vocab: serde_json::Map<String, serde_json::Value> =
+        TINY_VOCAB.iter().enumerate().map(|(id, word)| (word.to_string(), id.into())).collect();
+    let tokenizer = serde_json::json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "Whitespace" },
+        "post_processor": null,
+        "decoder": null,
+        "model": { "type": "WordLevel", "vocab": vocab, "unk_token": "[UNK]" },
+    });
+    std::fs::write(dir.join("tokenizer.json"), tokenizer.to_string()).unwrap();
+
+    let config: Config = serde_json::from_value(config).unwrap();
+    let weights = VarMap::new();
+    BertModel::load(VarBuilder::from_varmap(&weights, DType::F32, &Device::Cpu), &config).unwrap();
+    weights.save(dir.join("model.safetensors")).unwrap();
+}
+
+fn assert_close(a: &[f32], b: &[f32]) {
+    assert_eq!(a.len(), b.len());
+    for (x, y) in a.iter().zip(b) {
+        assert!((x - y).abs() < 1e-4, "{:?} != {:?}", a, b);
+    }
+}
+
+#[tokio::test]
+async fn padding_and_batching_do_not_change_embeddings() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tiny_model(dir.path());
+
+    // Texts of different lengths, so the shorter ones are padded within a batch
+    let texts = ["glaciers carve valleys", "rivers", "rivers carry the sediment to the valleys", "the glaciers"];
+    let batched = CandleEmbedder::load(dir.path(), 8).unwrap().embed(&texts).await.unwrap();
+    let one_by_one = CandleEmbedder::load(dir.path(), 1).unwrap().embed(&texts).await.unwrap();
+    assert_eq!(batched.len(), texts.len());
+
+    // Mean pooling skips padding, so each text embeds alike alone or beside longer ones
+    for (batched, alone) in batched.iter().zip(&one_by_one) {
+        assert_eq!(batched.len(), 16);
+        assert_close(batched, alone);
+        let norm: f32 = batched.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-4, "norm {}", norm);
+    }
+    assert!(batched[0].iter().zip(&batched[1]).any(|(x, y)| (x - y).abs() > 1e-3));
+}
+
+#[tokio::test]
+#[ignore = "needs ACS_TEST_CANDLE_MODEL_DIR"]
+async fn repeated_texts_are_embedded_once_and_cached() {
+    let embedder = load(2);
+
+    // Three misses, one repeated within the call and spanning two batches
+    let texts = ["Glaciers carve valleys", "Rivers carry sediment", "Glaciers carve valleys", "Deltas grow seaward"];
+    let first = embedder.embed(&texts).await.unwrap();
+    assert_eq!(first.len(), 4);
+    assert!(first.iter().all(|embedding| embedding.len() == embedder.dimension()));
+    assert_eq!(first[0], first[2]);
+    assert_ne!(first[0], first[1]);
+    assert_eq!(embedder.cached_embeddings(), 3);
+
+    // Hits return the cached embedding; only the new text is added
+    let second = embedder.embed(&["Rivers carry sediment", "Sea ice reflects sunlight"]).await.unwrap();
+    assert_eq!(second[0], first[1]);
+    assert_eq!(embedder.cached_embeddings(), 4);
+
+    let hits = embedder.embed(&texts).await.unwrap();
+    assert_eq!(hits, first);
+    assert_eq!(embedder.cached_embeddings(), 4);
+}
+
+#[tokio::test]
+#[ignore = "needs ACS_TEST_CANDLE_MODEL_DIR"]
+async fn texts_differing_slightly_are_cached_separately() {
+    let embedder = load(8);
+
+    let texts = ["Rivers carry sediment", "Rivers carry sediment.", "rivers carry sediment"];
+    assert_eq!(embedder.embed(&texts).await.unwrap().len(), 3);
+    assert_eq!(embedder.cached_embeddings(), 3);
+}
@@ -0,0 +1,265 @@
+//! Helpers shared by the integration tests
+//!
+//! `TestStorage` builds the coordinator each test opens. `FaultyIndex` wraps
+//! a real vector index so tests can make writes fail, reject particular
+//! points, or hold the next write until the test releases it; `FaultyBackend`
+//! does the same for the writes REDB makes to its file.
+
+#![allow(dead_code)]
+
+use acs_example::storage::{
+    ConsistencyMode, HybridStorageCoordinator, KnowledgeEntity, StorageConfig, StorageError, VectorBackend,
+    VectorIndex, VectorMatch, VectorPoint,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use redb::backends::FileBackend;
+use redb::{Database, StorageBackend};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// Storage for one test: `<dir>/<name>.redb` with the in-process vector index,
+/// Immediate consistency and no background compaction unless changed
+#[derive(Clone)]
+pub struct TestStorage {
+    pub config: StorageConfig,
+    vectors: Option<Arc<dyn VectorIndex>>,
+}
+
+impl TestStorage {
+    pub fn new(dir: &Path, name: &str) -> Self {
+        let mut config = StorageConfig {
+            redb_path: dir.join(format!("{}.redb", name)).to_string_lossy().to_string(),
+            vector_backend: VectorBackend::InProcess,
+            consistency_mode: ConsistencyMode::Immediate,
+            ..Default::default()
+        };
+        config.retention.compaction_interval_secs = 0;
+        Self { config, vectors: None }
+    }
+
+    pub fn consistency(mut self, consistency_mode: ConsistencyMode) -> Self {
+        self.config.consistency_mode = consistency_mode;
+        self
+    }
+
+    /// Use `vectors` instead of an index the coordinator opens itself
+    pub fn vectors(mut self, vectors: Arc<dyn VectorIndex>) -> Self {
+        self.vectors = Some(vectors);
+        self
+    }
+
+    pub fn configure(mut self, configure: impl FnOnce(&mut StorageConfig)) -> Self {
+        configure(&mut self.config);
+        self
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        PathBuf::from(&self.config.redb_path)
+    }
+
+    pub async fn try_open(&self) -> Result<HybridStorageCoordinator, StorageError> {
+        match &self.vectors {
+            Some(vectors) => HybridStorageCoordinator::with_vector_index(self.config.clone(), vectors.clone()).await,
+            None => HybridStorageCoordinator::new(self.config.clone()).await,
+        }
+    }
+
+    pub async fn open(&self) -> HybridStorageCoordinator {
+        self.try_open().await.unwrap()
+    }
+
+    /// Open the database file through a `FaultyBackend`; the vector index must be set with `vectors`
+    pub async fn open_faulty(&self) -> (HybridStorageCoordinator, FaultyBackend) {
+        let file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(false)
+            .open(self.db_path())
+            .unwrap();
+        let backend = FaultyBackend { file: Arc::new(FileBackend::new(file).unwrap()), fail_writes: Arc::default() };
+        let redb = Database::builder().create_with_backend(backend.clone()).unwrap();
+        let vectors = self.vectors.clone().expect("open_faulty needs a vector index");
+        let storage = HybridStorageCoordinator::with_database(self.config.clone(), redb, vectors).await.unwrap();
+        (storage, backend)
+    }
+
+    /// The same configuration over in-memory storage; the database path and vector index are not used
+    pub async fn open_in_memory(&self) -> HybridStorageCoordinator {
+        HybridStorageCoordinator::in_memory(self.config.clone()).await.unwrap()
+    }
+}
+
+/// REDB file backend whose writes can be failed on demand
+///
+/// REDB refuses all I/O once a write has failed, until the database is reopened.
+#[derive(Debug, Clone)]
+pub struct FaultyBackend {
+    file: Arc<FileBackend>,
+    fail_writes: Arc<AtomicBool>,
+}
+
+impl FaultyBackend {
+    /// Make every following write and sync fail (or succeed again)
+    pub fn fail_writes(&self, fail: bool) {
+        self.fail_writes.store(fail, Ordering::SeqCst);
+    }
+
+    fn check(&self) -> Result<(), std::io::Error> {
+        if self.fail_writes.load(Ordering::SeqCst) {
+            return Err(std::io::Error::other("injected REDB write failure"));
+        }
+        Ok(())
+    }
+}
+
+impl StorageBackend for FaultyBackend {
+    fn len(&self) -> Result<u64, std::io::Error> {
+        self.file.len()
+    }
+
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, std::io::Error> {
+        self.file.read(offset, len)
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), std::io::Error> {
+        self.check()?;
+        self.file.set_len(len)
+    }
+
+    fn sync_data(&self, eventual: bool) -> Result<(), std::io::Error> {
+        self.check()?;
+        self.file.sync_data(eventual)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> Result<(), std::io::Error> {
+        self.check()?;
+        self.file.write(offset, data)
+    }
+}
+
+/// Vector index whose writes can be failed or held on demand
+pub struct FaultyIndex {
+    inner: Arc<dyn VectorIndex>,
+    fail_writes: AtomicBool,
+    fail_next: AtomicBool,
+    writes: AtomicUsize,
+    rejected: Mutex<Vec<Uuid>>,
+    hold: Mutex<Option<Arc<Hold>>>,
+}
+
+/// A held write: `reached` fires when the write arrives, `release` lets it through
+pub struct Hold {
+    pub reached: Notify,
+    pub release: Notify,
+}
+
+impl FaultyIndex {
+    pub fn new(inner: Arc<dyn VectorIndex>) -> Arc<Self> {
+        Arc::new(Self {
+            inner,
+            fail_writes: AtomicBool::new(false),
+            fail_next: AtomicBool::new(false),
+            writes: AtomicUsize::new(0),
+            rejected: Mutex::new(Vec::new()),
+            hold: Mutex::new(None),
+        })
+    }
+
+    /// Make every following upsert and delete fail (or succeed again)
+    pub fn fail_writes(&self, fail: bool) {
+        self.fail_writes.store(fail, Ordering::SeqCst);
+    }
+
+    /// Make the next upsert or delete fail, including a held one once released
+    pub fn fail_next_write(&self) {
+        self.fail_next.store(true, Ordering::SeqCst);
+    }
+
+    /// Make every upsert containing the point `id` fail (or succeed again)
+    pub fn reject_point(&self, id: Uuid, reject: bool) {
+        let mut rejected = self.rejected.lock().unwrap();
+        rejected.retain(|rejected| *rejected != id);
+        if reject {
+            rejected.push(id);
+        }
+    }
+
+    /// Number of upsert and delete calls that reached the wrapped index
+    pub fn writes(&self) -> usize {
+        self.writes.load(Ordering::SeqCst)
+    }
+
+    /// Hold the next write until `Hold::release` is notified
+    pub fn hold_next_write(&self) -> Arc<Hold> {
+        let hold = Arc::new(Hold { reached: Notify::new(), release: Notify::new() });
+        *self.hold.lock().unwrap() = Some(hold.clone());
+        hold
+    }
+
+    async fn before_write(&self) -> Result<(), StorageError> {
+        let hold = self.hold.lock().unwrap().take();
+        if let Some(hold) = hold {
+            hold.reached.notify_one();
+            hold.release.notified().await;
+        }
+        if self.fail_next.swap(false, Ordering::SeqCst) || self.fail_writes.load(Ordering::SeqCst) {
+            return Err(StorageError::VectorError("injected vector failure".to_string()));
+        }
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VectorIndex for FaultyIndex {
+    async fn ensure_collection(&self, collection: &str, dimension: usize) -> Result<(), StorageError> {
+        self.inner.ensure_collection(collection, dimension).await
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<(), StorageError> {
+        if let Some(point) = points.iter().find(|point| self.rejected.lock().unwrap().contains(&point.id)) {
+            return Err(StorageError::VectorError(format!("injected rejection of point {}", point.id)));
+        }
+        self.before_write().await?;
+        self.inner.upsert(collection, points).await
+    }
+
+    async fn delete(&self, collection: &str, ids: &[Uuid]) -> Result<(), StorageError> {
+        self.before_write().await?;
+        self.inner.delete(collection, ids).await
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<(), StorageError> {
+        self.inner.delete_collection(collection).await
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        offset: Option<Uuid>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<Uuid>), StorageError> {
+        self.inner.scroll(collection, offset, limit).await
+    }
+
+    async fn search(&self, collection: &str, vector: &[f32], limit: usize) -> Result<Vec<VectorMatch>, StorageError> {
+        self.inner.search(collection, vector, limit).await
+    }
+}
+
+pub fn knowledge(content: &str) -> KnowledgeEntity {
+    KnowledgeEntity {
+        id: Uuid::new_v4(),
+        title: None,
+        content: content.to_string(),
+        metadata: Default::default(),
+        embeddings: None,
+        source: "integration".to_string(),
+        credibility_rating: "A1".to_string(),
+        created_at: Utc::now(),
+        tags: Vec::new(),
+        parent_id: None,
+        chunk_index: None,
+    }
+}
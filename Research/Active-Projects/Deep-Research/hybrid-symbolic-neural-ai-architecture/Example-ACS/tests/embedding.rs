@@ -0,0 +1,151 @@
+//! Embedding provider tests
+//!
+//! The hashing embedder's similarity, the coordinator's checks that every
+//! vector it writes has `embedding_dimension` components, and batch writes
+//! with a provider that fails on some inputs.
+
+mod common;
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::{
+    ConsistencyMode, EmbeddingProvider, HashingEmbedder, HybridStorage, HybridStorageCoordinator, KnowledgeEntity,
+    StorageError, TaskHistoryEntity,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use common::{knowledge, TestStorage};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Hashing embedder that fails any call including a text that mentions "unembeddable"
+struct FailingEmbedder(HashingEmbedder);
+
+#[async_trait]
+impl EmbeddingProvider for FailingEmbedder {
+    fn dimension(&self) -> usize {
+        self.0.dimension()
+    }
+
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, StorageError> {
+        if texts.iter().any(|text| text.contains("unembeddable")) {
+            return Err(StorageError::VectorError("injected embedding failure".to_string()));
+        }
+        self.0.embed(texts).await
+    }
+}
+
+/// Cosine similarity of two hashing embeddings, which are unit length
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[tokio::test]
+async fn shared_words_score_higher_than_unrelated_text() {
+    let embedder = HashingEmbedder::new(384);
+    let embeddings = embedder
+        .embed(&[
+            "volcanoes erupt molten lava",
+            "lava flows after volcanoes erupt",
+            "whales migrate through cold oceans",
+        ])
+        .await
+        .unwrap();
+    assert!(embeddings.iter().all(|embedding| embedding.len() == 384));
+
+    let related = similarity(&embeddings[0], &embeddings[1]);
+    let unrelated = similarity(&embeddings[0], &embeddings[2]);
+    assert!(related > unrelated, "related {} <= unrelated {}", related, unrelated);
+
+    // Deterministic across calls and instances
+    let again = HashingEmbedder::new(384).embed(&["volcanoes erupt molten lava"]).await.unwrap();
+    assert_eq!(again[0], embeddings[0]);
+}
+
+#[tokio::test]
+async fn provider_dimension_must_match_the_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = TestStorage::new(dir.path(), "mismatch").config;
+    assert_eq!(config.embedding_dimension, 384);
+
+    let result = HybridStorageCoordinator::with_backends(
+        config,
+        Arc::new(LocalVectorIndex::in_memory().unwrap()),
+        Arc::new(HashingEmbedder::new(8)),
+    )
+    .await;
+    match result {
+        Err(StorageError::ConfigurationError(message)) => assert!(message.contains("8-dimensional"), "{}", message),
+        other => panic!("expected a dimension mismatch, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn wrong_dimension_writes_are_rejected_before_anything_is_stored() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = TestStorage::new(dir.path(), "dimension")
+        .consistency(ConsistencyMode::Eventually)
+        .open()
+        .await;
+    storage.stop_sync_worker().await;
+
+    let entry = KnowledgeEntity { embeddings: Some(vec![0.5; 3]), ..knowledge("Basalt cools into columns") };
+    let result = storage.store_knowledge(&entry).await;
+    assert!(matches!(result, Err(StorageError::ConfigurationError(_))), "{:?}", result.map(|_| ()));
+    assert!(storage.get_knowledge(&entry.id).await.unwrap().is_none());
+
+    // An existing entry cannot be updated to a vector of another length either
+    let stored = knowledge("Pumice floats on water");
+    storage.store_knowledge(&stored).await.unwrap();
+    let update = KnowledgeEntity { embeddings: Some(vec![0.5; 3]), ..stored.clone() };
+    assert!(matches!(storage.update_knowledge(&update).await, Err(StorageError::ConfigurationError(_))));
+
+    let task = TaskHistoryEntity {
+        id: Uuid::new_v4(),
+        session_id: Uuid::new_v4(),
+        task_id: Uuid::new_v4(),
+        agent_id: Uuid::new_v4(),
+        action_type: "research".to_string(),
+        status: "Completed".to_string(),
+        summary: "map lava tubes".to_string(),
+        execution_time_ms: 5,
+        embeddings: Some(vec![0.5; 3]),
+        created_at: Utc::now(),
+    };
+    assert!(matches!(storage.record_task_history(&task).await, Err(StorageError::ConfigurationError(_))));
+    assert!(storage.search_task_history("lava tubes", 5).await.unwrap().is_empty());
+
+    // Only the valid store was journaled, and it applies
+    assert_eq!(storage.sync_status().await.pending_count, 1);
+    assert!(storage.synchronize().await.unwrap().success);
+    assert_eq!(storage.get_knowledge(&stored.id).await.unwrap().unwrap().content, stored.content);
+    assert!(storage.verify().await.unwrap().is_consistent());
+}
+
+#[tokio::test]
+async fn batch_items_that_fail_to_embed_fail_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = TestStorage::new(dir.path(), "failing").config;
+    let storage = HybridStorageCoordinator::with_backends(
+        config.clone(),
+        Arc::new(LocalVectorIndex::in_memory().unwrap()),
+        Arc::new(FailingEmbedder(HashingEmbedder::new(config.embedding_dimension))),
+    )
+    .await
+    .unwrap();
+
+    let entries = [
+        knowledge("Moraines mark where glaciers stopped"),
+        knowledge("This unembeddable entry breaks the provider"),
+        knowledge("Eskers are ridges left by meltwater"),
+    ];
+    let report = storage.store_knowledge_batch(&entries).await.unwrap();
+    assert_eq!((report.succeeded, report.failed), (2, 1));
+    assert!(report.items[0].success && report.items[2].success);
+    assert!(!report.items[1].success);
+    assert!(report.items[1].error.as_deref().is_some_and(|error| error.contains("injected embedding failure")));
+
+    assert!(storage.get_knowledge(&entries[0].id).await.unwrap().is_some());
+    assert!(storage.get_knowledge(&entries[1].id).await.unwrap().is_none());
+    assert!(storage.verify().await.unwrap().is_consistent());
+    storage.stop_sync_worker().await;
+}
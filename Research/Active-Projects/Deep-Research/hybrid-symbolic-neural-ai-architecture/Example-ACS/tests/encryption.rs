@@ -0,0 +1,228 @@
+//! Encryption at rest tests
+//!
+//! Each test writes through an encrypted coordinator, then inspects or edits
+//! the raw REDB file with the coordinator closed.
+
+mod common;
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::{
+    ConsistencyIssueKind, EmbeddingProvider, EncryptionConfig, FusionStrategy, HashingEmbedder, HybridStorage,
+    HybridStorageCoordinator, KeySource, KnowledgeEntity, ReindexOptions, StorageError, TagMatch, VectorIndex,
+};
+use common::{knowledge, TestStorage};
+use redb::{ReadableTable, TableDefinition};
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const KNOWLEDGE_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("knowledge");
+const COORDINATION_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("coordination");
+const LEXICAL_POSTINGS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("lexical_postings");
+
+/// Write a key file holding `byte` repeated, as 64 hex digits
+fn key_file(dir: &Path, byte: u8) -> KeySource {
+    let path = dir.join(format!("key-{:02x}", byte));
+    std::fs::write(&path, format!("{:02x}", byte).repeat(32)).unwrap();
+    KeySource::File(path.to_string_lossy().to_string())
+}
+
+fn with_key(dir: &Path, key: KeySource, previous_keys: Vec<KeySource>) -> TestStorage {
+    TestStorage::new(dir, "encrypted")
+        .configure(|config| config.encryption = Some(EncryptionConfig { key, previous_keys }))
+}
+
+async fn close(storage: HybridStorageCoordinator) {
+    storage.stop_sync_worker().await;
+}
+
+fn raw_row(encrypted: &TestStorage, table: TableDefinition<&str, &[u8]>, key: &str) -> Option<Vec<u8>> {
+    let db = redb::Database::create(encrypted.db_path()).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(table).unwrap();
+    let row = table.get(key).unwrap().map(|value| value.value().to_vec());
+    row
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[tokio::test]
+async fn rows_are_sealed_and_opened() {
+    let dir = tempfile::tempdir().unwrap();
+    let encrypted = with_key(dir.path(), key_file(dir.path(), 0x11), Vec::new());
+
+    let storage = encrypted.open().await;
+    let entry = knowledge("Volcanic ash grounds flights across the region");
+    storage.store_knowledge(&entry).await.unwrap();
+    assert_eq!(storage.get_knowledge(&entry.id).await.unwrap().unwrap().content, entry.content);
+    close(storage).await;
+
+    let row = raw_row(&encrypted, KNOWLEDGE_TABLE, &entry.id.to_string()).unwrap();
+    assert!(row.starts_with(b"ACSe"));
+    assert!(!contains(&row, b"Volcanic"));
+
+    let storage = encrypted.open().await;
+    assert_eq!(storage.get_knowledge(&entry.id).await.unwrap().unwrap().content, entry.content);
+    close(storage).await;
+}
+
+#[tokio::test]
+async fn rows_moved_to_another_table_fail_authentication() {
+    let dir = tempfile::tempdir().unwrap();
+    let encrypted = with_key(dir.path(), key_file(dir.path(), 0x22), Vec::new());
+
+    let storage = encrypted.open().await;
+    let entry = knowledge("A row sealed for the knowledge table");
+    storage.store_knowledge(&entry).await.unwrap();
+    close(storage).await;
+
+    // The table name is authenticated, so a sealed row copied elsewhere does not open
+    let row = raw_row(&encrypted, KNOWLEDGE_TABLE, &entry.id.to_string()).unwrap();
+    let moved_key = Uuid::new_v4().to_string();
+    {
+        let db = redb::Database::create(encrypted.db_path()).unwrap();
+        let write_txn = db.begin_write().unwrap();
+        write_txn.open_table(COORDINATION_TABLE).unwrap().insert(moved_key.as_str(), row.as_slice()).unwrap();
+        write_txn.commit().unwrap();
+    }
+
+    let storage = encrypted.open().await;
+    let report = storage.verify().await.unwrap();
+    let moved = report.issues.iter().find(|issue| issue.key == moved_key).unwrap();
+    assert!(
+        matches!(&moved.kind, ConsistencyIssueKind::Unreadable(error) if error.contains("authentication failed")),
+        "{:?}", moved.kind
+    );
+    close(storage).await;
+}
+
+#[tokio::test]
+async fn rows_do_not_open_with_another_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = with_key(dir.path(), key_file(dir.path(), 0x33), Vec::new()).open().await;
+    let entry = knowledge("Only the key that sealed a row opens it");
+    storage.store_knowledge(&entry).await.unwrap();
+    close(storage).await;
+
+    let storage = with_key(dir.path(), key_file(dir.path(), 0x44), Vec::new()).open().await;
+    let error = storage.get_knowledge(&entry.id).await.unwrap_err();
+    assert!(
+        matches!(&error, StorageError::SerializationError(message) if message.contains("not configured")),
+        "{}", error
+    );
+    close(storage).await;
+
+    // Without any key the database refuses to open
+    let unencrypted = TestStorage::new(dir.path(), "encrypted");
+    assert!(matches!(unencrypted.try_open().await, Err(StorageError::InitializationError(_))));
+}
+
+#[tokio::test]
+async fn rotation_reencrypts_with_the_current_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let old_key = key_file(dir.path(), 0x55);
+    let new_key = key_file(dir.path(), 0x66);
+
+    let before = with_key(dir.path(), old_key.clone(), Vec::new());
+    let storage = before.open().await;
+    let entry = knowledge("Key rotation keeps every row readable");
+    storage.store_knowledge(&entry).await.unwrap();
+    close(storage).await;
+    let old_row = raw_row(&before, KNOWLEDGE_TABLE, &entry.id.to_string()).unwrap();
+
+    // Opening with a new current key re-encrypts rows sealed with the retired one
+    let rotated = with_key(dir.path(), new_key.clone(), vec![old_key]);
+    let storage = rotated.open().await;
+    let report = storage.rotate_encryption_key().unwrap();
+    assert_eq!(report.reencrypted, 0, "rows were re-encrypted on open");
+    assert!(report.failures.is_empty());
+    close(storage).await;
+
+    let new_row = raw_row(&rotated, KNOWLEDGE_TABLE, &entry.id.to_string()).unwrap();
+    assert_ne!(new_row[4..12], old_row[4..12], "key ID in the sealed header changes");
+
+    // The retired key is no longer needed
+    let storage = with_key(dir.path(), new_key, Vec::new()).open().await;
+    assert_eq!(storage.get_knowledge(&entry.id).await.unwrap().unwrap().content, entry.content);
+    let results = storage.search_knowledge_hybrid("rotation", 1, FusionStrategy::default()).await.unwrap();
+    assert_eq!(results[0].entity.id, entry.id);
+    close(storage).await;
+}
+
+#[tokio::test]
+async fn lookups_use_blinded_terms() {
+    let dir = tempfile::tempdir().unwrap();
+    let encrypted = with_key(dir.path(), key_file(dir.path(), 0x77), Vec::new());
+
+    let storage = encrypted.open().await;
+    let entry = KnowledgeEntity {
+        tags: vec!["geology".to_string()],
+        ..knowledge("Basalt columns form as lava cools slowly")
+    };
+    storage.store_knowledge(&entry).await.unwrap();
+
+    let results = storage.search_knowledge_hybrid("basalt", 1, FusionStrategy::default()).await.unwrap();
+    assert_eq!(results[0].entity.id, entry.id);
+    let tagged = storage.find_by_tags(&["geology".to_string()], TagMatch::All).await.unwrap();
+    assert_eq!(tagged.len(), 1);
+    close(storage).await;
+
+    // Index keys hold keyed hashes, never the terms themselves
+    let db = redb::Database::create(encrypted.db_path()).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let postings = read_txn.open_table(LEXICAL_POSTINGS_TABLE).unwrap();
+    let mut terms = 0;
+    for row in postings.iter().unwrap() {
+        let (key, _) = row.unwrap();
+        assert!(!key.value().starts_with("basalt\0"));
+        terms += 1;
+    }
+    assert!(terms > 0);
+}
+
+/// Vector of the only point in the active collection
+async fn only_vector(storage: &HybridStorageCoordinator, index: &LocalVectorIndex) -> Vec<f32> {
+    let (points, _) = index.scroll(&storage.active_collection().await, None, 10).await.unwrap();
+    assert_eq!(points.len(), 1);
+    points[0].vector.clone()
+}
+
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[tokio::test]
+async fn hashing_vectors_are_keyed_with_the_data_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let old_key = key_file(dir.path(), 0x99);
+    let index = Arc::new(LocalVectorIndex::in_memory().unwrap());
+    let encrypted = with_key(dir.path(), old_key.clone(), Vec::new()).vectors(index.clone());
+
+    let storage = encrypted.open().await;
+    let entry = knowledge("Obsidian forms when felsic lava cools quickly");
+    storage.store_knowledge(&entry).await.unwrap();
+    let keyed = only_vector(&storage, &index).await;
+    assert_eq!(storage.search_knowledge(&entry.content, 1).await.unwrap()[0].entity.id, entry.id);
+    close(storage).await;
+
+    // The unkeyed embedding of the same text does not match the stored vector
+    let dimension = encrypted.config.embedding_dimension;
+    let guessed = HashingEmbedder::new(dimension).embed(&[entry.content.as_str()]).await.unwrap().remove(0);
+    assert!(similarity(&keyed, &guessed) < 0.5, "guessed text scores {}", similarity(&keyed, &guessed));
+
+    // A new key embeds differently, and reindexing re-embeds the stored vectors under it
+    let new_key = key_file(dir.path(), 0xaa);
+    let storage = with_key(dir.path(), new_key, vec![old_key]).vectors(index.clone()).open().await;
+    let before = storage.search_knowledge(&entry.content, 1).await.unwrap();
+    assert!(before.first().is_none_or(|result| result.score < 0.5), "{:?}", before.first().map(|result| result.score));
+
+    storage.reindex(&ReindexOptions::default(), |_| {}).await.unwrap();
+    let rekeyed = only_vector(&storage, &index).await;
+    assert!(similarity(&keyed, &rekeyed) < 0.5);
+    let after = storage.search_knowledge(&entry.content, 1).await.unwrap();
+    assert_eq!(after[0].entity.id, entry.id);
+    assert!(after[0].score > 0.99);
+    close(storage).await;
+}
@@ -0,0 +1,270 @@
+//! Intent journal tests
+//!
+//! Vector writes go through `common::FaultyIndex`, which fails or holds them
+//! on demand to reproduce vector outages and in-flight operations, and REDB
+//! writes through `common::FaultyBackend` where a test needs them to fail. A crash is
+//! simulated by dropping the coordinator with entries left in the journal and
+//! reopening its files with a healthy index; in between, tests may read or
+//! rewrite the raw journal rows.
+
+mod common;
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::{ConsistencyMode, HybridStorage, HybridStorageCoordinator, KnowledgeEntity, StorageError};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use common::{knowledge, FaultyIndex, TestStorage};
+use redb::{ReadableTable, TableDefinition};
+use std::path::Path;
+use std::sync::Arc;
+
+const JOURNAL_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("intent_journal");
+const KNOWLEDGE_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("knowledge");
+
+/// Storage over `dir` whose vector writes go through a fresh `FaultyIndex`
+async fn open(dir: &Path, consistency_mode: ConsistencyMode) -> (HybridStorageCoordinator, Arc<FaultyIndex>) {
+    let index = FaultyIndex::new(Arc::new(LocalVectorIndex::open(dir.join("vectors.redb")).unwrap()));
+    let storage = TestStorage::new(dir, "journal").consistency(consistency_mode).vectors(index.clone()).open().await;
+    (storage, index)
+}
+
+#[tokio::test]
+async fn recovery_skips_operations_in_flight() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, index) = open(dir.path(), ConsistencyMode::Immediate).await;
+
+    // The write's vector upsert is held after its journal entry commits
+    let hold = index.hold_next_write();
+    let entry = knowledge("Journal entries of running operations belong to their caller");
+    let writer = {
+        let storage = storage.clone();
+        let entry = entry.clone();
+        tokio::spawn(async move { storage.store_knowledge(&entry).await })
+    };
+    hold.reached.notified().await;
+
+    // A recovery pass during a vector outage must not roll the running write back
+    index.fail_writes(true);
+    storage.synchronize().await.unwrap();
+    index.fail_writes(false);
+
+    hold.release.notify_one();
+    writer.await.unwrap().unwrap();
+
+    assert_eq!(storage.get_knowledge(&entry.id).await.unwrap().unwrap().content, entry.content);
+    let report = storage.recover().await.unwrap();
+    assert_eq!(report.rolled_forward + report.rolled_back + report.pending, 0);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn operations_whose_journal_updates_fail_are_left_to_recovery() {
+    for vector_write_fails in [false, true] {
+        let dir = tempfile::tempdir().unwrap();
+        let index = FaultyIndex::new(Arc::new(LocalVectorIndex::open(dir.path().join("vectors.redb")).unwrap()));
+        let test_storage = TestStorage::new(dir.path(), "journal").vectors(index.clone());
+        let (storage, backend) = test_storage.open_faulty().await;
+
+        // REDB fails once the row is committed: clearing the journal entry, or rolling the write back
+        let hold = index.hold_next_write();
+        let entry = knowledge("Journal updates can fail after the vector write");
+        let writer = {
+            let storage = storage.clone();
+            let entry = entry.clone();
+            tokio::spawn(async move { storage.store_knowledge(&entry).await })
+        };
+        hold.reached.notified().await;
+        backend.fail_writes(true);
+        if vector_write_fails {
+            index.fail_next_write();
+        }
+        hold.release.notify_one();
+        assert!(matches!(writer.await.unwrap(), Err(StorageError::TransactionError(_))));
+
+        // The operation is no longer in flight, and its failure is counted
+        assert_eq!(storage.sync_status().await.error_count, 1);
+        storage.stop_sync_worker().await;
+        drop(storage);
+        drop(backend);
+
+        // The entry left in the journal is recovered on open
+        let storage = test_storage.open().await;
+        assert_eq!(storage.get_knowledge(&entry.id).await.unwrap().unwrap().content, entry.content);
+        let report = storage.verify().await.unwrap();
+        assert!(report.is_consistent(), "{:?}", report.issues);
+        assert_eq!(report.pending_writes, 0);
+        storage.stop_sync_worker().await;
+    }
+}
+
+#[tokio::test]
+async fn rollback_leaves_a_later_write_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, index) = open(dir.path(), ConsistencyMode::Immediate).await;
+    let original = knowledge("Glaciers retreat as summers lengthen");
+    storage.store_knowledge(&original).await.unwrap();
+
+    // The first update's vector write is held after its row commits
+    let hold = index.hold_next_write();
+    let first = KnowledgeEntity { id: original.id, ..knowledge("An update whose vector write fails") };
+    let writer = {
+        let storage = storage.clone();
+        let first = first.clone();
+        tokio::spawn(async move { storage.update_knowledge(&first).await })
+    };
+    hold.reached.notified().await;
+
+    // A second update to the same entry completes in the meantime
+    let second = KnowledgeEntity { id: original.id, ..knowledge("Meltwater lakes form at the foot of glaciers") };
+    storage.update_knowledge(&second).await.unwrap();
+
+    // Rolling the first update back must not undo the acknowledged second one
+    index.fail_next_write();
+    hold.release.notify_one();
+    assert!(writer.await.unwrap().is_err());
+
+    assert_eq!(storage.get_knowledge(&original.id).await.unwrap().unwrap().content, second.content);
+    let results = storage.search_knowledge("meltwater lakes glaciers", 1).await.unwrap();
+    assert_eq!((results[0].entity.id, &results[0].entity.content), (original.id, &second.content));
+    let report = storage.verify().await.unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    assert_eq!(report.pending_writes, 0);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn interrupted_rollback_finishes_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, index) = open(dir.path(), ConsistencyMode::Immediate).await;
+    let original = knowledge("Rollbacks restore the previous row");
+    storage.store_knowledge(&original).await.unwrap();
+
+    // Neither the update nor its compensation reaches the vector index before the crash
+    index.fail_writes(true);
+    let update = KnowledgeEntity { id: original.id, ..knowledge("An update that never reached the vector index") };
+    assert!(storage.store_knowledge(&update).await.is_err());
+    assert_eq!(storage.get_knowledge(&original.id).await.unwrap().unwrap().content, original.content);
+    drop(storage);
+    drop(index);
+
+    let (storage, index) = open(dir.path(), ConsistencyMode::Immediate).await;
+    assert_eq!(index.writes(), 1, "compensation is applied on open");
+    assert_eq!(storage.get_knowledge(&original.id).await.unwrap().unwrap().content, original.content);
+    let report = storage.verify().await.unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    assert_eq!(report.pending_writes, 0);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn deferred_writes_roll_forward_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, index) = open(dir.path(), ConsistencyMode::Eventually).await;
+
+    // The write commits to REDB but its vector upsert is still journaled at the crash
+    index.fail_writes(true);
+    let entry = knowledge("Deferred vector writes survive a crash");
+    storage.store_knowledge(&entry).await.unwrap();
+    assert_eq!(storage.sync_status().await.pending_count, 1);
+    storage.stop_sync_worker().await;
+    drop(storage);
+    drop(index);
+
+    let (storage, index) = open(dir.path(), ConsistencyMode::Eventually).await;
+    assert_eq!(index.writes(), 1, "the journaled upsert is applied on open");
+    let report = storage.verify().await.unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    assert_eq!(report.pending_writes, 0);
+
+    let results = storage.search_knowledge("deferred vector writes crash", 1).await.unwrap();
+    assert_eq!(results[0].entity.id, entry.id);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn only_vector_and_commit_failures_count_as_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, index) = open(dir.path(), ConsistencyMode::Immediate).await;
+
+    // Rejected before anything is written
+    let missing = knowledge("An update of an entry that was never stored");
+    assert!(matches!(storage.update_knowledge(&missing).await, Err(StorageError::NotFound(_))));
+    assert_eq!(storage.sync_status().await.error_count, 0);
+
+    index.fail_next_write();
+    assert!(storage.store_knowledge(&knowledge("A write whose vector upsert fails")).await.is_err());
+    assert_eq!(storage.sync_status().await.error_count, 1);
+    storage.stop_sync_worker().await;
+}
+
+/// Journal rows of `storage`'s database, read with the coordinator closed
+fn journal_rows(storage: &TestStorage) -> Vec<(String, Vec<u8>)> {
+    let db = redb::Database::create(storage.db_path()).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(JOURNAL_TABLE).unwrap();
+    let rows = table.iter().unwrap()
+        .map(|row| {
+            let (key, value) = row.unwrap();
+            (key.value().to_string(), value.value().to_vec())
+        })
+        .collect();
+    rows
+}
+
+#[tokio::test]
+async fn undo_payloads_are_compact_and_older_entries_still_recover() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, index) = open(dir.path(), ConsistencyMode::Immediate).await;
+    let original = knowledge("Sediment settles where rivers slow down");
+    storage.store_knowledge(&original).await.unwrap();
+
+    // The update and its compensation both fail, leaving the rollback journaled
+    index.fail_writes(true);
+    let update = KnowledgeEntity { content: "An update lost in an outage".to_string(), ..original.clone() };
+    assert!(storage.update_knowledge(&update).await.is_err());
+    storage.stop_sync_worker().await;
+    drop(storage);
+    drop(index);
+
+    let test_storage = TestStorage::new(dir.path(), "journal");
+    let rows = journal_rows(&test_storage);
+    assert_eq!(rows.len(), 1);
+    let (key, data) = &rows[0];
+    let mut entry: serde_json::Value = serde_json::from_slice(data).unwrap();
+    let row_len = {
+        let db = redb::Database::create(test_storage.db_path()).unwrap();
+        let read_txn = db.begin_read().unwrap();
+        let table = read_txn.open_table(KNOWLEDGE_TABLE).unwrap();
+        let len = table.get(original.id.to_string().as_str()).unwrap().unwrap().value().len();
+        len
+    };
+
+    // Base64 row images take under twice the bytes of the row; number arrays took three to four times
+    let undo = entry["undo"].as_array_mut().unwrap();
+    let previous = undo[0]["previous"].as_str().unwrap().len();
+    let written = undo[0]["written"].as_str().unwrap().len();
+    assert!(previous + written < 2 * 2 * row_len, "{} + {} bytes for a {}-byte row", previous, written, row_len);
+
+    // Rewrite the undo records the way older versions did, as arrays of numbers
+    for record in undo.iter_mut() {
+        for field in ["previous", "written"] {
+            if let Some(encoded) = record[field].as_str() {
+                record[field] = serde_json::json!(STANDARD.decode(encoded).unwrap());
+            }
+        }
+    }
+    let db = redb::Database::create(test_storage.db_path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    write_txn.open_table(JOURNAL_TABLE).unwrap()
+        .insert(key.as_str(), serde_json::to_vec(&entry).unwrap().as_slice()).unwrap();
+    write_txn.commit().unwrap();
+    drop(db);
+
+    let (storage, index) = open(dir.path(), ConsistencyMode::Immediate).await;
+    assert_eq!(index.writes(), 1, "compensation is applied on open");
+    assert_eq!(storage.get_knowledge(&original.id).await.unwrap().unwrap().content, original.content);
+    let report = storage.verify().await.unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    assert_eq!(report.pending_writes, 0);
+    storage.stop_sync_worker().await;
+}
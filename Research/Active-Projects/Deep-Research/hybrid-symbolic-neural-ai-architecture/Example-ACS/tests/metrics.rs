@@ -0,0 +1,99 @@
+//! Storage metrics tests
+//!
+//! A store is made to fail through `common::FaultyIndex`, so error counts are
+//! checked on both the operation and the vector backend it failed in.
+
+mod common;
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::metrics::LATENCY_BUCKETS_MS;
+use acs_example::storage::{HybridStorage, LatencyHistogram};
+use common::{knowledge, FaultyIndex, TestStorage};
+use std::sync::Arc;
+
+/// The bucket a sample of `ms` belongs in
+fn bucket_of(ms: f64) -> usize {
+    LATENCY_BUCKETS_MS.iter().position(|bound| ms <= *bound).unwrap_or(LATENCY_BUCKETS_MS.len())
+}
+
+#[tokio::test]
+async fn operations_and_backend_calls_are_counted() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = FaultyIndex::new(Arc::new(LocalVectorIndex::in_memory().unwrap()));
+    let storage = TestStorage::new(dir.path(), "metrics").vectors(index.clone()).open().await;
+    let before = storage.get_metrics().await;
+    assert_eq!(before.errors_total(), 0);
+
+    let stored = knowledge("Sea ice reflects most incoming sunlight");
+    storage.store_knowledge(&stored).await.unwrap();
+    storage.get_knowledge(&stored.id).await.unwrap().unwrap();
+    storage.search_knowledge("sea ice sunlight", 5).await.unwrap();
+    index.fail_next_write();
+    assert!(storage.store_knowledge(&knowledge("Open water absorbs sunlight")).await.is_err());
+    assert_eq!(storage.sync_status().await.error_count, 1);
+
+    let metrics = storage.get_metrics().await;
+    let store = &metrics.operations["store_knowledge"];
+    assert_eq!((store.latency.count, store.latency.errors), (2, 1));
+    assert!(store.redb.count >= 2);
+    assert!(store.vector.errors >= 1 && store.vector.count > store.vector.errors);
+
+    let get = &metrics.operations["get_knowledge"];
+    assert_eq!((get.latency.count, get.latency.errors), (1, 0));
+    assert!(get.redb.count >= 1);
+    assert_eq!(get.vector.count, 0);
+
+    let search = &metrics.operations["search_knowledge"];
+    assert_eq!(search.latency.count, 1);
+    assert!(search.redb.count >= 1 && search.vector.count >= 1);
+
+    // Totals cover every foreground operation, both metrics reads included
+    assert_eq!(metrics.operations["get_metrics"].latency.count, 2);
+    assert_eq!(metrics.operations["sync_status"].latency.count, 1);
+    assert_eq!(metrics.errors_total(), 1);
+    assert_eq!(metrics.operations_total(), 7);
+    assert!(metrics.throughput() > 0.0);
+    assert!(metrics.last_updated.is_some());
+    assert_eq!(metrics.usage.knowledge_entries, 1);
+
+    // Every sample sits in one bucket, the slowest in the last one used
+    for histogram in [&store.latency, &store.redb, &store.vector, &get.latency, &search.latency] {
+        assert_eq!(histogram.buckets.iter().sum::<u64>(), histogram.count);
+        let slowest = histogram.buckets.iter().rposition(|samples| *samples > 0).unwrap();
+        assert_eq!(slowest, bucket_of(histogram.max_ms));
+        assert!(histogram.percentile_ms(1.0) <= histogram.max_ms);
+        assert!(histogram.mean_ms() <= histogram.max_ms);
+    }
+
+    storage.shutdown().await;
+    let shutdown = &storage.get_metrics().await.operations["shutdown"];
+    assert_eq!((shutdown.latency.count, shutdown.latency.errors), (1, 0));
+}
+
+fn empty_buckets() -> Vec<u64> {
+    vec![0; LATENCY_BUCKETS_MS.len() + 1]
+}
+
+#[test]
+fn percentiles_report_the_bucket_bound() {
+    let mut buckets = empty_buckets();
+    // 90 samples up to 1 ms, 9 up to 25 ms and one over the last bound
+    buckets[bucket_of(0.8)] = 90;
+    buckets[bucket_of(20.0)] = 9;
+    buckets[LATENCY_BUCKETS_MS.len()] = 1;
+    let histogram = LatencyHistogram { count: 100, errors: 0, sum_ms: 7_000.0, max_ms: 6_500.0, buckets };
+
+    assert_eq!(histogram.percentile_ms(0.5), 1.0);
+    assert_eq!(histogram.percentile_ms(0.9), 1.0);
+    assert_eq!(histogram.percentile_ms(0.95), 25.0);
+    assert_eq!(histogram.percentile_ms(0.99), 25.0);
+    assert_eq!(histogram.percentile_ms(1.0), 6_500.0);
+    assert_eq!(histogram.mean_ms(), 70.0);
+
+    // A bucket bound above the slowest sample is capped at it
+    let mut buckets = empty_buckets();
+    buckets[bucket_of(0.2)] = 1;
+    let fast = LatencyHistogram { count: 1, errors: 0, sum_ms: 0.2, max_ms: 0.2, buckets };
+    assert_eq!(fast.percentile_ms(0.5), 0.2);
+    assert_eq!(LatencyHistogram::default().percentile_ms(0.99), 0.0);
+}
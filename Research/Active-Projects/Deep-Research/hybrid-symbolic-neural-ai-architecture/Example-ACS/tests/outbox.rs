@@ -0,0 +1,176 @@
+//! Transactional outbox tests
+//!
+//! Under `ConsistencyMode::EventDriven` writes append outbox events that a
+//! projector applies to the vector index. The background worker is stopped
+//! so each test projects explicitly.
+
+mod common;
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::{
+    ConsistencyMode, HybridStorage, HybridStorageCoordinator, StorageConfig, StorageError, VectorIndex,
+};
+use common::{knowledge, FaultyIndex, TestStorage};
+use redb::TableDefinition;
+use std::path::Path;
+use std::sync::Arc;
+
+const METADATA_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("metadata");
+
+async fn open(dir: &Path) -> (HybridStorageCoordinator, Arc<FaultyIndex>) {
+    open_with(dir, |_| {}).await
+}
+
+async fn open_with(dir: &Path, configure: impl FnOnce(&mut StorageConfig)) -> (HybridStorageCoordinator, Arc<FaultyIndex>) {
+    let index = FaultyIndex::new(Arc::new(LocalVectorIndex::open(dir.join("vectors.redb")).unwrap()));
+    let storage = TestStorage::new(dir, "outbox")
+        .consistency(ConsistencyMode::EventDriven)
+        .vectors(index.clone())
+        .configure(configure)
+        .open()
+        .await;
+    storage.stop_sync_worker().await;
+    (storage, index)
+}
+
+#[tokio::test]
+async fn projected_events_replay_from_an_earlier_sequence() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, index) = open(dir.path()).await;
+
+    let entries: Vec<_> = (0..3).map(|i| knowledge(&format!("Outbox event number {}", i))).collect();
+    for entry in &entries {
+        storage.store_knowledge(entry).await.unwrap();
+    }
+    assert_eq!(storage.sync_status().await.pending_count, 3);
+    let report = storage.project_outbox().await.unwrap();
+    assert_eq!((report.events_applied, report.checkpoint, report.lag), (3, 3, 0));
+    assert_eq!(storage.sync_status().await.pending_count, 0);
+
+    // Projected events stay in the log
+    assert_eq!(storage.read_outbox(0, 10).unwrap().len(), 3);
+
+    // Points lost from the index come back by replaying the events that wrote them
+    let collection = storage.active_collection().await;
+    index.delete(&collection, &[entries[1].id, entries[2].id]).await.unwrap();
+    assert!(!storage.verify().await.unwrap().is_consistent());
+
+    let report = storage.replay_outbox(2).await.unwrap();
+    assert_eq!((report.events_applied, report.checkpoint, report.lag), (2, 3, 0));
+    let verified = storage.verify().await.unwrap();
+    assert!(verified.is_consistent(), "{:?}", verified.issues);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn pruning_keeps_the_configured_number_of_events() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, _index) = open_with(dir.path(), |config| config.outbox_retained_events = 1).await;
+
+    for i in 0..3 {
+        storage.store_knowledge(&knowledge(&format!("Outbox event number {}", i))).await.unwrap();
+    }
+    storage.project_outbox().await.unwrap();
+    let retained = storage.read_outbox(0, 10).unwrap();
+    assert_eq!(retained.iter().map(|event| event.sequence).collect::<Vec<_>>(), vec![3]);
+
+    assert!(matches!(storage.replay_outbox(2).await, Err(StorageError::NotFound(_))));
+    let report = storage.replay_outbox(3).await.unwrap();
+    assert_eq!((report.events_applied, report.checkpoint), (1, 3));
+
+    // Numbering continues after pruned events
+    storage.store_knowledge(&knowledge("An event written after pruning")).await.unwrap();
+    assert_eq!(storage.read_outbox(3, 10).unwrap()[0].sequence, 4);
+    let report = storage.project_outbox().await.unwrap();
+    assert_eq!((report.events_applied, report.checkpoint), (1, 4));
+    assert!(storage.verify().await.unwrap().is_consistent());
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn failing_events_are_dead_lettered_and_projection_moves_on() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, index) = open(dir.path()).await;
+
+    // An outage longer than the attempt limit quarantines nothing
+    index.fail_writes(true);
+    storage.store_knowledge(&knowledge("Written during an outage")).await.unwrap();
+    storage.store_knowledge(&knowledge("Also written during the outage")).await.unwrap();
+    for _ in 0..5 {
+        let report = storage.project_outbox().await.unwrap();
+        assert_eq!((report.events_applied, report.events_quarantined, report.lag), (0, 0, 2));
+    }
+    index.fail_writes(false);
+    assert_eq!(storage.project_outbox().await.unwrap().events_applied, 2);
+
+    // An event the index keeps rejecting is dead-lettered once a later one applies
+    let rejected = knowledge("An event the index rejects");
+    index.reject_point(rejected.id, true);
+    storage.store_knowledge(&rejected).await.unwrap();
+    let after = knowledge("An event written after the rejected one");
+    storage.store_knowledge(&after).await.unwrap();
+    for _ in 0..2 {
+        let report = storage.project_outbox().await.unwrap();
+        assert_eq!((report.events_applied, report.checkpoint, report.lag), (0, 2, 2));
+    }
+    let report = storage.project_outbox().await.unwrap();
+    assert_eq!((report.events_applied, report.events_quarantined, report.checkpoint, report.lag), (1, 1, 4, 0));
+    let status = storage.sync_status().await;
+    assert_eq!((status.pending_count, status.quarantined_count), (0, 1));
+    assert!(storage.synchronize().await.unwrap().success);
+
+    // Requeued events are appended again and apply once the index accepts them
+    index.reject_point(rejected.id, false);
+    assert_eq!(storage.requeue_quarantined().await.unwrap(), 1);
+    let report = storage.project_outbox().await.unwrap();
+    assert_eq!((report.events_applied, report.checkpoint), (1, 5));
+    assert_eq!(storage.sync_status().await.quarantined_count, 0);
+    let verified = storage.verify().await.unwrap();
+    assert!(verified.is_consistent(), "{:?}", verified.issues);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn projection_resumes_from_the_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, index) = open(dir.path()).await;
+
+    storage.store_knowledge(&knowledge("Projected before the outage")).await.unwrap();
+    storage.project_outbox().await.unwrap();
+
+    // Events written during a vector outage stay behind the checkpoint
+    index.fail_writes(true);
+    storage.store_knowledge(&knowledge("Written during the outage")).await.unwrap();
+    storage.store_knowledge(&knowledge("Also written during the outage")).await.unwrap();
+    let report = storage.project_outbox().await.unwrap();
+    assert_eq!((report.events_applied, report.checkpoint, report.lag), (0, 1, 2));
+    drop(storage);
+    drop(index);
+
+    // After a restart only the pending events are applied
+    let (storage, index) = open(dir.path()).await;
+    assert!(storage.synchronize().await.unwrap().success);
+    assert_eq!(storage.outbox_checkpoint().unwrap(), 3);
+    assert_eq!(index.writes(), 2);
+
+    let report = storage.verify().await.unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    assert_eq!(report.points_checked, 3);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn malformed_checkpoint_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let test_storage = TestStorage::new(dir.path(), "outbox");
+    {
+        let db = redb::Database::create(test_storage.db_path()).unwrap();
+        let write_txn = db.begin_write().unwrap();
+        write_txn.open_table(METADATA_TABLE).unwrap().insert("outbox_checkpoint", [1u8, 0, 0].as_slice()).unwrap();
+        write_txn.commit().unwrap();
+    }
+
+    let storage = test_storage.open().await;
+    assert!(matches!(storage.outbox_checkpoint(), Err(StorageError::SerializationError(_))));
+    storage.stop_sync_worker().await;
+}
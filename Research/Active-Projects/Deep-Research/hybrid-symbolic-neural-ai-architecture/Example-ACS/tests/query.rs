@@ -0,0 +1,130 @@
+//! Filtered and faceted knowledge query tests
+//!
+//! Entries are created a day apart in the past, so browse order and
+//! `created_at` ranges do not depend on when each write happened.
+
+mod common;
+
+use acs_example::storage::{
+    HybridStorage, HybridStorageCoordinator, KnowledgeEntity, KnowledgeQuery, KnowledgeQueryResult, StorageError,
+};
+use chrono::{Duration, Utc};
+use common::{knowledge, TestStorage};
+use std::collections::BTreeMap;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+/// Five entries, oldest first, stored newest first
+async fn open() -> (HybridStorageCoordinator, Vec<KnowledgeEntity>, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = TestStorage::new(dir.path(), "query").open().await;
+
+    let entry = |days_ago, content, source: &str, rating: &str, tags: &[&str], region: &str| KnowledgeEntity {
+        created_at: Utc::now() - Duration::days(days_ago),
+        source: source.to_string(),
+        credibility_rating: rating.to_string(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        metadata: [("region".to_string(), serde_json::json!(region))].into_iter().collect(),
+        ..knowledge(content)
+    };
+    let entries = vec![
+        entry(5, "Glaciers carve U-shaped valleys", "usgs", "A1", &["glaciers"], "alps"),
+        entry(4, "Glacial meltwater feeds alpine rivers", "usgs", "B2", &["glaciers", "rivers"], "alps"),
+        entry(3, "River deltas build up from sediment", "blog", "C3", &["rivers"], "nile"),
+        entry(2, "Glaciers advance during cold periods", "wiki", "B3", &["glaciers"], "andes"),
+        entry(1, "Rivers meander across flat floodplains", "wiki", "F6", &["rivers"], "amazon"),
+    ];
+    for entry in entries.iter().rev() {
+        storage.store_knowledge(entry).await.unwrap();
+    }
+    (storage, entries, dir)
+}
+
+fn browse() -> KnowledgeQuery {
+    KnowledgeQuery { limit: 10, ..Default::default() }
+}
+
+fn ids(result: &KnowledgeQueryResult) -> Vec<Uuid> {
+    result.results.iter().map(|entry| entry.id).collect()
+}
+
+fn counts(pairs: &[(&str, usize)]) -> BTreeMap<String, usize> {
+    pairs.iter().map(|(key, count)| (key.to_string(), *count)).collect()
+}
+
+#[tokio::test]
+async fn browsing_lists_entries_oldest_first() {
+    let (storage, entries, _dir) = open().await;
+    let all: Vec<Uuid> = entries.iter().map(|entry| entry.id).collect();
+
+    let result = storage.query_knowledge(&browse()).await.unwrap();
+    assert_eq!(ids(&result), all);
+    assert!(result.warnings.is_empty());
+
+    // Facets cover every entry that passes, not just the page returned
+    let result = storage.query_knowledge(&KnowledgeQuery { limit: 2, ..browse() }).await.unwrap();
+    assert_eq!(ids(&result), all[..2]);
+    assert_eq!(result.candidate_facets.candidates, 5);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn each_filter_narrows_the_results() {
+    let (storage, entries, _dir) = open().await;
+    let query = |query: KnowledgeQuery| {
+        let storage = storage.clone();
+        async move { storage.query_knowledge(&query).await.unwrap() }
+    };
+    let expect = |indexes: &[usize]| indexes.iter().map(|&index| entries[index].id).collect::<Vec<_>>();
+
+    // Reliability and credibility must both be at least as good as the minimum
+    let result = query(KnowledgeQuery { min_credibility: Some("B2".to_string()), ..browse() }).await;
+    assert_eq!(ids(&result), expect(&[0, 1]));
+    let result = query(KnowledgeQuery { min_credibility: Some("c3".to_string()), ..browse() }).await;
+    assert_eq!(ids(&result), expect(&[0, 1, 2, 3]));
+
+    let result = query(KnowledgeQuery { sources: vec!["wiki".to_string()], ..browse() }).await;
+    assert_eq!(ids(&result), expect(&[3, 4]));
+    let result = query(KnowledgeQuery { excluded_sources: vec!["usgs".to_string()], ..browse() }).await;
+    assert_eq!(ids(&result), expect(&[2, 3, 4]));
+
+    // `created_after` is inclusive, `created_before` exclusive
+    let range = KnowledgeQuery {
+        created_after: Some(entries[1].created_at),
+        created_before: Some(entries[3].created_at),
+        ..browse()
+    };
+    assert_eq!(ids(&query(range).await), expect(&[1, 2]));
+
+    let alps = [("region".to_string(), serde_json::json!("alps"))].into_iter().collect();
+    assert_eq!(ids(&query(KnowledgeQuery { metadata: alps, ..browse() }).await), expect(&[0, 1]));
+    let both = vec!["glaciers".to_string(), "rivers".to_string()];
+    assert_eq!(ids(&query(KnowledgeQuery { tags: both, ..browse() }).await), expect(&[1]));
+
+    let invalid = KnowledgeQuery { min_credibility: Some("Z9".to_string()), ..browse() };
+    assert!(matches!(storage.query_knowledge(&invalid).await, Err(StorageError::ConfigurationError(_))));
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn facets_count_the_ranked_candidates_that_pass() {
+    let (storage, entries, _dir) = open().await;
+
+    // The candidate pool holds every entry, so all four that pass are counted
+    let query = KnowledgeQuery {
+        text: "glaciers".to_string(),
+        limit: 1,
+        excluded_sources: vec!["blog".to_string()],
+        ..Default::default()
+    };
+    let result = storage.query_knowledge(&query).await.unwrap();
+    assert_eq!(result.results.len(), 1);
+    assert_ne!(result.results[0].id, entries[2].id);
+
+    let facets = &result.candidate_facets;
+    assert_eq!(facets.candidates, 4);
+    assert_eq!(facets.sources, counts(&[("usgs", 2), ("wiki", 2)]));
+    assert_eq!(facets.credibility, counts(&[("A1", 1), ("B2", 1), ("B3", 1), ("F6", 1)]));
+    assert_eq!(facets.tags, counts(&[("glaciers", 3), ("rivers", 2)]));
+    storage.stop_sync_worker().await;
+}
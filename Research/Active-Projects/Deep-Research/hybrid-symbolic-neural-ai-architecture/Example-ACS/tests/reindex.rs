@@ -0,0 +1,233 @@
+//! Vector collection reindex tests
+//!
+//! A reindex is interrupted by failing vector writes through
+//! `common::FaultyIndex` from its progress callback, which leaves the
+//! checkpoint behind exactly as a crash after the last batch would.
+
+mod common;
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::{
+    EmbeddingProvider, EncryptionConfig, HashingEmbedder, HybridStorage, HybridStorageCoordinator, KeySource,
+    KnowledgeEntity, ReindexOptions, ReindexPhase, StorageError,
+};
+use async_trait::async_trait;
+use common::{knowledge, FaultyIndex, TestStorage};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Another model of the same dimension as the default one
+struct OtherModel(HashingEmbedder);
+
+#[async_trait]
+impl EmbeddingProvider for OtherModel {
+    fn dimension(&self) -> usize {
+        self.0.dimension()
+    }
+
+    fn identity(&self) -> String {
+        "other-model".to_string()
+    }
+
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, StorageError> {
+        self.0.embed(texts).await
+    }
+}
+
+async fn open() -> (HybridStorageCoordinator, Arc<FaultyIndex>, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let index = FaultyIndex::new(Arc::new(LocalVectorIndex::in_memory().unwrap()));
+    let storage = TestStorage::new(dir.path(), "reindex").vectors(index.clone()).open().await;
+    (storage, index, dir)
+}
+
+/// Three entries, sorted by key, which is the order the reindex scans them in
+async fn store_entries(storage: &HybridStorageCoordinator) -> Vec<KnowledgeEntity> {
+    let mut entries = vec![
+        knowledge("Meteors burn up in the upper atmosphere"),
+        knowledge("Lichens grow slowly on bare rock"),
+        knowledge("Fjords were carved by retreating glaciers"),
+    ];
+    for entry in &entries {
+        storage.store_knowledge(entry).await.unwrap();
+    }
+    entries.sort_by_key(|entry| entry.id.to_string());
+    entries
+}
+
+const ONE_PER_BATCH: ReindexOptions = ReindexOptions { batch_size: Some(1), drop_previous: false };
+
+#[tokio::test]
+async fn interrupted_reindex_resumes_and_catches_up() {
+    let (storage, index, _dir) = open().await;
+    let entries = store_entries(&storage).await;
+
+    // Stop after the first batch
+    let result = {
+        let index = index.clone();
+        storage.reindex(&ONE_PER_BATCH, move |progress| {
+            if progress.indexed == 1 {
+                index.fail_writes(true);
+            }
+        }).await
+    };
+    assert!(result.is_err());
+    index.fail_writes(false);
+
+    let progress = storage.reindex_progress().unwrap().unwrap();
+    assert_eq!((progress.phase, progress.indexed, progress.total), (ReindexPhase::Indexing, 1, 3));
+
+    // The scan has passed this entry, so only change tracking brings the update across
+    let updated = KnowledgeEntity { id: entries[0].id, ..knowledge("Auroras glow where the solar wind meets the poles") };
+    storage.update_knowledge(&updated).await.unwrap();
+
+    let mut phases = Vec::new();
+    let report = storage.reindex(&ONE_PER_BATCH, |progress| phases.push(progress.phase)).await.unwrap();
+    assert!(report.resumed);
+    assert_eq!(report.indexed, 3);
+    assert_eq!(report.caught_up, 1);
+    assert_ne!(report.collection, report.previous_collection);
+    assert_eq!(phases.last(), Some(&ReindexPhase::Swapped));
+    assert!(storage.reindex_progress().unwrap().is_none());
+
+    let results = storage.search_knowledge("auroras solar wind poles", 1).await.unwrap();
+    assert_eq!(results[0].entity.id, updated.id);
+    assert_eq!(results[0].entity.content, updated.content);
+    let consistency = storage.verify().await.unwrap();
+    assert!(consistency.is_consistent(), "{:?}", consistency.issues);
+    assert_eq!(consistency.points_checked, 3);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn interrupted_catch_up_is_reported_and_resumed() {
+    let (storage, index, _dir) = open().await;
+    let entries = store_entries(&storage).await;
+
+    let result = {
+        let index = index.clone();
+        storage.reindex(&ONE_PER_BATCH, move |progress| {
+            if progress.indexed == 1 {
+                index.fail_writes(true);
+            }
+        }).await
+    };
+    assert!(result.is_err());
+    index.fail_writes(false);
+    let updated = KnowledgeEntity { id: entries[0].id, ..knowledge("Geysers erupt when groundwater boils") };
+    storage.update_knowledge(&updated).await.unwrap();
+
+    // Fail again once the scan is done and writes are blocked for the catch-up
+    let result = {
+        let index = index.clone();
+        storage.reindex(&ONE_PER_BATCH, move |progress| {
+            if progress.phase == ReindexPhase::CatchingUp {
+                index.fail_writes(true);
+            }
+        }).await
+    };
+    assert!(result.is_err());
+    index.fail_writes(false);
+
+    let progress = storage.reindex_progress().unwrap().unwrap();
+    assert_eq!((progress.phase, progress.indexed), (ReindexPhase::CatchingUp, 3));
+
+    let report = storage.reindex(&ONE_PER_BATCH, |_| {}).await.unwrap();
+    assert!(report.resumed);
+    assert_eq!(report.caught_up, 1);
+    let results = storage.search_knowledge("geysers groundwater", 1).await.unwrap();
+    assert_eq!(results[0].entity.id, updated.id);
+    assert!(storage.verify().await.unwrap().is_consistent());
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn reindex_started_by_another_model_starts_over() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = FaultyIndex::new(Arc::new(LocalVectorIndex::in_memory().unwrap()));
+    let test_storage = TestStorage::new(dir.path(), "reindex").vectors(index.clone());
+    let storage = test_storage.open().await;
+    store_entries(&storage).await;
+
+    let result = {
+        let index = index.clone();
+        storage.reindex(&ONE_PER_BATCH, move |progress| {
+            if progress.indexed == 1 {
+                index.fail_writes(true);
+            }
+        }).await
+    };
+    assert!(result.is_err());
+    index.fail_writes(false);
+    let interrupted = storage.reindex_progress().unwrap().unwrap().collection;
+    storage.stop_sync_worker().await;
+    drop(storage);
+
+    // Same dimension, different model: the partial collection must not be reused
+    let embedder = Arc::new(OtherModel(HashingEmbedder::new(test_storage.config.embedding_dimension)));
+    let storage = HybridStorageCoordinator::with_backends(test_storage.config.clone(), index.clone(), embedder)
+        .await
+        .unwrap();
+    let report = storage.reindex(&ONE_PER_BATCH, |_| {}).await.unwrap();
+    assert!(!report.resumed);
+    assert_ne!(report.collection, interrupted);
+    assert_eq!(report.indexed, 3);
+    assert!(storage.verify().await.unwrap().is_consistent());
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn rewritten_embeddings_do_not_keep_a_failed_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_path = dir.path().join("key");
+    std::fs::write(&key_path, "42".repeat(32)).unwrap();
+    let index = FaultyIndex::new(Arc::new(LocalVectorIndex::in_memory().unwrap()));
+    // Sealing gives every rewrite of a row new bytes
+    let storage = TestStorage::new(dir.path(), "reindex")
+        .vectors(index.clone())
+        .configure(|config| {
+            config.encryption = Some(EncryptionConfig {
+                key: KeySource::File(key_path.to_string_lossy().to_string()),
+                previous_keys: Vec::new(),
+            })
+        })
+        .open()
+        .await;
+
+    let original = knowledge("Sand dunes migrate downwind");
+    storage.store_knowledge(&original).await.unwrap();
+
+    // Hold the update's vector write, with its row committed
+    let hold = index.hold_next_write();
+    let update = {
+        let storage = storage.clone();
+        let updated = KnowledgeEntity { content: "Sand dunes never move".to_string(), ..original.clone() };
+        tokio::spawn(async move { storage.update_knowledge(&updated).await })
+    };
+    hold.reached.notified().await;
+
+    // The reindex stores a new embedding with the updated row, then the held write fails
+    let reindex = {
+        let storage = storage.clone();
+        let index = index.clone();
+        tokio::spawn(async move {
+            storage.reindex(&ONE_PER_BATCH, move |progress| {
+                if progress.phase == ReindexPhase::Indexing && progress.indexed == 1 {
+                    index.fail_next_write();
+                    hold.release.notify_one();
+                }
+            }).await
+        })
+    };
+
+    assert!(update.await.unwrap().is_err());
+    let report = reindex.await.unwrap().unwrap();
+    assert_eq!(report.caught_up, 1);
+
+    let stored = storage.get_knowledge(&original.id).await.unwrap().unwrap();
+    assert_eq!(stored.content, original.content);
+    let results = storage.search_knowledge("sand dunes migrate", 1).await.unwrap();
+    assert_eq!(results[0].entity.content, original.content);
+    assert!(storage.verify().await.unwrap().is_consistent());
+    storage.stop_sync_worker().await;
+}
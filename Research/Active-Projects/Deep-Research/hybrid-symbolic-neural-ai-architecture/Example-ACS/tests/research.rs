@@ -0,0 +1,105 @@
+//! Systematic research agent tests against stored knowledge
+
+mod common;
+
+use acs_example::behavioral::systematic_research::{SearchQuery, ValidationConfig};
+use acs_example::storage::{Direction, EntityRef, HybridStorage, KnowledgeEntity, RelationKind};
+use acs_example::{CredibilityRating, SystematicResearchAgent, SystematicResearcher};
+use common::{knowledge, TestStorage};
+use std::sync::Arc;
+
+fn agent(storage: Arc<dyn HybridStorage>) -> SystematicResearchAgent {
+    SystematicResearchAgent::new(ValidationConfig {
+        minimum_sources: 3,
+        required_credibility: CredibilityRating::B3,
+        enable_bias_detection: true,
+        cross_validation_threshold: 0.75,
+    })
+    .with_storage(storage)
+}
+
+fn query(text: &str) -> SearchQuery {
+    SearchQuery {
+        text: text.to_string(),
+        domain: "glaciology".to_string(),
+        scope: "comprehensive".to_string(),
+        max_sources: 20,
+    }
+}
+
+#[tokio::test]
+async fn stored_knowledge_is_searched_at_the_evidence_threshold() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = TestStorage::new(dir.path(), "research").open().await;
+
+    let rated = |content, rating: &str| KnowledgeEntity { credibility_rating: rating.to_string(), ..knowledge(content) };
+    let entries = [
+        rated("Glaciers carve U-shaped valleys", "A1"),
+        rated("Glaciers retreat as summers warm", "B3"),
+        rated("Glaciers are slowly growing everywhere", "C2"),
+        rated("Glaciers move a metre a day", "B4"),
+    ];
+    for entry in &entries {
+        storage.store_knowledge(entry).await.unwrap();
+    }
+
+    let results = agent(Arc::new(storage)).execute_systematic_search(&query("glaciers")).await.unwrap();
+
+    // Only B3 or better reaches the agent; B4 and C2 are filtered out by the storage query
+    let mut stored: Vec<&str> = results.findings.iter()
+        .filter(|finding| entries.iter().any(|entry| entry.id.to_string() == finding.id))
+        .map(|finding| finding.content.as_str())
+        .collect();
+    stored.sort();
+    assert_eq!(stored, ["Glaciers carve U-shaped valleys", "Glaciers retreat as summers warm"]);
+
+    let finding = results.findings.iter().find(|finding| finding.id == entries[1].id.to_string()).unwrap();
+    assert!(matches!(finding.evidence[0].credibility_rating, CredibilityRating::B3));
+    assert_eq!(finding.topic.as_deref(), Some("glaciology"));
+}
+
+#[tokio::test]
+async fn conflicts_between_stored_findings_are_carried_across_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Arc::new(TestStorage::new(dir.path(), "research").open().await);
+
+    let concluding = |content, conclusion: &str| KnowledgeEntity {
+        metadata: [("conclusions".to_string(), serde_json::json!([conclusion]))].into_iter().collect(),
+        ..knowledge(content)
+    };
+    let retreating = concluding("Alpine glaciers lost a tenth of their volume", "Glaciers are retreating");
+    let mut advancing = concluding("Some glaciers gained mass last winter", "Glaciers aren't retreating");
+    let unrelated = concluding("Glaciers carve U-shaped valleys", "Valleys are shaped by ice");
+    for entry in [&retreating, &advancing, &unrelated] {
+        storage.store_knowledge(entry).await.unwrap();
+    }
+    let conflict = "Opposite conclusions: \"Glaciers are retreating\" vs \"Glaciers aren't retreating\"";
+
+    // First run detects the opposite conclusions and records them between the two entries
+    let first = agent(storage.clone());
+    let findings = first.execute_systematic_search(&query("glaciers")).await.unwrap().findings;
+    let validation = first.cross_validate_findings(&findings).await;
+    assert_eq!(validation.conflicts_detected, 1);
+    let pair = validation.validation_pairs.iter().find(|pair| !pair.conflicts.is_empty()).unwrap();
+    assert_eq!(pair.conflicts, [conflict]);
+
+    let relations = storage
+        .neighbors(&EntityRef::knowledge(retreating.id), Direction::Both, &[RelationKind::Contradicts])
+        .await
+        .unwrap();
+    assert_eq!(relations.len(), 1);
+    assert!(relations[0].from == EntityRef::knowledge(advancing.id) || relations[0].to == EntityRef::knowledge(advancing.id));
+
+    // The second run no longer detects it, but still reports the recorded conflict
+    advancing.metadata.insert("conclusions".to_string(), serde_json::json!(["Winter snowfall varies"]));
+    storage.update_knowledge(&advancing).await.unwrap();
+
+    let second = agent(storage.clone());
+    let findings = second.execute_systematic_search(&query("glaciers")).await.unwrap().findings;
+    let validation = second.cross_validate_findings(&findings).await;
+    assert_eq!(validation.conflicts_detected, 1);
+    let pair = validation.validation_pairs.iter().find(|pair| !pair.conflicts.is_empty()).unwrap();
+    let ids = [pair.finding_a_id.as_str(), pair.finding_b_id.as_str()];
+    assert!(ids.contains(&retreating.id.to_string().as_str()) && ids.contains(&advancing.id.to_string().as_str()));
+    assert_eq!(pair.conflicts, [conflict]);
+}
@@ -0,0 +1,143 @@
+//! Retention and background task lifetime tests
+//!
+//! Retention passes run on demand here, with records timestamped in the past
+//! so age limits apply without waiting. The sync worker and the retention
+//! compactor run on copies of the coordinator; the last test checks they stop
+//! with the handles users hold.
+
+mod common;
+
+use acs_example::storage::{
+    ConsistencyMode, CoordinationEntity, Direction, EntityRef, HybridStorage, HybridStorageCoordinator, Relation,
+    RelationKind, RetentionPolicy, StorageError, TaskHistoryEntity,
+};
+use chrono::{DateTime, Utc};
+use common::{knowledge, TestStorage};
+use std::time::Duration;
+use uuid::Uuid;
+
+fn coordination(session_id: Uuid, minutes_ago: i64) -> CoordinationEntity {
+    CoordinationEntity {
+        id: Uuid::new_v4(),
+        session_id,
+        operation_type: "task_execution".to_string(),
+        status: "completed".to_string(),
+        data: serde_json::json!({}),
+        timestamp: ago(minutes_ago),
+        agent_id: None,
+    }
+}
+
+fn task_history(summary: &str, minutes_ago: i64) -> TaskHistoryEntity {
+    TaskHistoryEntity {
+        id: Uuid::new_v4(),
+        session_id: Uuid::new_v4(),
+        task_id: Uuid::new_v4(),
+        agent_id: Uuid::new_v4(),
+        action_type: "research".to_string(),
+        status: "completed".to_string(),
+        summary: summary.to_string(),
+        execution_time_ms: 10,
+        embeddings: None,
+        created_at: ago(minutes_ago),
+    }
+}
+
+fn ago(minutes: i64) -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::minutes(minutes)
+}
+
+#[tokio::test]
+async fn limits_keep_the_newest_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = TestStorage::new(dir.path(), "retention")
+        .configure(|config| {
+            config.retention.coordination = RetentionPolicy {
+                max_age_secs: Some(3600),
+                max_count: None,
+                max_per_session: Some(2),
+            };
+            config.retention.task_history = RetentionPolicy { max_count: Some(3), ..Default::default() };
+        })
+        .open()
+        .await;
+
+    // Four records in one session, the newest two kept
+    let busy = Uuid::new_v4();
+    let busy_records: Vec<_> = [10, 20, 30, 40].into_iter().map(|minutes| coordination(busy, minutes)).collect();
+    // One record past the age limit
+    let stale = coordination(Uuid::new_v4(), 120);
+    // Records without a session are not capped per session
+    let sessionless: Vec<_> = [5, 15, 25].into_iter().map(|minutes| coordination(Uuid::nil(), minutes)).collect();
+    for record in busy_records.iter().chain([&stale]).chain(&sessionless) {
+        storage.update_coordination(record).await.unwrap();
+    }
+
+    // An expired record takes its relations with it
+    let note = knowledge("A finding produced during an expired coordination step");
+    storage.store_knowledge(&note).await.unwrap();
+    let oldest = EntityRef::coordination(busy_records[3].id);
+    storage.add_relation(&Relation::new(EntityRef::knowledge(note.id), RelationKind::DerivedFrom, oldest)).await.unwrap();
+    assert_eq!(storage.neighbors(&EntityRef::knowledge(note.id), Direction::Outgoing, &[]).await.unwrap().len(), 1);
+
+    for minutes in 1..=5 {
+        storage.record_task_history(&task_history(&format!("Task finished {} minutes ago", minutes), minutes)).await.unwrap();
+    }
+
+    let report = storage.compact_retention().await.unwrap();
+    assert_eq!((report.coordination_removed, report.task_history_removed), (3, 2));
+
+    let kept: Vec<Uuid> = storage.list_coordination(&busy).await.unwrap().iter().map(|record| record.id).collect();
+    assert_eq!(kept, vec![busy_records[1].id, busy_records[0].id]);
+    assert!(storage.list_coordination(&stale.session_id).await.unwrap().is_empty());
+    assert_eq!(storage.list_coordination(&Uuid::nil()).await.unwrap().len(), 3);
+    let relations = storage.neighbors(&EntityRef::knowledge(note.id), Direction::Outgoing, &[]).await.unwrap();
+    assert!(relations.is_empty(), "{:?}", relations);
+
+    let history = storage.search_task_history("task finished minutes ago", 10).await.unwrap();
+    let mut summaries: Vec<String> = history.into_iter().map(|scored| scored.entity.summary).collect();
+    summaries.sort();
+    assert_eq!(summaries, ["Task finished 1 minutes ago", "Task finished 2 minutes ago", "Task finished 3 minutes ago"]);
+
+    // A second pass finds nothing left to remove
+    let report = storage.compact_retention().await.unwrap();
+    assert_eq!((report.coordination_removed, report.task_history_removed), (0, 0));
+    storage.stop_sync_worker().await;
+}
+
+/// Open `test_storage`, giving background tasks of a dropped coordinator a moment to exit
+async fn reopen(test_storage: &TestStorage) -> Result<HybridStorageCoordinator, StorageError> {
+    let mut attempts = 0;
+    loop {
+        match test_storage.try_open().await {
+            Err(_) if attempts < 50 => {
+                attempts += 1;
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[tokio::test]
+async fn dropped_coordinator_releases_database() {
+    let dir = tempfile::tempdir().unwrap();
+    // Both background tasks run: deferred vector writes and hourly compaction
+    let test_storage = TestStorage::new(dir.path(), "retention")
+        .consistency(ConsistencyMode::Eventually)
+        .configure(|config| config.retention.compaction_interval_secs = 3600);
+
+    let storage = test_storage.open().await;
+    let entry = knowledge("Background tasks stop with the last coordinator handle");
+    storage.store_knowledge(&entry).await.unwrap();
+
+    // A clone keeps the tasks running; dropping both stops them
+    let clone = storage.clone();
+    drop(storage);
+    clone.synchronize().await.unwrap();
+    drop(clone);
+
+    let storage = reopen(&test_storage).await.unwrap();
+    assert_eq!(storage.get_knowledge(&entry.id).await.unwrap().unwrap().content, entry.content);
+    storage.stop_sync_worker().await;
+}
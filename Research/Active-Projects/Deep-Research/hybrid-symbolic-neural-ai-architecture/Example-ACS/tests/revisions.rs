@@ -0,0 +1,90 @@
+//! Knowledge revision and as-of search tests
+//!
+//! Each write is separated by a short sleep so the instants captured between
+//! them fall strictly inside one revision's validity.
+
+mod common;
+
+use acs_example::storage::{HybridStorage, HybridStorageCoordinator, KnowledgeEntity};
+use chrono::{DateTime, Utc};
+use common::{knowledge, TestStorage};
+use std::time::Duration;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+async fn open() -> (HybridStorageCoordinator, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    (TestStorage::new(dir.path(), "revisions").open().await, dir)
+}
+
+/// The current instant, strictly after the previous write and before the next
+async fn instant() -> DateTime<Utc> {
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let now = Utc::now();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    now
+}
+
+async fn content_as_of(storage: &HybridStorageCoordinator, id: Uuid, as_of: DateTime<Utc>) -> Option<String> {
+    storage.search_knowledge_as_of("river delta sediment", as_of, 10).await.unwrap()
+        .into_iter()
+        .find(|result| result.entity.id == id)
+        .map(|result| result.entity.content)
+}
+
+#[tokio::test]
+async fn as_of_search_returns_the_revision_valid_at_the_time() {
+    let (storage, _dir) = open().await;
+    let created_at = Utc::now() - chrono::Duration::hours(1);
+    let first = KnowledgeEntity { created_at, ..knowledge("River deltas build up from deposited sediment") };
+    storage.store_knowledge(&first).await.unwrap();
+    let while_first = instant().await;
+
+    let second = KnowledgeEntity {
+        content: "River deltas grow where sediment settles faster than tides remove it".to_string(),
+        ..first.clone()
+    };
+    storage.update_knowledge(&second).await.unwrap();
+    // A rewrite that changes nothing is not a new revision
+    storage.update_knowledge(&second).await.unwrap();
+    let while_second = instant().await;
+
+    assert!(storage.delete_knowledge(&first.id).await.unwrap());
+    let after_delete = instant().await;
+
+    let history = storage.get_knowledge_history(&first.id).await.unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!((history[0].revision, history[1].revision), (1, 2));
+    assert_eq!(history[0].valid_from, created_at);
+    assert_eq!(history[0].entity.content, first.content);
+    assert_eq!(Some(history[1].valid_from), history[0].valid_to);
+    assert_eq!(history[1].entity.content, second.content);
+    assert!(history[1].valid_to.is_some_and(|deleted| deleted > while_second && deleted < after_delete));
+
+    assert_eq!(content_as_of(&storage, first.id, created_at - chrono::Duration::minutes(1)).await, None);
+    assert_eq!(content_as_of(&storage, first.id, while_first).await, Some(first.content.clone()));
+    assert_eq!(content_as_of(&storage, first.id, while_second).await, Some(second.content.clone()));
+    assert_eq!(content_as_of(&storage, first.id, after_delete).await, None);
+
+    // Current search no longer finds the deleted entry
+    let current = storage.search_knowledge("river delta sediment", 10).await.unwrap();
+    assert!(current.iter().all(|result| result.entity.id != first.id));
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn current_rows_are_valid_from_creation() {
+    let (storage, _dir) = open().await;
+    let created_at = Utc::now() - chrono::Duration::minutes(30);
+    let entry = KnowledgeEntity { created_at, ..knowledge("River delta wetlands filter sediment from floodwater") };
+    storage.store_knowledge(&entry).await.unwrap();
+
+    let history = storage.get_knowledge_history(&entry.id).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!((history[0].valid_from, history[0].valid_to), (created_at, None));
+
+    assert_eq!(content_as_of(&storage, entry.id, created_at - chrono::Duration::minutes(1)).await, None);
+    assert_eq!(content_as_of(&storage, entry.id, created_at).await, Some(entry.content.clone()));
+    assert_eq!(content_as_of(&storage, entry.id, Utc::now()).await, Some(entry.content.clone()));
+    storage.stop_sync_worker().await;
+}
@@ -0,0 +1,215 @@
+//! Schema migration tests
+//!
+//! `tests/fixtures/schema_v<N>.json` holds the raw REDB rows of a database
+//! written at schema version N (table -> key -> hex-encoded value). Each test
+//! loads a fixture into a fresh database and opens it with the current build.
+//!
+//! Both fixtures contain the same entities: knowledge 1 and 2 with empty
+//! metadata, knowledge 3 with metadata, and agent 10. Version 2 adds
+//! coordination row 20 in session 21. In version 1, rows with
+//! `serde_json::Value` content were written with bincode, which cannot read
+//! them back, so knowledge 3 and agent 10 are unrecoverable there. Rows with titles and tags in metadata, as
+//! written before version 3, are built by `legacy_knowledge_row`.
+
+mod common;
+
+use acs_example::storage::{
+    FusionStrategy, HybridStorage, SchemaMigration, StorageError, TagMatch, CURRENT_SCHEMA_VERSION,
+};
+use common::TestStorage;
+use redb::TableDefinition;
+use std::path::Path;
+use uuid::Uuid;
+
+const METADATA_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("metadata");
+
+fn id(n: u128) -> Uuid {
+    Uuid::from_u128(n)
+}
+
+/// Write the rows of a fixture into a new database at `path`
+fn load_fixture(name: &str, path: &Path) -> u32 {
+    let fixture_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    let fixture: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(fixture_path).unwrap()).unwrap();
+
+    let db = redb::Database::create(path).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    for (table_name, rows) in fixture["tables"].as_object().unwrap() {
+        let mut table = write_txn.open_table(TableDefinition::<&str, &[u8]>::new(table_name)).unwrap();
+        for (key, hex) in rows.as_object().unwrap() {
+            let hex = hex.as_str().unwrap();
+            let value: Vec<u8> = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect();
+            table.insert(key.as_str(), value.as_slice()).unwrap();
+        }
+    }
+    write_txn.commit().unwrap();
+
+    fixture["schema_version"].as_u64().unwrap() as u32
+}
+
+fn fixture_storage(dir: &Path, schema_migration: SchemaMigration) -> TestStorage {
+    TestStorage::new(dir, "fixture").configure(|config| config.schema_migration = schema_migration)
+}
+
+#[tokio::test]
+async fn v1_rows_are_migrated_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = fixture_storage(dir.path(), SchemaMigration::OnOpen);
+    assert_eq!(load_fixture("schema_v1.json", &fixture.db_path()), 1);
+
+    let storage = fixture.open().await;
+    assert_eq!(storage.schema_version().unwrap(), CURRENT_SCHEMA_VERSION);
+
+    let knowledge = storage.get_knowledge(&id(1)).await.unwrap().unwrap();
+    assert_eq!(knowledge.source, "rust-lang.org");
+    assert_eq!(knowledge.credibility_rating, "A1");
+    assert!(knowledge.embeddings.is_some());
+
+    // Fixtures carry no vector index, but the lexical index is rebuilt from the migrated rows
+    let results = storage
+        .search_knowledge_hybrid("garbage collection", 1, FusionStrategy::default())
+        .await
+        .unwrap();
+    assert_eq!(results[0].entity.id, id(1));
+
+    assert!(matches!(storage.get_knowledge(&id(3)).await, Err(StorageError::SerializationError(_))));
+    assert!(matches!(storage.get_agent(&id(10)).await, Err(StorageError::SerializationError(_))));
+
+    // Nothing is left to upgrade; the unrecoverable rows are reported again
+    let report = storage.migrate_schema().unwrap();
+    assert_eq!(report.from_version, CURRENT_SCHEMA_VERSION);
+    assert_eq!(report.migrated, 0);
+    assert_eq!(report.failures.len(), 2);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn v1_rows_are_migrated_lazily() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = fixture_storage(dir.path(), SchemaMigration::Lazy);
+    load_fixture("schema_v1.json", &fixture.db_path());
+
+    let storage = fixture.open().await;
+    assert_eq!(storage.schema_version().unwrap(), 1);
+
+    // Old rows are upgraded on read and rewritten on their next write
+    let mut knowledge = storage.get_knowledge(&id(2)).await.unwrap().unwrap();
+    assert_eq!(knowledge.source, "qdrant.tech");
+    knowledge.metadata.insert("reviewed".to_string(), true.into());
+    storage.update_knowledge(&knowledge).await.unwrap();
+    let updated = storage.get_knowledge(&id(2)).await.unwrap().unwrap();
+    assert_eq!(updated.metadata.get("reviewed"), Some(&serde_json::Value::Bool(true)));
+
+    let report = storage.migrate_schema().unwrap();
+    assert_eq!(report.from_version, 1);
+    assert_eq!(report.to_version, CURRENT_SCHEMA_VERSION);
+    assert_eq!(report.migrated, 1);
+    let mut failed: Vec<&str> = report.failures.iter().map(|failure| failure.table.as_str()).collect();
+    failed.sort_unstable();
+    assert_eq!(failed, ["agents", "knowledge"]);
+    assert_eq!(storage.schema_version().unwrap(), CURRENT_SCHEMA_VERSION);
+    storage.stop_sync_worker().await;
+}
+
+/// Version 2 knowledge row keeping its title and tags in metadata
+fn legacy_knowledge_row(id: Uuid, metadata: serde_json::Value) -> Vec<u8> {
+    let entity = serde_json::json!({
+        "id": id,
+        "content": "Ownership replaces garbage collection",
+        "metadata": metadata,
+        "embeddings": null,
+        "source": "rust-lang.org",
+        "credibility_rating": "A1",
+        "created_at": "2024-01-01T00:00:00Z",
+    });
+    let mut row = b"ACSv".to_vec();
+    row.extend_from_slice(&2u32.to_le_bytes());
+    row.extend_from_slice(&serde_json::to_vec(&entity).unwrap());
+    row
+}
+
+#[tokio::test]
+async fn v2_rows_are_migrated_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = fixture_storage(dir.path(), SchemaMigration::OnOpen);
+    assert_eq!(load_fixture("schema_v2.json", &fixture.db_path()), 2);
+
+    let storage = fixture.open().await;
+    assert_eq!(storage.schema_version().unwrap(), CURRENT_SCHEMA_VERSION);
+
+    let knowledge = storage.get_knowledge(&id(3)).await.unwrap().unwrap();
+    assert_eq!(knowledge.metadata.get("domain"), Some(&serde_json::json!("ai-architecture")));
+    let agent = storage.get_agent(&id(10)).await.unwrap().unwrap();
+    assert_eq!(agent.state, serde_json::json!({ "phase": "search" }));
+
+    // The coordination indexes are built for rows written before they existed
+    let session = storage.list_coordination(&id(21)).await.unwrap();
+    assert_eq!(session.iter().map(|record| record.id).collect::<Vec<_>>(), [id(20)]);
+    assert_eq!(storage.list_by_status("completed").await.unwrap().len(), 1);
+    assert_eq!(storage.rebuild_coordination_index().unwrap(), 1);
+
+    let report = storage.migrate_schema().unwrap();
+    assert_eq!(report.migrated, 0);
+    assert!(report.failures.is_empty());
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn v2_title_and_tags_move_out_of_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = fixture_storage(dir.path(), SchemaMigration::Lazy);
+    load_fixture("schema_v2.json", &fixture.db_path());
+    {
+        let db = redb::Database::open(fixture.db_path()).unwrap();
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut knowledge = write_txn.open_table(TableDefinition::<&str, &[u8]>::new("knowledge")).unwrap();
+            let tagged = legacy_knowledge_row(id(4), serde_json::json!({ "title": "Ownership", "tags": ["rust", "memory"] }));
+            knowledge.insert(id(4).to_string().as_str(), tagged.as_slice()).unwrap();
+            // Not the shape the framework wrote, so left in metadata
+            let untagged = legacy_knowledge_row(id(5), serde_json::json!({ "tags": "rust" }));
+            knowledge.insert(id(5).to_string().as_str(), untagged.as_slice()).unwrap();
+        }
+        write_txn.commit().unwrap();
+    }
+
+    // Rows are upgraded in memory before the tag index is built on open
+    let storage = fixture.open().await;
+    assert_eq!(storage.schema_version().unwrap(), 2);
+
+    let knowledge = storage.get_knowledge(&id(4)).await.unwrap().unwrap();
+    assert_eq!(knowledge.title.as_deref(), Some("Ownership"));
+    assert_eq!(knowledge.tags, ["rust", "memory"]);
+    assert!(knowledge.metadata.is_empty());
+    let knowledge = storage.get_knowledge(&id(5)).await.unwrap().unwrap();
+    assert!(knowledge.tags.is_empty());
+    assert_eq!(knowledge.metadata.get("tags"), Some(&serde_json::json!("rust")));
+
+    assert_eq!(storage.list_tags().await.unwrap().get("rust"), Some(&1));
+    let tagged = storage.find_by_tags(&["memory".to_string()], TagMatch::All).await.unwrap();
+    assert_eq!(tagged.iter().map(|knowledge| knowledge.id).collect::<Vec<_>>(), [id(4)]);
+
+    storage.migrate_schema().unwrap();
+    assert_eq!(storage.get_knowledge(&id(4)).await.unwrap().unwrap().tags, ["rust", "memory"]);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn newer_schema_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = fixture_storage(dir.path(), SchemaMigration::OnOpen);
+    load_fixture("schema_v2.json", &fixture.db_path());
+    {
+        let db = redb::Database::open(fixture.db_path()).unwrap();
+        let write_txn = db.begin_write().unwrap();
+        write_txn.open_table(METADATA_TABLE).unwrap()
+            .insert("schema_version", (CURRENT_SCHEMA_VERSION + 1).to_le_bytes().as_slice())
+            .unwrap();
+        write_txn.commit().unwrap();
+    }
+
+    assert!(matches!(fixture.try_open().await, Err(StorageError::InitializationError(_))));
+}
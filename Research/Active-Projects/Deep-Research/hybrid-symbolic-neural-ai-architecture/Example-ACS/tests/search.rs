@@ -0,0 +1,129 @@
+//! Scored and paginated knowledge search tests
+//!
+//! Pages are compared against one search covering every result, so the
+//! expected order never depends on how the embedder scores a given text.
+
+mod common;
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::{
+    ConsistencyWarning, ConsistencyWarningKind, HybridStorage, HybridStorageCoordinator, KnowledgeSearch,
+    ScoredKnowledge, VectorIndex, VectorPoint,
+};
+use common::{knowledge, TestStorage};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const QUERY: &str = "rivers carry sediment to the sea";
+
+/// Storage over `dir` holding `count` entries, with its vector index and collection shared with the test
+async fn open(dir: &Path, count: usize) -> (HybridStorageCoordinator, Arc<LocalVectorIndex>, String) {
+    let vectors = Arc::new(LocalVectorIndex::open(dir.join("vectors.redb")).unwrap());
+    let test_storage = TestStorage::new(dir, "search").vectors(vectors.clone());
+    let storage = test_storage.open().await;
+    for n in 0..count {
+        let content = format!("River {} carries sediment downstream across {} kilometres", n, n * 40 + 10);
+        storage.store_knowledge(&knowledge(&content)).await.unwrap();
+    }
+    (storage, vectors, test_storage.config.collection_name)
+}
+
+fn search(limit: usize) -> KnowledgeSearch {
+    KnowledgeSearch { query: QUERY.to_string(), limit, ..Default::default() }
+}
+
+fn ids(results: &[ScoredKnowledge]) -> Vec<Uuid> {
+    results.iter().map(|result| result.entity.id).collect()
+}
+
+#[tokio::test]
+async fn cursor_pages_cover_every_result_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, _, _) = open(dir.path(), 12).await;
+    let all = storage.search_knowledge_page(&search(100)).await.unwrap();
+    assert_eq!(all.results.len(), 12);
+    assert_eq!(all.next_cursor, None);
+
+    let mut paged = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = storage.search_knowledge_page(&KnowledgeSearch { cursor, ..search(5) }).await.unwrap();
+        assert!(page.results.len() <= 5);
+        paged.extend(page.results);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(ids(&paged), ids(&all.results));
+    assert_eq!(paged.iter().map(|result| result.rank).collect::<Vec<_>>(), (1..=12).collect::<Vec<_>>());
+    assert_eq!(ids(&paged).into_iter().collect::<HashSet<_>>().len(), 12);
+
+    // Offset pages agree with cursor pages
+    let page = storage.search_knowledge_page(&KnowledgeSearch { offset: 5, ..search(5) }).await.unwrap();
+    assert_eq!(ids(&page.results), ids(&all.results[5..10]));
+    assert_eq!(page.results[0].rank, 6);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn cursor_taken_before_a_write_stays_stable() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, _, _) = open(dir.path(), 9).await;
+    let before = storage.search_knowledge_page(&search(100)).await.unwrap().results;
+    let first = storage.search_knowledge_page(&search(3)).await.unwrap();
+    assert_eq!(ids(&first.results), ids(&before[..3]));
+
+    // An entry ranking ahead of the cursor would shift an offset page by one
+    let ahead = knowledge(QUERY);
+    storage.store_knowledge(&ahead).await.unwrap();
+    let after = storage.search_knowledge_page(&search(100)).await.unwrap().results;
+    assert_eq!(after[0].entity.id, ahead.id);
+
+    let next = KnowledgeSearch { cursor: first.next_cursor.clone(), ..search(3) };
+    let second = storage.search_knowledge_page(&next).await.unwrap();
+    assert_eq!(ids(&second.results), ids(&before[3..6]));
+    assert_eq!(second.results.iter().map(|result| result.rank).collect::<Vec<_>>(), [4, 5, 6]);
+
+    let shifted = storage.search_knowledge_page(&KnowledgeSearch { offset: 3, ..search(3) }).await.unwrap();
+    assert_eq!(ids(&shifted.results), ids(&before[2..5]));
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn min_score_drops_lower_scoring_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, _, _) = open(dir.path(), 8).await;
+    let all = storage.search_knowledge_page(&search(100)).await.unwrap().results;
+    let min_score = all[2].score;
+    let passing = all.iter().filter(|result| result.score >= min_score).count();
+    assert!(passing >= 3 && passing < all.len());
+
+    let page = storage
+        .search_knowledge_page(&KnowledgeSearch { min_score: Some(min_score), ..search(100) })
+        .await
+        .unwrap();
+    assert_eq!(ids(&page.results), ids(&all[..passing]));
+    assert!(page.results.iter().all(|result| result.score >= min_score));
+    assert_eq!(page.next_cursor, None);
+    storage.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn point_without_a_row_is_a_warning() {
+    let dir = tempfile::tempdir().unwrap();
+    let (storage, vectors, collection) = open(dir.path(), 4).await;
+    let all = storage.search_knowledge_page(&search(100)).await.unwrap().results;
+
+    // A copy of the best match's point ranks alongside it but has no row to load
+    let (mut points, _) = vectors.scroll(&collection, Some(all[0].entity.id), 1).await.unwrap();
+    let orphan = VectorPoint { id: Uuid::new_v4(), ..points.remove(0) };
+    vectors.upsert(&collection, vec![orphan.clone()]).await.unwrap();
+
+    let page = storage.search_knowledge_page(&search(100)).await.unwrap();
+    assert_eq!(page.warnings, [ConsistencyWarning { id: orphan.id, kind: ConsistencyWarningKind::Missing }]);
+    assert_eq!(ids(&page.results), ids(&all));
+    storage.stop_sync_worker().await;
+}
@@ -0,0 +1,127 @@
+//! Snapshot export and import tests
+//!
+//! Each test exports one coordinator's knowledge base and imports it into
+//! another, then checks the target through the public API.
+
+mod common;
+
+use acs_example::storage::{
+    AgentEntity, ExportOptions, HybridStorage, ImportMode, ImportOptions, KnowledgeEntity, StorageError,
+};
+use chrono::Utc;
+use common::{knowledge, TestStorage};
+use uuid::Uuid;
+
+fn agent() -> AgentEntity {
+    AgentEntity {
+        id: Uuid::new_v4(),
+        agent_type: "researcher".to_string(),
+        state: serde_json::json!({ "phase": "synthesis" }),
+        capabilities: vec!["search".to_string()],
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    }
+}
+
+#[tokio::test]
+async fn replace_restores_exactly_the_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("knowledge.acs.jsonl");
+
+    let source = TestStorage::new(dir.path(), "source").open().await;
+    let entries = [
+        knowledge("Glaciers carve valleys over thousands of years"),
+        knowledge("Coral reefs bleach when the water warms"),
+    ];
+    for entry in &entries {
+        source.store_knowledge(entry).await.unwrap();
+    }
+    let researcher = agent();
+    source.store_agent(&researcher).await.unwrap();
+
+    let exported = source.export_snapshot(&path, &ExportOptions::default()).await.unwrap();
+    assert_eq!((exported.counts.knowledge, exported.counts.agents), (2, 1));
+    source.stop_sync_worker().await;
+
+    let target = TestStorage::new(dir.path(), "target").open().await;
+    let stale = knowledge("An entry the snapshot does not know about");
+    target.store_knowledge(&stale).await.unwrap();
+
+    let report = target.import_snapshot(&path, &ImportOptions { mode: ImportMode::Replace }).await.unwrap();
+    assert_eq!(report.imported.knowledge, 2);
+    assert_eq!(report.imported.agents, 1);
+    assert_eq!(report.removed, 1);
+    assert!(!report.re_embedded);
+    assert!(report.failed.is_empty());
+
+    assert!(target.get_knowledge(&stale.id).await.unwrap().is_none());
+    for entry in &entries {
+        assert_eq!(target.get_knowledge(&entry.id).await.unwrap().unwrap().content, entry.content);
+    }
+    assert_eq!(target.get_agent(&researcher.id).await.unwrap().unwrap().state, researcher.state);
+
+    let results = target.search_knowledge("coral reefs bleach", 1).await.unwrap();
+    assert_eq!(results[0].entity.id, entries[1].id);
+    let consistency = target.verify().await.unwrap();
+    assert!(consistency.is_consistent(), "{:?}", consistency.issues);
+    assert_eq!(consistency.points_checked, 2);
+    target.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn merge_keeps_existing_entries_and_overwrites_shared_ids() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("knowledge.acs.jsonl");
+
+    let source = TestStorage::new(dir.path(), "source").open().await;
+    let shared = knowledge("Tides follow the pull of the moon");
+    source.store_knowledge(&shared).await.unwrap();
+    // Without vectors the importer embeds every entry itself
+    source.export_snapshot(&path, &ExportOptions { include_vectors: false }).await.unwrap();
+    source.stop_sync_worker().await;
+
+    let target = TestStorage::new(dir.path(), "target").open().await;
+    let local = knowledge("Only the target knows about this entry");
+    target.store_knowledge(&local).await.unwrap();
+    target.store_knowledge(&KnowledgeEntity { id: shared.id, ..knowledge("An older version of the shared entry") })
+        .await
+        .unwrap();
+
+    let report = target.import_snapshot(&path, &ImportOptions { mode: ImportMode::Merge }).await.unwrap();
+    assert_eq!(report.imported.knowledge, 1);
+    assert_eq!(report.removed, 0);
+    assert!(report.re_embedded);
+
+    assert_eq!(target.get_knowledge(&local.id).await.unwrap().unwrap().content, local.content);
+    let merged = target.get_knowledge(&shared.id).await.unwrap().unwrap();
+    assert_eq!(merged.content, shared.content);
+    assert!(merged.embeddings.is_some());
+
+    let results = target.search_knowledge("tides moon", 1).await.unwrap();
+    assert_eq!(results[0].entity.id, shared.id);
+    assert!(target.verify().await.unwrap().is_consistent());
+    target.stop_sync_worker().await;
+}
+
+#[tokio::test]
+async fn tampered_snapshots_are_rejected_before_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("knowledge.acs.jsonl");
+
+    let source = TestStorage::new(dir.path(), "source").open().await;
+    source.store_knowledge(&knowledge("Sand dunes migrate with the prevailing wind")).await.unwrap();
+    source.export_snapshot(&path, &ExportOptions::default()).await.unwrap();
+    source.stop_sync_worker().await;
+
+    let snapshot = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, snapshot.replace("prevailing wind", "prevailing tide")).unwrap();
+
+    let target = TestStorage::new(dir.path(), "target").open().await;
+    let existing = knowledge("Replace mode must not remove this after a failed check");
+    target.store_knowledge(&existing).await.unwrap();
+
+    let result = target.import_snapshot(&path, &ImportOptions { mode: ImportMode::Replace }).await;
+    assert!(matches!(result, Err(StorageError::SerializationError(_))), "{:?}", result.map(|report| report.imported));
+    assert!(target.get_knowledge(&existing.id).await.unwrap().is_some());
+    target.stop_sync_worker().await;
+}
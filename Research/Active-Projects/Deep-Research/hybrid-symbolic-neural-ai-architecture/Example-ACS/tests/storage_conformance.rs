@@ -0,0 +1,683 @@
+//! HybridStorage conformance tests
+//!
+//! Every check runs against each backend: file-backed REDB with the
+//! in-process vector index, and in-memory storage. Set `ACS_TEST_QDRANT_URL`
+//! to also run them against a Qdrant server; each run uses a fresh collection.
+//!
+//! Checks only go through the `HybridStorage` trait, so a new implementation
+//! is covered by adding it to `backends`.
+
+mod common;
+
+use acs_example::storage::{
+    AgentEntity, ChunkStrategy, ChunkingConfig, CoordinationEntity, DeduplicationConfig, Direction, DocumentSearch,
+    DuplicateAction, EntityRef, FusionStrategy, HybridStorage, IngestOutcome, KnowledgeEntity, PathQuery, Relation,
+    RelationKind, StorageError, TagMatch, TaskHistoryEntity, TimeRange, VectorBackend,
+};
+use chrono::{Duration, Utc};
+use common::{knowledge, TestStorage};
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+struct Backend {
+    name: &'static str,
+    storage: Arc<dyn HybridStorage>,
+    // Keeps the database files of file-backed storage alive
+    _dir: Option<TempDir>,
+}
+
+/// Storage writing to a collection of its own
+fn test_storage(dir: &Path) -> TestStorage {
+    TestStorage::new(dir, "conformance")
+        .configure(|config| config.collection_name = format!("conformance_{}", Uuid::new_v4().simple()))
+}
+
+async fn backends() -> Vec<Backend> {
+    let dir = tempfile::tempdir().unwrap();
+    let mut backends = vec![
+        Backend {
+            name: "file",
+            storage: Arc::new(test_storage(dir.path()).open().await),
+            _dir: Some(dir),
+        },
+        Backend {
+            name: "memory",
+            storage: Arc::new(test_storage(Path::new("")).open_in_memory().await),
+            _dir: None,
+        },
+    ];
+
+    if let Ok(qdrant_url) = std::env::var("ACS_TEST_QDRANT_URL") {
+        let dir = tempfile::tempdir().unwrap();
+        let qdrant = test_storage(dir.path()).configure(|config| {
+            config.vector_backend = VectorBackend::Qdrant;
+            config.qdrant_url = qdrant_url;
+        });
+        backends.push(Backend {
+            name: "qdrant",
+            storage: Arc::new(qdrant.open().await),
+            _dir: Some(dir),
+        });
+    }
+
+    backends
+}
+
+fn coordination(session_id: Uuid, agent_id: Uuid, status: &str, age_secs: i64) -> CoordinationEntity {
+    CoordinationEntity {
+        id: Uuid::new_v4(),
+        session_id,
+        operation_type: "task_execution".to_string(),
+        status: status.to_string(),
+        data: serde_json::json!({}),
+        timestamp: Utc::now() - Duration::seconds(age_secs),
+        agent_id: Some(agent_id),
+    }
+}
+
+#[tokio::test]
+async fn agents_round_trip() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let agent = AgentEntity {
+            id: Uuid::new_v4(),
+            agent_type: "researcher".to_string(),
+            state: serde_json::json!({ "phase": "search" }),
+            capabilities: vec!["search".to_string()],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        storage.store_agent(&agent).await.unwrap();
+        let stored = storage.get_agent(&agent.id).await.unwrap().unwrap();
+        assert_eq!(stored.state, agent.state, "{}", backend.name);
+
+        assert!(storage.delete_agent(&agent.id).await.unwrap(), "{}", backend.name);
+        assert!(storage.get_agent(&agent.id).await.unwrap().is_none(), "{}", backend.name);
+        assert!(!storage.delete_agent(&agent.id).await.unwrap(), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn knowledge_round_trip() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let mut entry = knowledge("Rust manages memory through ownership");
+
+        storage.store_knowledge(&entry).await.unwrap();
+        assert!(storage.get_knowledge(&entry.id).await.unwrap().unwrap().embeddings.is_some(), "{}", backend.name);
+
+        entry.content = "Rust manages memory through ownership and borrowing".to_string();
+        storage.update_knowledge(&entry).await.unwrap();
+        assert_eq!(storage.get_knowledge(&entry.id).await.unwrap().unwrap().content, entry.content, "{}", backend.name);
+        assert_eq!(storage.get_knowledge_history(&entry.id).await.unwrap().len(), 2, "{}", backend.name);
+
+        assert!(storage.delete_knowledge(&entry.id).await.unwrap(), "{}", backend.name);
+        assert!(storage.get_knowledge(&entry.id).await.unwrap().is_none(), "{}", backend.name);
+        assert!(!storage.delete_knowledge(&entry.id).await.unwrap(), "{}", backend.name);
+        assert!(
+            matches!(storage.update_knowledge(&entry).await, Err(StorageError::NotFound(_))),
+            "{}",
+            backend.name
+        );
+        assert!(storage.verify().await.unwrap().is_consistent(), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn search_ranks_closest_entry_first() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let entries = [
+            knowledge("Garbage collection pauses affect latency"),
+            knowledge("Volcanoes erupt magma and lava"),
+            knowledge("Whales swim in deep ocean currents"),
+        ];
+        let report = storage.store_knowledge_batch(&entries).await.unwrap();
+        assert_eq!(report.succeeded, 3, "{}", backend.name);
+
+        let results = storage.search_knowledge("volcanoes erupt lava", 3).await.unwrap();
+        assert_eq!(results[0].entity.id, entries[1].id, "{}", backend.name);
+        assert!(results.windows(2).all(|pair| pair[0].score >= pair[1].score), "{}", backend.name);
+
+        let results = storage
+            .search_knowledge_hybrid("whales ocean", 1, FusionStrategy::default())
+            .await
+            .unwrap();
+        assert_eq!(results[0].entity.id, entries[2].id, "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn hybrid_search_ranks_rare_exact_terms_first() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let entries = [
+            knowledge("Protocols were registered before PRISMA 2020 appeared"),
+            knowledge("Reporting guidelines for systematic reviews list what to report"),
+            knowledge("Reporting guidelines help systematic reviews stay transparent"),
+            knowledge("Systematic reviews follow reporting guidelines"),
+        ];
+        let report = storage.store_knowledge_batch(&entries).await.unwrap();
+        assert_eq!(report.succeeded, 4, "{}", backend.name);
+
+        // The common words dominate vector similarity; only BM25 weighs the rare standard ID
+        let query = "PRISMA 2020 reporting guidelines";
+        let results = storage.search_knowledge(query, 1).await.unwrap();
+        assert_ne!(results[0].entity.id, entries[0].id, "{}", backend.name);
+
+        let fusion = FusionStrategy::Weighted { lexical: 0.5, vector: 0.5 };
+        let results = storage.search_knowledge_hybrid(query, 1, fusion).await.unwrap();
+        assert_eq!(results[0].entity.id, entries[0].id, "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn batch_reports_each_failure_against_its_item() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let first = knowledge("Tides rise twice a day along most coasts");
+        let entries = [
+            first.clone(),
+            KnowledgeEntity { content: "Tides follow the moon".to_string(), ..first.clone() },
+            KnowledgeEntity { embeddings: Some(vec![0.5; 3]), ..knowledge("Currents move heat poleward") },
+            knowledge("Waves break when the water gets shallow"),
+        ];
+        let report = storage.store_knowledge_batch(&entries).await.unwrap();
+        assert_eq!((report.succeeded, report.failed), (2, 2), "{}", backend.name);
+        assert_eq!(
+            report.items.iter().map(|item| item.id).collect::<Vec<_>>(),
+            entries.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+            "{}",
+            backend.name
+        );
+
+        let errors: Vec<Option<&str>> = report.items.iter().map(|item| item.error.as_deref()).collect();
+        assert!(report.items[0].success && errors[0].is_none(), "{}", backend.name);
+        assert!(!report.items[1].success, "{}", backend.name);
+        assert!(errors[1].is_some_and(|error| error.contains("Duplicate knowledge ID")), "{}", backend.name);
+        assert!(!report.items[2].success, "{}", backend.name);
+        assert!(errors[2].is_some_and(|error| error.contains("dimension 3")), "{}", backend.name);
+        assert!(report.items[3].success && errors[3].is_none(), "{}", backend.name);
+
+        // The first entry under an ID is the one stored; a failed item leaves nothing behind
+        let stored = storage.get_knowledge(&first.id).await.unwrap().unwrap();
+        assert_eq!(stored.content, first.content, "{}", backend.name);
+        assert!(storage.get_knowledge(&entries[2].id).await.unwrap().is_none(), "{}", backend.name);
+        assert!(storage.get_knowledge(&entries[3].id).await.unwrap().is_some(), "{}", backend.name);
+        assert!(storage.verify().await.unwrap().is_consistent(), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn duplicates_are_skipped() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let original = knowledge("The quick brown fox jumps over the lazy dog.");
+        assert_eq!(storage.store_knowledge(&original).await.unwrap().outcome, IngestOutcome::Stored, "{}", backend.name);
+
+        let duplicate = knowledge("the QUICK brown fox, jumps over the lazy dog");
+        let report = storage.store_knowledge(&duplicate).await.unwrap();
+        assert_eq!(report.outcome, IngestOutcome::Skipped, "{}", backend.name);
+        assert_eq!(report.id, original.id, "{}", backend.name);
+        assert!(report.duplicate_of.is_some_and(|duplicate| duplicate.exact), "{}", backend.name);
+        assert!(storage.get_knowledge(&duplicate.id).await.unwrap().is_none(), "{}", backend.name);
+
+        // Allow writes duplicates as given
+        let allow = DeduplicationConfig { action: DuplicateAction::Allow, ..Default::default() };
+        let report = storage.store_knowledge_with(&duplicate, &allow).await.unwrap();
+        assert_eq!((report.id, report.outcome), (duplicate.id, IngestOutcome::Stored), "{}", backend.name);
+        assert!(storage.get_knowledge(&duplicate.id).await.unwrap().is_some(), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn duplicates_merge_metadata_or_become_revisions() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let mut original = knowledge("Sea ice reflects most of the sunlight that reaches it");
+        original.metadata.insert("region".to_string(), "arctic".into());
+        original.tags = vec!["climate".to_string()];
+        storage.store_knowledge(&original).await.unwrap();
+
+        let mut annotated = knowledge("Sea ice reflects most of the sunlight that reaches it!");
+        annotated.metadata.insert("region".to_string(), "antarctic".into());
+        annotated.metadata.insert("season".to_string(), "summer".into());
+        annotated.tags = vec!["albedo".to_string(), "climate".to_string()];
+        let merge = DeduplicationConfig { action: DuplicateAction::MergeMetadata, ..Default::default() };
+        let report = storage.store_knowledge_with(&annotated, &merge).await.unwrap();
+        assert_eq!((report.id, report.outcome), (original.id, IngestOutcome::MergedMetadata), "{}", backend.name);
+
+        // New values win, the content and tags already there stay
+        let merged = storage.get_knowledge(&original.id).await.unwrap().unwrap();
+        assert_eq!(merged.content, original.content, "{}", backend.name);
+        assert_eq!(merged.metadata.get("region"), Some(&serde_json::json!("antarctic")), "{}", backend.name);
+        assert_eq!(merged.metadata.get("season"), Some(&serde_json::json!("summer")), "{}", backend.name);
+        assert_eq!(merged.tags, ["climate", "albedo"], "{}", backend.name);
+        assert!(storage.get_knowledge(&annotated.id).await.unwrap().is_none(), "{}", backend.name);
+
+        let reworded = knowledge("sea ice reflects most of the sunlight that reaches it");
+        let revise = DeduplicationConfig { action: DuplicateAction::NewRevision, ..Default::default() };
+        let report = storage.store_knowledge_with(&reworded, &revise).await.unwrap();
+        assert_eq!((report.id, report.outcome), (original.id, IngestOutcome::NewRevision), "{}", backend.name);
+
+        // The duplicate's content replaces the entry, whose earlier revisions are archived
+        let revised = storage.get_knowledge(&original.id).await.unwrap().unwrap();
+        assert_eq!(revised.content, reworded.content, "{}", backend.name);
+        assert_eq!(revised.created_at, original.created_at, "{}", backend.name);
+        let history = storage.get_knowledge_history(&original.id).await.unwrap();
+        let contents: Vec<&str> = history.iter().map(|revision| revision.entity.content.as_str()).collect();
+        assert_eq!(contents, [&original.content, &merged.content, &reworded.content], "{}", backend.name);
+        assert!(history[..2].iter().all(|revision| revision.valid_to.is_some()), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn near_duplicates_match_above_the_threshold() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let original = knowledge("Migrating birds navigate by the stars and the earth's magnetic field");
+        storage.store_knowledge(&original).await.unwrap();
+        let similar = knowledge("Migrating birds navigate using the stars and the magnetic field of the earth");
+        let unrelated = knowledge("Sourdough bread rises through wild yeast fermentation");
+
+        let near = DeduplicationConfig { near_duplicate_threshold: Some(0.8), ..Default::default() };
+        let report = storage.store_knowledge_with(&similar, &near).await.unwrap();
+        assert_eq!((report.id, report.outcome), (original.id, IngestOutcome::Skipped), "{}", backend.name);
+        let duplicate = report.duplicate_of.unwrap();
+        assert!(!duplicate.exact && duplicate.similarity >= 0.8, "{}: {:?}", backend.name, duplicate);
+        let report = storage.store_knowledge_with(&unrelated, &near).await.unwrap();
+        assert_eq!(report.outcome, IngestOutcome::Stored, "{}", backend.name);
+
+        // Without a threshold only identical content is a duplicate
+        let report = storage.store_knowledge(&similar).await.unwrap();
+        assert_eq!((report.id, report.outcome), (similar.id, IngestOutcome::Stored), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn tags_are_indexed() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let tagged = |content: &str, tags: &[&str]| KnowledgeEntity {
+            title: Some(content.to_string()),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..knowledge(content)
+        };
+        let rust = tagged("Ownership replaces garbage collection", &["rust", "memory"]);
+        let mut qdrant = tagged("Qdrant filters payloads", &["vector"]);
+        storage.store_knowledge(&rust).await.unwrap();
+        storage.store_knowledge(&qdrant).await.unwrap();
+
+        let stored = storage.get_knowledge(&rust.id).await.unwrap().unwrap();
+        assert_eq!((stored.title, stored.tags), (rust.title.clone(), rust.tags.clone()), "{}", backend.name);
+
+        let tags = storage.list_tags().await.unwrap();
+        assert_eq!(tags.into_iter().collect::<Vec<_>>(), [
+            ("memory".to_string(), 1),
+            ("rust".to_string(), 1),
+            ("vector".to_string(), 1),
+        ], "{}", backend.name);
+
+        let find = |tags: &[&str], matching| {
+            let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+            async move {
+                let found = storage.find_by_tags(&tags, matching).await.unwrap();
+                found.into_iter().map(|knowledge| knowledge.id).collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(find(&["rust", "memory"], TagMatch::All).await, [rust.id], "{}", backend.name);
+        assert!(find(&["rust", "vector"], TagMatch::All).await.is_empty(), "{}", backend.name);
+        assert_eq!(find(&["rust", "vector"], TagMatch::Any).await, [rust.id, qdrant.id], "{}", backend.name);
+
+        // Retagging and deleting keep the index in step with the rows
+        qdrant.tags = vec!["rust".to_string()];
+        storage.update_knowledge(&qdrant).await.unwrap();
+        assert!(find(&["vector"], TagMatch::Any).await.is_empty(), "{}", backend.name);
+        assert_eq!(storage.list_tags().await.unwrap().get("rust"), Some(&2), "{}", backend.name);
+        storage.delete_knowledge(&rust.id).await.unwrap();
+        assert_eq!(find(&["rust"], TagMatch::All).await, [qdrant.id], "{}", backend.name);
+        assert!(!storage.list_tags().await.unwrap().contains_key("memory"), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn relations_are_traversed() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let agent = AgentEntity {
+            id: Uuid::new_v4(),
+            agent_type: "researcher".to_string(),
+            state: serde_json::json!({}),
+            capabilities: vec![],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let claim = knowledge("Ownership prevents data races");
+        let study = knowledge("A study of data races in Rust programs");
+        let rebuttal = knowledge("Unsafe blocks still allow data races");
+        storage.store_agent(&agent).await.unwrap();
+        for entry in [&claim, &study, &rebuttal] {
+            storage.store_knowledge(entry).await.unwrap();
+        }
+
+        let (agent_ref, claim_ref, study_ref, rebuttal_ref) = (
+            EntityRef::agent(agent.id),
+            EntityRef::knowledge(claim.id),
+            EntityRef::knowledge(study.id),
+            EntityRef::knowledge(rebuttal.id),
+        );
+        storage.add_relation(&Relation::new(study_ref, RelationKind::Supports, claim_ref)).await.unwrap();
+        storage.add_relation(&Relation::new(rebuttal_ref, RelationKind::Contradicts, claim_ref)).await.unwrap();
+        storage.add_relation(&Relation::new(study_ref, RelationKind::ProducedBy, agent_ref)).await.unwrap();
+
+        // Both endpoints must exist
+        let missing = Relation::new(claim_ref, RelationKind::Cites, EntityRef::knowledge(Uuid::new_v4()));
+        assert!(matches!(storage.add_relation(&missing).await, Err(StorageError::NotFound(_))), "{}", backend.name);
+
+        let sources = |relations: Vec<Relation>| relations.into_iter().map(|r| (r.from, r.kind)).collect::<Vec<_>>();
+        let incoming = storage.neighbors(&claim_ref, Direction::Incoming, &[]).await.unwrap();
+        assert_eq!(incoming.len(), 2, "{}", backend.name);
+        let contradicting = storage.neighbors(&claim_ref, Direction::Incoming, &[RelationKind::Contradicts]).await.unwrap();
+        assert_eq!(sources(contradicting), [(rebuttal_ref, RelationKind::Contradicts)], "{}", backend.name);
+        let outgoing = storage.neighbors(&study_ref, Direction::Outgoing, &[]).await.unwrap();
+        assert_eq!(outgoing.len(), 2, "{}", backend.name);
+
+        // Storing a relation again replaces it
+        let mut supports = Relation::new(study_ref, RelationKind::Supports, claim_ref);
+        supports.metadata.insert("strength".to_string(), serde_json::json!(0.9));
+        storage.add_relation(&supports).await.unwrap();
+        let outgoing = storage.neighbors(&study_ref, Direction::Outgoing, &[RelationKind::Supports]).await.unwrap();
+        assert_eq!(outgoing.len(), 1, "{}", backend.name);
+        assert_eq!(outgoing[0].metadata.get("strength"), Some(&serde_json::json!(0.9)), "{}", backend.name);
+
+        // The rebuttal reaches the agent only by following edges against their direction
+        let path = storage.find_path(&rebuttal_ref, &agent_ref, &PathQuery::default()).await.unwrap();
+        assert!(path.is_none(), "{}", backend.name);
+        let both = PathQuery { direction: Direction::Both, ..Default::default() };
+        let path = storage.find_path(&rebuttal_ref, &agent_ref, &both).await.unwrap().unwrap();
+        let kinds: Vec<RelationKind> = path.iter().map(|relation| relation.kind).collect();
+        assert_eq!(kinds, [RelationKind::Contradicts, RelationKind::Supports, RelationKind::ProducedBy], "{}", backend.name);
+        let shallow = PathQuery { max_depth: 2, ..both.clone() };
+        assert!(storage.find_path(&rebuttal_ref, &agent_ref, &shallow).await.unwrap().is_none(), "{}", backend.name);
+
+        assert!(storage.remove_relation(&rebuttal_ref, RelationKind::Contradicts, &claim_ref).await.unwrap(), "{}", backend.name);
+        assert!(!storage.remove_relation(&rebuttal_ref, RelationKind::Contradicts, &claim_ref).await.unwrap(), "{}", backend.name);
+
+        // Deleting an entity removes its relations
+        storage.delete_knowledge(&study.id).await.unwrap();
+        assert!(storage.neighbors(&claim_ref, Direction::Both, &[]).await.unwrap().is_empty(), "{}", backend.name);
+        assert!(storage.neighbors(&agent_ref, Direction::Both, &[]).await.unwrap().is_empty(), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn documents_are_chunked_and_grouped() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let document = knowledge(
+            "# Notes\n\n## Volcanoes\nMagma erupts from volcanoes.\n\n## Oceans\nWhales swim in deep ocean currents.",
+        );
+
+        let report = storage.store_document(&document, &ChunkingConfig::default()).await.unwrap();
+        let chunks = storage.get_document_chunks(&document.id).await.unwrap();
+        assert_eq!(chunks.len(), report.chunks.len(), "{}", backend.name);
+        assert!(chunks.iter().all(|chunk| chunk.parent_id == Some(document.id)), "{}", backend.name);
+
+        let search = DocumentSearch { query: "whales ocean".to_string(), ..Default::default() };
+        let matches = storage.search_documents(&search).await.unwrap();
+        assert_eq!(matches[0].document_id, document.id, "{}", backend.name);
+
+        assert!(storage.delete_knowledge(&document.id).await.unwrap(), "{}", backend.name);
+        assert!(storage.get_document_chunks(&document.id).await.unwrap().is_empty(), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn chunks_are_not_entries() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let document = knowledge(
+            "# Notes\n\n## Volcanoes\nMagma erupts from volcanoes.\n\n## Oceans\nWhales swim in deep ocean currents.",
+        );
+        storage.store_document(&document, &ChunkingConfig::default()).await.unwrap();
+        let chunks = storage.get_document_chunks(&document.id).await.unwrap();
+
+        // Content matching a chunk is a new entry, not a duplicate of the chunk
+        let paragraph = knowledge(&chunks.last().unwrap().content);
+        let report = storage.store_knowledge(&paragraph).await.unwrap();
+        assert_eq!(report.outcome, IngestOutcome::Stored, "{}", backend.name);
+
+        let results = storage.search_knowledge("whales ocean currents", 10).await.unwrap();
+        assert!(results.iter().all(|result| result.entity.parent_id.is_none()), "{}", backend.name);
+        assert!(results.iter().any(|result| result.entity.id == paragraph.id), "{}", backend.name);
+
+        let usage = storage.get_metrics().await.usage;
+        assert_eq!(usage.knowledge_entries, 2, "{}", backend.name);
+        assert_eq!(usage.knowledge_chunks, chunks.len() as u64, "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn document_matches_carry_surrounding_chunks() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let geography = knowledge(
+            "# Geography\n\n## Volcanoes\nMagma erupts from volcanoes.\n\n## Oceans\nWhales swim in deep ocean currents.\n\n## Deserts\nDunes shift across dry deserts.",
+        );
+        let engineering = knowledge("# Engineering\n\n## Bridges\nSteel cables hold suspension bridges.\n\n## Tunnels\nBoring machines cut through rock.");
+        for document in [&geography, &engineering] {
+            storage.store_document(document, &ChunkingConfig::default()).await.unwrap();
+        }
+        let sections = storage.get_document_chunks(&geography.id).await.unwrap();
+        assert_eq!(sections.len(), 3, "{}", backend.name);
+
+        let search = DocumentSearch {
+            query: "whales ocean currents".to_string(),
+            limit: 2,
+            passages_per_document: 1,
+            context_chunks: 1,
+            fusion: None,
+        };
+        let matches = storage.search_documents(&search).await.unwrap();
+        assert_eq!(matches.len(), 2, "{}", backend.name);
+        assert_eq!((matches[0].rank, matches[1].rank), (1, 2), "{}", backend.name);
+        assert!(matches[0].score >= matches[1].score, "{}", backend.name);
+        assert!(matches.iter().all(|found| found.passages.len() == 1), "{}", backend.name);
+        assert!(
+            matches.iter().all(|found| found.passages[0].chunk.parent_id == Some(found.document_id)),
+            "{}",
+            backend.name
+        );
+
+        // The middle section matches, with one neighbour on each side
+        let best = &matches[0];
+        assert_eq!(best.document_id, geography.id, "{}", backend.name);
+        assert_eq!(best.document.as_ref().map(|document| &document.content), Some(&geography.content), "{}", backend.name);
+        let passage = &best.passages[0];
+        let ids = |chunks: &[KnowledgeEntity]| chunks.iter().map(|chunk| chunk.id).collect::<Vec<_>>();
+        assert_eq!(passage.chunk.id, sections[1].id, "{}", backend.name);
+        assert_eq!(ids(&passage.before), [sections[0].id], "{}", backend.name);
+        assert_eq!(ids(&passage.after), [sections[2].id], "{}", backend.name);
+
+        // Context stops at the ends of the document
+        let wide = DocumentSearch { context_chunks: 5, ..search };
+        let matches = storage.search_documents(&wide).await.unwrap();
+        let passage = &matches[0].passages[0];
+        assert_eq!((passage.before.len(), passage.after.len()), (1, 1), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn token_windows_overlap_and_restoring_replaces_chunks() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let document = knowledge("one two three four five six seven eight nine ten");
+        let windows = ChunkingConfig { strategy: ChunkStrategy::TokenWindows, max_tokens: 4, overlap_tokens: 1 };
+
+        let report = storage.store_document(&document, &windows).await.unwrap();
+        let chunks = storage.get_document_chunks(&document.id).await.unwrap();
+        let contents: Vec<&str> = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+        assert_eq!(contents, ["one two three four", "four five six seven", "seven eight nine ten"], "{}", backend.name);
+        assert_eq!(chunks.iter().map(|chunk| chunk.id).collect::<Vec<_>>(), report.chunks, "{}", backend.name);
+        assert_eq!(report.replaced_chunks, 0, "{}", backend.name);
+
+        // Storing the document again swaps in a fresh set of chunks
+        let wider = ChunkingConfig { max_tokens: 6, ..windows };
+        let report = storage.store_document(&document, &wider).await.unwrap();
+        assert_eq!(report.replaced_chunks, 3, "{}", backend.name);
+        let chunks = storage.get_document_chunks(&document.id).await.unwrap();
+        assert_eq!(chunks.len(), 2, "{}", backend.name);
+        for old in &contents {
+            assert!(chunks.iter().all(|chunk| chunk.content != *old), "{}", backend.name);
+        }
+        assert_eq!(storage.get_metrics().await.usage.knowledge_chunks, 2, "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn coordination_is_listed_oldest_first() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let (session, agent) = (Uuid::new_v4(), Uuid::new_v4());
+        let older = coordination(session, agent, "Completed", 200);
+        let newer = coordination(session, agent, "Failed", 100);
+        let other_session = coordination(Uuid::new_v4(), agent, "Completed", 10);
+        for record in [&newer, &other_session, &older] {
+            storage.update_coordination(record).await.unwrap();
+        }
+
+        let ids = |records: Vec<CoordinationEntity>| records.into_iter().map(|record| record.id).collect::<Vec<_>>();
+        assert_eq!(ids(storage.list_coordination(&session).await.unwrap()), [older.id, newer.id], "{}", backend.name);
+        assert_eq!(
+            ids(storage.list_by_agent(&agent, TimeRange::default()).await.unwrap()),
+            [older.id, newer.id, other_session.id],
+            "{}",
+            backend.name
+        );
+        assert_eq!(
+            ids(storage.list_by_status("Completed").await.unwrap()),
+            [older.id, other_session.id],
+            "{}",
+            backend.name
+        );
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn coordination_indexes_follow_updates_and_ranges() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let (session, agent) = (Uuid::new_v4(), Uuid::new_v4());
+        let records: Vec<_> = [300, 200, 100].into_iter()
+            .map(|age_secs| coordination(session, agent, "Running", age_secs))
+            .collect();
+        let unassigned = CoordinationEntity { agent_id: None, ..coordination(session, agent, "Running", 50) };
+        for record in records.iter().chain([&unassigned]) {
+            storage.update_coordination(record).await.unwrap();
+        }
+
+        let ids = |records: Vec<CoordinationEntity>| records.into_iter().map(|record| record.id).collect::<Vec<_>>();
+        // Ranges include `from` and exclude `until`
+        let range = TimeRange { from: Some(records[0].timestamp), until: Some(records[2].timestamp) };
+        assert_eq!(
+            ids(storage.list_by_agent(&agent, range).await.unwrap()),
+            [records[0].id, records[1].id],
+            "{}",
+            backend.name
+        );
+        let range = TimeRange { from: Some(records[1].timestamp), until: None };
+        assert_eq!(
+            ids(storage.list_by_agent(&agent, range).await.unwrap()),
+            [records[1].id, records[2].id],
+            "{}",
+            backend.name
+        );
+        assert_eq!(storage.list_coordination(&session).await.unwrap().len(), 4, "{}", backend.name);
+
+        // An update moves the record to its new status and timestamp
+        let finished = CoordinationEntity {
+            status: "Completed".to_string(),
+            timestamp: Utc::now(),
+            ..records[0].clone()
+        };
+        storage.update_coordination(&finished).await.unwrap();
+        assert_eq!(
+            ids(storage.list_by_status("Running").await.unwrap()),
+            [records[1].id, records[2].id, unassigned.id],
+            "{}",
+            backend.name
+        );
+        let completed = storage.list_by_status("Completed").await.unwrap();
+        assert_eq!(ids(completed.clone()), [finished.id], "{}", backend.name);
+        assert_eq!(completed[0].timestamp, finished.timestamp, "{}", backend.name);
+        assert_eq!(
+            ids(storage.list_coordination(&session).await.unwrap()),
+            [records[1].id, records[2].id, unassigned.id, finished.id],
+            "{}",
+            backend.name
+        );
+        assert!(storage.list_by_status("Unknown").await.unwrap().is_empty(), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn task_history_is_searchable() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        let (session_id, agent_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let task = |summary: &str| TaskHistoryEntity {
+            id: Uuid::new_v4(),
+            session_id,
+            task_id: Uuid::new_v4(),
+            agent_id,
+            action_type: "research".to_string(),
+            status: "Completed".to_string(),
+            summary: summary.to_string(),
+            execution_time_ms: 5,
+            embeddings: None,
+            created_at: Utc::now(),
+        };
+        let volcanoes = task("research volcano eruptions");
+        storage.record_task_history(&volcanoes).await.unwrap();
+        storage.record_task_history(&task("summarize ocean currents")).await.unwrap();
+
+        let results = storage.search_task_history("volcano eruptions", 1).await.unwrap();
+        assert_eq!(results[0].entity.id, volcanoes.id, "{}", backend.name);
+        // Task history stays out of knowledge search
+        assert!(storage.search_knowledge("volcano eruptions", 5).await.unwrap().is_empty(), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
+
+#[tokio::test]
+async fn synchronize_leaves_nothing_pending() {
+    for backend in backends().await {
+        let storage = &backend.storage;
+        storage.store_knowledge(&knowledge("Coral reefs host fish")).await.unwrap();
+
+        assert!(storage.synchronize().await.unwrap().success, "{}", backend.name);
+        assert_eq!(storage.sync_status().await.pending_count, 0, "{}", backend.name);
+        assert_eq!(storage.get_metrics().await.usage.knowledge_entries, 1, "{}", backend.name);
+        assert!(storage.verify().await.unwrap().is_consistent(), "{}", backend.name);
+        storage.shutdown().await;
+    }
+}
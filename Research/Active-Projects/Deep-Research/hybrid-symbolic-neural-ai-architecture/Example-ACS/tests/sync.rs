@@ -0,0 +1,133 @@
+//! Deferred vector write tests
+//!
+//! Under `ConsistencyMode::Eventually` vector writes are journaled and applied
+//! in batches. The background worker is stopped so each test decides when the
+//! journal drains; `common::FaultyIndex` counts the calls that reach the index
+//! and rejects the points a test names.
+
+mod common;
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::{ConsistencyMode, HybridStorage, HybridStorageCoordinator};
+use common::{knowledge, FaultyIndex, TestStorage};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+async fn open(sync_batch_size: usize) -> (HybridStorageCoordinator, Arc<FaultyIndex>, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let index = FaultyIndex::new(Arc::new(LocalVectorIndex::in_memory().unwrap()));
+    let storage = TestStorage::new(dir.path(), "sync")
+        .consistency(ConsistencyMode::Eventually)
+        .configure(|config| config.sync_batch_size = sync_batch_size)
+        .vectors(index.clone())
+        .open()
+        .await;
+    storage.stop_sync_worker().await;
+    (storage, index, dir)
+}
+
+#[tokio::test]
+async fn journal_drains_in_coalesced_batches() {
+    let (storage, index, _dir) = open(2).await;
+
+    let entries: Vec<_> = (0..5)
+        .map(|i| knowledge(&format!("Deferred write number {} waits for the next batch", i)))
+        .collect();
+    for entry in &entries {
+        storage.store_knowledge(entry).await.unwrap();
+    }
+    assert_eq!(storage.sync_status().await.pending_count, 5);
+    assert_eq!(index.writes(), 0);
+
+    // Batches of two upserts each become one call
+    let result = storage.synchronize().await.unwrap();
+    assert!(result.success);
+    assert_eq!(result.operations_processed, 5);
+    assert_eq!(index.writes(), 3);
+    assert_eq!(storage.sync_status().await.pending_count, 0);
+
+    let report = storage.verify().await.unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    assert_eq!(report.points_checked, 5);
+}
+
+#[tokio::test]
+async fn coalescing_keeps_deletes_after_upserts() {
+    let (storage, index, _dir) = open(10).await;
+
+    let removed = knowledge("An entry deleted before the journal drained");
+    let kept = knowledge("An entry that stays in the knowledge base");
+    storage.store_knowledge(&removed).await.unwrap();
+    storage.store_knowledge(&kept).await.unwrap();
+    assert!(storage.delete_knowledge(&removed.id).await.unwrap());
+    assert_eq!(storage.sync_status().await.pending_count, 3);
+
+    // One upsert of both points, then the delete
+    storage.synchronize().await.unwrap();
+    assert_eq!(index.writes(), 2);
+
+    let report = storage.verify().await.unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    assert_eq!(report.points_checked, 1);
+}
+
+#[tokio::test]
+async fn failed_batches_stay_pending() {
+    let (storage, index, _dir) = open(10).await;
+
+    index.fail_writes(true);
+    storage.store_knowledge(&knowledge("A write made during a vector outage")).await.unwrap();
+    let result = storage.synchronize().await.unwrap();
+    assert!(!result.success);
+
+    let status = storage.sync_status().await;
+    assert_eq!(status.pending_count, 1);
+    assert!(status.last_error.is_some());
+
+    index.fail_writes(false);
+    assert!(storage.synchronize().await.unwrap().success);
+    assert_eq!(storage.sync_status().await.pending_count, 0);
+}
+
+#[tokio::test]
+async fn rejected_entries_are_quarantined_and_the_rest_apply() {
+    let (storage, index, _dir) = open(10).await;
+
+    let entries: Vec<_> = (0..3)
+        .map(|i| knowledge(&format!("Entry {} of a batch with one point the index rejects", i)))
+        .collect();
+    index.reject_point(entries[1].id, true);
+    for entry in &entries {
+        storage.store_knowledge(entry).await.unwrap();
+    }
+
+    // The batch fails, each entry is retried alone and the rejected one is set aside
+    let result = storage.synchronize().await.unwrap();
+    assert!(result.success);
+    assert_eq!(result.operations_processed, 2);
+
+    let status = storage.sync_status().await;
+    assert_eq!((status.pending_count, status.quarantined_count), (0, 1));
+    assert_eq!(status.error_count, 1);
+    assert!(status.last_error.is_none());
+
+    // Later writes are not held back
+    let later = knowledge("An entry written after the quarantine");
+    storage.store_knowledge(&later).await.unwrap();
+    assert!(storage.synchronize().await.unwrap().success);
+
+    let report = storage.verify().await.unwrap();
+    assert_eq!(report.issues.len(), 1, "{:?}", report.issues);
+    assert_eq!(report.issues[0].key, entries[1].id.to_string());
+
+    // Requeued once the index accepts the point
+    index.reject_point(entries[1].id, false);
+    assert_eq!(storage.requeue_quarantined().await.unwrap(), 1);
+    assert_eq!(storage.sync_status().await.pending_count, 1);
+    assert!(storage.synchronize().await.unwrap().success);
+
+    let status = storage.sync_status().await;
+    assert_eq!((status.pending_count, status.quarantined_count), (0, 0));
+    let report = storage.verify().await.unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+}
@@ -0,0 +1,38 @@
+//! VectorIndex implementation tests
+//!
+//! Checks run against the in-process index, and against a Qdrant server when
+//! `ACS_TEST_QDRANT_URL` is set; each run uses a fresh collection.
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::{StorageError, VectorIndex};
+use uuid::Uuid;
+
+fn indexes() -> Vec<(&'static str, Box<dyn VectorIndex>)> {
+    let mut indexes: Vec<(&'static str, Box<dyn VectorIndex>)> =
+        vec![("in-process", Box::new(LocalVectorIndex::in_memory().unwrap()))];
+
+    #[cfg(feature = "qdrant")]
+    if let Ok(qdrant_url) = std::env::var("ACS_TEST_QDRANT_URL") {
+        use acs_example::storage::qdrant_integration::QdrantIndex;
+        indexes.push(("qdrant", Box::new(QdrantIndex::connect(&qdrant_url).unwrap())));
+    }
+
+    indexes
+}
+
+#[tokio::test]
+async fn existing_collection_must_match_the_dimension() {
+    for (name, index) in indexes() {
+        let collection = format!("dimension_{}", Uuid::new_v4().simple());
+        index.ensure_collection(&collection, 4).await.unwrap();
+        index.ensure_collection(&collection, 4).await.unwrap();
+
+        match index.ensure_collection(&collection, 8).await {
+            Err(StorageError::ConfigurationError(message)) => {
+                assert!(message.contains("dimension 4") && message.contains("expected 8"), "{}: {}", name, message);
+            }
+            other => panic!("{}: expected a dimension mismatch, got {:?}", name, other.map(|_| ())),
+        }
+        index.delete_collection(&collection).await.unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Consistency verification and repair tests
+//!
+//! Drift is introduced behind the coordinator's back: through the shared
+//! vector index for points, and by editing the raw REDB file with the
+//! coordinator closed for undecodable rows.
+
+mod common;
+
+use acs_example::storage::local_index::LocalVectorIndex;
+use acs_example::storage::{
+    ConsistencyIssueKind, ConsistencyReport, HybridStorage, KnowledgeEntity, MissingVectorRepair, OrphanedVectorRepair,
+    PayloadMismatchRepair, RepairPolicy, UnreadableRowRepair, VectorIndex, VectorPoint,
+};
+use common::{knowledge, TestStorage};
+use redb::TableDefinition;
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const KNOWLEDGE_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("knowledge");
+const QUARANTINE_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("quarantine");
+
+/// Storage over `dir` with a vector index the test can also edit directly
+fn with_shared_vectors(dir: &Path) -> (TestStorage, Arc<LocalVectorIndex>) {
+    let vectors = Arc::new(LocalVectorIndex::open(dir.join("vectors.redb")).unwrap());
+    (TestStorage::new(dir, "verify").vectors(vectors.clone()), vectors)
+}
+
+/// Entries damaged by `introduce_drift`, one per issue kind
+struct Drift {
+    missing_vector: KnowledgeEntity,
+    mismatched: KnowledgeEntity,
+    unreadable: KnowledgeEntity,
+    orphaned: Uuid,
+}
+
+/// Store three entries and damage each differently, plus add a point without a row
+async fn introduce_drift(test_storage: &TestStorage, vectors: &LocalVectorIndex) -> Drift {
+    let collection = &test_storage.config.collection_name;
+    let storage = test_storage.open().await;
+    let drift = Drift {
+        missing_vector: knowledge("Stalactites hang from cave ceilings"),
+        mismatched: knowledge("Estuaries mix fresh and salt water"),
+        unreadable: knowledge("Permafrost thaws as the climate warms"),
+        orphaned: Uuid::new_v4(),
+    };
+    for entry in [&drift.missing_vector, &drift.mismatched, &drift.unreadable] {
+        storage.store_knowledge(entry).await.unwrap();
+    }
+    storage.store_knowledge(&knowledge("An entry that stays consistent")).await.unwrap();
+    storage.stop_sync_worker().await;
+    drop(storage);
+
+    vectors.delete(collection, &[drift.missing_vector.id]).await.unwrap();
+
+    let (mut points, _) = vectors.scroll(collection, Some(drift.mismatched.id), 1).await.unwrap();
+    let mut point = points.remove(0);
+    point.payload.insert("source".to_string(), "tampered".into());
+    let orphan = VectorPoint { id: drift.orphaned, ..point.clone() };
+    vectors.upsert(collection, vec![point, orphan]).await.unwrap();
+
+    let db = redb::Database::create(test_storage.db_path()).unwrap();
+    let write_txn = db.begin_write().unwrap();
+    write_txn.open_table(KNOWLEDGE_TABLE).unwrap()
+        .insert(drift.unreadable.id.to_string().as_str(), b"not a knowledge row".as_slice())
+        .unwrap();
+    write_txn.commit().unwrap();
+
+    drift
+}
+
+fn kind_of<'a>(report: &'a ConsistencyReport, id: &Uuid) -> Option<&'a ConsistencyIssueKind> {
+    report.issues.iter().find(|issue| issue.key == id.to_string()).map(|issue| &issue.kind)
+}
+
+#[tokio::test]
+async fn each_issue_kind_is_found_and_repaired() {
+    let dir = tempfile::tempdir().unwrap();
+    let (test_storage, vectors) = with_shared_vectors(dir.path());
+    let drift = introduce_drift(&test_storage, &vectors).await;
+
+    let storage = test_storage.open().await;
+    let report = storage.verify().await.unwrap();
+    assert_eq!(report.issues.len(), 4, "{:?}", report.issues);
+    assert_eq!(kind_of(&report, &drift.missing_vector.id), Some(&ConsistencyIssueKind::MissingVector));
+    assert_eq!(kind_of(&report, &drift.orphaned), Some(&ConsistencyIssueKind::OrphanedVector));
+    assert_eq!(
+        kind_of(&report, &drift.mismatched.id),
+        Some(&ConsistencyIssueKind::PayloadMismatch { fields: vec!["source".to_string()] })
+    );
+    assert!(matches!(kind_of(&report, &drift.unreadable.id), Some(ConsistencyIssueKind::Unreadable(_))));
+
+    let repaired = storage.repair(&RepairPolicy::default()).await.unwrap();
+    assert_eq!((repaired.repaired, repaired.ignored), (4, 0));
+    assert!(repaired.failures.is_empty(), "{:?}", repaired.failures);
+
+    let report = storage.verify().await.unwrap();
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    assert_eq!(report.points_checked, 3);
+    let results = storage.search_knowledge("stalactites cave ceilings", 1).await.unwrap();
+    assert_eq!(results[0].entity.id, drift.missing_vector.id);
+    storage.stop_sync_worker().await;
+    drop(storage);
+
+    // The unreadable row's bytes are kept for inspection
+    let db = redb::Database::create(test_storage.db_path()).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let quarantine = read_txn.open_table(QUARANTINE_TABLE).unwrap();
+    let row = quarantine.get(format!("knowledge/{}", drift.unreadable.id).as_str()).unwrap().unwrap();
+    assert_eq!(row.value(), b"not a knowledge row");
+}
+
+#[tokio::test]
+async fn repair_policy_selects_the_fix_for_each_kind() {
+    let dir = tempfile::tempdir().unwrap();
+    let (test_storage, vectors) = with_shared_vectors(dir.path());
+    let drift = introduce_drift(&test_storage, &vectors).await;
+
+    let storage = test_storage.open().await;
+    let policy = RepairPolicy {
+        missing_vector: MissingVectorRepair::DeleteRow,
+        orphaned_vector: OrphanedVectorRepair::Ignore,
+        payload_mismatch: PayloadMismatchRepair::Ignore,
+        unreadable_row: UnreadableRowRepair::Delete,
+    };
+    let repaired = storage.repair(&policy).await.unwrap();
+    assert_eq!((repaired.repaired, repaired.ignored), (2, 2));
+
+    assert!(storage.get_knowledge(&drift.missing_vector.id).await.unwrap().is_none());
+    assert!(storage.get_knowledge(&drift.unreadable.id).await.unwrap().is_none());
+
+    // Ignored issues are still there
+    let report = storage.verify().await.unwrap();
+    assert_eq!(report.issues.len(), 2, "{:?}", report.issues);
+    assert_eq!(kind_of(&report, &drift.orphaned), Some(&ConsistencyIssueKind::OrphanedVector));
+    assert!(matches!(kind_of(&report, &drift.mismatched.id), Some(ConsistencyIssueKind::PayloadMismatch { .. })));
+    storage.stop_sync_worker().await;
+    drop(storage);
+
+    let db = redb::Database::create(test_storage.db_path()).unwrap();
+    let read_txn = db.begin_read().unwrap();
+    assert!(read_txn.open_table(QUARANTINE_TABLE).is_err(), "deleted rows are not quarantined");
+}